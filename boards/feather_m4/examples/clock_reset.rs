@@ -0,0 +1,57 @@
+//! This example tears the clock tree down and sets it up again.
+//!
+//! The LED blinks quickly with the clocks derived from the external 32 kHz
+//! crystal. The clock tree is then returned to its reset state, as it would
+//! be before handing over to a bootloader, and set up again from the
+//! internal 32 kHz oscillator. The LED then blinks slowly.
+
+#![no_std]
+#![no_main]
+
+use feather_m4 as hal;
+use panic_halt as _;
+
+use hal::clock::GenericClockController;
+use hal::delay::Delay;
+use hal::entry;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    for _ in 0..10 {
+        red_led.toggle();
+        delay.delay_ms(100u16);
+    }
+
+    // The delay holds the GCLK0 frequency, so it is freed first
+    let syst = delay.free();
+    let gclk = clocks.reset(&mut peripherals.OSCCTRL);
+    // The CPU now runs at 48 MHz from the DFLL, as after a reset
+
+    let mut clocks = GenericClockController::with_internal_32kosc(
+        gclk,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut delay = Delay::new(syst, &mut clocks);
+    loop {
+        red_led.toggle();
+        delay.delay_ms(1000u16);
+    }
+}
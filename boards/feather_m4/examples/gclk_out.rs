@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+// Outputting a clock generator on a pin
+//
+// GCLK2 divides the 48 MHz DFLL down to 1 MHz, and is output on D5 (PA16),
+// one of the GCLK_IO pins of GCLK2, for a frequency counter or a scope. The
+// output is turned on and off every second, along with the red LED.
+
+extern crate cortex_m;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+use hal::clock::gclk_out::Gclk2Id;
+use hal::clock::{ClockGenId, ClockSource, GclkOut, GenericClockController};
+use hal::delay::Delay;
+use hal::entry;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+
+    clocks
+        .configure_gclk_divider_and_source(ClockGenId::GCLK2, 48, ClockSource::DFLL, false)
+        .unwrap();
+    // D5 can only output GCLK2: any other generator fails to compile
+    let mut pin = pins.d5.into_function_m(&mut pins.port).into();
+
+    loop {
+        let out: GclkOut<Gclk2Id, _> = clocks.enable_gclk_out(pin, false).ok().unwrap();
+        red_led.set_high().unwrap();
+        delay.delay_ms(1000u16);
+
+        pin = clocks.disable_gclk_out(out);
+        red_led.set_low().unwrap();
+        delay.delay_ms(1000u16);
+    }
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+// Four-phase interleaved PWM at 100 kHz
+//
+// cargo build --features="unproven"
+//
+// Drives the four stages of an interleaved buck converter, each 90 degrees
+// behind the previous one, at a 25% duty cycle:
+//
+// - Phase 0: D10 (PA20, TCC0 WO[0])
+// - Phase 1: D6 (PA18, TCC1 WO[2])
+// - Phase 2: D4 (PA14, TCC2 WO[0])
+// - Phase 3: D1 (PB16, TCC3 WO[0])
+//
+// The alignment of the counters is checked once per second. The start
+// latency of the followers measured after the first alignment is compensated
+// for, and the red LED is lit while every phase is aligned.
+
+extern crate cortex_m_rt;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+use cortex_m_rt::entry;
+use hal::clock::GenericClockController;
+use hal::delay::Delay;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+use hal::pwm::Channel;
+use hal::synced_pwm::{Alignment, SyncedPwmGroup};
+
+/// Output channel of each phase
+const CHANNELS: [Channel; 4] = [Channel::_0, Channel::_2, Channel::_0, Channel::_0];
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+
+    // Every TCC clock comes from GCLK0, at 120 MHz
+    let gclk0 = clocks.gclk0();
+    let tcc_clock = clocks.tcc0_tcc1(&gclk0).unwrap();
+    clocks.tcc2_tcc3(&gclk0).unwrap();
+    let _phase0 = pins.d10.into_function_g(&mut pins.port);
+    let _phase1 = pins.d6.into_function_f(&mut pins.port);
+    let _phase2 = pins.d4.into_function_f(&mut pins.port);
+    let _phase3 = pins.d1.into_function_f(&mut pins.port);
+
+    let mut group = SyncedPwmGroup::new(
+        [
+            peripherals.TCC0.into(),
+            peripherals.TCC1.into(),
+            peripherals.TCC2.into(),
+            peripherals.TCC3.into(),
+        ],
+        tcc_clock.freq(),
+        100.khz(),
+        0,
+        &mut peripherals.MCLK,
+    )
+    .unwrap();
+    group.set_phases_evenly();
+    let duty = group.max_duty() / 4;
+    for (member, &channel) in CHANNELS.iter().enumerate() {
+        group.set_duty(member, channel, duty);
+    }
+
+    // Every follower starts with the same latency: compensate for the error
+    // of the first one
+    delay.delay_ms(1u8);
+    let latency = group.verify()[1];
+    if !latency.is_aligned() && latency.error > 0 {
+        group.set_start_compensation(latency.error as u32);
+        group.align();
+    }
+
+    loop {
+        if group.verify().iter().all(Alignment::is_aligned) {
+            red_led.set_high().unwrap();
+        } else {
+            red_led.set_low().unwrap();
+        }
+        delay.delay_ms(1000u16);
+    }
+}
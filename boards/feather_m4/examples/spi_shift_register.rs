@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+// Driving a chain of three 74HC595 shift registers with a transmit-only SPI
+//
+// The shift registers only need a clock and a data line, so the SPI has no
+// Data In pad, and the receiver stays disabled. The chain is clocked by SCK
+// (PA17), its data input is on D5 (PA16, SERCOM1 PAD0), and the storage
+// register clock (RCLK) of every chip is on D6. A single lit output walks
+// along the 24 outputs of the chain.
+
+extern crate cortex_m;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+#[macro_use(block)]
+extern crate nb;
+
+use embedded_hal::blocking::spi::Write as SpiWrite;
+use embedded_hal::serial::Write as SerialWrite;
+use hal::clock::GenericClockController;
+use hal::delay::Delay;
+use hal::entry;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+use hal::sercom::v2::pads::{IoSet1, Pad0};
+use hal::sercom::v2::{spi, Sercom1};
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let gclk0 = clocks.gclk0();
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut latch = pins.d6.into_push_pull_output(&mut pins.port);
+    latch.set_low().unwrap();
+
+    // No Data In pad: the SPI can only transmit
+    let pads = spi::Pads::<Sercom1, IoSet1>::new()
+        .sclk(pins.sck)
+        .data_out::<Pad0, _>(pins.d5);
+    let freq = clocks.sercom1_core(&gclk0).unwrap().freq();
+    let mut spi = spi::Config::new(&peripherals.MCLK, peripherals.SERCOM1, pads, freq)
+        .baud(4.mhz())
+        .spi_mode(spi::MODE_0)
+        .enable();
+
+    let mut outputs: u32 = 1;
+    loop {
+        // The last chip of the chain is shifted out first
+        let bytes = outputs.to_le_bytes();
+        SpiWrite::write(&mut spi, &[bytes[2], bytes[1], bytes[0]]).unwrap();
+        // Wait for the last bit to be shifted out, then latch the outputs
+        block!(SerialWrite::flush(&mut spi)).unwrap();
+        latch.set_high().unwrap();
+        latch.set_low().unwrap();
+
+        outputs = (outputs << 1) & 0x00FF_FFFF;
+        if outputs == 0 {
+            outputs = 1;
+        }
+        delay.delay_ms(100u8);
+    }
+}
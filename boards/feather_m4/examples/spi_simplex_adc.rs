@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+// Reading an MCP3201 ADC with a receive-only SPI
+//
+// The MCP3201 has no data input, so the SPI has no Data Out pad. The ADC is
+// clocked by SCK (PA17), its data output is on D5 (PA16, SERCOM1 PAD0), and
+// its chip select on D6. The red LED is lit while the input is above half of
+// the reference voltage.
+//
+// The SERCOM still shifts out dummy words to clock in each sample, but they
+// are routed to the unused PAD3, so D9 (PA19) stays free.
+
+extern crate cortex_m;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+#[macro_use(block)]
+extern crate nb;
+
+use hal::clock::GenericClockController;
+use hal::delay::Delay;
+use hal::entry;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+use hal::sercom::v2::pads::{IoSet1, Pad0};
+use hal::sercom::v2::spi::lengths::U2;
+use hal::sercom::v2::{spi, Sercom1};
+
+/// Half of the 12-bit full scale
+const HALF_SCALE: u16 = 1 << 11;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let gclk0 = clocks.gclk0();
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+    let mut cs = pins.d6.into_push_pull_output(&mut pins.port);
+    cs.set_high().unwrap();
+
+    // No Data Out pad: the SPI can only receive
+    let pads = spi::Pads::<Sercom1, IoSet1>::new()
+        .sclk(pins.sck)
+        .data_in::<Pad0, _>(pins.d5);
+    let freq = clocks.sercom1_core(&gclk0).unwrap().freq();
+    // A sample takes 16 clocks: two to sample the input, a null bit, 12 data
+    // bits, and one more bit which is discarded
+    let mut spi = spi::Config::new(&peripherals.MCLK, peripherals.SERCOM1, pads, freq)
+        .baud(1.mhz())
+        .spi_mode(spi::MODE_0)
+        .length::<U2>()
+        .enable();
+
+    loop {
+        cs.set_low().unwrap();
+        let word: u16 = block!(spi.read()).unwrap();
+        cs.set_high().unwrap();
+
+        // The first byte received is the least significant one of the word
+        let [first, second] = word.to_le_bytes();
+        let sample = ((first as u16 & 0x1F) << 7) | (second as u16 >> 1);
+        if sample > HALF_SCALE {
+            red_led.set_high().unwrap();
+        } else {
+            red_led.set_low().unwrap();
+        }
+        delay.delay_ms(10u8);
+    }
+}
@@ -0,0 +1,145 @@
+//! This example logs the A0 analog input while the CPU sleeps in standby.
+//!
+//! An RTC periodic event starts an ADC conversion 32 times per second, and the
+//! DMAC moves every result to a buffer. The CPU is only woken up once the
+//! buffer is full ("SleepWalking").
+//!
+//! EVSYS is configured with raw register writes, since the HAL does not
+//! abstract it yet.
+
+#![no_std]
+#![no_main]
+
+use cortex_m::asm;
+use feather_m4 as hal;
+use panic_halt as _;
+
+use hal::adc::Adc;
+use hal::clock::{ClockGenId, GenericClockController};
+use hal::dmac::{DmaController, PriorityLevel, Transfer, TriggerAction, TriggerSource};
+use hal::entry;
+use hal::pac::gclk::pchctrl::GEN_A::GCLK11;
+use hal::pac::{interrupt, CorePeripherals, Peripherals, ADC0, DMAC};
+use hal::prelude::*;
+use hal::rtc;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::peripheral::NVIC;
+
+/// Set by the DMAC interrupt once the buffer is full
+static BUFFER_FULL: AtomicBool = AtomicBool::new(false);
+
+/// Number of samples logged per wake-up
+const LENGTH: usize = 64;
+
+/// RTC periodic event used to start the conversions. With a 1024 Hz RTC
+/// clock, `PER2` runs at 1024 / 2^(2 + 3) = 32 Hz.
+const PERIODIC_EVENT: u8 = 2;
+
+/// EVSYS channel carrying the RTC event to the ADC
+const EVSYS_CHANNEL: usize = 0;
+/// EVSYS event generator ID of `RTC_PER0`
+const EVGEN_RTC_PER0: u8 = 0x04;
+/// EVSYS event user index of the `ADC0_START` input
+const USER_ADC0_START: usize = 55;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let mut core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_internal_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut pins = hal::Pins::new(peripherals.PORT);
+
+    // The RTC is clocked from the 32 kHz oscillator, which runs in standby
+    let mut timer = rtc::Rtc::count32_mode(peripherals.RTC, 1024.hz(), &mut peripherals.MCLK);
+    timer.enable_periodic_event(PERIODIC_EVENT);
+
+    // Route the RTC periodic event to the ADC start input. The asynchronous
+    // path needs no EVSYS clock, so it keeps working in standby.
+    peripherals
+        .MCLK
+        .apbbmask
+        .modify(|_, w| w.evsys_().set_bit());
+    let evsys = peripherals.EVSYS;
+    evsys.channel[EVSYS_CHANNEL].channel.write(|w| unsafe {
+        w.evgen()
+            .bits(EVGEN_RTC_PER0 + PERIODIC_EVENT)
+            .path()
+            .asynchronous()
+            .runstdby()
+            .set_bit()
+    });
+    evsys.user[USER_ADC0_START].write(|w| unsafe { w.channel().bits(EVSYS_CHANNEL as u8 + 1) });
+
+    // The ADC and its clock chain must keep running in standby
+    let mut adc0 = Adc::adc0(peripherals.ADC0, &mut peripherals.MCLK, &mut clocks, GCLK11);
+    peripherals
+        .OSCCTRL
+        .dfllctrla
+        .modify(|_, w| w.runstdby().set_bit());
+    clocks.configure_standby(ClockGenId::GCLK11, true);
+    let mut a0 = pins.a0.into_function_b(&mut pins.port);
+    adc0.enable_start_event();
+    adc0.run_in_standby(true);
+    adc0.enable_for_events(&mut a0);
+
+    // Every result ready triggers one DMA beat from the RESULT register
+    let mut dmac = DmaController::init(peripherals.DMAC, &mut peripherals.PM);
+    let channels = dmac.split();
+    let mut chan0 = channels.0.init(&mut dmac, PriorityLevel::LVL0, false);
+    chan0.run_in_standby(&mut dmac, true);
+
+    // SAFETY: The RESULT register is only read by the DMAC
+    let result: &'static mut u16 = unsafe { &mut *(*ADC0::ptr()).result.as_ptr() };
+    let buffer: &'static mut [u16; LENGTH] =
+        cortex_m::singleton!(: [u16; LENGTH] = [0; LENGTH]).unwrap();
+
+    // Wake up on transfer complete
+    unsafe {
+        (*DMAC::ptr()).channel[0]
+            .chintenset
+            .write(|w| w.tcmpl().set_bit());
+        core.NVIC.set_priority(interrupt::DMAC_0, 2);
+        NVIC::unmask(interrupt::DMAC_0);
+    }
+    core.SCB.set_sleepdeep();
+
+    let mut xfer = Transfer::new(chan0, result, buffer, false).begin(
+        &mut dmac,
+        TriggerSource::ADC0_RESRDY,
+        TriggerAction::BURST,
+    );
+
+    loop {
+        // Sleep until the buffer is full
+        while !BUFFER_FULL.swap(false, Ordering::Acquire) {
+            asm::wfi();
+        }
+        let (chan0, result, buffer, _) = xfer.wait(&mut dmac);
+
+        // Process the samples
+        let _average = buffer.iter().map(|&s| s as u32).sum::<u32>() / LENGTH as u32;
+
+        xfer = Transfer::new(chan0, result, buffer, false).begin(
+            &mut dmac,
+            TriggerSource::ADC0_RESRDY,
+            TriggerAction::BURST,
+        );
+    }
+}
+
+#[interrupt]
+fn DMAC_0() {
+    unsafe {
+        (*DMAC::ptr()).channel[0]
+            .chintflag
+            .write(|w| w.tcmpl().set_bit());
+    }
+    BUFFER_FULL.store(true, Ordering::Release);
+}
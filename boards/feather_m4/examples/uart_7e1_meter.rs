@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+// Reading an energy meter over its IEC 62056-21 optical port
+//
+// The optical probe is connected to the TX and RX pins. The meter talks 7E1
+// at 300 baud: seven data bits, even parity and one stop bit. The sign-on
+// message "/?!" is sent, and the identification message the meter answers
+// with is echoed on the red LED, one blink per character.
+
+extern crate cortex_m;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+#[macro_use(block)]
+extern crate nb;
+
+use hal::clock::GenericClockController;
+use hal::delay::Delay;
+use hal::entry;
+use hal::pac::gclk::genctrl::SRC_A;
+use hal::pac::gclk::pchctrl::GEN_A;
+use hal::pac::{CorePeripherals, Peripherals};
+use hal::prelude::*;
+use hal::sercom::{
+    CharSize, FrameFormat, PadPin, Parity, Sercom5Pad0, Sercom5Pad1, StopBits, UART5,
+};
+use hal::time::Hertz;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let core = CorePeripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    clocks.configure_gclk_divider_and_source(GEN_A::GCLK2, 1, SRC_A::DFLL, false);
+    let gclk2 = clocks
+        .get_gclk(GEN_A::GCLK2)
+        .expect("Could not get clock 2");
+
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut delay = Delay::new(core.SYST, &mut clocks);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+
+    let tx: Sercom5Pad0<_> = pins
+        .d1
+        .into_pull_down_input(&mut pins.port)
+        .into_pad(&mut pins.port);
+    let rx: Sercom5Pad1<_> = pins
+        .d0
+        .into_pull_down_input(&mut pins.port)
+        .into_pad(&mut pins.port);
+    let uart_clk = clocks
+        .sercom5_core(&gclk2)
+        .expect("Could not configure sercom5 clock");
+
+    let mut uart = UART5::new(
+        &uart_clk,
+        Hertz(300),
+        peripherals.SERCOM5,
+        &mut peripherals.MCLK,
+        (rx, tx),
+    );
+    uart.set_frame_format(FrameFormat::new(
+        CharSize::Seven,
+        Parity::Even,
+        StopBits::One,
+    ));
+
+    // Sign-on message
+    for byte in b"/?!\r\n" {
+        block!(uart.write(*byte)).unwrap();
+    }
+
+    loop {
+        // The identification message ends with CR LF
+        let byte = block!(uart.read()).unwrap();
+        if byte == b'\n' {
+            break;
+        }
+        red_led.set_high().unwrap();
+        delay.delay_ms(50u16);
+        red_led.set_low().unwrap();
+        delay.delay_ms(50u16);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+
+// Syncing to the baud rate of a USB-serial adapter
+//
+// The adapter is connected to the TX and RX pins, and set to any rate from
+// 9600 to 115200 baud. Type a few characters, ideally "UUU": the board
+// measures the rate on the RX pin, then opens the UART at that rate and
+// echoes everything back. The red LED stays on until the rate is detected.
+//
+// The edges of the RX pin (PB17, EXTINT1) go through EVSYS to the capture
+// channel 0 of TCC0. EVSYS and the EIC events are configured with raw register
+// writes, since the HAL does not abstract them yet.
+
+extern crate cortex_m;
+extern crate feather_m4 as hal;
+extern crate panic_halt;
+
+#[macro_use(block)]
+extern crate nb;
+
+use hal::clock::GenericClockController;
+use hal::entry;
+use hal::gpio::v2::{Pin, PB17};
+use hal::gpio::Pb17;
+use hal::pac::Peripherals;
+use hal::prelude::*;
+use hal::sercom::{AutoBaud, PadPin, Sercom5Pad0, Sercom5Pad1, UART5};
+use hal::tcc_capture::TccCapture;
+use hal::timer::TimerCounter;
+
+/// EVSYS channel carrying the RX pin edges to TCC0
+const EVSYS_CHANNEL: usize = 0;
+/// EVSYS event generator ID of `EIC_EXTINT1`
+const EVGEN_EIC_EXTINT1: u8 = 0x13;
+/// EVSYS event user index of the `TCC0_MC0` input
+const USER_TCC0_MC0: usize = 19;
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let mut clocks = GenericClockController::with_external_32kosc(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let gclk0 = clocks.gclk0();
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+    red_led.set_high().unwrap();
+
+    // Detect both edges of EXTINT1, and output them as events
+    let _eic_clock = clocks.eic(&gclk0).unwrap();
+    peripherals.MCLK.apbamask.modify(|_, w| w.eic_().set_bit());
+    let eic = peripherals.EIC;
+    eic.config[0].modify(|_, w| w.sense1().both());
+    eic.evctrl
+        .modify(|r, w| unsafe { w.extinteo().bits(r.extinteo().bits() | 1 << 1) });
+    eic.ctrla.modify(|_, w| w.enable().set_bit());
+    while eic.syncbusy.read().enable().bit_is_set() {}
+
+    // Route the EIC event to the capture input of TCC0 channel 0
+    peripherals
+        .MCLK
+        .apbbmask
+        .modify(|_, w| w.evsys_().set_bit());
+    let evsys = peripherals.EVSYS;
+    evsys.channel[EVSYS_CHANNEL]
+        .channel
+        .write(|w| unsafe { w.evgen().bits(EVGEN_EIC_EXTINT1).path().asynchronous() });
+    evsys.user[USER_TCC0_MC0].write(|w| unsafe { w.channel().bits(EVSYS_CHANNEL as u8 + 1) });
+
+    let tcc0_clock = clocks.tcc0_tcc1(&gclk0).unwrap();
+    let mut capture = TccCapture::tcc0(&tcc0_clock, peripherals.TCC0, &mut peripherals.MCLK, 0b1);
+    let timer_clock = clocks.tc2_tc3(&gclk0).unwrap();
+    let mut timer = TimerCounter::tc2_(&timer_clock, peripherals.TC2, &mut peripherals.MCLK);
+
+    // Retry until the adapter sends something usable
+    let mut rx: Pin<PB17, _> = pins.d0.into();
+    let baud = loop {
+        let (pin, baud) = AutoBaud::detect(rx, &mut capture, 0, &mut timer, 500.ms());
+        rx = pin;
+        if let Ok(baud) = baud {
+            break baud;
+        }
+    };
+    red_led.set_low().unwrap();
+
+    let rx: Sercom5Pad1<_> = Pb17::from(rx).into_pad(&mut pins.port);
+    let tx: Sercom5Pad0<_> = pins.d1.into_pad(&mut pins.port);
+    let uart_clk = clocks.sercom5_core(&gclk0).unwrap();
+    let mut uart = UART5::new(
+        &uart_clk,
+        baud,
+        peripherals.SERCOM5,
+        &mut peripherals.MCLK,
+        (rx, tx),
+    );
+
+    loop {
+        let byte = block!(uart.read()).unwrap_or(b'?');
+        block!(uart.write(byte)).unwrap();
+    }
+}
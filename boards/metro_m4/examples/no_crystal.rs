@@ -0,0 +1,147 @@
+#![no_std]
+#![no_main]
+
+// Running without any crystal, with the RTC timebase trimmed over USB
+//
+// The clocks run from the internal oscillators only, with the DFLL locked to
+// the start-of-frame packets of the USB host. Once the board enumerates,
+// OSCULP32K is trimmed against the 120 MHz main clock with the FREQM, and the
+// accuracy of the clocks is reported on the USB serial port whenever a
+// character is received.
+//
+// The FREQM is configured with raw register writes, since the HAL does not
+// abstract it yet.
+
+extern crate cortex_m;
+extern crate metro_m4 as hal;
+extern crate panic_halt;
+extern crate usb_device;
+extern crate usbd_serial;
+
+use core::fmt::Write;
+
+use hal::clock::{presets, OSC120M_FREQ};
+use hal::entry;
+use hal::pac::Peripherals;
+use hal::prelude::*;
+
+use usb_device::prelude::*;
+use usbd_serial::{SerialPort, USB_CLASS_CDC};
+
+/// Number of OSCULP32K periods of a FREQM measurement
+const REFNUM: u8 = 255;
+
+/// Resolution of a FREQM measurement, in ppm: one period of the 120 MHz
+/// main clock, out of about 933,838
+const RESOLUTION_PPM: u32 = 2;
+
+/// Formatting buffer for the report
+struct Buffer {
+    bytes: [u8; 128],
+    len: usize,
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(core::fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = Peripherals::take().unwrap();
+    let (mut clocks, report) = presets::no_crystal(
+        peripherals.GCLK,
+        &mut peripherals.MCLK,
+        &mut peripherals.OSC32KCTRL,
+        &mut peripherals.OSCCTRL,
+        &mut peripherals.NVMCTRL,
+    );
+    let mut osc32kctrl = peripherals.OSC32KCTRL;
+
+    // Measure GCLK0 (120 MHz) during REFNUM periods of GCLK1 (OSCULP32K)
+    let gclk0 = clocks.gclk0();
+    let gclk1 = clocks.gclk1();
+    let _msr_clock = clocks.freq_m_msr(&gclk0).unwrap();
+    let _ref_clock = clocks.freq_m_ref(&gclk1).unwrap();
+    peripherals
+        .MCLK
+        .apbamask
+        .modify(|_, w| w.freqm_().set_bit());
+    let freqm = peripherals.FREQM;
+    freqm.cfga.write(|w| unsafe { w.refnum().bits(REFNUM) });
+    freqm.ctrla.write(|w| w.enable().set_bit());
+    while freqm.syncbusy.read().enable().bit_is_set() {}
+
+    let mut pins = hal::Pins::new(peripherals.PORT);
+    let bus_allocator = hal::usb_allocator(
+        pins.usb_dm,
+        pins.usb_dp,
+        peripherals.USB,
+        &mut clocks,
+        &mut peripherals.MCLK,
+    );
+    let mut serial = SerialPort::new(&bus_allocator);
+    let mut usb_dev = UsbDeviceBuilder::new(&bus_allocator, UsbVidPid(0x2222, 0x3333))
+        .manufacturer("Fake company")
+        .product("No crystal")
+        .serial_number("TEST")
+        .device_class(USB_CLASS_CDC)
+        .build();
+    let mut red_led = pins.d13.into_open_drain_output(&mut pins.port);
+    red_led.set_high().unwrap();
+
+    // The DFLL only tracks the host once it sends start-of-frame packets
+    while usb_dev.state() != UsbDeviceState::Configured {
+        usb_dev.poll(&mut [&mut serial]);
+    }
+
+    let start = clocks.osculp32k_calibration(&osc32kctrl);
+    let trim = presets::trim_osculp32k(start, |calib| {
+        clocks.set_osculp32k_calibration(&mut osc32kctrl, calib);
+        // Let OSCULP32K settle for a whole measurement before the real one
+        for _ in 0..2 {
+            freqm.ctrlb.write(|w| w.start().set_bit());
+            while freqm.status.read().busy().bit_is_set() {
+                usb_dev.poll(&mut [&mut serial]);
+            }
+        }
+        let value = freqm.value.read().value().bits();
+        presets::reference_freq_millihertz(OSC120M_FREQ, REFNUM, value)
+    });
+    clocks.set_osculp32k_calibration(&mut osc32kctrl, trim.calib);
+    let trimmed = report.with_rtc_trim(&trim, RESOLUTION_PPM);
+    red_led.set_low().unwrap();
+
+    loop {
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+        let mut received = [0u8; 16];
+        if let Ok(count) = serial.read(&mut received) {
+            if count == 0 {
+                continue;
+            }
+            let mut buffer = Buffer {
+                bytes: [0; 128],
+                len: 0,
+            };
+            let _ = write!(
+                buffer,
+                "main {} ppm, RTC {} ppm ({} s/day), was {} ppm, CALIB {}\r\n",
+                trimmed.main_clock_ppm,
+                trimmed.rtc_ppm,
+                trimmed.rtc_drift_per_day(),
+                report.rtc_ppm,
+                trim.calib,
+            );
+            let _ = serial.write(&buffer.bytes[..buffer.len]);
+        }
+    }
+}
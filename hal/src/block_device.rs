@@ -0,0 +1,70 @@
+//! # Block devices
+//!
+//! [`BlockDevice`] is the common interface of the SD card drivers, so that
+//! application code, like a filesystem, doesn't depend on how the card is
+//! connected:
+//!
+//! - `sd_card::SdCard` uses the SDHC peripheral of the SAMD5x
+//! - [`sdspi::SdSpi`](crate::sdspi::SdSpi) uses a SERCOM in SPI mode, on
+//!   every chip
+//!
+//! ```
+//! fn read_boot_sector<D: BlockDevice>(card: &mut D) -> Result<[u8; BLOCK_SIZE], D::Error> {
+//!     let mut block = [0u8; BLOCK_SIZE];
+//!     card.read_block(0, &mut block)?;
+//!     Ok(block)
+//! }
+//! ```
+
+/// Size of a data block, in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+/// Device storing data in blocks of [`BLOCK_SIZE`] bytes
+///
+/// Blocks are addressed by their index `lba`, whatever the addressing used by
+/// the card.
+pub trait BlockDevice {
+    /// Error returned by the block accesses
+    type Error;
+
+    /// Read the block `lba`
+    fn read_block(&mut self, lba: u32, block: &mut [u8; BLOCK_SIZE]) -> Result<(), Self::Error>;
+
+    /// Write the block `lba`
+    fn write_block(&mut self, lba: u32, block: &[u8; BLOCK_SIZE]) -> Result<(), Self::Error>;
+
+    /// Read consecutive blocks starting at `lba`, filling `blocks`
+    ///
+    /// `blocks` must hold a whole number of blocks. Implementations may have
+    /// further requirements, like an alignment or a maximum length.
+    fn read_blocks(&mut self, lba: u32, blocks: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write consecutive blocks starting at `lba`, from `blocks`
+    ///
+    /// `blocks` must hold a whole number of blocks. Implementations may have
+    /// further requirements, like an alignment or a maximum length.
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Number of blocks in a buffer of `len` bytes, if it holds a non-zero whole
+/// number of blocks
+pub(crate) fn block_count(len: usize) -> Option<usize> {
+    if len == 0 || len % BLOCK_SIZE != 0 {
+        None
+    } else {
+        Some(len / BLOCK_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_blocks() {
+        assert_eq!(block_count(0), None);
+        assert_eq!(block_count(BLOCK_SIZE), Some(1));
+        assert_eq!(block_count(3 * BLOCK_SIZE), Some(3));
+        assert_eq!(block_count(BLOCK_SIZE + 4), None);
+    }
+}
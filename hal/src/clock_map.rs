@@ -0,0 +1,264 @@
+//! # Clock map
+//!
+//! A clock map records the intended configuration of the clock tree in the
+//! firmware image, so that tools can read it from the ELF file without running
+//! the firmware, and so that the firmware can check it against the hardware.
+//!
+//! The [`clock_map!`] macro emits a static table of [`ClockMapEntry`] in the
+//! `.clock_map` link section. It lists the source and divider of each clock
+//! generator, and the generator of each peripheral channel, as declared:
+//!
+//! ```
+//! atsamd_hal::clock_map! {
+//!     generators: {
+//!         GCLK0: (DPLL0, 1),
+//!         GCLK1: (DFLL, 1),
+//!         GCLK2: (OSCULP32K, 32),
+//!     },
+//!     peripherals: {
+//!         SERCOM0_CORE: GCLK1,
+//!         ADC0: GCLK1,
+//!     },
+//! }
+//! ```
+//!
+//! The linker only keeps the section if the linker script places it. With
+//! `cortex-m-rt`, add the following to `memory.x`:
+//!
+//! ```text
+//! SECTIONS {
+//!     .clock_map : ALIGN(4) { KEEP(*(.clock_map)) } > FLASH
+//! } INSERT AFTER .rodata;
+//! ```
+//!
+//! `tools/clock_map.py` dumps the table of an ELF file. At runtime,
+//! [`GenericClockController::verify_clock_map`] compares the table with the
+//! generator configuration read back by
+//! [`GenericClockController::read_gclk_config`] and with the peripheral
+//! channel registers, to catch drift between the declared clock tree and the
+//! one actually configured.
+//!
+//! This module requires the `clock_map` feature.
+
+use crate::clock::{ClockGenId, ClockId, ClockSource, GClockConfig, GenericClockController};
+
+/// Kind of a [`ClockMapEntry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockMapKind {
+    /// The source and divider of a clock generator
+    Generator = 0,
+    /// The clock generator of a peripheral channel
+    Peripheral = 1,
+}
+
+/// One entry of a clock map
+///
+/// The layout is fixed at 8 bytes, so that tools can parse the table:
+///
+/// | Byte | Generator entry     | Peripheral entry      |
+/// |------|---------------------|-----------------------|
+/// | 0    | `0`                 | `1`                   |
+/// | 1    | generator number    | generator number      |
+/// | 2    | `GENCTRL.SRC` value | peripheral channel ID |
+/// | 3    | reserved            | reserved              |
+/// | 4..8 | divider, LE `u32`   | `0`                   |
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct ClockMapEntry {
+    kind: ClockMapKind,
+    gclk: ClockGenId,
+    id: u8,
+    reserved: u8,
+    div: u32,
+}
+
+impl ClockMapEntry {
+    /// Entry declaring that `gclk` divides `src` by `div`
+    pub const fn generator(gclk: ClockGenId, src: ClockSource, div: u32) -> Self {
+        ClockMapEntry {
+            kind: ClockMapKind::Generator,
+            gclk,
+            id: src as u8,
+            reserved: 0,
+            div,
+        }
+    }
+
+    /// Entry declaring that the peripheral channel `clock` is fed by `gclk`
+    pub const fn peripheral(clock: ClockId, gclk: ClockGenId) -> Self {
+        ClockMapEntry {
+            kind: ClockMapKind::Peripheral,
+            gclk,
+            id: clock as u8,
+            reserved: 0,
+            div: 0,
+        }
+    }
+
+    /// Kind of the entry
+    pub fn kind(&self) -> ClockMapKind {
+        self.kind
+    }
+
+    /// Generator described by, or feeding the peripheral of, the entry
+    pub fn gclk(&self) -> ClockGenId {
+        self.gclk
+    }
+
+    /// Check the entry of a generator against its read-back configuration
+    fn matches_generator(&self, config: &GClockConfig) -> bool {
+        config.enabled
+            && config.src.map(|src| src as u8) == Some(self.id)
+            && config.div == self.div.max(1)
+    }
+}
+
+/// Discrepancy found by [`GenericClockController::verify_clock_map`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockMapMismatch {
+    /// A generator is disabled, or doesn't have the declared source or
+    /// divider
+    Generator {
+        /// The declared configuration
+        expected: ClockMapEntry,
+        /// The configuration read back from the hardware
+        actual: GClockConfig,
+    },
+    /// A peripheral channel is disabled, or fed by another generator
+    Peripheral {
+        /// The declared configuration
+        expected: ClockMapEntry,
+        /// The generator read back from the hardware, or `None` if the channel
+        /// is disabled
+        actual: Option<u8>,
+    },
+}
+
+impl GenericClockController {
+    /// Check every entry of a clock map against the hardware, and return the
+    /// first mismatch
+    pub fn verify_clock_map(&mut self, map: &[ClockMapEntry]) -> Result<(), ClockMapMismatch> {
+        for entry in map {
+            match entry.kind {
+                ClockMapKind::Generator => {
+                    let actual = self.read_gclk_config(entry.gclk);
+                    if !entry.matches_generator(&actual) {
+                        return Err(ClockMapMismatch::Generator {
+                            expected: *entry,
+                            actual,
+                        });
+                    }
+                }
+                ClockMapKind::Peripheral => {
+                    let actual = self.pclk_generator(entry.id);
+                    if actual != Some(entry.gclk as u8) {
+                        return Err(ClockMapMismatch::Peripheral {
+                            expected: *entry,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Emit a clock map in the `.clock_map` link section
+///
+/// Generators are given as `GCLKn: (SOURCE, divider)` and peripheral channels
+/// as `CHANNEL: GCLKn`, using the names of [`ClockGenId`], [`ClockSource`]
+/// and [`ClockId`]. The table is available as `CLOCK_MAP`, e.g. to pass it to
+/// [`GenericClockController::verify_clock_map`]. See the
+/// [module-level documentation](crate::clock_map) for more details.
+#[macro_export]
+macro_rules! clock_map {
+    (
+        generators: { $($gclk:ident: ($src:ident, $div:expr)),* $(,)? },
+        peripherals: { $($clock:ident: $pgclk:ident),* $(,)? } $(,)?
+    ) => {
+        #[used]
+        #[link_section = ".clock_map"]
+        pub static CLOCK_MAP: [$crate::clock_map::ClockMapEntry; $crate::clock_map!(@count $($gclk)* $($clock)*)] = [
+            $(
+                $crate::clock_map::ClockMapEntry::generator(
+                    $crate::clock::ClockGenId::$gclk,
+                    $crate::clock::ClockSource::$src,
+                    $div,
+                ),
+            )*
+            $(
+                $crate::clock_map::ClockMapEntry::peripheral(
+                    $crate::clock::ClockId::$clock,
+                    $crate::clock::ClockGenId::$pgclk,
+                ),
+            )*
+        ];
+    };
+    (@count $($id:ident)*) => {
+        <[()]>::len(&[$($crate::clock_map!(@unit $id)),*])
+    };
+    (@unit $id:ident) => {
+        ()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::size_of;
+
+    #[test]
+    fn entry_layout_is_fixed() {
+        assert_eq!(size_of::<ClockMapEntry>(), 8);
+        let entry = ClockMapEntry::generator(ClockGenId::GCLK2, ClockSource::OSCULP32K, 32);
+        let bytes: [u8; 8] = unsafe { core::mem::transmute(entry) };
+        assert_eq!(bytes, [0, 2, ClockSource::OSCULP32K as u8, 0, 32, 0, 0, 0]);
+    }
+
+    #[test]
+    fn generator_entries_match_read_back_config() {
+        let entry = ClockMapEntry::generator(ClockGenId::GCLK2, ClockSource::OSCULP32K, 32);
+        let mut config = GClockConfig {
+            src: Some(ClockSource::OSCULP32K),
+            div: 32,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        assert!(entry.matches_generator(&config));
+        config.div = 16;
+        assert!(!entry.matches_generator(&config));
+        config.div = 32;
+        config.enabled = false;
+        assert!(!entry.matches_generator(&config));
+
+        // A divider of zero means no division, as in `GENCTRL.DIV`
+        let entry = ClockMapEntry::generator(ClockGenId::GCLK2, ClockSource::OSCULP32K, 0);
+        config.div = 1;
+        config.enabled = true;
+        assert!(entry.matches_generator(&config));
+    }
+
+    clock_map! {
+        generators: {
+            GCLK2: (OSCULP32K, 32),
+        },
+        peripherals: {
+            EIC: GCLK2,
+        },
+    }
+
+    #[test]
+    fn macro_emits_table() {
+        assert_eq!(
+            CLOCK_MAP,
+            [
+                ClockMapEntry::generator(ClockGenId::GCLK2, ClockSource::OSCULP32K, 32),
+                ClockMapEntry::peripheral(ClockId::EIC, ClockGenId::GCLK2),
+            ]
+        );
+    }
+}
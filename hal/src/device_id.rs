@@ -0,0 +1,160 @@
+//! # Device identification
+//!
+//! Every chip carries a 128-bit unique serial number in its NVM, and the
+//! `DID` register of the Device Service Unit (DSU) identifies the exact part
+//! and silicon revision. Both are useful for provisioning and telemetry.
+//!
+//! The serial number words are stored at different addresses on each chip
+//! family. [`serial_number`] and [`split_serial_number`] take care of this, and
+//! [`serial_number_string`] formats the serial number the same way the UF2
+//! bootloader does for its USB serial string, so that host tooling sees the
+//! same value from both.
+//!
+//! ```
+//! let identity = device_identity();
+//! let serial = serial_number_string();
+//! writeln!(uart, "rev {} serial {}", identity.revision_letter(), serial.as_str());
+//! ```
+
+use core::fmt;
+
+use crate::target_device::DSU;
+
+pub use crate::{serial_number, split_serial_number};
+
+//==============================================================================
+//  DeviceIdentity
+//==============================================================================
+
+/// Fields of the DSU `DID` register
+///
+/// The field encodings are listed in the "Device Identification" section of
+/// the DSU chapter in the datasheet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DeviceIdentity {
+    /// Processor core, e.g. `1` for a Cortex-M0+ and `6` for a Cortex-M4
+    pub processor: u8,
+    /// Product family, `0` for the general purpose microcontrollers
+    pub family: u8,
+    /// Product series within the family
+    pub series: u8,
+    /// Die number
+    pub die: u8,
+    /// Silicon revision, where `0` is revision A
+    pub revision: u8,
+    /// Device variant (`DEVSEL`), identifying the exact part number
+    pub variant: u8,
+}
+
+impl DeviceIdentity {
+    /// Decode the raw value of the `DID` register
+    #[inline]
+    pub fn from_bits(did: u32) -> Self {
+        DeviceIdentity {
+            processor: (did >> 28) as u8,
+            family: ((did >> 23) & 0x1F) as u8,
+            series: ((did >> 16) & 0x3F) as u8,
+            die: ((did >> 12) & 0xF) as u8,
+            revision: ((did >> 8) & 0xF) as u8,
+            variant: did as u8,
+        }
+    }
+
+    /// Silicon revision as the letter used in the errata, e.g. `'A'`
+    #[inline]
+    pub fn revision_letter(&self) -> char {
+        (b'A' + self.revision) as char
+    }
+}
+
+/// Read and decode the DSU `DID` register
+pub fn device_identity() -> DeviceIdentity {
+    // Safe because `DID` is a read-only register
+    let did = unsafe { (*DSU::ptr()).did.read().bits() };
+    DeviceIdentity::from_bits(did)
+}
+
+//==============================================================================
+//  Serial number string
+//==============================================================================
+
+/// Serial number formatted as 32 upper-case hexadecimal characters
+///
+/// See [`serial_number_string`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SerialNumberString([u8; 32]);
+
+impl SerialNumberString {
+    /// Return the serial number string
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // Safe because the buffer only ever contains ASCII hex digits
+        unsafe { core::str::from_utf8_unchecked(&self.0) }
+    }
+
+    /// Return the serial number string as ASCII bytes
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for SerialNumberString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Format the serial number like the UF2 bootloader does
+///
+/// The UF2 bootloader prints each of the four serial number words, in the
+/// order returned by [`split_serial_number`], as eight upper-case hex digits
+/// starting from the *least* significant nibble. The result is therefore not
+/// the same as printing the words with `{:08X}`.
+pub fn serial_number_string() -> SerialNumberString {
+    uf2_serial_string(split_serial_number())
+}
+
+fn uf2_serial_string(words: (u32, u32, u32, u32)) -> SerialNumberString {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let words = [words.0, words.1, words.2, words.3];
+    let mut string = [0; 32];
+    for (chars, word) in string.chunks_mut(8).zip(words.iter()) {
+        for (i, c) in chars.iter_mut().enumerate() {
+            *c = HEX[((word >> (i * 4)) & 0xF) as usize];
+        }
+    }
+    SerialNumberString(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_did() {
+        // ATSAMD21G18A, revision D
+        let id = DeviceIdentity::from_bits(0x1001_0305);
+        assert_eq!(id.processor, 1);
+        assert_eq!(id.family, 0);
+        assert_eq!(id.series, 1);
+        assert_eq!(id.die, 0);
+        assert_eq!(id.revision, 3);
+        assert_eq!(id.revision_letter(), 'D');
+        assert_eq!(id.variant, 0x05);
+
+        // ATSAMD51J19A, revision A
+        let id = DeviceIdentity::from_bits(0x6006_0003);
+        assert_eq!(id.processor, 6);
+        assert_eq!(id.family, 0);
+        assert_eq!(id.series, 6);
+        assert_eq!(id.revision_letter(), 'A');
+        assert_eq!(id.variant, 0x03);
+    }
+
+    #[test]
+    fn uf2_serial_nibble_order() {
+        let serial = uf2_serial_string((0x0123_4567, 0x89AB_CDEF, 0, 0xFFFF_FFFF));
+        assert_eq!(serial.as_str(), "76543210FEDCBA9800000000FFFFFFFF");
+    }
+}
@@ -19,12 +19,46 @@
 //! to wait for multiple Beats before sending a Burst. See SAMD5x/E5x datasheet
 //! section 22.6.2.8 for more information.
 //!
+//! For real-time streams that must never be starved, such as audio,
+//! [`Channel::init_streaming`] initializes a channel at the highest priority
+//! level and sets both parameters at once. The burst length should match the
+//! depth of the peripheral's FIFO, so each trigger moves as much data as the
+//! peripheral can take. The FIFO threshold sets how many beats are read from
+//! the source before the destination write starts; a threshold larger than
+//! the burst length can never be reached within a burst, so it is capped to
+//! the largest threshold that fits.
+//!
 //! # Channel status
 //!
 //! Channels can be in any of three statuses: [`Uninitialized`], [`Ready`], and
 //! [`Busy`]. These statuses are checked at compile time to ensure they are
 //! properly initialized before launching DMA transfers.
 //!
+//! # Bound channels
+//!
+//! A `Ready` channel can be bound to the peripheral that uses it, with
+//! [`Channel::bind`]. The resulting [`BoundChannel`] records the owning
+//! [`DmaPeripheral`] in its type, so it can't be handed to another peripheral
+//! by mistake. It also starts transfers with the trigger sources of its
+//! peripheral, see [`BoundChannel::begin_rx`] and [`BoundChannel::begin_tx`],
+//! so the trigger can't be taken from the wrong instance either.
+//!
+//! Code that needs a channel for a given peripheral should accept any
+//! [`IntoBoundChannel`] for it. Both unbound `Ready` channels, which are bound
+//! on first use, and channels already bound to that peripheral are accepted,
+//! but channels bound to another peripheral are rejected at compile time.
+//! [`BoundChannel::unbind`] releases the channel for other uses.
+//!
+//! ```
+//! let chan0 = channels.0.init(&mut dmac, PriorityLevel::LVL0, false);
+//! let chan0: BoundChannel<_, Sercom0> = chan0.bind();
+//! let xfer = match chan0.begin_tx(&mut dmac, buf, spi_data_reg) {
+//!     Ok(xfer) => xfer,
+//!     // `buf` is longer than `MAX_BEATS`: the channel and buffers are handed back
+//!     Err((chan0, buf, spi_data_reg, error)) => return Err(error),
+//! };
+//! ```
+//!
 //! # Resetting
 //!
 //! Calling the [`Channel::reset`] method will reset the channel to its
@@ -32,11 +66,14 @@
 //! again before being able to use it with a `Transfer`.
 
 use super::dma_controller::{ChId, DmaController, PriorityLevel, TriggerAction, TriggerSource};
+use super::transfer::{
+    writeback_btcnt, Buffer, BufferError, BufferPair, ChannelFlags, Transfer, TransferErrorKind,
+};
 use crate::{
     target_device::DMAC,
     typelevel::{Is, Sealed},
 };
-use core::{marker::PhantomData, mem};
+use core::{cell::Cell, marker::PhantomData, mem};
 
 #[cfg(feature = "min-samd51g")]
 use super::dma_controller::{BurstLength, FifoThreshold};
@@ -44,6 +81,39 @@ use super::dma_controller::{BurstLength, FifoThreshold};
 #[cfg(feature = "min-samd51g")]
 use crate::target_device::dmac::CHANNEL;
 
+//==============================================================================
+// Burst length and FIFO threshold
+//==============================================================================
+/// Number of beats transferred by a burst of the given length
+#[cfg(feature = "min-samd51g")]
+#[inline]
+pub fn burst_beats(burst_length: BurstLength) -> u8 {
+    burst_length as u8 + 1
+}
+
+/// Number of beats read from the source before the destination write starts
+#[cfg(feature = "min-samd51g")]
+#[inline]
+pub fn threshold_beats(threshold: FifoThreshold) -> u8 {
+    1 << threshold as u8
+}
+
+/// Cap a FIFO threshold to the largest one reachable within a burst of the
+/// given length
+#[cfg(feature = "min-samd51g")]
+pub fn stream_threshold(burst_length: BurstLength, threshold: FifoThreshold) -> FifoThreshold {
+    let beats = burst_beats(burst_length);
+    if threshold_beats(threshold) <= beats {
+        threshold
+    } else if beats >= 4 {
+        FifoThreshold::_4BEATS
+    } else if beats >= 2 {
+        FifoThreshold::_2BEATS
+    } else {
+        FifoThreshold::_1BEAT
+    }
+}
+
 //==============================================================================
 // Channel Status
 //==============================================================================
@@ -125,6 +195,15 @@ pub(crate) fn new_chan<Id: ChId>(_id: PhantomData<Id>) -> Channel<Id, Uninitiali
     }
 }
 
+/// A `Ready` channel for the tests which don't access the DMAC
+#[cfg(test)]
+pub(crate) fn ready_chan<Id: ChId>() -> Channel<Id, Ready> {
+    Channel {
+        _id: PhantomData,
+        _status: PhantomData,
+    }
+}
+
 /// These methods may be used on any DMA channel in any configuration
 impl<Id: ChId, S: Status> Channel<Id, S> {
     /// Set channel ID and run the closure. A closure is needed to ensure
@@ -157,6 +236,21 @@ impl<Id: ChId, S: Status> Channel<Id, S> {
         fun(&mut ch);
     }
 
+    /// Number of beats left in the block of the last transfer of the channel,
+    /// read from its write-back descriptor.
+    ///
+    /// The DMAC updates the write-back descriptor when it stops servicing the
+    /// channel: once the block is complete, when the transfer is stopped or
+    /// suspended, and when it switches to another channel. The count is then
+    /// valid until the next transfer of the channel becomes active, e.g. to
+    /// find out how much of a stopped transfer was done. While the transfer
+    /// is active, use [`Transfer::remaining_beats`] instead, which also reads
+    /// the live count of the channel being serviced.
+    #[inline]
+    pub fn remaining_beats(&self) -> u16 {
+        writeback_btcnt(Id::U8)
+    }
+
     /// Configure the DMA channel so that it is ready to be used by a
     /// [`Transfer`](super::transfer::Transfer).
     ///
@@ -198,6 +292,32 @@ impl<Id: ChId, S: Status> Channel<Id, S> {
         }
     }
 
+    /// Configure the DMA channel for a real-time stream. The channel is
+    /// given the highest priority level, and the selected burst length and
+    /// FIFO threshold. The threshold is capped to the burst length, see
+    /// [`stream_threshold`].
+    ///
+    /// Channels sharing priority level 3 still compete with each other, so
+    /// keep that level free of other channels if the stream must never be
+    /// starved.
+    ///
+    /// # Return
+    ///
+    /// A `Channel` with a `Ready` status
+    #[cfg(feature = "min-samd51g")]
+    pub fn init_streaming(
+        self,
+        controller: &mut DmaController,
+        burst_length: BurstLength,
+        threshold: FifoThreshold,
+        enable_interrupts: bool,
+    ) -> Channel<Id, Ready> {
+        let mut chan = self.init(controller, PriorityLevel::LVL3, enable_interrupts);
+        chan.burst_length(controller, burst_length);
+        chan.fifo_threshold(controller, stream_threshold(burst_length, threshold));
+        chan
+    }
+
     #[inline]
     fn _reset_private(&mut self, dmac: &DMAC) {
         self.with_chid(dmac, |d| {
@@ -255,6 +375,31 @@ impl<Id: ChId> Channel<Id, Ready> {
         })
     }
 
+    /// Keep the channel running in standby sleep (`CHCTRLA.RUNSTDBY`), so
+    /// that peripheral triggers are still serviced while the CPU sleeps. See
+    /// [Transfers in standby](super#transfers-in-standby).
+    #[cfg(feature = "min-samd51g")]
+    #[inline]
+    pub fn run_in_standby(&mut self, dmac: &mut DmaController, enable: bool) {
+        let dmac = dmac.dmac();
+        self.with_chid(dmac, |d| {
+            d.chctrla.modify(|_, w| w.runstdby().bit(enable));
+        })
+    }
+
+    /// Enable the event output of the channel. The events are selected by
+    /// the `EVOSEL` field of each descriptor.
+    #[inline]
+    pub(crate) fn enable_event_output(&mut self, dmac: &DMAC) {
+        self.with_chid(dmac, |d| {
+            #[cfg(any(feature = "samd11", feature = "samd21"))]
+            d.chctrlb.modify(|_, w| w.evoe().set_bit());
+
+            #[cfg(feature = "min-samd51g")]
+            d.chevctrl.modify(|_, w| w.evoe().set_bit());
+        });
+    }
+
     /// Start transfer on channel using the specified trigger source.
     ///
     /// # Return
@@ -286,6 +431,16 @@ impl<Id: ChId> Channel<Id, Ready> {
                 });
             }
 
+            // Clear the flags left over from the previous transfer
+            d.chintflag
+                .write(|w| w.tcmpl().set_bit().terr().set_bit().susp().set_bit());
+
+            // The `Dispatcher` masks the TERR interrupt of a failed transfer,
+            // see `dispatch::take_flags`
+            if d.chintenset.read().tcmpl().bit_is_set() {
+                d.chintenset.write(|w| w.terr().set_bit());
+            }
+
             // Start channel
             d.chctrla.modify(|_, w| w.enable().set_bit());
         });
@@ -302,6 +457,14 @@ impl<Id: ChId> Channel<Id, Ready> {
     }
 }
 
+/// Software command of a channel, written to `CHCTRLB.CMD`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum Command {
+    Suspend = 1,
+    Resume = 2,
+}
+
 /// These methods may only be used on a `Busy` DMA channel
 impl<Id: ChId> Channel<Id, Busy> {
     /// Issue a software trigger to the channel
@@ -310,6 +473,42 @@ impl<Id: ChId> Channel<Id, Busy> {
         self._trigger_private(dmac);
     }
 
+    /// Suspend the channel, and wait until the DMAC acknowledges it with
+    /// `CHINTFLAG.SUSP`
+    ///
+    /// Returns `false` if the channel was disabled before it could be
+    /// suspended, because its transfer completed or failed.
+    #[inline]
+    pub(crate) fn suspend(&mut self, dmac: &DMAC) -> bool {
+        let suspended = Cell::new(false);
+        self.with_chid(dmac, |d| {
+            // SAFETY: The command is one of the values of `CHCTRLB.CMD`
+            d.chctrlb
+                .modify(|_, w| unsafe { w.cmd().bits(Command::Suspend as u8) });
+            loop {
+                if d.chintflag.read().susp().bit_is_set() {
+                    suspended.set(true);
+                    break;
+                }
+                if d.chctrla.read().enable().bit_is_clear() {
+                    break;
+                }
+            }
+        });
+        suspended.get()
+    }
+
+    /// Resume a suspended channel
+    #[inline]
+    pub(crate) fn resume(&mut self, dmac: &DMAC) {
+        self.with_chid(dmac, |d| {
+            d.chintflag.write(|w| w.susp().set_bit());
+            // SAFETY: The command is one of the values of `CHCTRLB.CMD`
+            d.chctrlb
+                .modify(|_, w| unsafe { w.cmd().bits(Command::Resume as u8) });
+        });
+    }
+
     /// Stop transfer on channel whether or not the transfer has completed
     ///
     /// # Return
@@ -337,6 +536,46 @@ impl<Id: ChId> Channel<Id, Busy> {
         dmac.busych.read().bits() & (1 << id) == 0 && dmac.pendch.read().bits() & (1 << id) == 0
     }
 
+    /// Returns the kind of error which aborted the transfer, if any.
+    ///
+    /// A fetch error (CHSTATUS.FERR) is raised when the channel fetches an
+    /// invalid descriptor; a transfer error (CHINTFLAG.TERR) is raised on a
+    /// bus error while reading the source or writing the destination. In both
+    /// cases the DMAC disables the channel.
+    #[inline]
+    pub(crate) fn xfer_error(&mut self, dmac: &DMAC) -> Option<TransferErrorKind> {
+        let kind = Cell::new(None);
+        self.with_chid(dmac, |d| {
+            if d.chstatus.read().ferr().bit_is_set() {
+                kind.set(Some(TransferErrorKind::Fetch));
+            } else if d.chintflag.read().terr().bit_is_set() {
+                kind.set(Some(TransferErrorKind::Transfer));
+            }
+        });
+        kind.get()
+    }
+
+    /// Returns a snapshot of the status flags of the channel
+    #[inline]
+    pub(crate) fn flags(&mut self, dmac: &DMAC) -> ChannelFlags {
+        let flags = Cell::new(ChannelFlags {
+            busy: !self.xfer_complete(dmac),
+            ..Default::default()
+        });
+        self.with_chid(dmac, |d| {
+            let intflag = d.chintflag.read();
+            flags.set(ChannelFlags {
+                tcmpl: intflag.tcmpl().bit_is_set(),
+                terr: intflag.terr().bit_is_set(),
+                susp: intflag.susp().bit_is_set(),
+                ferr: d.chstatus.read().ferr().bit_is_set(),
+                enabled: d.chctrla.read().enable().bit_is_set(),
+                ..flags.get()
+            });
+        });
+        flags.get()
+    }
+
     /// Wait for the channel to clear its busy status, then release the channel.
     ///
     /// # Return
@@ -352,3 +591,208 @@ impl<Id: ChId> Channel<Id, Busy> {
         }
     }
 }
+
+//==============================================================================
+// Bound channels
+//==============================================================================
+/// Peripheral that can own a DMA channel
+///
+/// See the [module-level documentation](self) for more details.
+pub trait DmaPeripheral {
+    /// Trigger source for transfers reading from the peripheral
+    const RX_TRIGGER: TriggerSource;
+    /// Trigger source for transfers writing to the peripheral
+    const TX_TRIGGER: TriggerSource;
+}
+
+/// Trigger action used by [`BoundChannel`] transfers
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+const PERIPHERAL_TRIGGER_ACTION: TriggerAction = TriggerAction::BEAT;
+/// Trigger action used by [`BoundChannel`] transfers
+#[cfg(feature = "min-samd51g")]
+const PERIPHERAL_TRIGGER_ACTION: TriggerAction = TriggerAction::BURST;
+
+/// `Ready` DMA channel, bound to the peripheral `P`
+pub struct BoundChannel<Id: ChId, P: DmaPeripheral> {
+    chan: Channel<Id, Ready>,
+    _periph: PhantomData<P>,
+}
+
+impl<Id: ChId> Channel<Id, Ready> {
+    /// Bind the channel to the peripheral `P`
+    #[inline]
+    pub fn bind<P: DmaPeripheral>(self) -> BoundChannel<Id, P> {
+        BoundChannel {
+            chan: self,
+            _periph: PhantomData,
+        }
+    }
+}
+
+impl<Id: ChId, P: DmaPeripheral> BoundChannel<Id, P> {
+    /// Release the channel, so that it can be bound to another peripheral
+    #[inline]
+    pub fn unbind(self) -> Channel<Id, Ready> {
+        self.chan
+    }
+
+    /// Begin a one-shot transfer from the peripheral, triggered by
+    /// [`DmaPeripheral::RX_TRIGGER`] for every beat (every burst on
+    /// SAMD5x/E5x)
+    ///
+    /// The channel is unbound when the transfer is released. The buffers are
+    /// checked like in [`Transfer::try_new`]: if they are rejected, the bound
+    /// channel and both buffers are handed back along with the
+    /// [`BufferError`], and nothing is written to the DMAC.
+    #[inline]
+    pub fn begin_rx<S, D>(
+        self,
+        dmac: &mut DmaController,
+        source: S,
+        destination: D,
+    ) -> BeginResult<Id, P, S, D>
+    where
+        S: Buffer,
+        D: Buffer<Beat = S::Beat>,
+    {
+        self.begin(dmac, source, destination, P::RX_TRIGGER)
+    }
+
+    /// Begin a one-shot transfer to the peripheral, triggered by
+    /// [`DmaPeripheral::TX_TRIGGER`] for every beat (every burst on
+    /// SAMD5x/E5x)
+    ///
+    /// The channel is unbound when the transfer is released. The buffers are
+    /// checked like in [`begin_rx`](Self::begin_rx).
+    #[inline]
+    pub fn begin_tx<S, D>(
+        self,
+        dmac: &mut DmaController,
+        source: S,
+        destination: D,
+    ) -> BeginResult<Id, P, S, D>
+    where
+        S: Buffer,
+        D: Buffer<Beat = S::Beat>,
+    {
+        self.begin(dmac, source, destination, P::TX_TRIGGER)
+    }
+
+    #[inline]
+    fn begin<S, D>(
+        self,
+        dmac: &mut DmaController,
+        source: S,
+        destination: D,
+        trigger: TriggerSource,
+    ) -> BeginResult<Id, P, S, D>
+    where
+        S: Buffer,
+        D: Buffer<Beat = S::Beat>,
+    {
+        match Transfer::try_new(self.chan, source, destination, false) {
+            Ok(xfer) => Ok(xfer.begin(dmac, trigger, PERIPHERAL_TRIGGER_ACTION)),
+            Err((chan, source, destination, error)) => {
+                Err((chan.bind(), source, destination, error))
+            }
+        }
+    }
+}
+
+/// Result of [`BoundChannel::begin_rx`] and [`BoundChannel::begin_tx`]: the
+/// running transfer, or the bound channel and both buffers if the buffers were
+/// rejected
+pub type BeginResult<Id, P, S, D> =
+    Result<Transfer<Channel<Id, Busy>, BufferPair<S, D>>, (BoundChannel<Id, P>, S, D, BufferError)>;
+
+/// Channel that can be used by the peripheral `P`
+///
+/// Implemented for unbound `Ready` channels, which are bound on first use,
+/// and for channels already bound to `P`.
+pub trait IntoBoundChannel<P: DmaPeripheral> {
+    /// Id of the channel
+    type Id: ChId;
+
+    /// Bind the channel to `P`, if it isn't already
+    fn into_bound(self) -> BoundChannel<Self::Id, P>;
+}
+
+impl<Id: ChId, P: DmaPeripheral> IntoBoundChannel<P> for Channel<Id, Ready> {
+    type Id = Id;
+
+    #[inline]
+    fn into_bound(self) -> BoundChannel<Id, P> {
+        self.bind()
+    }
+}
+
+impl<Id: ChId, P: DmaPeripheral> IntoBoundChannel<P> for BoundChannel<Id, P> {
+    type Id = Id;
+
+    #[inline]
+    fn into_bound(self) -> BoundChannel<Id, P> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_encoding() {
+        #[cfg(feature = "min-samd51g")]
+        use crate::target_device::dmac::channel::chctrlb::CMD_A;
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        use crate::target_device::dmac::chctrlb::CMD_A;
+
+        assert_eq!(Command::Suspend as u8, u8::from(CMD_A::SUSPEND));
+        assert_eq!(Command::Resume as u8, u8::from(CMD_A::RESUME));
+    }
+
+    #[test]
+    fn remaining_beats_from_writeback() {
+        use super::super::dma_controller::Ch2;
+        use super::super::WRITEBACK;
+
+        // A 200-beat transfer stopped with 123 beats left. Channel 2 isn't
+        // used by any other test.
+        let channel = new_chan(PhantomData::<Ch2>);
+        unsafe { WRITEBACK[2].btcnt = 123 };
+        assert_eq!(channel.remaining_beats(), 123);
+    }
+
+    #[cfg(feature = "min-samd51g")]
+    #[test]
+    fn burst_and_threshold_encoding() {
+        assert_eq!(burst_beats(BurstLength::SINGLE), 1);
+        assert_eq!(burst_beats(BurstLength::_4BEAT), 4);
+        assert_eq!(burst_beats(BurstLength::_16BEAT), 16);
+        assert_eq!(threshold_beats(FifoThreshold::_1BEAT), 1);
+        assert_eq!(threshold_beats(FifoThreshold::_2BEATS), 2);
+        assert_eq!(threshold_beats(FifoThreshold::_4BEATS), 4);
+        assert_eq!(threshold_beats(FifoThreshold::_8BEATS), 8);
+    }
+
+    #[cfg(feature = "min-samd51g")]
+    #[test]
+    fn sercom_triggers_match_instance() {
+        use crate::sercom::v2::{Sercom0, Sercom3};
+        assert!(Sercom0::RX_TRIGGER == TriggerSource::SERCOM0_RX);
+        assert!(Sercom0::TX_TRIGGER == TriggerSource::SERCOM0_TX);
+        assert!(Sercom3::RX_TRIGGER == TriggerSource::SERCOM3_RX);
+        assert!(Sercom3::TX_TRIGGER == TriggerSource::SERCOM3_TX);
+    }
+
+    #[cfg(feature = "min-samd51g")]
+    #[test]
+    fn stream_threshold_is_capped_to_burst() {
+        use FifoThreshold::*;
+        assert_eq!(stream_threshold(BurstLength::_16BEAT, _8BEATS), _8BEATS);
+        assert_eq!(stream_threshold(BurstLength::_8BEAT, _8BEATS), _8BEATS);
+        assert_eq!(stream_threshold(BurstLength::_7BEAT, _8BEATS), _4BEATS);
+        assert_eq!(stream_threshold(BurstLength::_3BEAT, _8BEATS), _2BEATS);
+        assert_eq!(stream_threshold(BurstLength::SINGLE, _4BEATS), _1BEAT);
+        assert_eq!(stream_threshold(BurstLength::_3BEAT, _1BEAT), _1BEAT);
+    }
+}
@@ -0,0 +1,289 @@
+//! # Interrupt dispatcher
+//!
+//! Each channel raises the DMAC interrupt on transfer completion, transfer
+//! errors and suspension, once initialized with interrupts enabled. A
+//! [`Dispatcher`] holds one [`Callback`] per channel, in a fixed array, and
+//! [`Dispatcher::dispatch`] calls the callbacks of the channels with pending
+//! interrupts. Wire it into every DMAC handler: `DMAC` on SAMD11/21, and
+//! `DMAC_0` to `DMAC_3` and `DMAC_OTHER` on SAMD5x/E5x.
+//!
+//! The dispatcher takes a snapshot of the [`ChannelFlags`] of each pending
+//! channel, and calls its callback with it. Only the `TCMPL` and `SUSP`
+//! interrupt flags are cleared, for channels without a callback as well, so
+//! that the interrupt doesn't fire again right away. The error flags are left
+//! for the [`Transfer`](super::Transfer), which reports them from
+//! [`Transfer::wait_checked`](super::Transfer::wait_checked) and
+//! [`Transfer::error`](super::Transfer::error). Instead, the `TERR` interrupt
+//! of a failed channel is disabled, until its next transfer starts.
+//!
+//! When several channels are pending at once, they are served in order of
+//! their priority level, highest first, and then by increasing channel
+//! number, like the static arbitration of the DMAC. Interrupts raised while
+//! dispatching are served by the next interrupt.
+//!
+//! ```
+//! static DISPATCHER: Mutex<RefCell<Dispatcher>> = Mutex::new(RefCell::new(Dispatcher::new()));
+//!
+//! fn adc_done(channel: u8, flags: ChannelFlags) {
+//!     BUFFER_FULL.store(flags.tcmpl, Ordering::Release);
+//! }
+//!
+//! interrupt::free(|cs| DISPATCHER.borrow(cs).borrow_mut().register::<Ch0>(adc_done)).unwrap();
+//!
+//! #[interrupt]
+//! fn DMAC_0() {
+//!     interrupt::free(|cs| DISPATCHER.borrow(cs).borrow().dispatch());
+//! }
+//! ```
+
+use super::dma_controller::ChId;
+use super::transfer::ChannelFlags;
+use super::NUM_CHANNELS;
+use crate::target_device::{dmac::RegisterBlock, DMAC};
+
+/// Function called by the [`Dispatcher`] with the number and the status
+/// flags of a channel
+pub type Callback = fn(u8, ChannelFlags);
+
+/// Channels managed by the driver, out of the 32 bits of `INTSTATUS`
+const CHANNEL_MASK: u32 = u32::MAX >> (32 - NUM_CHANNELS);
+
+/// Table of the DMAC interrupt callbacks, one per channel
+///
+/// See the [module-level documentation](self) for more details.
+pub struct Dispatcher {
+    callbacks: [Option<Callback>; NUM_CHANNELS],
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    /// Create a dispatcher, with no callback registered
+    pub const fn new() -> Self {
+        Dispatcher {
+            callbacks: [None; NUM_CHANNELS],
+        }
+    }
+
+    /// Register `callback` for channel `Id`, or hand it back if the channel
+    /// already has a callback
+    pub fn register<Id: ChId>(&mut self, callback: Callback) -> Result<(), Callback> {
+        let slot = &mut self.callbacks[Id::USIZE];
+        match slot {
+            Some(_) => Err(callback),
+            None => {
+                *slot = Some(callback);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the callback of channel `Id`, if any
+    pub fn remove<Id: ChId>(&mut self) -> Option<Callback> {
+        self.callbacks[Id::USIZE].take()
+    }
+
+    /// Call the callbacks of the channels with pending interrupts, after
+    /// clearing their `TCMPL` and `SUSP` flags
+    ///
+    /// Only `INTSTATUS`, `BUSYCH`, `PENDCH`, the priority levels and the
+    /// status and interrupt registers of the pending channels are accessed, so this can be called from the
+    /// interrupt handlers while the [`DmaController`](super::DmaController)
+    /// is used elsewhere.
+    pub fn dispatch(&self) {
+        // SAFETY: See above. On SAMD11/21, the channel registers are selected
+        // through `CHID` in critical sections, like in the rest of the driver.
+        let dmac = unsafe { &*DMAC::ptr() };
+        let pending = dmac.intstatus.read().bits();
+        self.dispatch_pending(
+            pending,
+            |id| priority_level(dmac, id),
+            |id| take_flags(dmac, id),
+        );
+    }
+
+    /// Serve the channels of `pending`, given their priority levels, and the
+    /// function that takes their flags
+    fn dispatch_pending(
+        &self,
+        pending: u32,
+        level: impl Fn(u8) -> u8,
+        mut take_flags: impl FnMut(u8) -> ChannelFlags,
+    ) {
+        let mut pending = pending & CHANNEL_MASK;
+        while let Some(id) = next_channel(pending, &level) {
+            pending &= !(1 << id);
+            let flags = take_flags(id);
+            if let Some(callback) = self.callbacks[id as usize] {
+                callback(id, flags);
+            }
+        }
+    }
+}
+
+/// Returns the pending channel to serve first: the one with the highest
+/// priority level, and the lowest number among those
+fn next_channel(pending: u32, level: impl Fn(u8) -> u8) -> Option<u8> {
+    let mut next: Option<(u8, u8)> = None;
+    for id in 0..32 {
+        if pending & (1 << id) == 0 {
+            continue;
+        }
+        let lvl = level(id);
+        if next.map_or(true, |(_, best)| lvl > best) {
+            next = Some((id, lvl));
+        }
+    }
+    next.map(|(id, _)| id)
+}
+
+/// Priority level of channel `id`
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+fn priority_level(dmac: &RegisterBlock, id: u8) -> u8 {
+    cortex_m::interrupt::free(|_| {
+        // SAFETY: `id` is the number of a channel of the DMAC
+        dmac.chid.modify(|_, w| unsafe { w.id().bits(id) });
+        dmac.chctrlb.read().lvl().bits()
+    })
+}
+
+/// Priority level of channel `id`
+#[cfg(feature = "min-samd51g")]
+fn priority_level(dmac: &RegisterBlock, id: u8) -> u8 {
+    dmac.channel[id as usize].chprilvl.read().prilvl().bits()
+}
+
+/// Take a snapshot of the flags of channel `id`, and clear its `TCMPL` and
+/// `SUSP` flags
+///
+/// `TERR` is kept, so that the [`Transfer`](super::Transfer) reports the
+/// error, and its interrupt is disabled instead. `Channel::start` enables it
+/// again.
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+fn take_flags(dmac: &RegisterBlock, id: u8) -> ChannelFlags {
+    let busy = channel_busy(dmac, id);
+    cortex_m::interrupt::free(|_| {
+        // SAFETY: `id` is the number of a channel of the DMAC
+        dmac.chid.modify(|_, w| unsafe { w.id().bits(id) });
+        let intflag = dmac.chintflag.read();
+        // SAFETY: Writing ones clears the flags
+        dmac.chintflag
+            .write(|w| unsafe { w.bits(acknowledged(intflag.bits())) });
+        if intflag.terr().bit_is_set() {
+            dmac.chintenclr.write(|w| w.terr().set_bit());
+        }
+        ChannelFlags {
+            tcmpl: intflag.tcmpl().bit_is_set(),
+            terr: intflag.terr().bit_is_set(),
+            susp: intflag.susp().bit_is_set(),
+            ferr: dmac.chstatus.read().ferr().bit_is_set(),
+            enabled: dmac.chctrla.read().enable().bit_is_set(),
+            busy,
+        }
+    })
+}
+
+/// Take a snapshot of the flags of channel `id`, and clear its `TCMPL` and
+/// `SUSP` flags
+///
+/// `TERR` is kept, so that the [`Transfer`](super::Transfer) reports the
+/// error, and its interrupt is disabled instead. `Channel::start` enables it
+/// again.
+#[cfg(feature = "min-samd51g")]
+fn take_flags(dmac: &RegisterBlock, id: u8) -> ChannelFlags {
+    let busy = channel_busy(dmac, id);
+    let channel = &dmac.channel[id as usize];
+    let intflag = channel.chintflag.read();
+    // SAFETY: Writing ones clears the flags
+    channel
+        .chintflag
+        .write(|w| unsafe { w.bits(acknowledged(intflag.bits())) });
+    if intflag.terr().bit_is_set() {
+        channel.chintenclr.write(|w| w.terr().set_bit());
+    }
+    ChannelFlags {
+        tcmpl: intflag.tcmpl().bit_is_set(),
+        terr: intflag.terr().bit_is_set(),
+        susp: intflag.susp().bit_is_set(),
+        ferr: channel.chstatus.read().ferr().bit_is_set(),
+        enabled: channel.chctrla.read().enable().bit_is_set(),
+        busy,
+    }
+}
+
+/// `CHINTFLAG` bits of `TCMPL` and `SUSP`, the same on all chips
+const ACKNOWLEDGED_FLAGS: u8 = 0b110;
+
+/// Returns the `CHINTFLAG` bits cleared by the dispatcher, out of the flags
+/// that are set
+fn acknowledged(intflag: u8) -> u8 {
+    intflag & ACKNOWLEDGED_FLAGS
+}
+
+/// Returns whether channel `id` is serviced, or has a pending trigger
+fn channel_busy(dmac: &RegisterBlock, id: u8) -> bool {
+    (dmac.busych.read().bits() | dmac.pendch.read().bits()) & (1 << id) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmac::dma_controller::{Ch0, Ch1, Ch2};
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    /// Channels served by `record`, one per nibble in order, plus one
+    static SERVED: AtomicU32 = AtomicU32::new(0);
+
+    fn record(channel: u8, _flags: ChannelFlags) {
+        let served = SERVED.load(Ordering::Relaxed);
+        SERVED.store(served << 4 | (channel as u32 + 1), Ordering::Relaxed);
+    }
+
+    #[test]
+    fn dispatcher_routes_pending_channels() {
+        let mut dispatcher = Dispatcher::new();
+        assert!(dispatcher.register::<Ch0>(record).is_ok());
+        assert!(dispatcher.register::<Ch2>(record).is_ok());
+        assert!(dispatcher.register::<Ch2>(record).is_err());
+
+        // Channel 1 has the highest priority level, but no callback: its flags
+        // are taken anyway. Channel 0 comes before channel 2 at the same
+        // level.
+        let levels = [1, 3, 1];
+        let mut taken = 0u32;
+        let take = |id| {
+            taken |= 1u32 << id;
+            ChannelFlags::default()
+        };
+        dispatcher.dispatch_pending(0b111, |id| levels[id as usize], take);
+        assert_eq!(SERVED.swap(0, Ordering::Relaxed), 0x13);
+        assert_eq!(taken, 0b111);
+
+        // Only the pending channels are served
+        assert!(dispatcher.remove::<Ch0>().is_some());
+        assert!(dispatcher.register::<Ch1>(record).is_ok());
+        dispatcher.dispatch_pending(0b101, |id| levels[id as usize], |_| Default::default());
+        assert_eq!(SERVED.swap(0, Ordering::Relaxed), 0x3);
+    }
+
+    #[test]
+    fn transfer_errors_are_left_for_the_transfer() {
+        // TERR is bit 0, TCMPL bit 1 and SUSP bit 2
+        assert_eq!(acknowledged(0b111), 0b110);
+        assert_eq!(acknowledged(0b001), 0);
+        assert_eq!(acknowledged(0b010), 0b010);
+        assert_eq!(acknowledged(0b100), 0b100);
+    }
+
+    #[test]
+    fn highest_level_then_lowest_channel_first() {
+        assert_eq!(next_channel(0, |_| 0), None);
+        assert_eq!(next_channel(0b1010, |_| 2), Some(1));
+        assert_eq!(next_channel(0b1010, |id| id), Some(3));
+        assert_eq!(next_channel(1 << 31, |_| 0), Some(31));
+    }
+}
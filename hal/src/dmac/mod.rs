@@ -8,8 +8,9 @@
 //! functions, including memory-to-memory,
 //! memory-to-peripheral, peripheral-to-memory,
 //! and peripheral-to-peripheral transfers.
-//! One-shot and circular transfers are supported. More complex
-//! transfer configurations, including multi-buffer
+//! One-shot and circular transfers are supported, as well as
+//! [strided transfers](strided), which gather or scatter every n-th beat of a
+//! buffer. More complex transfer configurations, including multi-buffer
 //! (linked-list descriptor) transfers, are not currently supported.
 //!
 //! Transfers are supported for `i8`, `u8`, `i16`, `u16`, `i32`, `u32` and `f32`
@@ -85,7 +86,9 @@
 //! complete, an error is detected or the channel is suspended. However, these
 //! interrupts will not be triggered unless the DMAC interrupt is unmasked in
 //! the NVIC. You will be responsible for clearing the interrupt flags in the
-//! ISR.
+//! ISR, or for calling a [`Dispatcher`](dispatch::Dispatcher), which clears
+//! the completion and suspension flags and calls a callback registered for
+//! each channel.
 //!
 //! # Payloads
 //!
@@ -95,6 +98,30 @@
 //! for instance, to take ownership of a peripheral until the transfer is
 //! complete and prevent data races.
 //!
+//! # Transfers in standby
+//!
+//! The DMAC can keep servicing peripheral triggers while the CPU is in
+//! standby sleep ("SleepWalking"), e.g. to log ADC readings and only wake the
+//! CPU once a buffer is full. Everything in the chain has to keep running in
+//! standby:
+//!
+//! - The trigger source, like an RTC periodic event
+//!   ([`Rtc::enable_periodic_event`]) routed through EVSYS to the ADC start
+//!   event input ([`Adc::enable_start_event`])
+//! - The peripheral and its clock generator ([`Adc::run_in_standby`] and
+//!   `GenericClockController::configure_standby`)
+//! - The DMA channel, with [`Channel::run_in_standby`] on SAMD5x/E5x. The
+//!   SAMD11/21 DMAC keeps running in standby as long as its clocks do.
+//!
+//! Enable the transfer complete interrupt of the channel to wake up the CPU.
+//! The `standby_adc_dma_logger` example of the `feather_m4` BSP puts these
+//! pieces together.
+//!
+//! [`Rtc::enable_periodic_event`]: crate::rtc::Rtc::enable_periodic_event
+//! [`Adc::enable_start_event`]: crate::adc::Adc::enable_start_event
+//! [`Adc::run_in_standby`]: crate::adc::Adc::run_in_standby
+//! [`Channel::run_in_standby`]: channel::Channel::run_in_standby
+//!
 //! # About static lifetimes
 //!
 //! The safe API this driver offers requires all buffers (source and
@@ -150,13 +177,18 @@
 
 use modular_bitfield::prelude::*;
 
+pub use dispatch::{Callback, Dispatcher};
 #[cfg(feature = "min-samd51g")]
 pub use dma_controller::{BurstLength, FifoThreshold};
 pub use dma_controller::{
     DmaController, PriorityLevel, PriorityLevelMask, RoundRobinMask, TriggerAction, TriggerSource,
 };
+pub use strided::{StrideError, StrideSide, StridedTransfer};
 use transfer::BeatSize;
-pub use transfer::{Beat, Buffer, Transfer};
+pub use transfer::{
+    check_buffers, Beat, Buffer, BufferError, ChannelFlags, Transfer, TransferError,
+    TransferErrorKind, MAX_BEATS,
+};
 
 #[cfg(all(feature = "samd11", feature = "max-channels"))]
 #[macro_export]
@@ -242,9 +274,11 @@ pub struct BlockTransferControl {
 
 // ----- DMAC SRAM registers ----- //
 /// Descriptor representing a SRAM register. Datasheet section 19.8.2
+///
+/// Only needed to provide the linked descriptors of a
+/// [`StridedTransfer`](strided::StridedTransfer).
 #[derive(Clone, Copy)]
 #[repr(C, align(16))]
-#[doc(hidden)]
 pub struct DmacDescriptor {
     btctrl: BlockTransferControl,
     btcnt: u16,
@@ -253,7 +287,7 @@ pub struct DmacDescriptor {
     descaddr: *const DmacDescriptor,
 }
 
-#[doc(hidden)]
+/// Empty descriptor, to initialize arrays of descriptors
 pub const DEFAULT_DESCRIPTOR: DmacDescriptor = DmacDescriptor {
     btctrl: BlockTransferControl::new(),
     btcnt: 0,
@@ -272,5 +306,7 @@ static mut WRITEBACK: [DmacDescriptor; NUM_CHANNELS] = [DEFAULT_DESCRIPTOR; NUM_
 static mut DESCRIPTOR_SECTION: [DmacDescriptor; NUM_CHANNELS] = [DEFAULT_DESCRIPTOR; NUM_CHANNELS];
 
 pub mod channel;
+pub mod dispatch;
 pub mod dma_controller;
+pub mod strided;
 pub mod transfer;
@@ -0,0 +1,445 @@
+//! # Strided transfers
+//!
+//! A strided transfer reads or writes every `stride`-th beat of one of its
+//! buffers, and every beat of the other one. It can gather a channel out of
+//! interleaved samples, e.g. the left and right halves of I2S stereo frames,
+//! or scatter a mono buffer into one channel of an interleaved buffer.
+//!
+//! The DMAC steps over beats by itself when the stride is a power of two, up
+//! to [`MAX_STEP`] beats (`BTCTRL.STEPSIZE`). The transfer is then a single
+//! block, like any other [`Transfer`]. Other strides are emulated with a linked
+//! list of one descriptor per beat, which costs 16 bytes of RAM per beat. The
+//! `count - 1` descriptors following the one of the channel are provided by the
+//! caller, see [`StridedTransfer::linked_descriptors`], and are held as the
+//! payload of the transfer until it is released.
+//!
+//! Start the transfer with [`TriggerAction::TRANSACTION`] for a single trigger
+//! to run every descriptor of the list. With a linked list,
+//! [`remaining_beats`](Transfer::remaining_beats) and
+//! [`progress`](Transfer::progress) only report the progress in the current
+//! descriptor.
+//!
+//! De-interleaving stereo samples into two mono buffers, one channel after
+//! the other:
+//!
+//! ```
+//! // frames: &'static mut [u16] of 2 * N samples, left and right:
+//! // &'static mut [u16] of N samples
+//! let (chan0, frames, left, _) = StridedTransfer::new(N)
+//!     .source_stride(2)
+//!     .build(chan0, frames, left, &mut [])
+//!     .begin(&mut dmac, TriggerSource::DISABLE, TriggerAction::TRANSACTION)
+//!     .wait(&mut dmac);
+//! let (chan0, _, right, _) = StridedTransfer::new(N)
+//!     .source_stride(2)
+//!     .build(chan0, &mut frames[1..], right, &mut [])
+//!     .begin(&mut dmac, TriggerSource::DISABLE, TriggerAction::TRANSACTION)
+//!     .wait(&mut dmac);
+//! ```
+//!
+//! [`TriggerAction::TRANSACTION`]: super::TriggerAction::TRANSACTION
+
+use super::{
+    channel::{AnyChannel, Ready},
+    dma_controller::ChId,
+    transfer::{Beat, BeatSize, BufferPair, Transfer, MAX_BEATS},
+    BlockTransferControl, DmacDescriptor, DESCRIPTOR_SECTION, WRITEBACK,
+};
+use core::ptr;
+
+/// Largest stride the DMAC steps over by itself, in beats
+pub const MAX_STEP: usize = 128;
+
+/// Buffer of a [`StridedTransfer`] which is accessed every `stride` beats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrideSide {
+    /// Gather beats out of the source
+    Source,
+    /// Scatter beats into the destination
+    Destination,
+}
+
+/// How the DMAC performs a [`StridedTransfer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrideMode {
+    /// In a single block, stepping over `2^STEPSIZE` beats. Holds `STEPSIZE`.
+    Stepped(u8),
+    /// With a linked list of one descriptor per beat
+    Linked,
+}
+
+/// Reasons why a [`StridedTransfer`] can't be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrideError {
+    /// The stride or the number of beats is zero
+    Zero,
+    /// The transfer would be longer than [`MAX_BEATS`]. Holds the requested
+    /// number of beats.
+    TooLong(usize),
+    /// The source is too short. Holds the number of beats it needs.
+    SourceTooShort(usize),
+    /// The destination is too short. Holds the number of beats it needs.
+    DestinationTooShort(usize),
+    /// Fewer linked descriptors than needed were provided. Holds the number
+    /// of descriptors needed.
+    NotEnoughDescriptors(usize),
+}
+
+/// How the DMAC performs a transfer with a given stride
+#[inline]
+pub fn stride_mode(stride: usize) -> StrideMode {
+    if stride.is_power_of_two() && stride <= MAX_STEP {
+        StrideMode::Stepped(stride.trailing_zeros() as u8)
+    } else {
+        StrideMode::Linked
+    }
+}
+
+/// Builder of a strided [`Transfer`]
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StridedTransfer {
+    count: usize,
+    side: StrideSide,
+    stride: usize,
+}
+
+impl StridedTransfer {
+    /// Transfer `count` beats, without a stride
+    #[inline]
+    pub fn new(count: usize) -> Self {
+        StridedTransfer {
+            count,
+            side: StrideSide::Source,
+            stride: 1,
+        }
+    }
+
+    /// Read every `stride`-th beat of the source
+    #[inline]
+    pub fn source_stride(self, stride: usize) -> Self {
+        self.stride(StrideSide::Source, stride)
+    }
+
+    /// Write every `stride`-th beat of the destination
+    #[inline]
+    pub fn destination_stride(self, stride: usize) -> Self {
+        self.stride(StrideSide::Destination, stride)
+    }
+
+    /// Access every `stride`-th beat of the buffer on `side`
+    #[inline]
+    pub fn stride(mut self, side: StrideSide, stride: usize) -> Self {
+        self.side = side;
+        self.stride = stride;
+        self
+    }
+
+    /// How the DMAC performs the transfer
+    #[inline]
+    pub fn mode(&self) -> StrideMode {
+        stride_mode(self.stride)
+    }
+
+    /// Number of descriptors to provide to [`build`](Self::build), on top of
+    /// the one of the channel
+    #[inline]
+    pub fn linked_descriptors(&self) -> usize {
+        match self.mode() {
+            StrideMode::Stepped(_) => 0,
+            StrideMode::Linked => self.count.saturating_sub(1),
+        }
+    }
+
+    /// Check that buffers of `source_len` and `destination_len` beats, and
+    /// `linked_len` linked descriptors, can be used for the transfer
+    ///
+    /// The strided buffer needs `(count - 1) * stride + 1` beats, and the
+    /// other one `count` beats.
+    pub fn check(
+        &self,
+        source_len: usize,
+        destination_len: usize,
+        linked_len: usize,
+    ) -> Result<(), StrideError> {
+        if self.count == 0 || self.stride == 0 {
+            return Err(StrideError::Zero);
+        }
+        if self.count > MAX_BEATS {
+            return Err(StrideError::TooLong(self.count));
+        }
+        let strided = (self.count - 1) * self.stride + 1;
+        let (source_needed, destination_needed) = match self.side {
+            StrideSide::Source => (strided, self.count),
+            StrideSide::Destination => (self.count, strided),
+        };
+        if source_len < source_needed {
+            return Err(StrideError::SourceTooShort(source_needed));
+        }
+        if destination_len < destination_needed {
+            return Err(StrideError::DestinationTooShort(destination_needed));
+        }
+        let linked = self.linked_descriptors();
+        if linked_len < linked {
+            return Err(StrideError::NotEnoughDescriptors(linked));
+        }
+        Ok(())
+    }
+
+    /// Construct the strided `Transfer`, which transfers from the start of
+    /// `source` to the start of `destination`. `linked` holds the descriptors
+    /// of a linked list, and may be empty if the DMAC steps by itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffers or the linked descriptors are too short, see
+    /// [`check`](Self::check).
+    pub fn build<C, T>(
+        self,
+        chan: C,
+        source: &'static mut [T],
+        destination: &'static mut [T],
+        linked: &'static mut [DmacDescriptor],
+    ) -> Transfer<C, BufferPair<&'static mut [T]>, &'static mut [DmacDescriptor]>
+    where
+        C: AnyChannel<Status = Ready>,
+        T: 'static + Beat,
+    {
+        if let Err(e) = self.check(source.len(), destination.len(), linked.len()) {
+            panic!("invalid strided DMA transfer: {:?}", e);
+        }
+        let id = <C as AnyChannel>::Id::USIZE;
+        let first = self.descriptors(
+            T::BEATSIZE,
+            source.as_ptr() as usize,
+            destination.as_ptr() as usize,
+            linked,
+        );
+
+        // SAFETY: The buffers and the linked descriptors are 'static, and
+        // held by the transfer until it is released. The descriptor written
+        // by `new_unchecked` is replaced by the strided one, which only
+        // accesses the buffers within the lengths checked above.
+        unsafe {
+            let transfer = Transfer::new_unchecked(chan, source, destination, false);
+            DESCRIPTOR_SECTION[id] = first;
+            ptr::write_volatile(&mut WRITEBACK[id].btcnt, first.btcnt);
+            transfer.with_payload(linked)
+        }
+    }
+
+    /// Fill the descriptors of a transfer between the `source` and
+    /// `destination` addresses, chaining the ones after the first in `linked`,
+    /// and return the first one
+    fn descriptors(
+        &self,
+        beat_size: BeatSize,
+        source: usize,
+        destination: usize,
+        linked: &mut [DmacDescriptor],
+    ) -> DmacDescriptor {
+        let beat_bytes = 1 << beat_size as usize;
+        let (source_step, destination_step) = match self.side {
+            StrideSide::Source => (self.stride * beat_bytes, beat_bytes),
+            StrideSide::Destination => (beat_bytes, self.stride * beat_bytes),
+        };
+        let btctrl = BlockTransferControl::new()
+            .with_beatsize(beat_size)
+            .with_valid(true);
+
+        match self.mode() {
+            StrideMode::Stepped(step_size) => DmacDescriptor {
+                btctrl: btctrl
+                    .with_srcinc(true)
+                    .with_dstinc(true)
+                    .with_stepsel(self.side == StrideSide::Source)
+                    .with_stepsize(step_size),
+                btcnt: self.count as u16,
+                // Incrementing addresses point past the last beat of the block
+                srcaddr: (source + self.count * source_step) as *const (),
+                dstaddr: (destination + self.count * destination_step) as *const (),
+                descaddr: ptr::null(),
+            },
+            StrideMode::Linked => {
+                // One beat per descriptor, at fixed addresses
+                let descriptor = |beat: usize, next: *const DmacDescriptor| DmacDescriptor {
+                    btctrl,
+                    btcnt: 1,
+                    srcaddr: (source + beat * source_step) as *const (),
+                    dstaddr: (destination + beat * destination_step) as *const (),
+                    descaddr: next,
+                };
+                let linked = &mut linked[..self.count - 1];
+                let base = linked.as_ptr();
+                for beat in 1..self.count {
+                    let next = if beat + 1 < self.count {
+                        base.wrapping_add(beat)
+                    } else {
+                        ptr::null()
+                    };
+                    linked[beat - 1] = descriptor(beat, next);
+                }
+                let next = if self.count > 1 { base } else { ptr::null() };
+                descriptor(0, next)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::DEFAULT_DESCRIPTOR;
+    use super::*;
+
+    /// Run a chain of descriptors the way the DMAC does
+    unsafe fn run(first: &DmacDescriptor) {
+        let mut descriptor: *const DmacDescriptor = first;
+        while let Some(d) = descriptor.as_ref() {
+            let beats = d.btcnt as usize;
+            let beat_bytes = 1 << d.btctrl.beatsize() as usize;
+            let step = 1 << d.btctrl.stepsize();
+            let source_step = match (d.btctrl.srcinc(), d.btctrl.stepsel()) {
+                (false, _) => 0,
+                (true, true) => step * beat_bytes,
+                (true, false) => beat_bytes,
+            };
+            let destination_step = match (d.btctrl.dstinc(), d.btctrl.stepsel()) {
+                (false, _) => 0,
+                (true, false) => step * beat_bytes,
+                (true, true) => beat_bytes,
+            };
+            let source = d.srcaddr as usize - beats * source_step;
+            let destination = d.dstaddr as usize - beats * destination_step;
+            for beat in 0..beats {
+                ptr::copy_nonoverlapping(
+                    (source + beat * source_step) as *const u8,
+                    (destination + beat * destination_step) as *mut u8,
+                    beat_bytes,
+                );
+            }
+            descriptor = d.descaddr;
+        }
+    }
+
+    /// De-interleave `channel` out of frames of `channels` samples, with the
+    /// DMAC model and with the CPU
+    fn deinterleave(frames: &[u16], channels: usize, channel: usize) {
+        let count = frames.len() / channels;
+        let mut mono = [0u16; 16];
+        let mut linked = [DEFAULT_DESCRIPTOR; 16];
+        let transfer = StridedTransfer::new(count).source_stride(channels);
+        transfer
+            .check(frames.len() - channel, mono.len(), linked.len())
+            .unwrap();
+        let first = transfer.descriptors(
+            BeatSize::HalfWord,
+            frames[channel..].as_ptr() as usize,
+            mono.as_mut_ptr() as usize,
+            &mut linked,
+        );
+        unsafe { run(&first) };
+
+        let reference = frames.iter().skip(channel).step_by(channels);
+        assert!(mono[..count].iter().eq(reference));
+        assert!(mono[count..].iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn stride_modes() {
+        assert_eq!(stride_mode(1), StrideMode::Stepped(0));
+        assert_eq!(stride_mode(2), StrideMode::Stepped(1));
+        assert_eq!(stride_mode(128), StrideMode::Stepped(7));
+        assert_eq!(stride_mode(3), StrideMode::Linked);
+        assert_eq!(stride_mode(256), StrideMode::Linked);
+        assert_eq!(
+            StridedTransfer::new(8)
+                .source_stride(2)
+                .linked_descriptors(),
+            0
+        );
+        assert_eq!(
+            StridedTransfer::new(8)
+                .source_stride(6)
+                .linked_descriptors(),
+            7
+        );
+    }
+
+    #[test]
+    fn buffer_lengths() {
+        let transfer = StridedTransfer::new(4).source_stride(3);
+        assert_eq!(transfer.check(10, 4, 3), Ok(()));
+        assert_eq!(
+            transfer.check(9, 4, 3),
+            Err(StrideError::SourceTooShort(10))
+        );
+        assert_eq!(
+            transfer.check(10, 3, 3),
+            Err(StrideError::DestinationTooShort(4))
+        );
+        assert_eq!(
+            transfer.check(10, 4, 2),
+            Err(StrideError::NotEnoughDescriptors(3))
+        );
+        let transfer = StridedTransfer::new(4).destination_stride(4);
+        assert_eq!(transfer.check(4, 13, 0), Ok(()));
+        assert_eq!(
+            transfer.check(4, 12, 0),
+            Err(StrideError::DestinationTooShort(13))
+        );
+        assert_eq!(
+            StridedTransfer::new(0).check(4, 4, 0),
+            Err(StrideError::Zero)
+        );
+        let transfer = StridedTransfer::new(4).source_stride(0);
+        assert_eq!(transfer.check(4, 4, 0), Err(StrideError::Zero));
+        let transfer = StridedTransfer::new(MAX_BEATS + 1);
+        assert_eq!(
+            transfer.check(MAX_BEATS + 1, MAX_BEATS + 1, 0),
+            Err(StrideError::TooLong(MAX_BEATS + 1))
+        );
+    }
+
+    #[test]
+    fn stereo_deinterleave() {
+        let frames: [u16; 16] = [
+            0x100, 0x200, 0x101, 0x201, 0x102, 0x202, 0x103, 0x203, 0x104, 0x204, 0x105, 0x205,
+            0x106, 0x206, 0x107, 0x207,
+        ];
+        deinterleave(&frames, 2, 0);
+        deinterleave(&frames, 2, 1);
+        // Three channels need linked descriptors
+        deinterleave(&frames[..15], 3, 0);
+        deinterleave(&frames[..15], 3, 2);
+        // A single beat needs no linked descriptor
+        deinterleave(&frames[..3], 3, 1);
+    }
+
+    #[test]
+    fn stepped_descriptor() {
+        let transfer = StridedTransfer::new(4).destination_stride(8);
+        let first = transfer.descriptors(BeatSize::Word, 0x2000_0000, 0x2000_1000, &mut []);
+        assert!(!first.btctrl.stepsel());
+        assert_eq!(first.btctrl.stepsize(), 3);
+        assert_eq!(first.btcnt, 4);
+        assert_eq!(first.srcaddr as usize, 0x2000_0010);
+        assert_eq!(first.dstaddr as usize, 0x2000_1080);
+        assert!(first.descaddr.is_null());
+    }
+
+    #[test]
+    fn interleave() {
+        // Scatter a mono buffer into the right channel of stereo frames
+        let mono: [u16; 4] = [1, 2, 3, 4];
+        let mut frames = [0u16; 8];
+        let first = StridedTransfer::new(4).destination_stride(2).descriptors(
+            BeatSize::HalfWord,
+            mono.as_ptr() as usize,
+            frames[1..].as_mut_ptr() as usize,
+            &mut [],
+        );
+        unsafe { run(&first) };
+        assert_eq!(frames, [0, 1, 0, 2, 0, 3, 0, 4]);
+    }
+}
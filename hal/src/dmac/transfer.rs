@@ -57,6 +57,14 @@
 //! completed. When it returns, it will release the source and destination
 //! buffers, as well as the DMA channel and the payload.
 //!
+//! [`poll`](Transfer::poll) checks the status of the transfer without
+//! blocking, so that a cooperative scheduler can run other tasks meanwhile.
+//! It returns [`Poll::Pending`] while the transfer runs, and
+//! [`Poll::Ready`] with the outcome of the transfer once it is complete or
+//! aborted by an error. The transfer must still be released with `wait` or
+//! [`wait_checked`](Transfer::wait_checked) afterwards, which then returns
+//! immediately.
+//!
 //! # Interrupting (stopping) a transfer
 //!
 //! A transfer can be stopped (regardless of whether it has completed or not) by
@@ -65,6 +73,36 @@
 //! it will release the source and destination buffers, as well as the DMA
 //! channel and the payload.
 //!
+//! # Suspending a transfer
+//!
+//! A running transfer can be paused with [`suspend`](Transfer::suspend), and
+//! picks up where it left off after [`resume`](Transfer::resume). The DMAC
+//! completes the beat, or the burst on SAMD5x, in progress before it
+//! suspends the channel, and `suspend` waits for that acknowledgement. Trigger
+//! requests received while suspended stay pending, and are serviced once the
+//! channel is resumed. A pipeline can then safely reconfigure the descriptors
+//! that the channel has not fetched yet, e.g. the next one of a linked list.
+//!
+//! A transfer which completes before the suspend command is executed can't
+//! be suspended, and `suspend` then returns `false`.
+//!
+//! # Transfer errors
+//!
+//! The DMAC aborts a transfer and disables its channel when it hits a bus
+//! error, or when it fetches an invalid descriptor. [`wait`](Transfer::wait)
+//! then returns as if the transfer had completed. Use
+//! [`wait_checked`](Transfer::wait_checked) or [`error`](Transfer::error)
+//! to find out whether the transfer failed, and where.
+//!
+//! # Transfer progress
+//!
+//! [`remaining_beats`](Transfer::remaining_beats) and
+//! [`progress`](Transfer::progress) report how far a running transfer has
+//! gotten, e.g. for a progress bar. They only read registers and descriptors,
+//! so they can be called from any priority level. The value is a snapshot,
+//! which the DMAC may have moved past by the time it is returned. For a
+//! circular transfer, it is the progress in the current block.
+//!
 //! # Trigger sources
 //!
 //! Most peripherals can issue triggers to a DMA channel. A software trigger is
@@ -91,11 +129,13 @@
 use super::{
     channel::{AnyChannel, Busy, Channel, ChannelId, Ready},
     dma_controller::{ChId, DmaController, TriggerAction, TriggerSource},
-    BlockTransferControl, DmacDescriptor, DESCRIPTOR_SECTION,
+    BlockTransferControl, DmacDescriptor, DESCRIPTOR_SECTION, WRITEBACK,
 };
+use crate::target_device::DMAC;
 use crate::typelevel::{Is, Sealed};
-use core::mem;
 use core::sync::atomic;
+use core::task::Poll;
+use core::{mem, ptr};
 use modular_bitfield::prelude::*;
 
 //==============================================================================
@@ -141,10 +181,191 @@ impl_beat!(
     (f32, Word)
 );
 
+//==============================================================================
+// TransferError
+//==============================================================================
+
+/// Kind of error which aborted a DMA transfer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferErrorKind {
+    /// Bus error while reading the source or writing the destination
+    Transfer,
+    /// The channel fetched an invalid transfer descriptor
+    Fetch,
+}
+
+/// Error which aborted a DMA transfer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferError {
+    /// Channel which faulted
+    pub channel: u8,
+    /// Kind of error
+    pub kind: TransferErrorKind,
+    /// Number of beats left in the block when the transfer was aborted, read
+    /// from the write-back descriptor. `None` for fetch errors.
+    pub remaining_beats: Option<u16>,
+    /// Source address of the beat the transfer was aborted at. `None` for
+    /// fetch errors.
+    pub source_address: Option<u32>,
+    /// Destination address of the beat the transfer was aborted at. `None` for
+    /// fetch errors.
+    pub destination_address: Option<u32>,
+}
+
+/// Snapshot of the status flags of a channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChannelFlags {
+    /// `CHINTFLAG.TCMPL`: the block transfer is complete
+    pub tcmpl: bool,
+    /// `CHINTFLAG.TERR`: the transfer was aborted by a bus error
+    pub terr: bool,
+    /// `CHINTFLAG.SUSP`: the channel was suspended
+    pub susp: bool,
+    /// `CHSTATUS.FERR`: the channel fetched an invalid descriptor
+    pub ferr: bool,
+    /// `CHCTRLA.ENABLE`: cleared by the DMAC once the transfer is over
+    pub enabled: bool,
+    /// `BUSYCH` or `PENDCH`: the channel is serviced, or has a pending
+    /// trigger
+    pub busy: bool,
+}
+
+/// Status of a transfer, given the flags of its channel
+///
+/// `TCMPL` may have been cleared by an interrupt handler, so a disabled and
+/// idle channel also counts as complete.
+fn transfer_status(flags: ChannelFlags) -> Poll<Result<(), TransferErrorKind>> {
+    if flags.ferr {
+        Poll::Ready(Err(TransferErrorKind::Fetch))
+    } else if flags.terr {
+        Poll::Ready(Err(TransferErrorKind::Transfer))
+    } else if (flags.tcmpl || !flags.enabled) && !flags.busy {
+        Poll::Ready(Ok(()))
+    } else {
+        Poll::Pending
+    }
+}
+
+/// Address of the beat a transfer stopped at, given the address stored in its
+/// descriptor. Incrementing addresses point past the last beat of the block.
+fn beat_address(addr: u32, incrementing: bool, remaining_beats: u16, beat_size: BeatSize) -> u32 {
+    if incrementing {
+        addr.wrapping_sub(remaining_beats as u32 * (1 << beat_size as u32))
+    } else {
+        addr
+    }
+}
+
+/// Snapshot of the `ACTIVE` register of the DMAC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ActiveChannel {
+    /// Channel being serviced by the DMAC
+    pub id: u8,
+    /// Whether the DMAC is servicing a channel. The other fields are only
+    /// valid while it is.
+    pub busy: bool,
+    /// Number of beats left in the block of the serviced channel
+    pub btcnt: u16,
+}
+
+impl ActiveChannel {
+    /// Read the `ACTIVE` register
+    fn read() -> Self {
+        // SAFETY: This is a read-only access to a register without read
+        // side-effects
+        let active = unsafe { (*DMAC::ptr()).active.read() };
+        ActiveChannel {
+            id: active.id().bits(),
+            busy: active.abusy().bit_is_set(),
+            btcnt: active.btcnt().bits(),
+        }
+    }
+}
+
+/// Number of beats left in the block of channel `id`.
+///
+/// The DMAC only writes the count back to the write-back descriptor when it
+/// switches to another channel, so the count of the channel it is servicing
+/// is read from the `ACTIVE` register instead.
+fn remaining_beats(id: u8, active: ActiveChannel, writeback_btcnt: u16) -> u16 {
+    if active.busy && active.id == id {
+        active.btcnt
+    } else {
+        writeback_btcnt
+    }
+}
+
+/// `BTCNT` of the write-back descriptor of channel `id`
+#[inline]
+pub(crate) fn writeback_btcnt(id: u8) -> u16 {
+    // SAFETY: The write-back descriptor is only read, and a torn read is not
+    // possible for an aligned `u16`
+    unsafe { ptr::read_volatile(&WRITEBACK[id as usize].btcnt) }
+}
+
+/// Number of beats transferred by the last transfer of channel `id`, once it
+/// is complete or stopped
+#[cfg(feature = "min-samd51g")]
+pub(crate) fn transferred_beats(id: u8) -> u16 {
+    // SAFETY: The descriptor is only written before the transfer begins
+    let total = unsafe { DESCRIPTOR_SECTION[id as usize].btcnt };
+    progress(total, writeback_btcnt(id)).0
+}
+
+/// `EVOSEL` value emitting an event on every beat on SAMD11/SAMD21, and on
+/// every burst on SAMD5x/E5x
+const EVOSEL_BEAT: u8 = 3;
+
+/// Number of transferred beats and length of a block of `total` beats with
+/// `remaining` beats left
+fn progress(total: u16, remaining: u16) -> (u16, u16) {
+    (total.saturating_sub(remaining), total)
+}
+
 //==============================================================================
 // Buffer
 //==============================================================================
 
+/// Maximum number of beats in a single block transfer, limited by the 16-bit
+/// BTCNT field of the transfer descriptor
+pub const MAX_BEATS: usize = u16::MAX as usize;
+
+/// Reasons why a pair of buffers can't be used for a [`Transfer`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferError {
+    /// Both buffers are longer than one beat, but their lengths differ
+    LengthMismatch,
+    /// The transfer would be longer than [`MAX_BEATS`]. Holds the requested
+    /// number of beats.
+    TooLong(usize),
+}
+
+/// Check that a pair of buffers can be used for a [`Transfer`], and return
+/// the number of beats it would transfer.
+///
+/// Buffers of length 1 are used as a fixed source or destination, so they can
+/// be paired with a buffer of any length. Otherwise, both buffers must have
+/// the same length. Either way, the transfer can't exceed [`MAX_BEATS`],
+/// because this driver doesn't link multiple descriptors together.
+pub fn check_buffers<S: Buffer, D: Buffer>(
+    source: &S,
+    destination: &D,
+) -> Result<usize, BufferError> {
+    transfer_length(source.buffer_len(), destination.buffer_len())
+}
+
+fn transfer_length(src_len: usize, dst_len: usize) -> Result<usize, BufferError> {
+    if src_len > 1 && dst_len > 1 && src_len != dst_len {
+        return Err(BufferError::LengthMismatch);
+    }
+    let length = core::cmp::max(src_len, dst_len);
+    if length > MAX_BEATS {
+        Err(BufferError::TooLong(length))
+    } else {
+        Ok(length)
+    }
+}
+
 /// Buffer useable by the DMAC.
 pub unsafe trait Buffer {
     type Beat: Beat;
@@ -314,7 +535,9 @@ where
     ///
     /// # Panics
     ///
-    /// Panics if both buffers have a length > 1 and are not of equal length.
+    /// Panics if both buffers have a length > 1 and are not of equal length,
+    /// or if the transfer would be longer than [`MAX_BEATS`]. Use
+    /// [`Transfer::try_new`] to handle these cases without panicking.
     #[allow(clippy::new_ret_no_self)]
     pub fn new(
         chan: C,
@@ -322,16 +545,33 @@ where
         destination: D,
         circular: bool,
     ) -> Transfer<C, BufferPair<S, D>> {
-        let src_len = source.buffer_len();
-        let dst_len = destination.buffer_len();
+        match Self::try_new(chan, source, destination, circular) {
+            Ok(xfer) => xfer,
+            Err((_, _, _, e)) => panic!("invalid DMA buffers: {:?}", e),
+        }
+    }
 
-        if src_len > 1 && dst_len > 1 {
-            assert_eq!(src_len, dst_len);
+    /// Construct a new `Transfer` like [`Transfer::new`], or hand back the
+    /// channel and both buffers, along with the reason why the buffers can't
+    /// be used, as reported by [`check_buffers`].
+    ///
+    /// Nothing is written to the DMAC if the buffers are rejected, so that
+    /// buffers sized at run time, e.g. from a received packet length, can be
+    /// split or reused.
+    #[allow(clippy::type_complexity)]
+    pub fn try_new(
+        chan: C,
+        source: S,
+        destination: D,
+        circular: bool,
+    ) -> Result<Transfer<C, BufferPair<S, D>>, (C, S, D, BufferError)> {
+        if let Err(e) = check_buffers(&source, &destination) {
+            return Err((chan, source, destination, e));
         }
 
         // SAFETY: The safety checks are done by the function signature and the buffer
         // length verification
-        unsafe { Self::new_unchecked(chan, source, destination, circular) }
+        Ok(unsafe { Self::new_unchecked(chan, source, destination, circular) })
     }
 
     /// Construct a new `Transfer` without checking for memory safety.
@@ -348,6 +588,9 @@ where
     ///   exacly the same, unless one or both buffers are of length 1. The
     ///   transfer length will be set to the longest of both buffers if they are
     ///   not of equal size.
+    ///
+    /// * Neither buffer may be longer than [`MAX_BEATS`], otherwise the
+    ///   transfer length is truncated.
     pub unsafe fn new_unchecked(
         chan: C,
         mut source: S,
@@ -406,6 +649,10 @@ where
         // in the entire library that this section or the array
         // will be written to.
         DESCRIPTOR_SECTION[id] = xfer_descriptor;
+        // Clear the count left by a previous transfer, so that the progress
+        // reads as zero until the DMAC writes back to the descriptor. This is
+        // safe, because the channel is not running.
+        ptr::write_volatile(&mut WRITEBACK[id].btcnt, length as u16);
 
         let buffers = BufferPair {
             source,
@@ -419,6 +666,17 @@ where
         }
     }
 
+    /// Emit a DMAC event on every beat (every burst on SAMD5x/E5x) of the
+    /// transfer. The events can be routed to other peripherals through
+    /// EVSYS, using the channel as the event generator.
+    pub fn with_beat_events(mut self, dmac: &mut DmaController) -> Self {
+        let id = <C as AnyChannel>::Id::USIZE;
+        // SAFETY: The descriptor belongs to our channel, which is not running
+        unsafe { DESCRIPTOR_SECTION[id].btctrl.set_evosel(EVOSEL_BEAT) };
+        self.chan.as_mut().enable_event_output(dmac.dmac());
+        self
+    }
+
     /// Append a payload to the transfer. This guarantees that it cannot safely
     /// be accessed while the transfer is ongoing.
     pub fn with_payload<P>(self, payload: P) -> Transfer<C, BufferPair<S, D>, P> {
@@ -462,6 +720,18 @@ where
             payload: self.payload,
         }
     }
+
+    /// Release the channel, the buffers and the payload of a transfer which
+    /// was never begun, e.g. when a transfer it depends on can't be
+    /// constructed
+    pub fn free(self) -> (C, S, D, P) {
+        (
+            self.chan,
+            self.buffers.source,
+            self.buffers.destination,
+            self.payload,
+        )
+    }
 }
 /// These methods are available to a `Transfer` holding a `Ready` channel and a
 /// `BufferPair` holding two arrays of equal type and length
@@ -501,6 +771,53 @@ where
         let dmac = dmac.dmac();
         self.chan.as_mut().software_trigger(dmac);
     }
+    /// Blocking; Suspend the transfer at the end of the current beat, or
+    /// burst on SAMD5x, and wait until it is suspended
+    ///
+    /// Returns `false` if the transfer completed or failed before it could be
+    /// suspended. See [Suspending a transfer](self#suspending-a-transfer).
+    #[inline]
+    pub fn suspend(&mut self, dmac: &mut DmaController) -> bool {
+        let dmac = dmac.dmac();
+        self.chan.as_mut().suspend(dmac)
+    }
+
+    /// Non-blocking; Resume a suspended transfer
+    #[inline]
+    pub fn resume(&mut self, dmac: &mut DmaController) {
+        let dmac = dmac.dmac();
+        self.chan.as_mut().resume(dmac);
+    }
+
+    /// Non-blocking; Return whether the transfer is complete
+    #[cfg(feature = "min-samd51g")]
+    #[inline]
+    pub(crate) fn is_complete(&self, dmac: &mut DmaController) -> bool {
+        self.chan.as_ref().xfer_complete(dmac.dmac())
+    }
+
+    /// Non-blocking; Return whether the transfer is complete, or was aborted
+    /// by an error
+    ///
+    /// The transfer is still owned afterwards: release it with
+    /// [`wait`](Transfer::wait) or [`wait_checked`](Transfer::wait_checked)
+    /// once this returns [`Poll::Ready`].
+    ///
+    /// The outcome is read from the `TCMPL` and `TERR` flags of the channel,
+    /// which stay set until the next transfer of the channel, so a late call
+    /// only delays the completion. However, call it often enough that an
+    /// interrupt handler clearing the flags of the channel, e.g. to
+    /// acknowledge `TCMPL`, can't run in between: a cleared `TERR` makes an
+    /// aborted transfer look successful.
+    pub fn poll(&mut self, dmac: &mut DmaController) -> Poll<Result<(), TransferError>> {
+        let flags = self.chan.as_mut().flags(dmac.dmac());
+        match transfer_status(flags) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(_)) => Poll::Ready(self.error(dmac).map_or(Ok(()), Err)),
+        }
+    }
+
     /// Blocking; Wait for the DMA transfer to complete and release all owned
     /// resources
     pub fn wait(self, dmac: &mut DmaController) -> (Channel<ChannelId<C>, Ready>, S, D, P) {
@@ -520,6 +837,95 @@ where
         )
     }
 
+    /// Blocking; Wait for the DMA transfer to complete or to be aborted by an
+    /// error, and release all owned resources. The error, if any, is returned
+    /// alongside the resources.
+    #[allow(clippy::type_complexity)]
+    pub fn wait_checked(
+        mut self,
+        dmac: &mut DmaController,
+    ) -> (
+        Channel<ChannelId<C>, Ready>,
+        S,
+        D,
+        P,
+        Result<(), TransferError>,
+    ) {
+        while !self.chan.as_ref().xfer_complete(dmac.dmac()) {}
+        let error = self.error(dmac);
+        let (chan, source, destination, payload) = self.wait(dmac);
+        (
+            chan,
+            source,
+            destination,
+            payload,
+            error.map_or(Ok(()), Err),
+        )
+    }
+
+    /// Non-blocking; Return the error which aborted the DMA transfer, if any.
+    ///
+    /// The faulting addresses are reconstructed from the number of beats left
+    /// in the write-back descriptor. They assume the beat which failed is the
+    /// first one not yet counted as transferred.
+    pub fn error(&mut self, dmac: &mut DmaController) -> Option<TransferError> {
+        let id = <C as AnyChannel>::Id::U8;
+        let kind = self.chan.as_mut().xfer_error(dmac.dmac())?;
+        let mut error = TransferError {
+            channel: id,
+            kind,
+            remaining_beats: None,
+            source_address: None,
+            destination_address: None,
+        };
+        if kind == TransferErrorKind::Transfer {
+            // SAFETY: The descriptor belongs to our channel, and the DMAC has
+            // disabled the channel, so it won't write back to it concurrently
+            let (descriptor, remaining) = unsafe {
+                (
+                    DESCRIPTOR_SECTION[id as usize],
+                    ptr::read_volatile(&WRITEBACK[id as usize].btcnt),
+                )
+            };
+            let btctrl = descriptor.btctrl;
+            error.remaining_beats = Some(remaining);
+            error.source_address = Some(beat_address(
+                descriptor.srcaddr as u32,
+                btctrl.srcinc(),
+                remaining,
+                btctrl.beatsize(),
+            ));
+            error.destination_address = Some(beat_address(
+                descriptor.dstaddr as u32,
+                btctrl.dstinc(),
+                remaining,
+                btctrl.beatsize(),
+            ));
+        }
+        Some(error)
+    }
+
+    /// Non-blocking; Return the number of beats left in the current block.
+    ///
+    /// This is a snapshot, see the [module-level
+    /// documentation](self#transfer-progress).
+    pub fn remaining_beats(&self) -> u16 {
+        let id = <C as AnyChannel>::Id::U8;
+        remaining_beats(id, ActiveChannel::read(), writeback_btcnt(id))
+    }
+
+    /// Non-blocking; Return the number of beats transferred in the current
+    /// block, and the length of the block.
+    ///
+    /// This is a snapshot, see the [module-level
+    /// documentation](self#transfer-progress).
+    pub fn progress(&self) -> (u16, u16) {
+        let id = <C as AnyChannel>::Id::USIZE;
+        // SAFETY: The descriptor is only written before the transfer begins
+        let total = unsafe { DESCRIPTOR_SECTION[id].btcnt };
+        progress(total, self.remaining_beats())
+    }
+
     /// Non-blocking; Immediately stop the DMA transfer and release all owned
     /// resources
     pub fn stop(self, dmac: &mut DmaController) -> (Channel<ChannelId<C>, Ready>, S, D, P) {
@@ -539,3 +945,157 @@ where
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_length_limits() {
+        assert_eq!(transfer_length(65535, 65535), Ok(65535));
+        assert_eq!(transfer_length(1, 65535), Ok(65535));
+        assert_eq!(
+            transfer_length(65536, 65536),
+            Err(BufferError::TooLong(65536))
+        );
+        assert_eq!(transfer_length(65536, 1), Err(BufferError::TooLong(65536)));
+        assert_eq!(transfer_length(4, 8), Err(BufferError::LengthMismatch));
+        assert_eq!(transfer_length(1, 1), Ok(1));
+    }
+
+    #[test]
+    fn rejected_buffers_are_handed_back() {
+        use super::super::channel::ready_chan;
+        use super::super::dma_controller::Ch0;
+
+        let mut source = [0u8; 4];
+        let mut destination = [0u8; 8];
+        let result = Transfer::try_new(
+            ready_chan::<Ch0>(),
+            &mut source[..],
+            &mut destination[..],
+            false,
+        );
+        let (_chan, source, destination, error) = match result {
+            Ok(_) => panic!("buffers of 4 and 8 beats were accepted"),
+            Err(rejected) => rejected,
+        };
+        assert_eq!(error, BufferError::LengthMismatch);
+        assert_eq!((source.len(), destination.len()), (4, 8));
+    }
+
+    #[test]
+    fn progress_from_active_and_writeback() {
+        let idle = ActiveChannel {
+            id: 0,
+            busy: false,
+            btcnt: 0,
+        };
+        // Not started yet: the write-back holds the full length
+        assert_eq!(progress(100, remaining_beats(2, idle, 100)), (0, 100));
+
+        // Channel 2 is being serviced, mid-block. The write-back is stale.
+        let servicing = ActiveChannel {
+            id: 2,
+            busy: true,
+            btcnt: 37,
+        };
+        assert_eq!(remaining_beats(2, servicing, 100), 37);
+        assert_eq!(progress(100, remaining_beats(2, servicing, 100)), (63, 100));
+
+        // Another channel is serviced: channel 2 was switched out mid-block
+        let other = ActiveChannel { id: 5, ..servicing };
+        assert_eq!(progress(100, remaining_beats(2, other, 40)), (60, 100));
+
+        // Complete
+        assert_eq!(progress(100, remaining_beats(2, idle, 0)), (100, 100));
+    }
+
+    #[cfg(feature = "min-samd51g")]
+    #[test]
+    fn transferred_beats_after_stop() {
+        // A 64-byte receive stopped on an idle line after 13 bytes. Channel 7
+        // isn't used by any other test.
+        unsafe {
+            DESCRIPTOR_SECTION[7].btcnt = 64;
+            WRITEBACK[7].btcnt = 51;
+        }
+        assert_eq!(transferred_beats(7), 13);
+    }
+
+    #[test]
+    fn status_from_flags() {
+        let running = ChannelFlags {
+            enabled: true,
+            busy: true,
+            ..Default::default()
+        };
+        assert_eq!(transfer_status(running), Poll::Pending);
+        // Enabled, waiting for a trigger, or suspended
+        let idle = ChannelFlags {
+            busy: false,
+            ..running
+        };
+        assert_eq!(transfer_status(idle), Poll::Pending);
+
+        // Complete: the DMAC sets TCMPL and disables the channel
+        let complete = ChannelFlags {
+            tcmpl: true,
+            enabled: false,
+            ..idle
+        };
+        assert_eq!(transfer_status(complete), Poll::Ready(Ok(())));
+        // TCMPL acknowledged by an interrupt handler
+        let acknowledged = ChannelFlags {
+            tcmpl: false,
+            ..complete
+        };
+        assert_eq!(transfer_status(acknowledged), Poll::Ready(Ok(())));
+        // Disabled, but the last beat is still being written
+        let draining = ChannelFlags {
+            busy: true,
+            ..acknowledged
+        };
+        assert_eq!(transfer_status(draining), Poll::Pending);
+
+        // Aborted
+        let bus_error = ChannelFlags {
+            terr: true,
+            ..acknowledged
+        };
+        assert_eq!(
+            transfer_status(bus_error),
+            Poll::Ready(Err(TransferErrorKind::Transfer))
+        );
+        let fetch_error = ChannelFlags {
+            ferr: true,
+            ..acknowledged
+        };
+        assert_eq!(
+            transfer_status(fetch_error),
+            Poll::Ready(Err(TransferErrorKind::Fetch))
+        );
+    }
+
+    #[test]
+    fn beat_address_counts_back_from_block_end() {
+        // 16 halfwords ending at 0x2000_0020, aborted with 6 beats left
+        assert_eq!(
+            beat_address(0x2000_0020, true, 6, BeatSize::HalfWord),
+            0x2000_0014
+        );
+        assert_eq!(
+            beat_address(0x2000_0020, true, 2, BeatSize::Word),
+            0x2000_0018
+        );
+        assert_eq!(
+            beat_address(0x2000_0020, true, 0, BeatSize::Byte),
+            0x2000_0020
+        );
+        // Fixed addresses, e.g. a peripheral data register, don't move
+        assert_eq!(
+            beat_address(0x4200_0828, false, 6, BeatSize::Byte),
+            0x4200_0828
+        );
+    }
+}
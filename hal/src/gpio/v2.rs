@@ -21,4 +21,10 @@ pub use pin::*;
 pub mod dynpin;
 pub use dynpin::*;
 
+pub mod snapshot;
+pub use snapshot::*;
+
+pub mod park;
+pub use park::*;
+
 mod reg;
@@ -0,0 +1,240 @@
+//! # Parking unused pins
+//!
+//! Floating inputs draw current when their level drifts between the logic
+//! thresholds, so the datasheet recommends giving every unused pin a defined
+//! level, either with a pull resistor or by driving it. This matters most in
+//! standby, where a few floating pins can dominate the current consumption.
+//!
+//! [`Pins`] has one public field per pin, which can be moved out
+//! individually, but the remaining pins can't be handed over as a whole
+//! afterwards. Convert it to a [`PinSet`] instead, take the pins the
+//! application needs with [`PinSet::take`], then park the others with
+//! [`PinSet::park_remaining`]:
+//!
+//! ```
+//! let mut pins = Pins::new(peripherals.PORT).into_set();
+//! let led: Pin<PA17, Reset> = pins.take().unwrap();
+//! let button: Pin<PA16, Reset> = pins.take().unwrap();
+//! let mut parked = pins.park_remaining(ParkPolicy::PullDown);
+//!
+//! // Later on, reclaim a parked pin as a `DynPin`
+//! let mut probe = parked.unpark(PA05::DYN).unwrap();
+//! probe.into_push_pull_output();
+//! ```
+//!
+//! The set of available pins is tracked at run-time, so taking a pin twice
+//! returns `None` instead of failing to compile.
+//!
+//! [`Pins`]: super::Pins
+
+use hal::digital::v2::OutputPin;
+
+use super::dynpin::*;
+use super::pin::{Pin, PinId, Reset};
+use super::snapshot::{group_index, GROUPS, NUM_GROUPS};
+
+/// Configuration given to parked pins
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParkPolicy {
+    /// Input buffer disabled, with the pull-down resistor enabled
+    PullDown,
+    /// Input buffer disabled, with the pull-up resistor enabled
+    PullUp,
+    /// Driven low
+    OutputLow,
+}
+
+impl ParkPolicy {
+    /// Mode of the parked pins
+    #[inline]
+    pub fn mode(self) -> DynPinMode {
+        match self {
+            ParkPolicy::PullDown => DYN_PULL_DOWN_DISABLED,
+            ParkPolicy::PullUp => DYN_PULL_UP_DISABLED,
+            ParkPolicy::OutputLow => DYN_PUSH_PULL_OUTPUT,
+        }
+    }
+}
+
+impl Default for ParkPolicy {
+    #[inline]
+    fn default() -> Self {
+        ParkPolicy::PullDown
+    }
+}
+
+//==============================================================================
+//  PinMask
+//==============================================================================
+
+/// Set of pins, with one bit per pin of each group
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(super) struct PinMask([u32; NUM_GROUPS]);
+
+impl PinMask {
+    #[inline]
+    pub(super) const fn empty() -> Self {
+        PinMask([0; NUM_GROUPS])
+    }
+
+    #[inline]
+    pub(super) fn insert(&mut self, id: DynPinId) {
+        self.0[group_index(id.group)] |= 1 << id.num;
+    }
+
+    /// Remove `id` from the set, and return whether it was present
+    #[inline]
+    fn remove(&mut self, id: DynPinId) -> bool {
+        let bits = &mut self.0[group_index(id.group)];
+        let present = *bits & (1 << id.num) != 0;
+        *bits &= !(1 << id.num);
+        present
+    }
+
+    #[inline]
+    fn contains(&self, id: DynPinId) -> bool {
+        self.0[group_index(id.group)] & (1 << id.num) != 0
+    }
+
+    /// Iterate over the pins of the set
+    fn iter(&self) -> impl Iterator<Item = DynPinId> + '_ {
+        GROUPS.iter().flat_map(move |&group| {
+            let bits = self.0[group_index(group)];
+            (0..32)
+                .filter(move |num| bits & (1 << num) != 0)
+                .map(move |num| DynPinId { group, num })
+        })
+    }
+}
+
+//==============================================================================
+//  PinSet
+//==============================================================================
+
+/// The pins of a [`Pins`](super::Pins) that were not taken yet
+///
+/// See the [module-level documentation](self) for more details.
+pub struct PinSet {
+    available: PinMask,
+}
+
+impl PinSet {
+    /// Create a set from the pins of a [`Pins`](super::Pins)
+    #[inline]
+    pub(super) fn new(available: PinMask) -> Self {
+        PinSet { available }
+    }
+
+    /// Take the pin `I`, if it was not taken yet
+    #[inline]
+    pub fn take<I: PinId>(&mut self) -> Option<Pin<I, Reset>> {
+        if self.available.remove(I::DYN) {
+            // Safe because the pin was removed from the set
+            Some(unsafe { Pin::new() })
+        } else {
+            None
+        }
+    }
+
+    /// Take the pin `id` as a [`DynPin`], if it was not taken yet
+    #[inline]
+    pub fn take_dyn(&mut self, id: DynPinId) -> Option<DynPin> {
+        if self.available.remove(id) {
+            // Safe because the pin was removed from the set
+            Some(unsafe { DynPin::new(id, DYN_FLOATING_DISABLED) })
+        } else {
+            None
+        }
+    }
+
+    /// Whether the pin `id` was not taken yet
+    #[inline]
+    pub fn contains(&self, id: DynPinId) -> bool {
+        self.available.contains(id)
+    }
+
+    /// Configure every remaining pin according to `policy`
+    ///
+    /// Pins used by the debugger, like `SWCLK` and `SWDIO`, are parked as
+    /// well. Take them first to keep debugging working.
+    pub fn park_remaining(self, policy: ParkPolicy) -> ParkedPins {
+        let mode = policy.mode();
+        for id in self.available.iter() {
+            // Safe because the pin is still owned by the set, and the
+            // `DynPin` is dropped right away
+            let mut pin = unsafe { DynPin::new(id, DYN_FLOATING_DISABLED) };
+            pin.into_mode(mode);
+            if policy == ParkPolicy::OutputLow {
+                // Cannot fail, since the pin is an output
+                let _ = pin.set_low();
+            }
+        }
+        ParkedPins {
+            parked: self.available,
+            mode,
+        }
+    }
+}
+
+//==============================================================================
+//  ParkedPins
+//==============================================================================
+
+/// Token owning the pins parked by [`PinSet::park_remaining`]
+pub struct ParkedPins {
+    parked: PinMask,
+    mode: DynPinMode,
+}
+
+impl ParkedPins {
+    /// Reclaim the parked pin `id`, if it is still parked
+    ///
+    /// The pin keeps its parked configuration until it is changed.
+    #[inline]
+    pub fn unpark(&mut self, id: DynPinId) -> Option<DynPin> {
+        if self.parked.remove(id) {
+            // Safe because the pin was removed from the token
+            Some(unsafe { DynPin::new(id, self.mode) })
+        } else {
+            None
+        }
+    }
+
+    /// Whether the pin `id` is parked
+    #[inline]
+    pub fn is_parked(&self, id: DynPinId) -> bool {
+        self.parked.contains(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_tracks_pins() {
+        let pa05 = DynPinId {
+            group: DynGroup::A,
+            num: 5,
+        };
+        let pa31 = DynPinId {
+            group: DynGroup::A,
+            num: 31,
+        };
+        let mut mask = PinMask::empty();
+        mask.insert(pa05);
+        mask.insert(pa31);
+        assert!(mask.contains(pa05));
+
+        let mut ids = [None; 3];
+        for (slot, id) in ids.iter_mut().zip(mask.iter()) {
+            *slot = Some(id);
+        }
+        assert_eq!(ids, [Some(pa05), Some(pa31), None]);
+
+        assert!(mask.remove(pa05));
+        assert!(!mask.remove(pa05));
+        assert!(!mask.contains(pa05));
+        assert_eq!(mask.iter().count(), 1);
+    }
+}
@@ -1045,6 +1045,27 @@ macro_rules! pins{
                 pub unsafe fn port(&mut self) -> PORT {
                     self.port.take().unwrap()
                 }
+                /// Convert into a [`PinSet`], to take pins individually and
+                /// park the remaining ones
+                ///
+                /// The [`PORT`] is dropped, unless it was taken before.
+                ///
+                /// [`PinSet`]: super::PinSet
+                #[inline]
+                pub fn into_set(self) -> super::PinSet {
+                    let mut available = super::park::PinMask::empty();
+                    $(
+                        $( #[$cfg] )?
+                        available.insert(<$Id as PinId>::DYN);
+                    )+
+                    super::PinSet::new(available)
+                }
+                /// Park every pin according to `policy`, see
+                /// [`PinSet::park_remaining`](super::PinSet::park_remaining)
+                #[inline]
+                pub fn park_remaining(self, policy: super::ParkPolicy) -> super::ParkedPins {
+                    self.into_set().park_remaining(policy)
+                }
             }
         }
     };
@@ -152,11 +152,11 @@ impl From<DynPinMode> for ModeFields {
 /// SAMD11 and SAMD21 PACs do not. Manually re-implement it here.
 #[repr(C)]
 pub(super) struct GROUP {
-    dir: DIR,
+    pub(super) dir: DIR,
     dirclr: DIRCLR,
     dirset: DIRSET,
     dirtgl: DIRTGL,
-    out: OUT,
+    pub(super) out: OUT,
     outclr: OUTCLR,
     outset: OUTSET,
     outtgl: OUTTGL,
@@ -164,8 +164,8 @@ pub(super) struct GROUP {
     ctrl: CTRL,
     wrconfig: WRCONFIG,
     _padding1: [u8; 4],
-    pmux: [PMUX; 16],
-    pincfg: [PINCFG; 32],
+    pub(super) pmux: [PMUX; 16],
+    pub(super) pincfg: [PINCFG; 32],
     _padding2: [u8; 32],
 }
 
@@ -0,0 +1,252 @@
+//! # Snapshots of the PORT configuration
+//!
+//! A [`PortSnapshot`] records the `DIR`, `OUT`, `PINCFG` and `PMUX` registers
+//! of every [`PORT`] group. It can be used to save the pin configuration
+//! before entering a low-power mode, or before handing the pins over to an
+//! external debugger, and to restore it afterwards.
+//!
+//! Capturing a snapshot only reads from the registers, so it is always safe.
+//! Restoring one overwrites the configuration of every pin, including pins
+//! owned by other [`Pin`]s or [`DynPin`]s, so [`PortSnapshot::restore`] is
+//! `unsafe`.
+//!
+//! Snapshots can also be compared with [`PortSnapshot::diff`], which lists
+//! the pins whose configuration differs. This is useful to find out which
+//! code changed a pin, or to validate the state left by a bootloader.
+//! ```
+//! let before = PortSnapshot::capture();
+//! do_something();
+//! for change in before.diff(&PortSnapshot::capture()) {
+//!     // Log `change.id`, `change.before` and `change.after`
+//! }
+//! ```
+//!
+//! [`PORT`]: crate::target_device::PORT
+//! [`Pin`]: super::Pin
+//! [`DynPin`]: super::DynPin
+
+use super::dynpin::{DynGroup, DynPinId};
+use super::reg::GROUP;
+use crate::target_device::PORT;
+
+/// Pin groups present on the chip
+pub(super) const GROUPS: [DynGroup; NUM_GROUPS] = [
+    DynGroup::A,
+    #[cfg(any(feature = "samd21", feature = "min-samd51g"))]
+    DynGroup::B,
+    #[cfg(feature = "min-samd51n")]
+    DynGroup::C,
+    #[cfg(feature = "min-samd51p")]
+    DynGroup::D,
+];
+
+/// Number of pin groups present on the chip
+pub(super) const NUM_GROUPS: usize = 1
+    + cfg!(any(feature = "samd21", feature = "min-samd51g")) as usize
+    + cfg!(feature = "min-samd51n") as usize
+    + cfg!(feature = "min-samd51p") as usize;
+
+#[inline]
+pub(super) fn group_index(group: DynGroup) -> usize {
+    match group {
+        DynGroup::A => 0,
+        #[cfg(any(feature = "samd21", feature = "min-samd51g"))]
+        DynGroup::B => 1,
+        #[cfg(feature = "min-samd51n")]
+        DynGroup::C => 2,
+        #[cfg(feature = "min-samd51p")]
+        DynGroup::D => 3,
+    }
+}
+
+//==============================================================================
+//  PinSnapshot
+//==============================================================================
+
+/// Configuration of a single pin, extracted from a [`PortSnapshot`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PinSnapshot {
+    /// `DIR` bit
+    pub dir: bool,
+    /// `OUT` bit
+    pub out: bool,
+    /// Raw `PINCFG` register
+    pub pincfg: u8,
+    /// `PMUX` field for this pin
+    pub pmux: u8,
+}
+
+/// A pin whose configuration differs between two [`PortSnapshot`]s
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PinChange {
+    /// The pin in question
+    pub id: DynPinId,
+    /// Configuration in the snapshot [`PortSnapshot::diff`] was called on
+    pub before: PinSnapshot,
+    /// Configuration in the snapshot passed to [`PortSnapshot::diff`]
+    pub after: PinSnapshot,
+}
+
+//==============================================================================
+//  PortSnapshot
+//==============================================================================
+
+/// Registers of a single [`PORT`](crate::target_device::PORT) group
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct GroupSnapshot {
+    dir: u32,
+    out: u32,
+    pmux: [u8; 16],
+    pincfg: [u8; 32],
+}
+
+/// Configuration of every pin on the chip
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PortSnapshot {
+    groups: [GroupSnapshot; NUM_GROUPS],
+}
+
+impl PortSnapshot {
+    /// Read the current configuration of every pin
+    pub fn capture() -> Self {
+        let mut groups = [GroupSnapshot {
+            dir: 0,
+            out: 0,
+            pmux: [0; 16],
+            pincfg: [0; 32],
+        }; NUM_GROUPS];
+        for (snapshot, group) in groups.iter_mut().zip(Self::groups()) {
+            snapshot.dir = group.dir.read().bits();
+            snapshot.out = group.out.read().bits();
+            for (value, pmux) in snapshot.pmux.iter_mut().zip(group.pmux.iter()) {
+                *value = pmux.read().bits();
+            }
+            for (value, pincfg) in snapshot.pincfg.iter_mut().zip(group.pincfg.iter()) {
+                *value = pincfg.read().bits();
+            }
+        }
+        PortSnapshot { groups }
+    }
+
+    /// Write the recorded configuration back to every pin
+    ///
+    /// The output levels are restored first and the directions last, so that
+    /// pins returning to an output mode immediately drive the recorded level.
+    ///
+    /// # Safety
+    ///
+    /// This changes the configuration of every pin, regardless of which
+    /// [`Pin`](super::Pin) or [`DynPin`](super::DynPin) owns it. The caller
+    /// must make sure the type-level state of any existing pins still matches
+    /// the restored configuration, e.g. by restoring a snapshot captured while
+    /// the same pins were in the same modes.
+    pub unsafe fn restore(&self) {
+        for (snapshot, group) in self.groups.iter().zip(Self::groups()) {
+            group.out.write(|w| w.bits(snapshot.out));
+            for (value, pmux) in snapshot.pmux.iter().zip(group.pmux.iter()) {
+                pmux.write(|w| w.bits(*value));
+            }
+            for (value, pincfg) in snapshot.pincfg.iter().zip(group.pincfg.iter()) {
+                pincfg.write(|w| w.bits(*value));
+            }
+            group.dir.write(|w| w.bits(snapshot.dir));
+        }
+    }
+
+    /// Return the recorded configuration of a single pin
+    pub fn pin(&self, id: DynPinId) -> PinSnapshot {
+        let group = &self.groups[group_index(id.group)];
+        let num = id.num as usize;
+        let pmux = group.pmux[num / 2];
+        PinSnapshot {
+            dir: group.dir & (1 << num) != 0,
+            out: group.out & (1 << num) != 0,
+            pincfg: group.pincfg[num],
+            pmux: if num % 2 == 0 { pmux & 0xF } else { pmux >> 4 },
+        }
+    }
+
+    /// Iterate over the pins whose configuration differs between `self` and
+    /// `other`
+    pub fn diff<'a>(&'a self, other: &'a PortSnapshot) -> impl Iterator<Item = PinChange> + 'a {
+        GROUPS
+            .iter()
+            .flat_map(|&group| (0..32).map(move |num| DynPinId { group, num }))
+            .filter_map(move |id| {
+                let before = self.pin(id);
+                let after = other.pin(id);
+                if before != after {
+                    Some(PinChange { id, before, after })
+                } else {
+                    None
+                }
+            })
+    }
+
+    #[inline]
+    fn groups() -> impl Iterator<Item = &'static GROUP> {
+        let ptr = PORT::ptr() as *const GROUP;
+        // Safe because only the groups present on the chip are accessed, and
+        // the returned references are only used to read and write whole
+        // registers
+        (0..NUM_GROUPS).map(move |i| unsafe { &*ptr.add(i) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> PortSnapshot {
+        PortSnapshot {
+            groups: [GroupSnapshot {
+                dir: 0,
+                out: 0,
+                pmux: [0; 16],
+                pincfg: [0; 32],
+            }; NUM_GROUPS],
+        }
+    }
+
+    #[test]
+    fn pin_extracts_its_pmux_nibble() {
+        let mut port = snapshot();
+        port.groups[0].pmux[4] = 0x3D;
+        port.groups[0].pincfg[9] = 0x01;
+        port.groups[0].dir = 1 << 9;
+        let pa08 = port.pin(DynPinId {
+            group: DynGroup::A,
+            num: 8,
+        });
+        let pa09 = port.pin(DynPinId {
+            group: DynGroup::A,
+            num: 9,
+        });
+        assert_eq!(pa08.pmux, 0xD);
+        assert!(!pa08.dir);
+        assert_eq!(pa09.pmux, 0x3);
+        assert!(pa09.dir);
+        assert_eq!(pa09.pincfg, 0x01);
+    }
+
+    #[test]
+    fn diff_lists_changed_pins() {
+        let before = snapshot();
+        let mut after = snapshot();
+        assert_eq!(before.diff(&after).count(), 0);
+
+        after.groups[0].out = 1 << 17;
+        after.groups[0].pmux[1] = 0x40;
+        let mut changes = before.diff(&after);
+        let change = changes.next().unwrap();
+        assert_eq!(change.id.num, 3);
+        assert_eq!(change.after.pmux, 4);
+        let change = changes.next().unwrap();
+        assert_eq!(change.id.num, 17);
+        assert!(!change.before.out);
+        assert!(change.after.out);
+        assert!(changes.next().is_none());
+    }
+}
@@ -1,5 +1,8 @@
 #![no_std]
 
+#[cfg(test)]
+extern crate std;
+
 pub extern crate embedded_hal as hal;
 
 pub use paste;
@@ -72,19 +75,35 @@ macro_rules! dbgprint {
     ($($arg:tt)*) => {{}};
 }
 
+#[cfg(feature = "unproven")]
+pub mod block_device;
+#[cfg(all(feature = "device", feature = "clock_map"))]
+pub mod clock_map;
 #[cfg(feature = "device")]
 pub mod delay;
 #[cfg(feature = "device")]
+pub mod device_id;
+#[cfg(feature = "device")]
 pub mod gpio;
 #[cfg(feature = "device")]
+pub(crate) mod periph;
+#[cfg(feature = "device")]
+pub mod peripheral_reset;
+#[cfg(feature = "device")]
 pub mod prelude;
 #[cfg(feature = "device")]
 pub mod rtc;
+#[cfg(all(feature = "device", feature = "unproven"))]
+pub mod sdspi;
 #[cfg(feature = "device")]
 pub mod sercom;
+#[cfg(feature = "device")]
+pub mod shared_pclk;
 pub mod sleeping_delay;
 #[cfg(feature = "device")]
 pub mod spi_common;
+#[cfg(feature = "device")]
+pub mod spi_device;
 pub mod time;
 pub mod timer_params;
 pub mod timer_traits;
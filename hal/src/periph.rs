@@ -0,0 +1,151 @@
+//! # Peripheral register blocks
+//!
+//! Drivers reach their registers through a [`Periph`] rather than through the
+//! PAC handle, so that their register sequences can be tested on the host.
+//!
+//! In normal builds, a `Periph` only wraps the handle, and dereferences to its
+//! register block like the handle does. Under `cfg(test)`, it can point to a
+//! [`MockPeriph`] instead: a block of RAM laid out like the peripheral, which
+//! the test presets, hands to the driver, and inspects afterwards.
+//!
+//! RAM has none of the side effects of the registers, and the tests rely on
+//! it:
+//!
+//! - `SYNCBUSY` reads as the test left it, i.e. never busy, so the waits for
+//!   synchronization return at once. Drivers mark each of these waits with
+//!   [`Periph::synced`], where the mock records a snapshot of the registers.
+//!   The snapshots tell which writes were synchronized before the next one.
+//! - A write-1-to-clear `INTFLAG` register keeps the value written, i.e. the
+//!   flags the driver cleared rather than the flags still set.
+
+use core::ops::Deref;
+
+#[cfg(test)]
+use std::{boxed::Box, cell::RefCell, vec::Vec};
+
+/// Register block of the PAC peripheral `P`
+///
+/// See the [module-level documentation](self).
+pub(crate) struct Periph<P>
+where
+    P: Deref,
+    P::Target: Sized + 'static,
+{
+    periph: P,
+    #[cfg(test)]
+    mock: Option<&'static MockPeriph<P::Target>>,
+}
+
+impl<P> Periph<P>
+where
+    P: Deref,
+    P::Target: Sized + 'static,
+{
+    /// Wrap the PAC handle of the peripheral
+    #[inline]
+    pub fn new(periph: P) -> Self {
+        Periph {
+            periph,
+            #[cfg(test)]
+            mock: None,
+        }
+    }
+
+    /// Use the registers of `mock` instead of those of `periph`
+    #[cfg(test)]
+    pub fn mock(periph: P, mock: &'static MockPeriph<P::Target>) -> Self {
+        Periph {
+            periph,
+            mock: Some(mock),
+        }
+    }
+
+    /// Release the PAC handle
+    #[inline]
+    pub fn free(self) -> P {
+        self.periph
+    }
+
+    /// Mark a point where the driver waited for its writes to be synchronized
+    #[inline(always)]
+    pub fn synced(&self) {
+        #[cfg(test)]
+        if let Some(mock) = self.mock {
+            mock.snapshot();
+        }
+    }
+}
+
+impl<P> Deref for Periph<P>
+where
+    P: Deref,
+    P::Target: Sized + 'static,
+{
+    type Target = P::Target;
+
+    #[inline(always)]
+    fn deref(&self) -> &P::Target {
+        #[cfg(test)]
+        if let Some(mock) = self.mock {
+            return &mock.registers.0;
+        }
+        &self.periph
+    }
+}
+
+/// Register block in RAM, aligned like the peripheral's
+#[cfg(test)]
+#[repr(C, align(4))]
+struct Block<B>(B);
+
+/// Registers of a peripheral in RAM, which record a snapshot at each point a
+/// driver waits for synchronization
+#[cfg(test)]
+pub(crate) struct MockPeriph<B> {
+    registers: Box<Block<B>>,
+    snapshots: RefCell<Vec<Box<Block<B>>>>,
+}
+
+#[cfg(test)]
+impl<B> MockPeriph<B> {
+    /// Registers which all read as zero, with no snapshot
+    pub fn new() -> &'static Self {
+        Box::leak(Box::new(MockPeriph {
+            registers: Self::zeroed(),
+            snapshots: RefCell::default(),
+        }))
+    }
+
+    fn zeroed() -> Box<Block<B>> {
+        // SAFETY: PAC register blocks only hold integer cells, which are valid
+        // when zeroed
+        Box::new(unsafe { core::mem::zeroed() })
+    }
+
+    /// The current registers, to preset or inspect them
+    pub fn registers(&self) -> &B {
+        &self.registers.0
+    }
+
+    fn snapshot(&self) {
+        let mut snapshot = Self::zeroed();
+        // SAFETY: Both blocks are valid and distinct, and the registers are
+        // only accessed by the current thread
+        unsafe { core::ptr::copy_nonoverlapping(&*self.registers, &mut *snapshot, 1) };
+        self.snapshots.borrow_mut().push(snapshot);
+    }
+
+    /// Map the snapshots taken so far, oldest first, and drop consecutive
+    /// duplicates, so that only the changes between synchronization points
+    /// are left
+    pub fn synced<T: PartialEq>(&self, mut map: impl FnMut(&B) -> T) -> Vec<T> {
+        let mut values: Vec<T> = Vec::new();
+        for snapshot in self.snapshots.borrow().iter() {
+            let value = map(&snapshot.0);
+            if values.last() != Some(&value) {
+                values.push(value);
+            }
+        }
+        values
+    }
+}
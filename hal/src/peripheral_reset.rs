@@ -0,0 +1,258 @@
+//! # Peripheral software reset
+//!
+//! Most peripherals can be returned to their reset state by setting the
+//! `SWRST` bit of their `CTRLA` register. Doing so cleanly takes a few steps:
+//!
+//! 1. Clear `CTRLA.ENABLE` and wait for it to synchronize
+//! 2. Set `CTRLA.SWRST`
+//! 3. Wait for the reset to complete
+//!
+//! The reset is performed in the peripheral's generic clock domain, so it
+//! only completes once it has been synchronized across the clock domains.
+//! Completion is signalled by the `SWRST` bit of the `SYNCBUSY` register (on
+//! SAMD5x/E5x, and on the SERCOM and TCC peripherals of SAMD11/21), or by the
+//! `STATUS.SYNCBUSY` bit together with `CTRLA.SWRST` itself (on the other
+//! SAMD11/21 peripherals). Until then, the peripheral registers must not be
+//! written.
+//!
+//! The [`ResetPeripheral`] trait implements this sequence for the PAC
+//! SERCOM, TC, TCC, ADC and DAC peripherals.
+//!
+//! Both the APB clock (in the `MCLK` or `PM` peripheral) and the generic
+//! clock of the peripheral must be enabled before resetting it. Without the
+//! APB clock the registers can't be accessed, and without the generic clock
+//! the synchronization never completes, so [`reset_peripheral`] would never
+//! return.
+//!
+//! ```
+//! let mut tc3 = peripherals.TC3;
+//! tc3.reset_peripheral();
+//! ```
+//!
+//! [`reset_peripheral`]: ResetPeripheral::reset_peripheral
+
+#[cfg(feature = "min-samd51g")]
+use crate::target_device::{ADC0, ADC1, DAC, SERCOM0, SERCOM1, SERCOM2, SERCOM3, SERCOM4, SERCOM5};
+#[cfg(feature = "min-samd51n")]
+use crate::target_device::{SERCOM6, SERCOM7, TC6, TC7};
+#[cfg(feature = "min-samd51g")]
+use crate::target_device::{TC0, TC1, TC2, TC3, TCC0, TCC1, TCC2};
+#[cfg(feature = "min-samd51j")]
+use crate::target_device::{TC4, TC5, TCC3, TCC4};
+
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+use crate::target_device::{ADC, DAC, SERCOM0, SERCOM1, TCC0};
+#[cfg(feature = "samd21")]
+use crate::target_device::{SERCOM2, SERCOM3, TC3, TC4, TC5, TCC1, TCC2};
+#[cfg(feature = "min-samd21g")]
+use crate::target_device::{SERCOM4, SERCOM5};
+#[cfg(feature = "samd11")]
+use crate::target_device::{TC1, TC2};
+#[cfg(feature = "samd21j")]
+use crate::target_device::{TC6, TC7};
+
+/// Return a peripheral to its reset state through `CTRLA.SWRST`
+///
+/// See the [module-level documentation](self) for more details.
+pub trait ResetPeripheral {
+    /// Disable the peripheral, reset it and wait for the reset to complete
+    fn reset_peripheral(&mut self);
+}
+
+/// Perform the software reset sequence
+///
+/// `enable_busy` and `swrst_busy` are polled until they return `false`.
+#[inline]
+fn reset_sequence(
+    disable: impl FnOnce(),
+    mut enable_busy: impl FnMut() -> bool,
+    swrst: impl FnOnce(),
+    mut swrst_busy: impl FnMut() -> bool,
+) {
+    disable();
+    while enable_busy() {}
+    swrst();
+    while swrst_busy() {}
+}
+
+/// Implement [`ResetPeripheral`] for peripherals with a `SYNCBUSY` register
+macro_rules! reset_syncbusy {
+    ($($Type:ident: |$p:ident| $regs:expr,)+) => {
+        $(
+            impl ResetPeripheral for $Type {
+                fn reset_peripheral(&mut self) {
+                    let $p = &*self;
+                    let regs = $regs;
+                    reset_sequence(
+                        || regs.ctrla.modify(|_, w| w.enable().clear_bit()),
+                        || regs.syncbusy.read().enable().bit_is_set(),
+                        || regs.ctrla.write(|w| w.swrst().set_bit()),
+                        || regs.syncbusy.read().swrst().bit_is_set(),
+                    );
+                }
+            }
+        )+
+    };
+}
+
+/// Implement [`ResetPeripheral`] for peripherals with a `STATUS.SYNCBUSY` bit
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+macro_rules! reset_status {
+    ($($Type:ident: |$p:ident| $regs:expr,)+) => {
+        $(
+            impl ResetPeripheral for $Type {
+                fn reset_peripheral(&mut self) {
+                    let $p = &*self;
+                    let regs = $regs;
+                    reset_sequence(
+                        || regs.ctrla.modify(|_, w| w.enable().clear_bit()),
+                        || regs.status.read().syncbusy().bit_is_set(),
+                        || regs.ctrla.write(|w| w.swrst().set_bit()),
+                        // Some SVDs mark SWRST as write-only, so read the raw
+                        // bit
+                        || {
+                            regs.status.read().syncbusy().bit_is_set()
+                                || regs.ctrla.read().bits() & 1 != 0
+                        },
+                    );
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "min-samd51g")]
+reset_syncbusy! {
+    SERCOM0: |p| p.usart_int(),
+    SERCOM1: |p| p.usart_int(),
+    SERCOM2: |p| p.usart_int(),
+    SERCOM3: |p| p.usart_int(),
+    SERCOM4: |p| p.usart_int(),
+    SERCOM5: |p| p.usart_int(),
+    TC0: |p| p.count16(),
+    TC1: |p| p.count16(),
+    TC2: |p| p.count16(),
+    TC3: |p| p.count16(),
+    TCC0: |p| p,
+    TCC1: |p| p,
+    TCC2: |p| p,
+    ADC0: |p| p,
+    ADC1: |p| p,
+    DAC: |p| p,
+}
+
+#[cfg(feature = "min-samd51j")]
+reset_syncbusy! {
+    TC4: |p| p.count16(),
+    TC5: |p| p.count16(),
+    TCC3: |p| p,
+    TCC4: |p| p,
+}
+
+#[cfg(feature = "min-samd51n")]
+reset_syncbusy! {
+    SERCOM6: |p| p.usart_int(),
+    SERCOM7: |p| p.usart_int(),
+    TC6: |p| p.count16(),
+    TC7: |p| p.count16(),
+}
+
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+reset_syncbusy! {
+    SERCOM0: |p| p.usart(),
+    SERCOM1: |p| p.usart(),
+    TCC0: |p| p,
+}
+
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+reset_status! {
+    ADC: |p| p,
+    DAC: |p| p,
+}
+
+#[cfg(feature = "samd11")]
+reset_status! {
+    TC1: |p| p.count16(),
+    TC2: |p| p.count16(),
+}
+
+#[cfg(feature = "samd21")]
+reset_syncbusy! {
+    SERCOM2: |p| p.usart(),
+    SERCOM3: |p| p.usart(),
+    TCC1: |p| p,
+    TCC2: |p| p,
+}
+
+#[cfg(feature = "samd21")]
+reset_status! {
+    TC3: |p| p.count16(),
+    TC4: |p| p.count16(),
+    TC5: |p| p.count16(),
+}
+
+#[cfg(feature = "min-samd21g")]
+reset_syncbusy! {
+    SERCOM4: |p| p.usart(),
+    SERCOM5: |p| p.usart(),
+}
+
+#[cfg(feature = "samd21j")]
+reset_status! {
+    TC6: |p| p.count16(),
+    TC7: |p| p.count16(),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    enum Step {
+        Disable,
+        PollEnable,
+        Reset,
+        PollReset,
+    }
+
+    #[test]
+    fn reset_waits_for_each_step() {
+        let log = RefCell::new([None; 8]);
+        let len = RefCell::new(0);
+        let push = |step| {
+            let mut len = len.borrow_mut();
+            log.borrow_mut()[*len] = Some(step);
+            *len += 1;
+        };
+        let mut enable_polls = 0;
+        let mut reset_polls = 0;
+        reset_sequence(
+            || push(Step::Disable),
+            || {
+                push(Step::PollEnable);
+                enable_polls += 1;
+                enable_polls < 2
+            },
+            || push(Step::Reset),
+            || {
+                push(Step::PollReset);
+                reset_polls += 1;
+                reset_polls < 3
+            },
+        );
+        assert_eq!(
+            *log.borrow(),
+            [
+                Some(Step::Disable),
+                Some(Step::PollEnable),
+                Some(Step::PollEnable),
+                Some(Step::Reset),
+                Some(Step::PollReset),
+                Some(Step::PollReset),
+                Some(Step::PollReset),
+                None,
+            ]
+        );
+    }
+}
@@ -1,10 +1,62 @@
 //! Real-time clock/counter
-use crate::target_device::rtc::{MODE0, MODE2};
+//!
+//! # Async alarm
+//!
+//! In clock/calendar mode, [`Rtc::wait_alarm`] programs alarm 0 and returns
+//! a future which completes once the alarm fires. The future is woken from
+//! the `RTC` interrupt, so the interrupt must be unmasked in the NVIC and its
+//! handler must call [`on_alarm_interrupt`]:
+//!
+//! ```
+//! #[interrupt]
+//! fn RTC() {
+//!     rtc::on_alarm_interrupt();
+//! }
+//! ```
+//!
+//! The RTC keeps running in standby, and the alarm interrupt wakes the CPU
+//! from it, so an executor can sleep until the alarm as long as the RTC clock
+//! source is configured to run in standby.
+//!
+//! # 16-bit counter mode
+//!
+//! [`Rtc16`] runs the RTC as a 16-bit counter (MODE1), which counts from zero
+//! up to its period (`PER`), then wraps around to zero and raises the overflow
+//! flag. It has two compare values, each with an interrupt and an event
+//! output. As a [`CountDown`] timer, it picks the prescaler and the period
+//! from the timeout, and [`wait`](CountDown::wait) completes on each
+//! overflow, so the timer is periodic without rewriting any register. It also
+//! makes a tick source for a low-power scheduler: a period of one tick, or of
+//! a few ticks, with the overflow interrupt enabled, and
+//! [`Rtc16::count_stable`] for the position within the period.
+//!
+//! Compared to the 32-bit counter mode (MODE0):
+//!
+//! - Current draw is the same for both modes, as the RTC runs from the
+//!   32.768 kHz or 1.024 kHz output of `OSC32KCTRL`, which keeps running in
+//!   standby. A TC needs a clock generator running in standby, from a faster
+//!   source, which draws more.
+//! - MODE0 periodic timing has to move its compare value forward at each
+//!   match, or clear the counter on match and give up the free-running count.
+//!   MODE1 wraps at the period by itself, and keeps both compare values for
+//!   events within the period.
+//! - MODE1 only counts up to 65535, so the resolution is coarser for long
+//!   periods: the prescaler has to divide the RTC clock until the period fits.
+//!   From 32.768 kHz, periods of 2 to 4 s have 61 µs steps, and the longest
+//!   period, 34 minutes, has 31.25 ms steps. MODE0 keeps a single tick
+//!   resolution for periods of up to 36 hours.
+use crate::periph::Periph;
+use crate::target_device::rtc::{MODE0, MODE1, MODE2};
 use crate::target_device::RTC;
 use crate::time::{Hertz, Nanoseconds};
 use crate::timer_traits::InterruptDrivenTimer;
 use crate::typelevel::Sealed;
+use core::cell::RefCell;
+use core::future::Future;
 use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use cortex_m::interrupt::{self, Mutex};
 use hal::timer::{CountDown, Periodic};
 use void::Void;
 
@@ -12,16 +64,27 @@ use void::Void;
 #[cfg(feature = "min-samd51g")]
 use crate::target_device::{
     rtc::mode0::ctrla::PRESCALER_A, rtc::mode0::CTRLA as MODE0_CTRLA,
-    rtc::mode2::CTRLA as MODE2_CTRLA, MCLK as PM,
+    rtc::mode1::CTRLA as MODE1_CTRLA, rtc::mode2::CTRLA as MODE2_CTRLA, MCLK as PM,
 };
 
+/// Fields of the clock/calendar compared against an alarm
+#[cfg(feature = "min-samd51g")]
+pub use crate::target_device::rtc::mode2::mask0::SEL_A as AlarmMask;
+
 // SAMD11/SAMD21 imports
 #[cfg(any(feature = "samd11", feature = "samd21"))]
 use crate::target_device::{
     rtc::mode0::ctrl::PRESCALER_A, rtc::mode0::CTRL as MODE0_CTRLA,
-    rtc::mode2::CTRL as MODE2_CTRLA, PM,
+    rtc::mode1::CTRL as MODE1_CTRLA, rtc::mode2::CTRL as MODE2_CTRLA, PM,
 };
 
+/// Fields of the clock/calendar compared against an alarm
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+pub use crate::target_device::rtc::mode2::mask::SEL_A as AlarmMask;
+
+/// Waker of the task awaiting an [`AlarmFuture`]
+static ALARM_WAKER: Mutex<RefCell<Option<Waker>>> = Mutex::new(RefCell::new(None));
+
 /// Datetime represents an RTC clock/calendar value.
 #[derive(Debug, Clone, Copy)]
 pub struct Datetime {
@@ -65,9 +128,31 @@ pub enum Count32Mode {}
 impl RtcMode for Count32Mode {}
 impl Sealed for Count32Mode {}
 
+/// Count16Mode represents the 16-bit counter mode. The counter counts up to
+/// its period, then wraps around to zero. The CountDown / Periodic traits use
+/// the period, and the compare registers are left to the user.
+pub enum Count16Mode {}
+
+impl RtcMode for Count16Mode {}
+impl Sealed for Count16Mode {}
+
+/// The RTC in 16-bit counter mode
+pub type Rtc16 = Rtc<Count16Mode>;
+
+/// Interrupt and event sources of the 16-bit counter mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Count16Event {
+    /// The counter wrapped around from its period to zero
+    Overflow,
+    /// The counter matched compare value 0
+    Compare0,
+    /// The counter matched compare value 1
+    Compare1,
+}
+
 /// Rtc represents the RTC peripheral for either clock/calendar or timer mode.
 pub struct Rtc<Mode: RtcMode> {
-    rtc: RTC,
+    rtc: Periph<RTC>,
     rtc_clock_freq: Hertz,
     _mode: PhantomData<Mode>,
 }
@@ -79,6 +164,11 @@ impl<Mode: RtcMode> Rtc<Mode> {
         self.rtc.mode0()
     }
 
+    #[inline]
+    fn mode1(&mut self) -> &MODE1 {
+        self.rtc.mode1()
+    }
+
     #[inline]
     fn mode2(&mut self) -> &MODE2 {
         self.rtc.mode2()
@@ -92,6 +182,14 @@ impl<Mode: RtcMode> Rtc<Mode> {
         return &self.mode0().ctrl;
     }
 
+    #[inline]
+    fn mode1_ctrla(&mut self) -> &MODE1_CTRLA {
+        #[cfg(feature = "min-samd51g")]
+        return &self.mode1().ctrla;
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        return &self.mode1().ctrl;
+    }
+
     #[inline]
     fn mode2_ctrla(&mut self) -> &MODE2_CTRLA {
         #[cfg(feature = "min-samd51g")]
@@ -106,6 +204,7 @@ impl<Mode: RtcMode> Rtc<Mode> {
         while self.mode2().syncbusy.read().bits() != 0 {}
         #[cfg(any(feature = "samd11", feature = "samd21"))]
         while self.mode2().status.read().syncbusy().bit_is_set() {}
+        self.rtc.synced();
     }
 
     #[inline]
@@ -124,7 +223,7 @@ impl<Mode: RtcMode> Rtc<Mode> {
         self.sync();
     }
 
-    fn create(rtc: RTC, rtc_clock_freq: Hertz) -> Self {
+    fn create(rtc: Periph<RTC>, rtc_clock_freq: Hertz) -> Self {
         Self {
             rtc,
             rtc_clock_freq,
@@ -161,6 +260,34 @@ impl<Mode: RtcMode> Rtc<Mode> {
         self.into_mode()
     }
 
+    /// Reconfigures the peripheral for 16-bit counter mode, with no prescaler
+    /// and the largest period.
+    pub fn into_count16_mode(mut self) -> Rtc<Count16Mode> {
+        self.enable(false);
+        self.sync();
+        self.mode1_ctrla().modify(|_, w| {
+            w.mode().count16();
+            // SAFETY: The value is one of the prescaler settings
+            unsafe { w.prescaler().bits(PRESCALER_A::DIV1.into()) }
+        });
+        self.sync();
+
+        // enable count sync on SAMx5x
+        #[cfg(feature = "min-samd51g")]
+        {
+            self.mode1_ctrla().modify(|_, w| {
+                w.countsync().set_bit() // synchronize the COUNT register
+            });
+
+            self.sync();
+        }
+
+        self.mode1().per.write(|w| unsafe { w.per().bits(0xFFFF) });
+        self.sync();
+        self.enable(true);
+        self.into_mode()
+    }
+
     /// Reconfigures the peripheral for clock/calendar mode. Requires the source
     /// clock to be running at 1024 Hz.
     pub fn into_clock_mode(mut self) -> Rtc<ClockMode> {
@@ -192,9 +319,28 @@ impl<Mode: RtcMode> Rtc<Mode> {
         self.into_mode()
     }
 
+    /// Applies a digital frequency correction, in steps of approximately
+    /// 1 ppm, to compensate for the error of the RTC crystal.
+    ///
+    /// The correction adds or skips a single prescaler count `|ppm|` times
+    /// over 976 periods of 1024 RTC clock cycles, which works out to
+    /// `1e6 / (1024 * 976)`, or 1.0006 ppm per step. A positive value slows
+    /// the RTC down, correcting a crystal that runs fast; a negative value
+    /// speeds it up. The range is limited to ±127 ppm, so -128 is applied as
+    /// -127.
+    ///
+    /// The correction only takes effect while the prescaler divides by more
+    /// than 1, e.g. in clock/calendar mode.
+    pub fn set_frequency_correction(&mut self, ppm: i8) {
+        let bits = freqcorr_bits(ppm);
+        self.sync();
+        self.mode0().freqcorr.write(|w| unsafe { w.bits(bits) });
+        self.sync();
+    }
+
     /// Releases the RTC resource
     pub fn free(self) -> RTC {
-        self.rtc
+        self.rtc.free()
     }
 }
 
@@ -205,7 +351,7 @@ impl Rtc<Count32Mode> {
         pm.apbamask.modify(|_, w| w.rtc_().set_bit());
 
         let mut new_rtc = Self {
-            rtc,
+            rtc: Periph::new(rtc),
             rtc_clock_freq,
             _mode: PhantomData,
         };
@@ -227,6 +373,31 @@ impl Rtc<Count32Mode> {
         self.mode0().count.read().bits()
     }
 
+    /// Returns the internal counter value, guaranteed to be coherent.
+    ///
+    /// The counter runs in the RTC clock domain, asynchronous to the CPU, and
+    /// a read which races with an increment can return a transient value,
+    /// off by much more than one count. This requests a synchronized read
+    /// first: a single read request (`READREQ.RREQ`) on SAMD11/21, or
+    /// waiting for `SYNCBUSY.COUNT` on SAMx5x, whose count reads are
+    /// synchronized by `CTRLA.COUNTSYNC`. The counter is then read until two
+    /// consecutive reads agree, so the RTC clock must be much slower than the
+    /// CPU, as it normally is.
+    pub fn count_stable(&mut self) -> u32 {
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        {
+            self.mode0()
+                .readreq
+                .write(|w| w.rreq().set_bit().rcont().clear_bit());
+            self.sync();
+        }
+        #[cfg(feature = "min-samd51g")]
+        while self.mode0().syncbusy.read().count().bit_is_set() {}
+
+        let count = &self.mode0().count;
+        read_stable(|| count.read().bits())
+    }
+
     /// Sets the internal counter value.
     #[inline]
     pub fn set_count32(&mut self, count: u32) {
@@ -242,6 +413,35 @@ impl Rtc<Count32Mode> {
         self.enable(true);
     }
 
+    /// Emits the periodic event `n` (`EVCTRL.PEREOn`), at a frequency of
+    /// `rtc_clock_freq / 2^(n + 3)`.
+    ///
+    /// Routed through EVSYS, the event can start other peripherals, like an
+    /// ADC conversion, while the CPU sleeps. It is generated from the
+    /// prescaler, so it runs regardless of the counter period.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than 7.
+    pub fn enable_periodic_event(&mut self, n: u8) {
+        assert!(n < 8, "invalid RTC periodic event {}", n);
+        // EVCTRL is enable-protected
+        self.enable(false);
+        self.mode0()
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(r.bits() | 1 << n) });
+        self.enable(true);
+    }
+
+    /// Stops emitting the periodic event `n`.
+    pub fn disable_periodic_event(&mut self, n: u8) {
+        self.enable(false);
+        self.mode0()
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << n)) });
+        self.enable(true);
+    }
+
     /// This resets the internal counter and sets the prescaler to match the
     /// provided timeout. You should configure the prescaler using the longest
     /// timeout you plan to measure.
@@ -273,6 +473,149 @@ impl Rtc<Count32Mode> {
     }
 }
 
+impl Rtc<Count16Mode> {
+    /// Configures the RTC in 16-bit counter mode with no prescaler, the
+    /// largest period, and the counter initialized to zero.
+    pub fn count16_mode(rtc: RTC, rtc_clock_freq: Hertz, pm: &mut PM) -> Self {
+        Rtc::count32_mode(rtc, rtc_clock_freq, pm).into_count16_mode()
+    }
+
+    /// Sets the prescaler, which divides the RTC clock into counter ticks.
+    /// The counter keeps its value.
+    pub fn set_prescaler(&mut self, divider: PRESCALER_A) {
+        // The prescaler is enable-protected
+        self.enable(false);
+        self.mode1_ctrla()
+            .modify(|_, w| unsafe { w.prescaler().bits(divider.into()) });
+        self.enable(true);
+    }
+
+    /// Returns the frequency of the counter ticks, i.e. the RTC clock
+    /// divided by the prescaler.
+    pub fn tick_freq(&mut self) -> Hertz {
+        let prescaler = self.mode1_ctrla().read().prescaler().bits();
+        Hertz(self.rtc_clock_freq.0 / prescaler_factor(prescaler))
+    }
+
+    /// Sets the period: the counter counts from zero to `period`, then wraps
+    /// around and raises the overflow flag, every `period + 1` ticks.
+    pub fn set_period(&mut self, period: u16) {
+        self.sync();
+        self.mode1().per.write(|w| unsafe { w.per().bits(period) });
+        self.sync();
+    }
+
+    /// Returns the period.
+    pub fn period(&mut self) -> u16 {
+        self.mode1().per.read().per().bits()
+    }
+
+    /// Sets compare value `n`, matched once per period if it isn't greater
+    /// than the period.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than 1.
+    pub fn set_compare(&mut self, n: usize, value: u16) {
+        assert!(n < 2, "invalid RTC compare {}", n);
+        self.sync();
+        self.mode1().comp[n].write(|w| unsafe { w.comp().bits(value) });
+        self.sync();
+    }
+
+    /// Returns the internal counter value, guaranteed to be coherent.
+    ///
+    /// Like [`Rtc::count_stable`](Rtc::<Count32Mode>::count_stable) in
+    /// 32-bit counter mode, this requests a synchronized read first, then
+    /// reads the counter until two consecutive reads agree.
+    pub fn count_stable(&mut self) -> u16 {
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        {
+            self.mode1()
+                .readreq
+                .write(|w| w.rreq().set_bit().rcont().clear_bit());
+            self.sync();
+        }
+        #[cfg(feature = "min-samd51g")]
+        while self.mode1().syncbusy.read().count().bit_is_set() {}
+
+        let count = &self.mode1().count;
+        read_stable(|| count.read().bits() as u32) as u16
+    }
+
+    /// Sets the internal counter value.
+    pub fn set_count16(&mut self, count: u16) {
+        self.sync();
+        self.enable(false);
+        self.sync();
+        self.mode1()
+            .count
+            .write(|w| unsafe { w.count().bits(count) });
+        self.sync();
+        self.enable(true);
+    }
+
+    /// Enables the interrupt of `event`. The `RTC` interrupt must also be
+    /// unmasked in the NVIC.
+    pub fn enable_event_interrupt(&mut self, event: Count16Event) {
+        self.mode1().intenset.write(|w| match event {
+            Count16Event::Overflow => w.ovf().set_bit(),
+            Count16Event::Compare0 => w.cmp0().set_bit(),
+            Count16Event::Compare1 => w.cmp1().set_bit(),
+        });
+    }
+
+    /// Disables the interrupt of `event`.
+    pub fn disable_event_interrupt(&mut self, event: Count16Event) {
+        self.mode1().intenclr.write(|w| match event {
+            Count16Event::Overflow => w.ovf().set_bit(),
+            Count16Event::Compare0 => w.cmp0().set_bit(),
+            Count16Event::Compare1 => w.cmp1().set_bit(),
+        });
+    }
+
+    /// Returns whether the flag of `event` is set, and clears it.
+    pub fn take_event_flag(&mut self, event: Count16Event) -> bool {
+        let intflag = self.mode1().intflag.read();
+        let set = match event {
+            Count16Event::Overflow => intflag.ovf().bit_is_set(),
+            Count16Event::Compare0 => intflag.cmp0().bit_is_set(),
+            Count16Event::Compare1 => intflag.cmp1().bit_is_set(),
+        };
+        if set {
+            // Writing a 1 clears the flag
+            self.mode1().intflag.write(|w| match event {
+                Count16Event::Overflow => w.ovf().set_bit(),
+                Count16Event::Compare0 => w.cmp0().set_bit(),
+                Count16Event::Compare1 => w.cmp1().set_bit(),
+            });
+        }
+        set
+    }
+
+    /// Emits an event on EVSYS for `event`, e.g. to start an ADC conversion
+    /// on each overflow while the CPU sleeps.
+    pub fn enable_event_output(&mut self, event: Count16Event) {
+        self.set_event_output(event, true);
+    }
+
+    /// Stops emitting the event of `event`.
+    pub fn disable_event_output(&mut self, event: Count16Event) {
+        self.set_event_output(event, false);
+    }
+
+    fn set_event_output(&mut self, event: Count16Event, enable: bool) {
+        // EVCTRL is enable-protected
+        self.enable(false);
+        self.mode1().evctrl.modify(|_, w| match event {
+            Count16Event::Overflow => w.ovfeo().bit(enable),
+            Count16Event::Compare0 => w.cmpeo0().bit(enable),
+            Count16Event::Compare1 => w.cmpeo1().bit(enable),
+        });
+        self.enable(true);
+    }
+}
+
 impl Rtc<ClockMode> {
     pub fn clock_mode(rtc: RTC, rtc_clock_freq: Hertz, pm: &mut PM) -> Self {
         Rtc::count32_mode(rtc, rtc_clock_freq, pm).into_clock_mode()
@@ -307,6 +650,166 @@ impl Rtc<ClockMode> {
         });
         self.sync();
     }
+
+    /// Sets alarm 0 to `time`. Only the fields selected by `mask` are
+    /// compared against the clock/calendar.
+    pub fn set_alarm(&mut self, time: Datetime, mask: AlarmMask) {
+        self.sync();
+        self.mode2().alarm0.write(|w| unsafe {
+            w.second()
+                .bits(time.seconds)
+                .minute()
+                .bits(time.minutes)
+                .hour()
+                .bits(time.hours)
+                .day()
+                .bits(time.day)
+                .month()
+                .bits(time.month)
+                .year()
+                .bits(time.year)
+        });
+        self.sync();
+        self.mode2().mask0.write(|w| w.sel().variant(mask));
+        self.sync();
+    }
+
+    /// Sets alarm 0 to `time` and returns a future which completes when the
+    /// alarm fires. See the [module-level documentation](self) for the
+    /// required interrupt handler.
+    pub fn wait_alarm(&mut self, time: Datetime, mask: AlarmMask) -> AlarmFuture<'_> {
+        self.mode2().intenclr.write(|w| w.alarm0().set_bit());
+        self.set_alarm(time, mask);
+        // Writing a 1 clears the flag
+        self.mode2().intflag.write(|w| w.alarm0().set_bit());
+        AlarmFuture { rtc: self }
+    }
+}
+
+/// Division factor of a `PRESCALER` field value
+#[cfg(feature = "min-samd51g")]
+fn prescaler_factor(bits: u8) -> u32 {
+    // Zero turns the counter off, one is DIV1
+    1 << (bits.clamp(1, 11) - 1)
+}
+
+/// Division factor of a `PRESCALER` field value
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+fn prescaler_factor(bits: u8) -> u32 {
+    1 << bits.min(10)
+}
+
+/// Prescaler and period of the 16-bit counter for `timeout`, with a period
+/// of at least one, i.e. two ticks
+fn period_params<T: Into<Nanoseconds>>(timeout: T, rtc_clock_freq: Hertz) -> (PRESCALER_A, u16) {
+    let params = TimerParams::new_us(timeout, rtc_clock_freq.0);
+    let period = params.cycles.saturating_sub(1).clamp(1, 0xFFFF) as u16;
+    (params.divider, period)
+}
+
+/// Reads a value until two consecutive reads agree, and returns it
+fn read_stable(mut read: impl FnMut() -> u32) -> u32 {
+    let mut last = read();
+    loop {
+        let value = read();
+        if value == last {
+            return value;
+        }
+        last = value;
+    }
+}
+
+/// Encodes a frequency correction as a `FREQCORR` register value, with the
+/// magnitude in `VALUE` and `SIGN` set for negative corrections.
+fn freqcorr_bits(ppm: i8) -> u8 {
+    let value = (ppm as i16).abs().min(127) as u8;
+    if ppm < 0 {
+        0x80 | value
+    } else {
+        value
+    }
+}
+
+/// Future returned by [`Rtc::wait_alarm`], completing when alarm 0 fires
+pub struct AlarmFuture<'a> {
+    rtc: &'a mut Rtc<ClockMode>,
+}
+
+impl Future for AlarmFuture<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mode2 = self.rtc.mode2();
+        interrupt::free(|cs| poll_alarm(mode2, ALARM_WAKER.borrow(cs), cx.waker()))
+    }
+}
+
+/// The alarm 0 flag and interrupt, as used by [`AlarmFuture`] and
+/// [`on_alarm_interrupt`]
+trait Alarm0 {
+    /// Returns whether alarm 0 fired, and clears its flag if it did
+    fn take_flag(&self) -> bool;
+    /// Enables or disables the alarm 0 interrupt
+    fn set_interrupt(&self, enabled: bool);
+}
+
+impl Alarm0 for MODE2 {
+    fn take_flag(&self) -> bool {
+        let fired = self.intflag.read().alarm0().bit_is_set();
+        if fired {
+            // Writing a 1 clears the flag
+            self.intflag.write(|w| w.alarm0().set_bit());
+        }
+        fired
+    }
+
+    fn set_interrupt(&self, enabled: bool) {
+        if enabled {
+            self.intenset.write(|w| w.alarm0().set_bit());
+        } else {
+            self.intenclr.write(|w| w.alarm0().set_bit());
+        }
+    }
+}
+
+/// Completes if the alarm fired, or stores `waker` in `slot` and enables the
+/// alarm interrupt
+fn poll_alarm(alarm: &impl Alarm0, slot: &RefCell<Option<Waker>>, waker: &Waker) -> Poll<()> {
+    if alarm.take_flag() {
+        return Poll::Ready(());
+    }
+    slot.replace(Some(waker.clone()));
+    // If the alarm fired since the flag was checked, enabling the interrupt
+    // pends it right away, so the wakeup isn't lost
+    alarm.set_interrupt(true);
+    Poll::Pending
+}
+
+/// Disables the alarm interrupt, and takes the waker stored in `slot`
+fn disarm_alarm(alarm: &impl Alarm0, slot: &RefCell<Option<Waker>>) -> Option<Waker> {
+    alarm.set_interrupt(false);
+    slot.borrow_mut().take()
+}
+
+impl Drop for AlarmFuture<'_> {
+    fn drop(&mut self) {
+        self.rtc.mode2().intenclr.write(|w| w.alarm0().set_bit());
+    }
+}
+
+/// Wakes the task awaiting an [`AlarmFuture`]. Call this from the `RTC`
+/// interrupt handler.
+///
+/// The alarm interrupt is disabled rather than its flag cleared, so that the
+/// future can still observe the flag when it is polled again.
+pub fn on_alarm_interrupt() {
+    // SAFETY: Only INTENCLR is written, which doesn't affect any other RTC
+    // configuration
+    let rtc = unsafe { &*RTC::ptr() };
+    let waker = interrupt::free(|cs| disarm_alarm(rtc.mode2(), ALARM_WAKER.borrow(cs)));
+    if let Some(waker) = waker {
+        waker.wake();
+    }
 }
 
 // --- Timer / Counter Functionality
@@ -330,8 +833,49 @@ impl CountDown for Rtc<Count32Mode> {
 
     fn wait(&mut self) -> nb::Result<(), Void> {
         if self.mode0().intflag.read().cmp0().bit_is_set() {
-            // Writing a 1 clears the flag
-            self.mode0().intflag.modify(|_, w| w.cmp0().set_bit());
+            // Writing a 1 clears the flag. A read-modify-write would clear the
+            // other flags as well.
+            self.mode0().intflag.write(|w| w.cmp0().set_bit());
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl Periodic for Rtc<Count16Mode> {}
+impl CountDown for Rtc<Count16Mode> {
+    type Time = Nanoseconds;
+
+    /// Restarts the counter from zero, with the prescaler and the period
+    /// which come closest to `timeout`.
+    ///
+    /// The period is a whole number of prescaled ticks, rounded down, and at
+    /// least two ticks: a shorter `timeout`, e.g. a single tick, is lengthened
+    /// to two ticks. The longest `timeout`, about 4.3 s, always fits in the
+    /// counter, with a prescaler of 4 from 32.768 kHz. The period achieved is
+    /// [`period`](Rtc::<Count16Mode>::period) + 1 ticks at
+    /// [`tick_freq`](Rtc::<Count16Mode>::tick_freq).
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Self::Time>,
+    {
+        let (divider, period) = period_params(timeout, self.rtc_clock_freq);
+
+        self.sync();
+        self.enable(false);
+        self.mode1_ctrla()
+            .modify(|_, w| unsafe { w.prescaler().bits(divider.into()) });
+        self.mode1().count.write(|w| unsafe { w.count().bits(0) });
+        self.mode1().per.write(|w| unsafe { w.per().bits(period) });
+        self.sync();
+        // Writing a 1 clears the flag
+        self.mode1().intflag.write(|w| w.ovf().set_bit());
+        self.enable(true);
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.take_event_flag(Count16Event::Overflow) {
             Ok(())
         } else {
             Err(nb::Error::WouldBlock)
@@ -339,6 +883,19 @@ impl CountDown for Rtc<Count32Mode> {
     }
 }
 
+impl InterruptDrivenTimer for Rtc<Count16Mode> {
+    /// Enable the overflow interrupt, raised at the end of each period.
+    /// This doesn't configure the interrupt controller.
+    fn enable_interrupt(&mut self) {
+        self.enable_event_interrupt(Count16Event::Overflow);
+    }
+
+    /// Disables the overflow interrupt.
+    fn disable_interrupt(&mut self) {
+        self.disable_event_interrupt(Count16Event::Overflow);
+    }
+}
+
 impl InterruptDrivenTimer for Rtc<Count32Mode> {
     /// Enable the interrupt generation for this hardware timer.
     /// This method only sets the clock configuration to trigger
@@ -411,3 +968,316 @@ impl TimerParams {
         TimerParams { divider, cycles }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::periph::MockPeriph;
+    use crate::target_device::rtc::RegisterBlock;
+    use core::cell::Cell;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable};
+
+    #[test]
+    fn freqcorr_encoding() {
+        assert_eq!(freqcorr_bits(0), 0x00);
+        assert_eq!(freqcorr_bits(20), 0x14);
+        assert_eq!(freqcorr_bits(127), 0x7F);
+        assert_eq!(freqcorr_bits(-20), 0x94);
+        assert_eq!(freqcorr_bits(-127), 0xFF);
+        assert_eq!(freqcorr_bits(-128), 0xFF);
+    }
+
+    /// Number of wakes of the tasks woken by `counting_waker`
+    static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+    /// A waker standing in for the executor, which counts its wakes
+    fn counting_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        fn wake(_: *const ()) {
+            WAKES.fetch_add(1, Ordering::Relaxed);
+        }
+        fn drop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+        // SAFETY: The vtable ignores the data pointer
+        unsafe { Waker::from_raw(clone(core::ptr::null())) }
+    }
+
+    /// The alarm 0 flag and interrupt enable of an RTC
+    #[derive(Default)]
+    struct MockAlarm {
+        flag: Cell<bool>,
+        interrupt: Cell<bool>,
+    }
+
+    impl Alarm0 for MockAlarm {
+        fn take_flag(&self) -> bool {
+            self.flag.replace(false)
+        }
+
+        fn set_interrupt(&self, enabled: bool) {
+            self.interrupt.set(enabled);
+        }
+    }
+
+    #[test]
+    fn alarm_future_completes_and_rearms() {
+        let alarm = MockAlarm::default();
+        let slot = RefCell::new(None);
+        let waker = counting_waker();
+
+        // The alarm hasn't fired: the task waits for the interrupt
+        assert_eq!(poll_alarm(&alarm, &slot, &waker), Poll::Pending);
+        assert!(alarm.interrupt.get());
+        assert!(slot.borrow().is_some());
+
+        // It fires: the handler disables the interrupt and wakes the task,
+        // which completes, clearing the flag
+        alarm.flag.set(true);
+        disarm_alarm(&alarm, &slot).unwrap().wake();
+        assert_eq!(WAKES.load(Ordering::Relaxed), 1);
+        assert!(!alarm.interrupt.get());
+        assert_eq!(poll_alarm(&alarm, &slot, &waker), Poll::Ready(()));
+        assert!(!alarm.flag.get());
+        // A spurious interrupt has no task to wake
+        assert!(disarm_alarm(&alarm, &slot).is_none());
+
+        // The next alarm arms the interrupt again
+        assert_eq!(poll_alarm(&alarm, &slot, &waker), Poll::Pending);
+        assert!(alarm.interrupt.get());
+        alarm.flag.set(true);
+        disarm_alarm(&alarm, &slot).unwrap().wake();
+        assert_eq!(WAKES.load(Ordering::Relaxed), 2);
+        assert_eq!(poll_alarm(&alarm, &slot, &waker), Poll::Ready(()));
+    }
+
+    #[test]
+    fn count16_period_fits_the_timeout() {
+        let freq = Hertz(32_768);
+        assert_eq!(
+            period_params(Nanoseconds(1_000_000_000), freq),
+            (PRESCALER_A::DIV1, 32_767)
+        );
+        // 131 072 ticks need a prescaler of 4 to fit in 16 bits
+        assert_eq!(
+            period_params(Nanoseconds(4_000_000_000), freq),
+            (PRESCALER_A::DIV4, 32_767)
+        );
+        // Shorter than two ticks, lengthened to two ticks
+        assert_eq!(period_params(Nanoseconds(0), freq), (PRESCALER_A::DIV1, 1));
+        assert_eq!(
+            period_params(Nanoseconds(30_518), freq),
+            (PRESCALER_A::DIV1, 1)
+        );
+        // The longest timeout, 140 737 ticks, fits with a prescaler of 4
+        assert_eq!(
+            period_params(Nanoseconds(u32::MAX), freq),
+            (PRESCALER_A::DIV4, 35_183)
+        );
+        assert_eq!(prescaler_factor(u8::from(PRESCALER_A::DIV1)), 1);
+        assert_eq!(prescaler_factor(u8::from(PRESCALER_A::DIV8)), 8);
+        assert_eq!(prescaler_factor(u8::from(PRESCALER_A::DIV1024)), 1024);
+    }
+
+    #[test]
+    fn stable_count_skips_transient_reads() {
+        // A read racing with the increment from 0xFFFF to 0x1_0000 sees the
+        // upper half already incremented, but not the lower one yet
+        let mut reads = [0x1_FFFF, 0x1_0000, 0x1_0000].iter();
+        assert_eq!(read_stable(|| *reads.next().unwrap()), 0x1_0000);
+        // An increment between the first reads
+        let mut reads = [41, 42, 42].iter();
+        assert_eq!(read_stable(|| *reads.next().unwrap()), 42);
+        let mut reads = [7, 7].iter();
+        assert_eq!(read_stable(|| *reads.next().unwrap()), 7);
+    }
+
+    /// An RTC in `Mode` at 32.768 kHz, and the mock of its registers
+    fn mock_rtc<Mode: RtcMode>() -> (Rtc<Mode>, &'static MockPeriph<RegisterBlock>) {
+        let mock = MockPeriph::new();
+        // SAFETY: The registers of the handle are never accessed
+        let rtc = unsafe { crate::target_device::Peripherals::steal() }.RTC;
+        (Rtc::create(Periph::mock(rtc, mock), Hertz(32_768)), mock)
+    }
+
+    fn mode0_ctrla(rtc: &RegisterBlock) -> &MODE0_CTRLA {
+        #[cfg(feature = "min-samd51g")]
+        return &rtc.mode0().ctrla;
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        return &rtc.mode0().ctrl;
+    }
+
+    fn mode1_ctrla(rtc: &RegisterBlock) -> &MODE1_CTRLA {
+        #[cfg(feature = "min-samd51g")]
+        return &rtc.mode1().ctrla;
+        #[cfg(any(feature = "samd11", feature = "samd21"))]
+        return &rtc.mode1().ctrl;
+    }
+
+    #[test]
+    fn count16_mode_is_configured_while_disabled() {
+        let (rtc, mock) = mock_rtc::<Count32Mode>();
+        mode0_ctrla(mock.registers()).write(|w| w.enable().set_bit());
+        let _rtc = rtc.into_count16_mode();
+
+        let synced = mock.synced(|rtc| {
+            let ctrla = mode1_ctrla(rtc).read();
+            let per = rtc.mode1().per.read().per().bits();
+            (ctrla.enable().bit(), ctrla.mode().bits(), per)
+        });
+        // The RTC is disabled, then the mode and the period are each
+        // synchronized before it is enabled again
+        assert_eq!(
+            synced,
+            [
+                (false, 0, 0),
+                (false, 1, 0),
+                (false, 1, 0xFFFF),
+                (true, 1, 0xFFFF)
+            ]
+        );
+    }
+
+    #[test]
+    fn count32_is_written_while_disabled() {
+        let (mut rtc, mock) = mock_rtc::<Count32Mode>();
+        mode0_ctrla(mock.registers()).write(|w| w.enable().set_bit());
+
+        rtc.set_count32(42);
+        let synced = mock.synced(|rtc| {
+            let enabled = mode0_ctrla(rtc).read().enable().bit();
+            (enabled, rtc.mode0().count.read().count().bits())
+        });
+        assert_eq!(synced, [(true, 0), (false, 0), (false, 42), (true, 42)]);
+    }
+
+    #[test]
+    fn frequency_correction_is_synchronized() {
+        let (mut rtc, mock) = mock_rtc::<ClockMode>();
+
+        rtc.set_frequency_correction(-20);
+        let synced = mock.synced(|rtc| rtc.mode0().freqcorr.read().bits());
+        assert_eq!(synced, [0x00, 0x94]);
+    }
+
+    #[test]
+    fn count16_start_clears_the_overflow_of_the_new_period() {
+        let (mut rtc, mock) = mock_rtc::<Count16Mode>();
+        let mode1 = mock.registers().mode1();
+        mode1_ctrla(mock.registers()).write(|w| w.enable().set_bit());
+        mode1.count.write(|w| unsafe { w.count().bits(1234) });
+        mode1.intflag.write(|w| w.ovf().set_bit().cmp0().set_bit());
+
+        rtc.start(Nanoseconds(1_000_000_000));
+        // The mock keeps the value written to INTFLAG, i.e. the flags cleared
+        let synced = mock.synced(|rtc| {
+            let mode1 = rtc.mode1();
+            let intflag = mode1.intflag.read();
+            (
+                mode1_ctrla(rtc).read().enable().bit(),
+                mode1.count.read().count().bits(),
+                mode1.per.read().per().bits(),
+                intflag.ovf().bit(),
+                intflag.cmp0().bit(),
+            )
+        });
+        // The counter restarts while disabled, and only the overflow flag is
+        // cleared, once the new period is synchronized
+        assert_eq!(
+            synced,
+            [
+                (true, 1234, 0, true, true),
+                (false, 1234, 0, true, true),
+                (false, 0, 32_767, true, true),
+                (true, 0, 32_767, true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn count32_wait_only_clears_compare0() {
+        let (mut rtc, mock) = mock_rtc::<Count32Mode>();
+        let intflag = &mock.registers().mode0().intflag;
+
+        // Nothing is written while the compare flag is clear
+        intflag.write(|w| w.ovf().set_bit());
+        assert!(matches!(rtc.wait(), Err(nb::Error::WouldBlock)));
+        assert!(intflag.read().ovf().bit_is_set());
+
+        intflag.write(|w| w.cmp0().set_bit().ovf().set_bit());
+        assert!(matches!(rtc.wait(), Ok(())));
+        let cleared = intflag.read();
+        assert!(cleared.cmp0().bit_is_set());
+        assert!(cleared.ovf().bit_is_clear());
+    }
+
+    #[test]
+    fn count16_event_flags_are_cleared_one_at_a_time() {
+        let (mut rtc, mock) = mock_rtc::<Count16Mode>();
+        let intflag = &mock.registers().mode1().intflag;
+
+        intflag.write(|w| w.ovf().set_bit());
+        assert!(!rtc.take_event_flag(Count16Event::Compare1));
+        assert!(intflag.read().ovf().bit_is_set());
+
+        intflag.write(|w| w.ovf().set_bit().cmp0().set_bit().cmp1().set_bit());
+        assert!(rtc.take_event_flag(Count16Event::Compare1));
+        let cleared = intflag.read();
+        assert!(cleared.cmp1().bit_is_set());
+        assert!(cleared.ovf().bit_is_clear());
+        assert!(cleared.cmp0().bit_is_clear());
+    }
+
+    #[test]
+    fn alarm_flag_is_cleared_once_the_alarm_is_set() {
+        let (mut rtc, mock) = mock_rtc::<ClockMode>();
+        let mode2 = mock.registers().mode2();
+        mode2
+            .intflag
+            .write(|w| w.alarm0().set_bit().ovf().set_bit());
+        let time = Datetime {
+            seconds: 30,
+            minutes: 0,
+            hours: 12,
+            day: 1,
+            month: 1,
+            year: 21,
+        };
+
+        drop(rtc.wait_alarm(time, AlarmMask::SS));
+        // The alarm is synchronized before its mask
+        let synced = mock.synced(|rtc| {
+            let mode2 = rtc.mode2();
+            let second = mode2.alarm0.read().second().bits();
+            (second, mode2.mask0.read().sel().bits())
+        });
+        assert_eq!(synced, [(0, 0), (30, 0), (30, u8::from(AlarmMask::SS))]);
+        let cleared = mode2.intflag.read();
+        assert!(cleared.alarm0().bit_is_set());
+        assert!(cleared.ovf().bit_is_clear());
+    }
+
+    #[test]
+    fn alarm_interrupt_leaves_the_other_flags() {
+        let mock = MockPeriph::<RegisterBlock>::new();
+        let mode2 = mock.registers().mode2();
+
+        mode2.intflag.write(|w| w.ovf().set_bit());
+        assert!(!mode2.take_flag());
+        assert!(mode2.intflag.read().ovf().bit_is_set());
+
+        mode2
+            .intflag
+            .write(|w| w.alarm0().set_bit().ovf().set_bit());
+        assert!(mode2.take_flag());
+        let cleared = mode2.intflag.read();
+        assert!(cleared.alarm0().bit_is_set());
+        assert!(cleared.ovf().bit_is_clear());
+
+        mode2.set_interrupt(false);
+        assert!(mode2.intenclr.read().alarm0().bit_is_set());
+        assert!(mode2.intenset.read().alarm0().bit_is_clear());
+    }
+}
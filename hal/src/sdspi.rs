@@ -0,0 +1,462 @@
+//! # SD card over SPI
+//!
+//! [`SdSpi`] accesses an SD card connected to a SERCOM in SPI mode, for boards
+//! without an SDHC peripheral or where the card is not routed to it. It
+//! implements the same [`BlockDevice`] trait as the SDHC driver of the SAMD5x,
+//! so application code works with both.
+//!
+//! [`SdSpi::init`] brings the card out of its idle state:
+//!
+//! | Command  | Purpose                                                     |
+//! |----------|-------------------------------------------------------------|
+//! | `CMD0`   | Reset the card and enter SPI mode                           |
+//! | `CMD8`   | Check the supply voltage; only version 2.00 cards answer    |
+//! | `CMD59`  | Enable CRC checking by the card                             |
+//! | `ACMD41` | Start the card initialization, until it leaves idle state   |
+//! | `CMD58`  | Read the OCR, to find out the card capacity                 |
+//! | `CMD16`  | Set the block length of standard capacity cards             |
+//!
+//! Initialization runs at 400 kHz, then the SPI baud rate is raised to 25 MHz
+//! with [`Spi::reconfigure`]. The baud rate saturates at half the GCLK
+//! frequency of the SERCOM. Single blocks are then transferred with `CMD17`
+//! and `CMD24`, and multiple blocks with `CMD18` and `CMD25`.
+//!
+//! Commands are protected by a CRC7 and data blocks by a CRC16. The SAMD
+//! chips have no hardware for either, so both are computed in software. The
+//! card rejects written blocks with a wrong CRC, and [`Error::Crc`] is
+//! returned for read blocks with a wrong CRC.
+//!
+//! The [`Spi`] must be configured as a master in SPI mode 0, with 8-bit
+//! characters sent MSB first. The chip select is driven by the driver.
+//!
+//! ```
+//! let spi = spi::Config::new(&mclk, sercom, pads, freq).enable();
+//! let mut card = SdSpi::new(spi, cs);
+//! card.init()?;
+//! let mut block = [0u8; 512];
+//! card.read_block(0, &mut block)?;
+//! ```
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::spi::FullDuplex;
+use nb::block;
+
+use crate::block_device::{block_count, BlockDevice, BLOCK_SIZE};
+use crate::sercom::v2::spi::{Spi, ValidConfig};
+use crate::time::{Hertz, U32Ext};
+
+/// Argument of `CMD8`: 2.7-3.6 V supply, with the `0xAA` check pattern
+const CMD8_ARG: u32 = 0x1AA;
+
+/// `ACMD41` argument and OCR bit of high capacity cards
+const OCR_CCS: u32 = 1 << 30;
+
+/// Number of `ACMD41` attempts before giving up, about one second at 400 kHz
+const ACMD41_ATTEMPTS: u32 = 4000;
+
+/// Number of bytes polled for a data token or the end of a busy signal, about
+/// 300 ms at 25 MHz
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// Number of bytes polled for a command response
+const RESPONSE_BYTES: u32 = 8;
+
+/// R1 flag of a card in the idle state
+const R1_IDLE: u8 = 0x01;
+/// R1 flag of an illegal command
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+
+/// Token preceding a block read, or written with `CMD24`
+const TOKEN_START_BLOCK: u8 = 0xFE;
+/// Token preceding a block written with `CMD25`
+const TOKEN_START_MULTI: u8 = 0xFC;
+/// Token ending a `CMD25` transfer
+const TOKEN_STOP_TRAN: u8 = 0xFD;
+
+/// SD card over SPI error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    /// Error reported by the SPI bus
+    Spi(E),
+    /// Error flags of an R1 response
+    Card(u8),
+    /// The card does not support the supply voltage, or is a version 1.x
+    /// card, which is not supported
+    UnsupportedCard,
+    /// The card did not complete its initialization in time
+    InitTimeout,
+    /// The card was not initialized
+    NotInitialized,
+    /// The card did not respond, send data or leave its busy state in time
+    Timeout,
+    /// The card sent a data error token instead of a block
+    DataToken(u8),
+    /// A read block failed its CRC check
+    Crc,
+    /// The card rejected a written block, with its data response
+    WriteRejected(u8),
+    /// The buffer is not a whole number of blocks
+    InvalidBuffer,
+}
+
+/// SPI bus whose baud rate can be changed
+pub trait SetBaud {
+    /// Change the baud rate of the bus
+    fn set_baud(&mut self, baud: Hertz);
+}
+
+impl<C> SetBaud for Spi<C>
+where
+    C: ValidConfig,
+    Spi<C>: FullDuplex<u8>,
+{
+    #[inline]
+    fn set_baud(&mut self, baud: Hertz) {
+        self.reconfigure(|config| config.baud(baud));
+    }
+}
+
+/// CRC7 of a command, with polynomial `x^7 + x^3 + 1`
+pub(crate) fn crc7(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        for bit in (0..8).rev() {
+            let feedback = ((byte >> bit) ^ (crc >> 6)) & 1;
+            crc = (crc << 1) & 0x7F;
+            if feedback != 0 {
+                crc ^= 0x09;
+            }
+        }
+    }
+    crc
+}
+
+/// CRC16 of a data block, with polynomial `x^16 + x^12 + x^5 + 1`
+pub(crate) fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Frame of the command `index` with `arg`, ending with its CRC7
+pub(crate) fn command_frame(index: u8, arg: u32) -> [u8; 6] {
+    let arg = arg.to_be_bytes();
+    let mut frame = [0x40 | index, arg[0], arg[1], arg[2], arg[3], 0];
+    frame[5] = crc7(&frame[..5]) << 1 | 1;
+    frame
+}
+
+/// Check an R1 response, ignoring the idle flag
+fn check_r1<E>(r1: u8) -> Result<u8, Error<E>> {
+    if r1 & !R1_IDLE != 0 {
+        Err(Error::Card(r1))
+    } else {
+        Ok(r1)
+    }
+}
+
+/// Check the data response to a written block
+fn check_data_response<E>(response: u8) -> Result<(), Error<E>> {
+    if response & 0x1F == 0x05 {
+        Ok(())
+    } else {
+        Err(Error::WriteRejected(response))
+    }
+}
+
+/// An SD card connected to an SPI bus
+///
+/// See the [module-level documentation](self) for more details.
+pub struct SdSpi<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+    initialized: bool,
+    high_capacity: bool,
+}
+
+impl<SPI, CS> SdSpi<SPI, CS>
+where
+    SPI: FullDuplex<u8> + SetBaud,
+    CS: OutputPin<Error = Infallible>,
+{
+    /// Wrap an SPI bus and the chip select of the card. The card must then be
+    /// initialized with [`SdSpi::init`].
+    pub fn new(spi: SPI, mut cs: CS) -> Self {
+        let _ = cs.set_high();
+        SdSpi {
+            spi,
+            cs,
+            initialized: false,
+            high_capacity: false,
+        }
+    }
+
+    /// Bring the card to SPI mode and initialize it, then switch to 25 MHz
+    pub fn init(&mut self) -> Result<(), Error<SPI::Error>> {
+        self.initialized = false;
+        self.spi.set_baud(400.khz().into());
+
+        // At least 74 clock cycles with the chip select and data high
+        let _ = self.cs.set_high();
+        for _ in 0..10 {
+            self.transfer(0xFF)?;
+        }
+
+        self.transaction(|card| {
+            let r1 = card.command(0, 0)?;
+            if r1 != R1_IDLE {
+                return Err(Error::Card(r1));
+            }
+            let r1 = card.command(8, CMD8_ARG)?;
+            if r1 & R1_ILLEGAL_COMMAND != 0 {
+                return Err(Error::UnsupportedCard);
+            }
+            check_r1(r1)?;
+            if card.read_u32()? & 0xFFF != CMD8_ARG {
+                return Err(Error::UnsupportedCard);
+            }
+            check_r1(card.command(59, 1)?)?;
+
+            let mut attempts = 0;
+            while check_r1(card.app_command(41, OCR_CCS)?)? & R1_IDLE != 0 {
+                attempts += 1;
+                if attempts == ACMD41_ATTEMPTS {
+                    return Err(Error::InitTimeout);
+                }
+            }
+
+            check_r1(card.command(58, 0)?)?;
+            card.high_capacity = card.read_u32()? & OCR_CCS != 0;
+            if !card.high_capacity {
+                check_r1(card.command(16, BLOCK_SIZE as u32)?)?;
+            }
+            Ok(())
+        })?;
+
+        self.spi.set_baud(25.mhz().into());
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// Whether the card is a high capacity (SDHC/SDXC) card
+    pub fn is_high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+
+    /// Return the SPI bus and the chip select
+    pub fn free(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    /// Argument of the block commands, for the block `lba`
+    fn block_arg(&self, lba: u32) -> Result<u32, Error<SPI::Error>> {
+        if !self.initialized {
+            Err(Error::NotInitialized)
+        } else if self.high_capacity {
+            Ok(lba)
+        } else {
+            Ok(lba * BLOCK_SIZE as u32)
+        }
+    }
+
+    /// Run `f` with the card selected, then release the bus
+    fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error<SPI::Error>>,
+    ) -> Result<T, Error<SPI::Error>> {
+        let _ = self.cs.set_low();
+        let result = f(self);
+        let _ = self.cs.set_high();
+        // The card releases its data output on the next clock edge
+        self.transfer(0xFF)?;
+        result
+    }
+
+    /// Exchange a byte
+    fn transfer(&mut self, byte: u8) -> Result<u8, Error<SPI::Error>> {
+        block!(self.spi.send(byte)).map_err(Error::Spi)?;
+        block!(self.spi.read()).map_err(Error::Spi)
+    }
+
+    /// Read 4 bytes, most significant first
+    fn read_u32(&mut self) -> Result<u32, Error<SPI::Error>> {
+        let mut value = 0;
+        for _ in 0..4 {
+            value = value << 8 | self.transfer(0xFF)? as u32;
+        }
+        Ok(value)
+    }
+
+    /// Wait until the card leaves its busy state
+    fn wait_ready(&mut self) -> Result<(), Error<SPI::Error>> {
+        for _ in 0..POLL_LIMIT {
+            if self.transfer(0xFF)? == 0xFF {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send the command `index`, and return its R1 response
+    fn command(&mut self, index: u8, arg: u32) -> Result<u8, Error<SPI::Error>> {
+        // The card may still be sending data before CMD0 and CMD12
+        if index != 0 && index != 12 {
+            self.wait_ready()?;
+        }
+        for &byte in command_frame(index, arg).iter() {
+            self.transfer(byte)?;
+        }
+        if index == 12 {
+            // Stuff byte
+            self.transfer(0xFF)?;
+        }
+        for _ in 0..RESPONSE_BYTES {
+            let r1 = self.transfer(0xFF)?;
+            if r1 & 0x80 == 0 {
+                return Ok(r1);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send `CMD55` and the application command `index`
+    fn app_command(&mut self, index: u8, arg: u32) -> Result<u8, Error<SPI::Error>> {
+        check_r1(self.command(55, 0)?)?;
+        self.command(index, arg)
+    }
+
+    /// Receive a data block and check its CRC
+    fn read_data(&mut self, block: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        let mut polls = 0;
+        loop {
+            match self.transfer(0xFF)? {
+                TOKEN_START_BLOCK => break,
+                0xFF => (),
+                token => return Err(Error::DataToken(token)),
+            }
+            polls += 1;
+            if polls == POLL_LIMIT {
+                return Err(Error::Timeout);
+            }
+        }
+        for byte in block.iter_mut() {
+            *byte = self.transfer(0xFF)?;
+        }
+        let crc = (self.transfer(0xFF)? as u16) << 8 | self.transfer(0xFF)? as u16;
+        if crc != crc16(block) {
+            return Err(Error::Crc);
+        }
+        Ok(())
+    }
+
+    /// Send a data block after `token`, and wait until it is programmed
+    fn write_data(&mut self, token: u8, block: &[u8]) -> Result<(), Error<SPI::Error>> {
+        self.transfer(token)?;
+        for &byte in block {
+            self.transfer(byte)?;
+        }
+        for &byte in crc16(block).to_be_bytes().iter() {
+            self.transfer(byte)?;
+        }
+        check_data_response(self.transfer(0xFF)?)?;
+        self.wait_ready()
+    }
+}
+
+impl<SPI, CS> BlockDevice for SdSpi<SPI, CS>
+where
+    SPI: FullDuplex<u8> + SetBaud,
+    CS: OutputPin<Error = Infallible>,
+{
+    type Error = Error<SPI::Error>;
+
+    fn read_block(&mut self, lba: u32, block: &mut [u8; BLOCK_SIZE]) -> Result<(), Self::Error> {
+        let arg = self.block_arg(lba)?;
+        self.transaction(|card| {
+            check_r1(card.command(17, arg)?)?;
+            card.read_data(block)
+        })
+    }
+
+    fn write_block(&mut self, lba: u32, block: &[u8; BLOCK_SIZE]) -> Result<(), Self::Error> {
+        let arg = self.block_arg(lba)?;
+        self.transaction(|card| {
+            check_r1(card.command(24, arg)?)?;
+            card.write_data(TOKEN_START_BLOCK, block)
+        })
+    }
+
+    fn read_blocks(&mut self, lba: u32, blocks: &mut [u8]) -> Result<(), Self::Error> {
+        let arg = self.block_arg(lba)?;
+        block_count(blocks.len()).ok_or(Error::InvalidBuffer)?;
+        self.transaction(|card| {
+            check_r1(card.command(18, arg)?)?;
+            let result = blocks
+                .chunks_exact_mut(BLOCK_SIZE)
+                .try_for_each(|block| card.read_data(block));
+            // The transfer is stopped even after an error
+            let stop = card.command(12, 0);
+            result?;
+            check_r1(stop?)?;
+            card.wait_ready()
+        })
+    }
+
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), Self::Error> {
+        let arg = self.block_arg(lba)?;
+        block_count(blocks.len()).ok_or(Error::InvalidBuffer)?;
+        self.transaction(|card| {
+            check_r1(card.command(25, arg)?)?;
+            let result = blocks
+                .chunks_exact(BLOCK_SIZE)
+                .try_for_each(|block| card.write_data(TOKEN_START_MULTI, block));
+            // The transfer is stopped even after an error
+            card.transfer(TOKEN_STOP_TRAN)?;
+            card.transfer(0xFF)?;
+            let ready = card.wait_ready();
+            result?;
+            ready
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_crc() {
+        assert_eq!(command_frame(0, 0), [0x40, 0, 0, 0, 0, 0x95]);
+        assert_eq!(command_frame(8, CMD8_ARG), [0x48, 0, 0, 0x01, 0xAA, 0x87]);
+        assert_eq!(command_frame(17, 0)[5], 0x55);
+    }
+
+    #[test]
+    fn data_crc() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(&[0xFF; BLOCK_SIZE]), 0x7FA1);
+    }
+
+    #[test]
+    fn responses() {
+        assert_eq!(check_r1::<()>(R1_IDLE), Ok(R1_IDLE));
+        assert_eq!(check_r1::<()>(0x00), Ok(0x00));
+        assert_eq!(check_r1::<()>(0x05), Err(Error::Card(0x05)));
+        assert_eq!(check_data_response::<()>(0xE5), Ok(()));
+        assert_eq!(
+            check_data_response::<()>(0xEB),
+            Err(Error::WriteRejected(0xEB))
+        );
+    }
+}
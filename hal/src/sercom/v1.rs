@@ -9,6 +9,21 @@
 pub mod pads;
 pub use pads::*;
 
+pub mod i2c_slave;
+pub use i2c_slave::{I2CListener, SlaveEvent};
+
+pub mod i2c_scan;
+pub use i2c_scan::{AddressSet, ProbeStrategy, SCAN_RANGE};
+
+pub mod self_test;
+pub use self_test::{SelfTest, SelfTestError};
+
+pub mod uart_frame;
+pub use uart_frame::{CharSize, FrameFormat, Parity, StopBits};
+
+pub mod uart_idle;
+pub use uart_idle::IdleDetector;
+
 #[cfg(any(feature = "samd11", feature = "samd21"))]
 pub use crate::common::thumbv6m::sercom::v1::*;
 
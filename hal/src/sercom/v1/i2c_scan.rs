@@ -0,0 +1,159 @@
+//! # I2C bus scanning
+//!
+//! `I2CMasterN::scan` probes every address of a range, and returns the
+//! [`AddressSet`] of the devices which acknowledged. A probe addresses a
+//! device without transferring any data, with one of two [`ProbeStrategy`]s:
+//! some devices only acknowledge writes, others only reads.
+//!
+//! After an address NACK, the SERCOM keeps owning the bus, and holds SCL low
+//! until it is told what to do next. Every probe ends with a STOP, whatever
+//! its outcome, and the bus state is forced back to idle after a bus error,
+//! an arbitration loss or a timeout, so that the next probe can start. Don't
+//! scan a multi-master bus while another master may be active.
+//!
+//! `I2CMasterN::scan_verbose` also reports the outcome of every probe. Bus
+//! errors or arbitration losses at addresses with no device usually mean
+//! that the lines rise too slowly, i.e. that the pull-ups are too weak for
+//! the bus capacitance and speed.
+//!
+//! ```
+//! let found = i2c.scan(SCAN_RANGE, ProbeStrategy::Write);
+//! for addr in found.iter() {
+//!     // ...
+//! }
+//! i2c.scan_verbose(SCAN_RANGE, ProbeStrategy::Read, |addr, result| {
+//!     if let Err(e) = result {
+//!         log_probe_error(addr, e);
+//!     }
+//! });
+//! ```
+
+use core::ops::RangeInclusive;
+
+/// Largest 7-bit address
+const MAX_ADDRESS: u8 = 0x7F;
+
+/// The 7-bit addresses which are not reserved by the I2C specification
+pub const SCAN_RANGE: RangeInclusive<u8> = 0x08..=0x77;
+
+/// How a device is addressed by a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeStrategy {
+    /// Address the device for a write, and STOP right away
+    ///
+    /// This is the usual "quick write". A few devices take it as a command,
+    /// e.g. some EEPROMs latch a write protection bit.
+    Write,
+    /// Address the device for a read, receive one byte, NACK it and STOP
+    ///
+    /// This doesn't change the state of the device, but write-only devices
+    /// don't acknowledge it.
+    Read,
+}
+
+/// Set of 7-bit I2C addresses, returned by a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AddressSet(u128);
+
+impl AddressSet {
+    /// Create an empty set
+    pub const fn new() -> Self {
+        AddressSet(0)
+    }
+
+    /// Add `addr` to the set. Panics if it isn't a 7-bit address.
+    pub fn insert(&mut self, addr: u8) {
+        assert!(addr <= MAX_ADDRESS, "invalid 7-bit address {}", addr);
+        self.0 |= 1 << addr;
+    }
+
+    /// Returns whether the set contains `addr`
+    pub fn contains(&self, addr: u8) -> bool {
+        addr <= MAX_ADDRESS && self.0 & (1 << addr) != 0
+    }
+
+    /// Returns the number of addresses in the set
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Returns whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the addresses of the set, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        let set = *self;
+        (0..=MAX_ADDRESS).filter(move |&addr| set.contains(addr))
+    }
+}
+
+/// Probe the 7-bit addresses of `range`, pass the outcome of each probe to
+/// `report`, and return the addresses which were acknowledged
+pub(crate) fn scan<E>(
+    range: RangeInclusive<u8>,
+    mut probe: impl FnMut(u8) -> Result<(), E>,
+    mut report: impl FnMut(u8, Result<(), E>),
+) -> AddressSet {
+    let mut found = AddressSet::new();
+    let end = (*range.end()).min(MAX_ADDRESS);
+    for addr in *range.start()..=end {
+        let result = probe(addr);
+        if result.is_ok() {
+            found.insert(addr);
+        }
+        report(addr, result);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum ProbeError {
+        Nack,
+        Bus,
+    }
+
+    /// A bus with devices at 0x3C and 0x68, and a bus error at 0x50
+    fn probe(addr: u8) -> Result<(), ProbeError> {
+        match addr {
+            0x3C | 0x68 => Ok(()),
+            0x50 => Err(ProbeError::Bus),
+            _ => Err(ProbeError::Nack),
+        }
+    }
+
+    #[test]
+    fn scan_finds_acknowledged_addresses() {
+        let mut probed = 0;
+        let mut errors = AddressSet::new();
+        let found = scan(SCAN_RANGE, probe, |addr, result| {
+            probed += 1;
+            if result == Err(ProbeError::Bus) {
+                errors.insert(addr);
+            }
+        });
+        assert_eq!(probed, 0x70);
+        assert_eq!(found.len(), 2);
+        let mut iter = found.iter();
+        assert_eq!(iter.next(), Some(0x3C));
+        assert_eq!(iter.next(), Some(0x68));
+        assert_eq!(iter.next(), None);
+        assert!(errors.contains(0x50));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn scan_range_is_limited_to_7_bits() {
+        let mut probed = AddressSet::new();
+        let found = scan(0x60..=0xFF, probe, |addr, _| probed.insert(addr));
+        assert_eq!(probed.len(), 0x20);
+        assert!(found.contains(0x68));
+        assert!(!found.contains(0xE8));
+        assert!(scan(0x80..=0xFF, probe, |_, _| ()).is_empty());
+    }
+}
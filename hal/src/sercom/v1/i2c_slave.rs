@@ -0,0 +1,127 @@
+//! # I2C multi-master arbitration fallback
+//!
+//! When two controllers share an I2C bus, both can start a transfer at the
+//! same time. The one that loses arbitration gets
+//! [`I2CError::ArbitrationLost`], and the winner may be addressing it right
+//! away. A SERCOM can't be a master and a slave at the same time, so the loser
+//! has to switch to slave mode quickly to answer.
+//!
+//! [`into_listen_after_arbitration_loss`] switches an I2C master to slave mode
+//! on the same pads, and returns an [`I2CListener`]. Poll it for
+//! [`SlaveEvent`]s until the other master sends a stop condition, then switch
+//! back with [`I2CListener::into_master`]. The bus frequency is preserved.
+//!
+//! ```no_run
+//! match i2c.write(PEER_ADDR, &message) {
+//!     Err(I2CError::ArbitrationLost) => {
+//!         let mut listener = i2c.into_listen_after_arbitration_loss(OWN_ADDR);
+//!         loop {
+//!             match listener.poll() {
+//!                 Some(SlaveEvent::Received(byte)) => inbox.push(byte),
+//!                 Some(SlaveEvent::ByteRequested) => listener.respond(status),
+//!                 Some(SlaveEvent::Stop) => break,
+//!                 _ => (),
+//!             }
+//!         }
+//!         i2c = listener.into_master();
+//!     }
+//!     result => result?,
+//! }
+//! ```
+//!
+//! [`I2CError::ArbitrationLost`]: super::I2CError::ArbitrationLost
+//! [`into_listen_after_arbitration_loss`]: super::I2CMaster0::into_listen_after_arbitration_loss
+
+/// `CTRLA.MODE` value of an I2C slave
+pub(crate) const MODE_I2C_SLAVE: u32 = 0x4;
+/// `CTRLA.MODE` value of an I2C master
+pub(crate) const MODE_I2C_MASTER: u32 = 0x5;
+
+/// Slave command acknowledging an address or a received byte, or sending a
+/// byte written to `DATA`
+pub(crate) const SLAVE_CMD_CONTINUE: u8 = 0x3;
+/// Slave command completing a transfer, once the master NACKed the last byte
+pub(crate) const SLAVE_CMD_COMPLETE: u8 = 0x2;
+
+/// An I2C master temporarily operating as a slave
+///
+/// See the [module-level documentation](self) for more details.
+pub struct I2CListener<M> {
+    pub(crate) master: M,
+    pub(crate) baud: u8,
+}
+
+/// Event reported by [`I2CListener::poll`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlaveEvent {
+    /// Another master addressed us, to read from us if `read` is set. The
+    /// address was acknowledged.
+    AddressMatch { read: bool },
+    /// A byte was received and acknowledged
+    Received(u8),
+    /// The master reads the next byte, to be provided with
+    /// [`I2CListener::respond`]
+    ByteRequested,
+    /// The master sent a stop condition
+    Stop,
+    /// A bus error or a collision occurred
+    Error,
+}
+
+/// Slave interrupt flag to handle next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SlaveFlag {
+    Error,
+    Stop,
+    AddressMatch,
+    DataReady,
+}
+
+/// Select the slave `INTFLAG` flag to handle next, if any
+///
+/// A stop condition is handled before a new address match, so that the end of
+/// a transfer is reported before the next one begins.
+pub(crate) fn next_slave_flag(intflag: u8) -> Option<SlaveFlag> {
+    if intflag & 0x80 != 0 {
+        Some(SlaveFlag::Error)
+    } else if intflag & 0x01 != 0 {
+        Some(SlaveFlag::Stop)
+    } else if intflag & 0x02 != 0 {
+        Some(SlaveFlag::AddressMatch)
+    } else if intflag & 0x04 != 0 {
+        Some(SlaveFlag::DataReady)
+    } else {
+        None
+    }
+}
+
+/// Replace the `CTRLA.MODE` field, and clear `CTRLA.ENABLE`
+///
+/// The other fields, like the pinout and SDA hold time, are shared by the
+/// master and slave modes and are kept.
+pub(crate) fn ctrla_with_mode(ctrla: u32, mode: u32) -> u32 {
+    (ctrla & !(0x7 << 2) & !(1 << 1)) | (mode << 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_switch_keeps_shared_fields() {
+        // Enabled master with SDAHOLD = 2 and SCLSM set
+        let master = (1 << 27) | (2 << 20) | (MODE_I2C_MASTER << 2) | (1 << 1);
+        let slave = ctrla_with_mode(master, MODE_I2C_SLAVE);
+        assert_eq!(slave, (1 << 27) | (2 << 20) | (MODE_I2C_SLAVE << 2));
+        assert_eq!(ctrla_with_mode(slave, MODE_I2C_MASTER), master & !(1 << 1));
+    }
+
+    #[test]
+    fn slave_flags_in_order() {
+        assert_eq!(next_slave_flag(0), None);
+        assert_eq!(next_slave_flag(0x04), Some(SlaveFlag::DataReady));
+        assert_eq!(next_slave_flag(0x06), Some(SlaveFlag::AddressMatch));
+        assert_eq!(next_slave_flag(0x03), Some(SlaveFlag::Stop));
+        assert_eq!(next_slave_flag(0x83), Some(SlaveFlag::Error));
+    }
+}
@@ -0,0 +1,146 @@
+//! # SERCOM self-tests
+//!
+//! [`SelfTest`] checks a configured SERCOM without any external wiring, e.g.
+//! for a power-on self-test or on the production line. The SERCOM is
+//! temporarily reconfigured, and its previous configuration is restored
+//! afterwards, whether the test passes or not.
+//!
+//! - UART: the receiver is moved to the TX pad (`CTRLA.RXPO`), so that every
+//!   transmitted character is received back. This is the loop-back mode
+//!   described in the datasheet.
+//! - SPI: the data input is moved to the data output pad (`CTRLA.DIPO`), so
+//!   that every transmitted byte is shifted back in.
+//! - I2C: there is no loop-back, so the Hs-mode master code is addressed
+//!   instead. No device acknowledges it, so the test passes when the address
+//!   is sent without arbitration loss or bus error, and is not acknowledged.
+//!   This checks that both lines are pulled up and not held by another device.
+//!
+//! The loop-back goes through the pad, so the transmitted pattern is visible
+//! on the pin during the UART and SPI tests. Devices connected to the bus
+//! should ignore it, e.g. with their chip select deasserted.
+//!
+//! ```
+//! let mut uart = UART0::new(&clock, 115200.hz(), peripherals.SERCOM0, &mut mclk, (rx, tx));
+//! uart.run().expect("UART self-test failed");
+//! ```
+
+/// Pattern sent by the UART and SPI self-tests
+///
+/// It toggles every bit, and catches stuck bits and bit order errors.
+pub const PATTERN: [u8; 6] = [0x00, 0xFF, 0x55, 0xAA, 0x01, 0x80];
+
+/// 7-bit address of the I2C Hs-mode master code, which no device acknowledges
+pub(crate) const HS_MASTER_CODE: u8 = 0x04;
+
+/// Number of polls before a self-test times out, a few tens of milliseconds
+/// at the usual core clocks, enough for one character at 1200 baud
+const SPIN_LIMIT: u32 = 1_000_000;
+
+/// Error returned by [`SelfTest::run`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestError {
+    /// A byte was not transmitted or received in time
+    Timeout,
+    /// A byte was received back with another value
+    Mismatch { sent: u8, received: u8 },
+    /// The receiver overflowed
+    Overrun,
+    /// A UART frame or parity error occurred
+    Framing,
+    /// The I2C bus is busy, stuck, or a device answered the master code
+    Bus,
+}
+
+/// Self-test of a SERCOM peripheral
+///
+/// See the [module-level documentation](self) for more details.
+pub trait SelfTest {
+    /// Run the self-test, then restore the previous configuration
+    fn run(&mut self) -> Result<(), SelfTestError>;
+}
+
+/// Poll `ready` until it returns `true`, or time out
+pub(crate) fn wait(mut ready: impl FnMut() -> bool) -> Result<(), SelfTestError> {
+    for _ in 0..SPIN_LIMIT {
+        if ready() {
+            return Ok(());
+        }
+    }
+    Err(SelfTestError::Timeout)
+}
+
+/// Send every byte of [`PATTERN`] with `exchange`, and compare it with the
+/// byte received back
+pub(crate) fn exchange_pattern(
+    mut exchange: impl FnMut(u8) -> Result<u8, SelfTestError>,
+) -> Result<(), SelfTestError> {
+    for &sent in PATTERN.iter() {
+        let received = exchange(sent)?;
+        if received != sent {
+            return Err(SelfTestError::Mismatch { sent, received });
+        }
+    }
+    Ok(())
+}
+
+/// Pad of the UART transmitter for a `CTRLA.TXPO` value
+///
+/// Only the SAMD11/SAMD21 can transmit on pad 2, with `TXPO = 1`. This value
+/// is reserved on the SAMD5x.
+pub(crate) fn uart_tx_pad(txpo: u8) -> u8 {
+    if txpo == 1 {
+        2
+    } else {
+        0
+    }
+}
+
+/// Pad of the SPI data output for a `CTRLA.DOPO` value
+///
+/// Only the SAMD11/SAMD21 support `DOPO = 1` and `DOPO = 3`, which are
+/// reserved on the SAMD5x.
+pub(crate) fn spi_data_out_pad(dopo: u8) -> u8 {
+    match dopo {
+        1 => 2,
+        2 => 3,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_pads() {
+        assert_eq!(uart_tx_pad(0), 0);
+        assert_eq!(uart_tx_pad(1), 2);
+        assert_eq!(uart_tx_pad(2), 0);
+        assert_eq!(spi_data_out_pad(0), 0);
+        assert_eq!(spi_data_out_pad(1), 2);
+        assert_eq!(spi_data_out_pad(2), 3);
+        assert_eq!(spi_data_out_pad(3), 0);
+    }
+
+    #[test]
+    fn pattern_mismatch_is_reported() {
+        assert_eq!(exchange_pattern(Ok), Ok(()));
+        let stuck_bit = exchange_pattern(|b| Ok(b | 0x01));
+        assert_eq!(
+            stuck_bit,
+            Err(SelfTestError::Mismatch {
+                sent: 0x00,
+                received: 0x01
+            })
+        );
+        let mut polls = 0;
+        assert_eq!(
+            wait(|| {
+                polls += 1;
+                polls == 3
+            }),
+            Ok(())
+        );
+        assert_eq!(wait(|| false), Err(SelfTestError::Timeout));
+    }
+}
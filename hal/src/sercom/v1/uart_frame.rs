@@ -0,0 +1,175 @@
+//! # UART frame format
+//!
+//! The UARTs are configured for 8N1 frames by `UARTn::new`: eight data bits,
+//! no parity and one stop bit. [`FrameFormat`] selects any combination of
+//! five to eight data bits, even, odd or no parity, and one or two stop bits,
+//! and is applied with `UARTn::set_frame_format`. Legacy devices often use
+//! 7E1 or 7O2 frames:
+//!
+//! ```
+//! uart.set_frame_format(FrameFormat::new(CharSize::Seven, Parity::Even, StopBits::One));
+//! ```
+//!
+//! Characters smaller than eight bits are masked to their width when read,
+//! and still use one byte, both for reads and for DMA beats.
+
+/// Number of data bits in a character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharSize {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+/// Parity bit of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// Number of stop bits of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// Format of the UART frames
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFormat {
+    pub char_size: CharSize,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl FrameFormat {
+    /// Create a frame format
+    #[inline]
+    pub const fn new(char_size: CharSize, parity: Parity, stop_bits: StopBits) -> Self {
+        FrameFormat {
+            char_size,
+            parity,
+            stop_bits,
+        }
+    }
+
+    /// Number of bits in a frame, including the start bit
+    #[inline]
+    pub fn bits(&self) -> u8 {
+        let data = match self.char_size {
+            CharSize::Five => 5,
+            CharSize::Six => 6,
+            CharSize::Seven => 7,
+            CharSize::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Even | Parity::Odd => 1,
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        1 + data + parity + stop
+    }
+
+    /// Value of `CTRLB.CHSIZE`
+    #[inline]
+    pub(crate) fn chsize(&self) -> u8 {
+        match self.char_size {
+            CharSize::Eight => 0,
+            CharSize::Five => 5,
+            CharSize::Six => 6,
+            CharSize::Seven => 7,
+        }
+    }
+
+    /// Value of `CTRLA.FORM`: a USART frame, with or without parity
+    #[inline]
+    pub(crate) fn form(&self) -> u8 {
+        match self.parity {
+            Parity::None => 0,
+            Parity::Even | Parity::Odd => 1,
+        }
+    }
+
+    /// Value of `CTRLB.PMODE`, set for odd parity
+    #[inline]
+    pub(crate) fn pmode(&self) -> bool {
+        self.parity == Parity::Odd
+    }
+
+    /// Value of `CTRLB.SBMODE`, set for two stop bits
+    #[inline]
+    pub(crate) fn sbmode(&self) -> bool {
+        self.stop_bits == StopBits::Two
+    }
+}
+
+impl Default for FrameFormat {
+    /// 8N1
+    #[inline]
+    fn default() -> Self {
+        FrameFormat::new(CharSize::Eight, Parity::None, StopBits::One)
+    }
+}
+
+/// Mask of the data bits of a received character, for a `CTRLB.CHSIZE` value
+#[inline]
+pub(crate) fn data_mask(chsize: u8) -> u8 {
+    match chsize {
+        5 => 0x1F,
+        6 => 0x3F,
+        7 => 0x7F,
+        _ => 0xFF,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_encoding_of_every_format() {
+        let sizes = [
+            (CharSize::Five, 5, 0x1F),
+            (CharSize::Six, 6, 0x3F),
+            (CharSize::Seven, 7, 0x7F),
+            (CharSize::Eight, 0, 0xFF),
+        ];
+        let parities = [
+            (Parity::None, 0, false),
+            (Parity::Even, 1, false),
+            (Parity::Odd, 1, true),
+        ];
+        let stops = [(StopBits::One, false), (StopBits::Two, true)];
+        for &(char_size, chsize, mask) in sizes.iter() {
+            for &(parity, form, pmode) in parities.iter() {
+                for &(stop_bits, sbmode) in stops.iter() {
+                    let frame = FrameFormat::new(char_size, parity, stop_bits);
+                    assert_eq!(frame.chsize(), chsize);
+                    assert_eq!(frame.form(), form);
+                    assert_eq!(frame.pmode(), pmode);
+                    assert_eq!(frame.sbmode(), sbmode);
+                    assert_eq!(data_mask(frame.chsize()), mask);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frame_lengths() {
+        assert_eq!(FrameFormat::default().bits(), 10);
+        let frame = FrameFormat::new(CharSize::Seven, Parity::Even, StopBits::One);
+        assert_eq!(frame.bits(), 10);
+        let frame = FrameFormat::new(CharSize::Seven, Parity::Odd, StopBits::Two);
+        assert_eq!(frame.bits(), 11);
+        let frame = FrameFormat::new(CharSize::Five, Parity::None, StopBits::One);
+        assert_eq!(frame.bits(), 7);
+    }
+}
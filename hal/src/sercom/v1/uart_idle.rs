@@ -0,0 +1,207 @@
+//! # UART receiver idle detection
+//!
+//! Frame-based protocols like Modbus RTU delimit frames with a period of
+//! silence on the line, e.g. 3.5 character times. The SERCOM USART has no
+//! receiver timeout, so [`IdleDetector`] builds one from a timer. The timer is
+//! restarted on every received character, and the line is reported idle once
+//! it expires without any new character.
+//!
+//! Any [`CountDown`] timer counting in [`Nanoseconds`] can be used, like a
+//! [`TimerCounter`]. Restarting a [`TimerCounter`] reconfigures it, which
+//! takes a few synchronization delays, but that is short compared to a
+//! character time.
+//!
+//! The detector can be polled with the UART [`Flags`], or fed from interrupt
+//! handlers. In the latter case, call [`IdleDetector::char_received`] from the
+//! UART `RXC` interrupt and [`IdleDetector::timer_expired`] from the timer
+//! interrupt.
+//!
+//! ```
+//! // Modbus RTU, 9600 baud: 3.5 character times, rounded up to 4
+//! let mut idle = IdleDetector::new(tc3, 9600.hz(), 4);
+//! let mut frame = [0u8; 256];
+//! let mut len = 0;
+//! loop {
+//!     if idle.poll(uart.read_flags()) {
+//!         handle_frame(&frame[..len]);
+//!         len = 0;
+//!     }
+//!     if let Ok(byte) = uart.read() {
+//!         frame[len] = byte;
+//!         len += 1;
+//!     }
+//! }
+//! ```
+//!
+//! [`TimerCounter`]: crate::timer::TimerCounter
+
+use crate::hal::timer::CountDown;
+use crate::sercom::v1::uart::Flags;
+use crate::time::{Hertz, Nanoseconds};
+
+/// Number of bits in a character with the default UART frame format: one
+/// start bit, eight data bits and one stop bit
+const BITS_PER_CHAR: u64 = 10;
+
+/// Duration of `char_times` characters at the given baud rate, rounded up
+fn char_timeout(baud: Hertz, char_times: u8) -> Nanoseconds {
+    let bits = char_times as u64 * BITS_PER_CHAR;
+    let baud = (baud.0 as u64).max(1);
+    let ns = (bits * 1_000_000_000 + baud - 1) / baud;
+    Nanoseconds(ns.min(u32::MAX as u64) as u32)
+}
+
+/// Detect an idle UART receiver with a timer
+///
+/// See the [module-level documentation](self) for more details.
+pub struct IdleDetector<T> {
+    timer: T,
+    timeout: Nanoseconds,
+    receiving: bool,
+}
+
+impl<T> IdleDetector<T>
+where
+    T: CountDown<Time = Nanoseconds>,
+{
+    /// Create a detector reporting the line idle after `char_times`
+    /// characters without activity at the given baud rate
+    ///
+    /// The character time assumes the default frame format of one start bit,
+    /// eight data bits and one stop bit.
+    #[inline]
+    pub fn new(timer: T, baud: impl Into<Hertz>, char_times: u8) -> Self {
+        Self::with_timeout(timer, char_timeout(baud.into(), char_times))
+    }
+
+    /// Create a detector with an explicit timeout
+    ///
+    /// Modbus RTU, for instance, uses a fixed 1.75 ms timeout above
+    /// 19200 baud.
+    #[inline]
+    pub fn with_timeout(timer: T, timeout: impl Into<Nanoseconds>) -> Self {
+        IdleDetector {
+            timer,
+            timeout: timeout.into(),
+            receiving: false,
+        }
+    }
+
+    /// Return the idle timeout
+    #[inline]
+    pub fn timeout(&self) -> Nanoseconds {
+        self.timeout
+    }
+
+    /// Recompute the timeout after a change of the baud rate
+    #[inline]
+    pub fn set_baud(&mut self, baud: impl Into<Hertz>, char_times: u8) {
+        self.timeout = char_timeout(baud.into(), char_times);
+    }
+
+    /// Restart the timer, because a character was received
+    #[inline]
+    pub fn char_received(&mut self) {
+        self.timer.start(self.timeout);
+        self.receiving = true;
+    }
+
+    /// Check whether the timer expired since the last character
+    ///
+    /// Return `true` only once per burst of characters, when the line becomes
+    /// idle. Later timer periods are ignored until a new character arrives.
+    #[inline]
+    pub fn timer_expired(&mut self) -> bool {
+        if self.receiving && self.timer.wait().is_ok() {
+            self.receiving = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Poll the detector with the UART interrupt flags
+    ///
+    /// The timer is restarted if `RXS` or `RXC` is set, so this must be called
+    /// before reading the received character. Return `true` when the line
+    /// becomes idle, see [`IdleDetector::timer_expired`].
+    #[inline]
+    pub fn poll(&mut self, flags: Flags) -> bool {
+        if flags.intersects(Flags::RXC | Flags::RXS) {
+            self.char_received();
+            false
+        } else {
+            self.timer_expired()
+        }
+    }
+
+    /// Whether characters were received since the line was last idle
+    #[inline]
+    pub fn is_receiving(&self) -> bool {
+        self.receiving
+    }
+
+    /// Return the timer
+    #[inline]
+    pub fn free(self) -> T {
+        self.timer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use void::Void;
+
+    struct MockTimer {
+        starts: u32,
+        expired: bool,
+    }
+
+    impl CountDown for MockTimer {
+        type Time = Nanoseconds;
+
+        fn start<D: Into<Nanoseconds>>(&mut self, _: D) {
+            self.starts += 1;
+            self.expired = false;
+        }
+
+        fn wait(&mut self) -> nb::Result<(), Void> {
+            if self.expired {
+                Ok(())
+            } else {
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+
+    #[test]
+    fn timeout_in_char_times() {
+        assert_eq!(char_timeout(Hertz(9600), 4), Nanoseconds(4_166_667));
+        assert_eq!(char_timeout(Hertz(115_200), 1), Nanoseconds(86_806));
+        assert_eq!(char_timeout(Hertz(1), 255), Nanoseconds(u32::MAX));
+    }
+
+    #[test]
+    fn idle_reported_once_per_burst() {
+        let timer = MockTimer {
+            starts: 0,
+            expired: false,
+        };
+        let mut idle = IdleDetector::new(timer, Hertz(9600), 4);
+
+        // Nothing received yet, so an expired timer means nothing
+        idle.timer.expired = true;
+        assert!(!idle.poll(Flags::empty()));
+
+        assert!(!idle.poll(Flags::RXS));
+        assert!(!idle.poll(Flags::RXC));
+        assert_eq!(idle.timer.starts, 2);
+        assert!(!idle.poll(Flags::empty()));
+
+        idle.timer.expired = true;
+        assert!(idle.poll(Flags::empty()));
+        assert!(!idle.poll(Flags::empty()));
+        assert!(!idle.is_receiving());
+    }
+}
@@ -3,6 +3,44 @@
 //! This module provides a new API for the SERCOM peripherals. So far, only the
 //! [`pads`] and [`spi`] modules have been updated, but it is expected that the
 //! `uart`, and `i2c` modules will eventually receive updates as well.
+//!
+//! ## Interrupts
+//!
+//! On SAMD5x/E5x, each SERCOM has four interrupt lines, `SERCOMn_0`,
+//! `SERCOMn_1`, `SERCOMn_2` and `SERCOMn_OTHER`. The `INTFLAG` bits are routed
+//! to them in a fixed way, listed in [`INTERRUPT_LINE_FLAGS`]. Bit 0 goes to
+//! line 0, bit 1 to line 1, bit 2 to line 2, and all the remaining bits share
+//! line 3. On SAMD11/21, each SERCOM has a single interrupt line, `SERCOMn`.
+//!
+//! The lines of each SERCOM are available as [`Sercom::INTERRUPTS`] on
+//! SAMD5x/E5x and [`Sercom::INTERRUPT`] on SAMD11/21. The
+//! [`sercom_interrupt_handlers`] macro defines the handlers for every line of
+//! a SERCOM, and forwards them all to a single function. It takes the line
+//! index as an argument, which is always zero on SAMD11/21.
+//!
+//! ```
+//! fn on_sercom2(line: u8) {
+//!     // Handle the flags in INTERRUPT_LINE_FLAGS[line as usize]
+//! }
+//!
+//! sercom_interrupt_handlers!(2, on_sercom2);
+//! ```
+//!
+//! The `rt` feature of the PAC must be enabled to use the macro.
+//!
+//! ## Core clock
+//!
+//! [`Sercom::core_clock_freq`] reads the live frequency of the core clock of a
+//! SERCOM: the generator selected by its peripheral channel, then the source
+//! and divider of that generator. The frequency of the source must be known
+//! to the [`GenericClockController`], which is the case for the internal
+//! oscillators, and for the crystal oscillators configured through it.
+//!
+//! ```
+//! let freq = sercom.core_clock_freq(&mut clocks).unwrap();
+//! ```
+//!
+//! [`sercom_interrupt_handlers`]: crate::sercom_interrupt_handlers
 
 use core::ops::Deref;
 
@@ -10,6 +48,10 @@ use paste::paste;
 use seq_macro::seq;
 
 use crate::target_device as pac;
+use pac::Interrupt;
+
+#[cfg(all(feature = "unproven", feature = "dma"))]
+use crate::dmac::{channel::DmaPeripheral, TriggerSource};
 
 #[cfg(feature = "min-samd51g")]
 use pac::MCLK as APB_CLK_CTRL;
@@ -30,6 +72,8 @@ pub use crate::common::thumbv6m::sercom::v2::*;
 #[cfg(feature = "min-samd51g")]
 pub use crate::common::thumbv7em::sercom::v2::*;
 
+use crate::clock::{ClockId, GenericClockController};
+use crate::time::Hertz;
 use crate::typelevel::Sealed;
 
 pub mod pads;
@@ -43,8 +87,29 @@ pub mod spi_future;
 pub trait Sercom: Sealed + Deref<Target = sercom0::RegisterBlock> {
     /// SERCOM number
     const NUM: usize;
+    /// Interrupt lines of this SERCOM, `SERCOMn_0` to `SERCOMn_2` and
+    /// `SERCOMn_OTHER`
+    #[cfg(feature = "min-samd51g")]
+    const INTERRUPTS: [Interrupt; 4];
+    /// Interrupt line of this SERCOM
+    #[cfg(any(feature = "samd11", feature = "samd21"))]
+    const INTERRUPT: Interrupt;
+    /// Peripheral channel of the core clock of this SERCOM
+    const CORE_CLOCK: ClockId;
     /// Enable the corresponding APB clock
     fn enable_apb_clock(&mut self, ctrl: &APB_CLK_CTRL);
+    /// Disable the corresponding APB clock
+    fn disable_apb_clock(&mut self, ctrl: &APB_CLK_CTRL);
+    /// Live frequency of the core clock of this SERCOM, or `None` if it is
+    /// not running
+    ///
+    /// The frequency is read from the hardware, see
+    /// [`GenericClockController::live_pclk_freq`], so that a driver can
+    /// compute its baud rate without being given the frequency.
+    #[inline]
+    fn core_clock_freq(&self, clocks: &mut GenericClockController) -> Option<Hertz> {
+        clocks.live_pclk_freq(Self::CORE_CLOCK)
+    }
 }
 
 macro_rules! sercom {
@@ -56,10 +121,29 @@ macro_rules! sercom {
                 impl Sealed for Sercom#N {}
                 impl Sercom for Sercom#N {
                     const NUM: usize = N;
+                    #[cfg(feature = "min-samd51g")]
+                    const INTERRUPTS: [Interrupt; 4] = [
+                        Interrupt::[<SERCOM#N _0>],
+                        Interrupt::[<SERCOM#N _1>],
+                        Interrupt::[<SERCOM#N _2>],
+                        Interrupt::[<SERCOM#N _OTHER>],
+                    ];
+                    #[cfg(any(feature = "samd11", feature = "samd21"))]
+                    const INTERRUPT: Interrupt = Interrupt::SERCOM#N;
+                    const CORE_CLOCK: ClockId = ClockId::[<SERCOM#N _CORE>];
                     #[inline]
                     fn enable_apb_clock(&mut self, ctrl: &APB_CLK_CTRL) {
                         ctrl.$apbmask.modify(|_, w| w.[<sercom#N _>]().set_bit());
                     }
+                    #[inline]
+                    fn disable_apb_clock(&mut self, ctrl: &APB_CLK_CTRL) {
+                        ctrl.$apbmask.modify(|_, w| w.[<sercom#N _>]().clear_bit());
+                    }
+                }
+                #[cfg(all(feature = "unproven", feature = "dma"))]
+                impl DmaPeripheral for Sercom#N {
+                    const RX_TRIGGER: TriggerSource = TriggerSource::[<SERCOM#N _RX>];
+                    const TX_TRIGGER: TriggerSource = TriggerSource::[<SERCOM#N _TX>];
                 }
             }
         });
@@ -81,3 +165,121 @@ sercom!(apbbmask: (2, 3));
 sercom!(apbdmask: (4, 5));
 #[cfg(feature = "min-samd51n")]
 sercom!(apbdmask: (6, 7));
+
+//==============================================================================
+//  Interrupts
+//==============================================================================
+
+/// `INTFLAG` bits routed to each of the four interrupt lines of a SERCOM
+///
+/// This mapping is the same for every SERCOM and every mode.
+#[cfg(feature = "min-samd51g")]
+pub const INTERRUPT_LINE_FLAGS: [u8; 4] = [0x01, 0x02, 0x04, 0xF8];
+
+/// Return the index of the interrupt line that an `INTFLAG` bit is routed to
+///
+/// `flag` must have exactly one bit set.
+#[cfg(feature = "min-samd51g")]
+#[inline]
+pub const fn interrupt_line(flag: u8) -> usize {
+    match flag {
+        0x01 => 0,
+        0x02 => 1,
+        0x04 => 2,
+        _ => 3,
+    }
+}
+
+/// Define the interrupt handlers of a SERCOM
+///
+/// The first argument is the SERCOM number, the second is the function called
+/// from each handler. It receives the index of the interrupt line, see the
+/// [module-level documentation](crate::sercom::v2) for more details. The
+/// function path is resolved relative to the module where the macro is
+/// invoked.
+#[cfg(feature = "min-samd51g")]
+#[macro_export]
+macro_rules! sercom_interrupt_handlers {
+    ($num:literal, $handler:path) => {
+        $crate::paste::paste! {
+            #[allow(non_snake_case)]
+            mod [<__sercom $num _interrupt_handlers>] {
+                use super::*;
+                use $crate::target_device::interrupt;
+
+                #[interrupt]
+                fn [<SERCOM $num _0>]() {
+                    $handler(0)
+                }
+
+                #[interrupt]
+                fn [<SERCOM $num _1>]() {
+                    $handler(1)
+                }
+
+                #[interrupt]
+                fn [<SERCOM $num _2>]() {
+                    $handler(2)
+                }
+
+                #[interrupt]
+                fn [<SERCOM $num _OTHER>]() {
+                    $handler(3)
+                }
+            }
+        }
+    };
+}
+
+/// Define the interrupt handler of a SERCOM
+///
+/// The first argument is the SERCOM number, the second is the function called
+/// from the handler. It receives the index of the interrupt line, which is
+/// always zero on SAMD11/21. See the [module-level
+/// documentation](crate::sercom::v2) for more details. The function path is
+/// resolved relative to the module where the macro is invoked.
+#[cfg(any(feature = "samd11", feature = "samd21"))]
+#[macro_export]
+macro_rules! sercom_interrupt_handlers {
+    ($num:literal, $handler:path) => {
+        $crate::paste::paste! {
+            #[allow(non_snake_case)]
+            mod [<__sercom $num _interrupt_handlers>] {
+                use super::*;
+                use $crate::target_device::interrupt;
+
+                #[interrupt]
+                fn [<SERCOM $num>]() {
+                    $handler(0)
+                }
+            }
+        }
+    };
+}
+
+#[cfg(all(test, feature = "min-samd51g"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_lines_cover_intflag() {
+        let mut all = 0;
+        for (line, &mask) in INTERRUPT_LINE_FLAGS.iter().enumerate() {
+            assert_eq!(all & mask, 0);
+            all |= mask;
+            for bit in 0..8 {
+                if mask & (1 << bit) != 0 {
+                    assert_eq!(interrupt_line(1 << bit), line);
+                }
+            }
+        }
+        assert_eq!(all, 0xFF);
+    }
+
+    #[test]
+    fn sercom_interrupts_in_line_order() {
+        assert_eq!(Sercom0::INTERRUPTS[0] as u8, Interrupt::SERCOM0_0 as u8);
+        assert_eq!(Sercom1::INTERRUPTS[2] as u8, Interrupt::SERCOM1_2 as u8);
+        assert_eq!(Sercom5::INTERRUPTS[3] as u8, Interrupt::SERCOM5_OTHER as u8);
+    }
+}
@@ -371,9 +371,16 @@ impl<P: AnyPad> AsMut<P> for SpecificPad<P> {
 /// Meta-type representing an optional [`Pad`].
 ///
 /// This trait is implemented for every [`Pad`], as well as for [`NoneT`].
-pub trait OptionalPad {}
-impl OptionalPad for NoneT {}
-impl<P: AnyPad> OptionalPad for P {}
+pub trait OptionalPad {
+    /// `true` for every [`Pad`] and `false` for [`NoneT`]
+    const IS_SOME: bool;
+}
+impl OptionalPad for NoneT {
+    const IS_SOME: bool = false;
+}
+impl<P: AnyPad> OptionalPad for P {
+    const IS_SOME: bool = true;
+}
 
 /// Meta-type representing a valid [`Pad`].
 ///
@@ -0,0 +1,134 @@
+//! # Peripheral clocks shared between peripherals
+//!
+//! Some peripheral channels of the GCLK clock several peripherals at once,
+//! like the SERCOM slow clock, which times the I2C SMBus timeouts of every
+//! SERCOM. `GenericClockController` still hands out a single token for such a
+//! channel, so that its generator is selected once. [`SharedPclk`] takes that
+//! token and hands out any number of [`PclkUser`]s, each a proof that the
+//! channel runs at [`PclkUser::freq`].
+//!
+//! The users are counted. The token, and with it the ability to gate the
+//! channel, is only given back by [`SharedPclk::free`] once every user has
+//! been [released](SharedPclk::release). A user that is dropped instead is
+//! never released, and keeps the channel locked.
+//!
+//! ```
+//! let slow = clocks.slow_32k(&gclk1).unwrap();
+//! let mut slow = SharedPclk::new(slow);
+//! i2c2.enable_scl_low_timeout(slow.user());
+//! i2c5.enable_scl_low_timeout(slow.user());
+//! // Fails: both I2Cs hold a user
+//! let slow = slow.free().unwrap_err();
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::time::Hertz;
+
+/// Token of a configured peripheral channel, like `Sercom0CoreClock`
+pub trait PclkToken {
+    /// Frequency of the peripheral channel
+    fn pclk_freq(&self) -> Hertz;
+}
+
+/// Peripheral channel shared by several users
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug)]
+pub struct SharedPclk<C: PclkToken> {
+    token: C,
+    users: usize,
+}
+
+impl<C: PclkToken> SharedPclk<C> {
+    /// Share the peripheral channel of `token`
+    #[inline]
+    pub fn new(token: C) -> Self {
+        SharedPclk { token, users: 0 }
+    }
+
+    /// Frequency of the peripheral channel
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.token.pclk_freq()
+    }
+
+    /// Number of users not released yet
+    #[inline]
+    pub fn users(&self) -> usize {
+        self.users
+    }
+
+    /// Add a user of the peripheral channel
+    #[inline]
+    pub fn user(&mut self) -> PclkUser<C> {
+        self.users += 1;
+        PclkUser {
+            freq: self.freq(),
+            token: PhantomData,
+        }
+    }
+
+    /// Release a user of the peripheral channel
+    #[inline]
+    pub fn release(&mut self, _user: PclkUser<C>) {
+        self.users -= 1;
+    }
+
+    /// Return the token of the peripheral channel, if it has no users left
+    #[inline]
+    pub fn free(self) -> Result<C, Self> {
+        if self.users == 0 {
+            Ok(self.token)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// Proof that a shared peripheral channel runs, at a fixed frequency
+///
+/// Only a [`SharedPclk`] creates users.
+#[derive(Debug)]
+pub struct PclkUser<C: PclkToken> {
+    freq: Hertz,
+    token: PhantomData<C>,
+}
+
+impl<C: PclkToken> PclkUser<C> {
+    /// Frequency of the peripheral channel
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct SlowClock;
+
+    impl PclkToken for SlowClock {
+        fn pclk_freq(&self) -> Hertz {
+            Hertz(32_768)
+        }
+    }
+
+    #[test]
+    fn channel_is_freed_once_every_user_is_released() {
+        let mut shared = SharedPclk::new(SlowClock);
+        let first = shared.user();
+        let second = shared.user();
+        assert_eq!(first.freq(), Hertz(32_768));
+        assert_eq!(shared.users(), 2);
+
+        let mut shared = shared.free().unwrap_err();
+        shared.release(first);
+        let mut shared = shared.free().unwrap_err();
+        shared.release(second);
+        assert_eq!(shared.users(), 0);
+        assert!(shared.free().is_ok());
+    }
+}
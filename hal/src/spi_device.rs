@@ -0,0 +1,557 @@
+//! # SPI devices sharing a bus
+//!
+//! Several SPI devices usually share the SCK, MOSI and MISO lines of a SERCOM,
+//! each with its own chip select. [`SharedBus`] owns the SPI and an array of
+//! chip select pins, and hands out a [`SpiDevice`] for each of them. A device
+//! implements the blocking SPI traits of `embedded-hal` by asserting its chip
+//! select around each transfer, so that drivers written for a single device
+//! on a dedicated bus can share the bus.
+//!
+//! `embedded-hal` 0.2 has no `SpiDevice` trait, and drivers toggle the chip
+//! select themselves. Such drivers must be given a dummy pin, and the device
+//! as their SPI. [`SpiDevice::transaction`] keeps the chip select asserted
+//! over a sequence of transfers, e.g. a command and its response.
+//!
+//! Some devices need the chip select to be asserted for a while before the
+//! first clock edge, or after the last one. These setup and hold times are
+//! converted to CPU cycles, given the CPU frequency, and waited for with
+//! busy loops.
+//!
+//! ## Multi-operation transactions
+//!
+//! [`SpiDevice::run`] runs a sequence of [`Operation`]s with the chip select
+//! asserted throughout, e.g. a write enable, then a page program. The SPI
+//! must implement [`DmaSpi`] along with the blocking traits: operations of at
+//! least [`SharedBus::with_dma_threshold`] bytes are run with DMA, and the
+//! shorter ones by the CPU, for which setting up the DMA isn't worth it. Some
+//! flash devices need a pause between the operations of a transaction, added
+//! with [`SharedBus::with_inter_op_delay`]. If an operation fails, the
+//! following ones are not run, the chip select is deasserted, and the error
+//! holds the index of the failed operation:
+//!
+//! ```
+//! let bus = SharedBus::new(spi, [flash_cs], 120.mhz())?
+//!     .with_dma_threshold(64)
+//!     .with_inter_op_delay(100);
+//! let mut flash = bus.device(0).unwrap();
+//! let mut ops = [
+//!     Operation::Write(&[WRITE_ENABLE]),
+//!     Operation::Write(&program),
+//! ];
+//! if let Err(Error::Operation { index, .. }) = flash.run(&mut ops) {
+//!     // Retry from operation `index`
+//! }
+//! ```
+//!
+//! The chip selects are active low, and all pins must have the same type,
+//! e.g. [`DynPin`](crate::gpio::v2::DynPin). The bus is borrowed through a
+//! `RefCell`, so it must only be used from one context. To share it with an
+//! interrupt handler, put the `SharedBus` in a `cortex_m::interrupt::Mutex`
+//! and create the devices inside the critical section.
+//!
+//! ```
+//! let bus = SharedBus::new(spi, [flash_cs.into(), display_cs.into()], 120.mhz())?
+//!     .with_delays(50, 20);
+//! let mut flash = bus.device(0).unwrap();
+//! let mut display = bus.device(1).unwrap();
+//! flash.transaction(|spi| {
+//!     spi.write(&[READ_ID])?;
+//!     spi.transfer(&mut id)
+//! })?;
+//! display.write(&frame)?;
+//! ```
+
+use core::cell::RefCell;
+
+use cortex_m::asm::delay as cycle_delay;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::time::Hertz;
+
+/// Errors of a [`SpiDevice`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E, P> {
+    /// The SPI failed
+    Spi(E),
+    /// A chip select pin failed
+    Pin(P),
+    /// The bus is already used by another transaction
+    Busy,
+    /// The SPI failed during the operation at `index` of
+    /// [`SpiDevice::run`]
+    Operation {
+        /// Index of the failed operation
+        index: usize,
+        /// Error of the SPI
+        error: E,
+    },
+}
+
+/// An operation of [`SpiDevice::run`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Operation<'a> {
+    /// Write the words, and discard the words received
+    Write(&'a [u8]),
+    /// Write the words, and replace them with the words received
+    Transfer(&'a mut [u8]),
+}
+
+impl Operation<'_> {
+    /// Number of words of the operation
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            Operation::Write(words) => words.len(),
+            Operation::Transfer(words) => words.len(),
+        }
+    }
+
+    /// Returns whether the operation has no words
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// SPI which can also run blocking transfers with DMA
+///
+/// [`SpiDevice::run`] uses these methods for the operations of at least
+/// [`SharedBus::with_dma_threshold`] bytes. Both must only return once the
+/// last word is shifted out, so that the chip select can be deasserted.
+///
+/// The HAL doesn't implement it: `Spi::transfer_dma` takes ownership of its
+/// channels and `'static` buffers. Implement it for a wrapper holding the
+/// SPI, the DMA channels and a bounce buffer.
+pub trait DmaSpi {
+    /// Error of a transfer
+    type Error;
+
+    /// Write `words` with DMA, and discard the words received
+    fn dma_write(&mut self, words: &[u8]) -> Result<(), Self::Error>;
+
+    /// Write `words` with DMA, and replace them with the words received
+    fn dma_transfer(&mut self, words: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// Number of CPU cycles to wait for `ns` nanoseconds, rounded up
+fn delay_cycles(ns: u32, cpu_freq: Hertz) -> u32 {
+    let cycles = (ns as u64 * cpu_freq.0 as u64 + 999_999_999) / 1_000_000_000;
+    cycles as u32
+}
+
+/// Busy wait for `cycles` CPU cycles. `asm::delay` would wait for a whole
+/// wrap of its counter if given zero cycles.
+#[inline]
+fn wait(cycles: u32) {
+    if cycles > 0 {
+        cycle_delay(cycles);
+    }
+}
+
+/// SPI shared by the devices of an array of chip selects
+///
+/// See the [module-level documentation](self) for more details.
+pub struct SharedBus<SPI, CS, const N: usize> {
+    spi: RefCell<SPI>,
+    cs: RefCell<[CS; N]>,
+    cpu_freq: Hertz,
+    setup_cycles: u32,
+    hold_cycles: u32,
+    dma_threshold: usize,
+    inter_op_cycles: u32,
+}
+
+impl<SPI, CS, const N: usize> SharedBus<SPI, CS, N>
+where
+    CS: OutputPin,
+{
+    /// Share `spi` between the devices selected by `cs`, driving every chip
+    /// select high, while the CPU runs at `cpu_freq`
+    pub fn new(spi: SPI, mut cs: [CS; N], cpu_freq: impl Into<Hertz>) -> Result<Self, CS::Error> {
+        for pin in cs.iter_mut() {
+            pin.set_high()?;
+        }
+        Ok(SharedBus {
+            spi: RefCell::new(spi),
+            cs: RefCell::new(cs),
+            cpu_freq: cpu_freq.into(),
+            setup_cycles: 0,
+            hold_cycles: 0,
+            dma_threshold: usize::MAX,
+            inter_op_cycles: 0,
+        })
+    }
+
+    /// Wait at least `setup_ns` nanoseconds between asserting a chip select
+    /// and the first transfer, and `hold_ns` between the last transfer and
+    /// deasserting it
+    pub fn with_delays(mut self, setup_ns: u32, hold_ns: u32) -> Self {
+        self.setup_cycles = delay_cycles(setup_ns, self.cpu_freq);
+        self.hold_cycles = delay_cycles(hold_ns, self.cpu_freq);
+        self
+    }
+
+    /// Run the operations of [`SpiDevice::run`] of at least
+    /// `dma_threshold_bytes` bytes with DMA. By default, every operation is
+    /// run by the CPU.
+    pub fn with_dma_threshold(mut self, dma_threshold_bytes: usize) -> Self {
+        self.dma_threshold = dma_threshold_bytes;
+        self
+    }
+
+    /// Wait at least `inter_op_delay_ns` nanoseconds between the operations
+    /// of [`SpiDevice::run`], with the chip select still asserted
+    pub fn with_inter_op_delay(mut self, inter_op_delay_ns: u32) -> Self {
+        self.inter_op_cycles = delay_cycles(inter_op_delay_ns, self.cpu_freq);
+        self
+    }
+
+    /// Device selected by the chip select at `index`, or `None` if there is
+    /// no such chip select
+    #[inline]
+    pub fn device(&self, index: usize) -> Option<SpiDevice<'_, SPI, CS, N>> {
+        if index < N {
+            Some(SpiDevice { bus: self, index })
+        } else {
+            None
+        }
+    }
+
+    /// Return the SPI and the chip select pins
+    #[inline]
+    pub fn free(self) -> (SPI, [CS; N]) {
+        (self.spi.into_inner(), self.cs.into_inner())
+    }
+}
+
+/// Device of a [`SharedBus`], selected by one of its chip selects
+pub struct SpiDevice<'a, SPI, CS, const N: usize> {
+    bus: &'a SharedBus<SPI, CS, N>,
+    index: usize,
+}
+
+impl<SPI, CS, const N: usize> SpiDevice<'_, SPI, CS, N>
+where
+    CS: OutputPin,
+{
+    /// Run `f` on the SPI with the chip select of this device asserted
+    ///
+    /// The chip select is deasserted again once `f` returns, even if it
+    /// fails.
+    pub fn transaction<R, E>(
+        &mut self,
+        f: impl FnOnce(&mut SPI) -> Result<R, E>,
+    ) -> Result<R, Error<E, CS::Error>> {
+        let bus = self.bus;
+        let mut spi = bus.spi.try_borrow_mut().map_err(|_| Error::Busy)?;
+        let mut cs = bus.cs.try_borrow_mut().map_err(|_| Error::Busy)?;
+        let pin = &mut cs[self.index];
+
+        pin.set_low().map_err(Error::Pin)?;
+        wait(bus.setup_cycles);
+        let result = f(&mut spi);
+        wait(bus.hold_cycles);
+        pin.set_high().map_err(Error::Pin)?;
+        result.map_err(Error::Spi)
+    }
+
+    /// Run `operations` in order, with the chip select of this device
+    /// asserted throughout
+    ///
+    /// Each operation is run with DMA if it is at least as long as the DMA
+    /// threshold of the bus, and by the CPU otherwise. If an operation fails,
+    /// the following ones are not run, the chip select is deasserted, and
+    /// [`Error::Operation`] holds the index of the failed operation.
+    pub fn run<E>(&mut self, operations: &mut [Operation<'_>]) -> Result<(), Error<E, CS::Error>>
+    where
+        SPI: Write<u8, Error = E> + Transfer<u8, Error = E> + DmaSpi<Error = E>,
+    {
+        let dma_threshold = self.bus.dma_threshold;
+        let inter_op_cycles = self.bus.inter_op_cycles;
+        let mut current = 0;
+        let result = self.transaction(|spi| {
+            for (index, operation) in operations.iter_mut().enumerate() {
+                if index > 0 {
+                    wait(inter_op_cycles);
+                }
+                current = index;
+                let dma = operation.len() >= dma_threshold;
+                match operation {
+                    Operation::Write(words) if dma => spi.dma_write(words)?,
+                    Operation::Write(words) => Write::write(spi, words)?,
+                    Operation::Transfer(words) if dma => spi.dma_transfer(words)?,
+                    Operation::Transfer(words) => {
+                        Transfer::transfer(spi, words)?;
+                    }
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Err(Error::Spi(error)) => Err(Error::Operation {
+                index: current,
+                error,
+            }),
+            result => result,
+        }
+    }
+}
+
+impl<SPI, CS, const N: usize> Transfer<u8> for SpiDevice<'_, SPI, CS, N>
+where
+    SPI: Transfer<u8>,
+    CS: OutputPin,
+{
+    type Error = Error<SPI::Error, CS::Error>;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.transaction(move |spi| spi.transfer(words))
+    }
+}
+
+impl<SPI, CS, const N: usize> Write<u8> for SpiDevice<'_, SPI, CS, N>
+where
+    SPI: Write<u8>,
+    CS: OutputPin,
+{
+    type Error = Error<SPI::Error, CS::Error>;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(|spi| spi.write(words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Select(u8),
+        Deselect(u8),
+        Write(u8),
+        Transfer(u8),
+        Dma(u8),
+    }
+
+    /// Events of the mock pins and SPI, in order
+    #[derive(Default)]
+    struct Log {
+        events: RefCell<[Option<Event>; 8]>,
+        len: Cell<usize>,
+    }
+
+    impl Log {
+        fn push(&self, event: Event) {
+            self.events.borrow_mut()[self.len.get()] = Some(event);
+            self.len.set(self.len.get() + 1);
+        }
+
+        fn take(&self) -> [Option<Event>; 8] {
+            self.len.set(0);
+            self.events.replace([None; 8])
+        }
+    }
+
+    struct Pin<'a>(u8, &'a Log);
+
+    impl OutputPin for Pin<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.1.push(Event::Select(self.0));
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.1.push(Event::Deselect(self.0));
+            Ok(())
+        }
+    }
+
+    /// SPI which fails on transfers starting with 0xFF
+    struct Spi<'a>(&'a Log);
+
+    impl Spi<'_> {
+        fn log(&self, event: Event, words: &[u8]) -> Result<(), ()> {
+            self.0.push(event);
+            if words[0] == 0xFF {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Write<u8> for Spi<'_> {
+        type Error = ();
+
+        fn write(&mut self, words: &[u8]) -> Result<(), ()> {
+            self.log(Event::Write(words[0]), words)
+        }
+    }
+
+    impl Transfer<u8> for Spi<'_> {
+        type Error = ();
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], ()> {
+            self.log(Event::Transfer(words[0]), words)?;
+            Ok(words)
+        }
+    }
+
+    impl DmaSpi for Spi<'_> {
+        type Error = ();
+
+        fn dma_write(&mut self, words: &[u8]) -> Result<(), ()> {
+            self.log(Event::Dma(words[0]), words)
+        }
+
+        fn dma_transfer(&mut self, words: &mut [u8]) -> Result<(), ()> {
+            self.log(Event::Dma(words[0]), words)
+        }
+    }
+
+    #[test]
+    fn chip_select_is_asserted_around_a_transfer() {
+        use Event::*;
+        let log = Log::default();
+        let pins = [Pin(0, &log), Pin(1, &log), Pin(2, &log)];
+        let bus = SharedBus::new(Spi(&log), pins, Hertz(48_000_000)).unwrap();
+        assert_eq!(
+            log.take()[..4],
+            [
+                Some(Deselect(0)),
+                Some(Deselect(1)),
+                Some(Deselect(2)),
+                None
+            ]
+        );
+
+        let mut device = bus.device(1).unwrap();
+        device.write(&[0x9F]).unwrap();
+        assert_eq!(
+            log.take()[..4],
+            [Some(Select(1)), Some(Write(0x9F)), Some(Deselect(1)), None]
+        );
+
+        // Deselected even if the transfer fails
+        assert_eq!(bus.device(2).unwrap().write(&[0xFF]), Err(Error::Spi(())));
+        assert_eq!(
+            log.take()[..4],
+            [Some(Select(2)), Some(Write(0xFF)), Some(Deselect(2)), None]
+        );
+        assert!(bus.device(3).is_none());
+    }
+
+    #[test]
+    fn nested_transactions_are_rejected() {
+        let log = Log::default();
+        let bus =
+            SharedBus::new(Spi(&log), [Pin(0, &log), Pin(1, &log)], Hertz(48_000_000)).unwrap();
+        let mut outer = bus.device(0).unwrap();
+        let mut inner = bus.device(1).unwrap();
+        let result = outer.transaction(|_| Ok::<_, ()>(inner.write(&[0x01])));
+        assert_eq!(result, Ok(Err(Error::Busy)));
+    }
+
+    #[test]
+    fn long_operations_use_dma() {
+        use Event::*;
+        let log = Log::default();
+        let bus = SharedBus::new(Spi(&log), [Pin(0, &log)], Hertz(48_000_000))
+            .unwrap()
+            .with_dma_threshold(4);
+        log.take();
+
+        let mut device = bus.device(0).unwrap();
+        let mut status = [0x05, 0x00];
+        let mut page = [0x02; 4];
+        let mut ops = [
+            Operation::Write(&[0x06]),
+            Operation::Transfer(&mut status),
+            Operation::Write(&[0x03; 4]),
+            Operation::Transfer(&mut page),
+        ];
+        assert_eq!(device.run(&mut ops), Ok(()));
+        assert_eq!(
+            log.take()[..7],
+            [
+                Some(Select(0)),
+                Some(Write(0x06)),
+                Some(Transfer(0x05)),
+                Some(Dma(0x03)),
+                Some(Dma(0x02)),
+                Some(Deselect(0)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_operation_releases_chip_select() {
+        use Event::*;
+        let log = Log::default();
+        let bus = SharedBus::new(Spi(&log), [Pin(0, &log), Pin(1, &log)], Hertz(48_000_000))
+            .unwrap()
+            .with_dma_threshold(2);
+        log.take();
+
+        // The second operation fails, and the third one is never run
+        let mut device = bus.device(1).unwrap();
+        let mut response = [0x00; 4];
+        let mut ops = [
+            Operation::Write(&[0x06]),
+            Operation::Write(&[0xFF, 0x00]),
+            Operation::Transfer(&mut response),
+        ];
+        assert_eq!(
+            device.run(&mut ops),
+            Err(Error::Operation {
+                index: 1,
+                error: ()
+            })
+        );
+        assert_eq!(
+            log.take()[..5],
+            [
+                Some(Select(1)),
+                Some(Write(0x06)),
+                Some(Dma(0xFF)),
+                Some(Deselect(1)),
+                None
+            ]
+        );
+
+        // The bus is released, and a failure of the first operation is
+        // reported as well
+        let mut ops = [Operation::Transfer(&mut [0xFF])];
+        assert_eq!(
+            bus.device(0).unwrap().run(&mut ops),
+            Err(Error::Operation {
+                index: 0,
+                error: ()
+            })
+        );
+        assert_eq!(
+            log.take()[..4],
+            [
+                Some(Select(0)),
+                Some(Transfer(0xFF)),
+                Some(Deselect(0)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn delays_are_rounded_up_to_cycles() {
+        assert_eq!(delay_cycles(0, Hertz(120_000_000)), 0);
+        assert_eq!(delay_cycles(50, Hertz(120_000_000)), 6);
+        assert_eq!(delay_cycles(20, Hertz(48_000_000)), 1);
+        assert_eq!(delay_cycles(1_000, Hertz(48_000_000)), 48);
+    }
+}
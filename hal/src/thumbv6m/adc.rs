@@ -2,10 +2,86 @@ use crate::clock::GenericClockController;
 use crate::gpio::v1;
 use crate::gpio::v2::*;
 use crate::hal::adc::{Channel, OneShot};
+use crate::target_device::generic::Variant;
 use crate::target_device::{adc, ADC, PM};
+use crate::time::Hertz;
 
 pub struct Adc<ADC> {
     adc: ADC,
+    clock_freq: Hertz,
+}
+
+/// A snapshot of the ADC's configuration, along with the resulting
+/// conversion timing. Returned by `Adc::config_report`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcConfigReport {
+    /// Frequency of the generic clock feeding the ADC
+    pub clock_freq: Hertz,
+    /// Division factor of the ADC prescaler
+    pub prescaler: u16,
+    /// Result resolution
+    pub resolution: adc::ctrlb::RESSEL_A,
+    /// Sampling time, in half ADC clock cycles minus one (`SAMPLEN`)
+    pub sample_length: u8,
+    /// Number of conversions accumulated into one result
+    pub samples: u16,
+    /// Selected reference, or `None` if the field holds a reserved value
+    pub reference: Option<adc::refctrl::REFSEL_A>,
+    /// Time taken by one result, in nanoseconds
+    pub conversion_time_ns: u32,
+    /// Highest achievable rate of results
+    pub max_sample_rate: Hertz,
+}
+
+/// Returns the number of half ADC clock cycles needed for one result.
+///
+/// Each conversion takes `SAMPLEN + 1` half cycles of sampling, followed by
+/// `1 + resolution / 2` cycles of conversion; the 16-bit averaging mode
+/// converts at 12 bits. When averaging, one result is made of `samples`
+/// conversions. The extra half cycle taken by a gain stage other than 1x is
+/// not included.
+pub const fn conversion_half_cycles(sample_length: u8, resolution_bits: u8, samples: u16) -> u32 {
+    (sample_length as u32 + 1 + 2 + resolution_bits as u32) * samples as u32
+}
+
+/// Returns the time taken by one result, in nanoseconds, rounded up.
+/// `prescaler` is the division factor between the generic clock and the ADC
+/// clock. See `conversion_half_cycles` for the other parameters.
+pub const fn conversion_time_ns(
+    clock_freq: Hertz,
+    prescaler: u16,
+    sample_length: u8,
+    resolution_bits: u8,
+    samples: u16,
+) -> u32 {
+    let half_cycles = conversion_half_cycles(sample_length, resolution_bits, samples) as u64;
+    let ns = half_cycles * prescaler as u64 * 1_000_000_000;
+    let div = 2 * clock_freq.0 as u64;
+    ((ns + div - 1) / div) as u32
+}
+
+/// Returns the highest achievable rate of results, rounded down. See
+/// `conversion_time_ns` for the parameters.
+pub const fn max_sample_rate(
+    clock_freq: Hertz,
+    prescaler: u16,
+    sample_length: u8,
+    resolution_bits: u8,
+    samples: u16,
+) -> Hertz {
+    let half_cycles = conversion_half_cycles(sample_length, resolution_bits, samples);
+    Hertz(2 * clock_freq.0 / (prescaler as u32 * half_cycles))
+}
+
+/// `EVCTRL.RESRDYEO`: an event is emitted on every result ready, the same
+/// bit on all chips
+const EVCTRL_RESRDYEO: u8 = 1 << 4;
+
+/// Returns the `EVCTRL` value `bits` with `RESRDYEO` set to `enable`, and the
+/// other event settings kept
+fn evctrl_result_event(bits: u8, enable: bool) -> u8 {
+    let resrdyeo = if enable { EVCTRL_RESRDYEO } else { 0 };
+    bits & !EVCTRL_RESRDYEO | resrdyeo
 }
 
 impl Adc<ADC> {
@@ -14,7 +90,7 @@ impl Adc<ADC> {
 
         // set to 1 / (1 / (48000000 / 32) * 6) = 250000 SPS
         let gclk0 = clocks.gclk0();
-        clocks.adc(&gclk0).expect("adc clock setup failed");
+        let clock_freq = clocks.adc(&gclk0).expect("adc clock setup failed").freq();
         while adc.status.read().syncbusy().bit_is_set() {}
 
         adc.ctrla.modify(|_, w| w.swrst().set_bit());
@@ -32,7 +108,7 @@ impl Adc<ADC> {
         adc.inputctrl.modify(|_, w| w.muxneg().gnd()); // No negative input (internal gnd)
         while adc.status.read().syncbusy().bit_is_set() {}
 
-        let mut newadc = Self { adc };
+        let mut newadc = Self { adc, clock_freq };
         newadc.samples(adc::avgctrl::SAMPLENUM_A::_1);
         newadc.gain(adc::inputctrl::GAIN_A::DIV2);
         newadc.reference(adc::refctrl::REFSEL_A::INTVCC1);
@@ -71,6 +147,114 @@ impl Adc<ADC> {
         while self.adc.status.read().syncbusy().bit_is_set() {}
     }
 
+    /// Emit an event on every result ready (`EVCTRL.RESRDYEO`)
+    ///
+    /// Through EVSYS, this lets other peripherals react to each conversion
+    /// without involving the CPU. Select the ADC's `RESRDY` event generator
+    /// in an EVSYS `CHANNEL` register, then select that channel as the `USER`
+    /// of the consumer, e.g. the DAC start event or a DMAC channel event
+    /// input. To copy each result with the DMAC, configure the channel's
+    /// event input action as a beat trigger (`CHCTRLB.EVACT`) and point its
+    /// source at the `RESULT` register. This HAL does not abstract EVSYS
+    /// yet.
+    pub fn enable_result_event(&mut self) {
+        // SAFETY: Only RESRDYEO changes, the other event settings are kept
+        self.adc
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(evctrl_result_event(r.bits(), true)) });
+    }
+
+    /// Stop emitting result ready events
+    pub fn disable_result_event(&mut self) {
+        // SAFETY: Only RESRDYEO changes, the other event settings are kept
+        self.adc
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(evctrl_result_event(r.bits(), false)) });
+    }
+
+    /// Start a conversion on every incoming event (`EVCTRL.STARTEI`)
+    ///
+    /// The ADC must be enabled for the events to start conversions, and the
+    /// input selected, e.g. with [`Adc::enable_for_events`]. Route the event
+    /// generator, like an RTC periodic event, to the ADC's `START` event user
+    /// through EVSYS.
+    pub fn enable_start_event(&mut self) {
+        self.adc.evctrl.modify(|_, w| w.startei().set_bit());
+    }
+
+    /// Stop starting conversions on incoming events
+    pub fn disable_start_event(&mut self) {
+        self.adc.evctrl.modify(|_, w| w.startei().clear_bit());
+    }
+
+    /// Keep the ADC running in standby sleep (`CTRLA.RUNSTDBY`)
+    ///
+    /// This is needed for event-started conversions to complete while the
+    /// CPU sleeps. The ADC clock generator must also run in standby.
+    pub fn run_in_standby(&mut self, enable: bool) {
+        // CTRLA.RUNSTDBY is enable-protected; the ADC is only enabled during
+        // conversions
+        self.adc.ctrla.modify(|_, w| w.runstdby().bit(enable));
+    }
+
+    /// Select `pin` as the input and keep the ADC enabled, so that
+    /// conversions can be started by events, see [`Adc::enable_start_event`]
+    pub fn enable_for_events<PIN: Channel<ADC, ID = u8>>(&mut self, _pin: &mut PIN) {
+        let chan = PIN::channel();
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+        self.adc
+            .inputctrl
+            .modify(|_, w| unsafe { w.muxpos().bits(chan) });
+        self.power_up();
+    }
+
+    /// Disable the ADC after [`Adc::enable_for_events`]
+    pub fn disable_for_events(&mut self) {
+        self.power_down();
+    }
+
+    /// Reads back the ADC configuration and computes the resulting
+    /// conversion time and maximum sample rate.
+    pub fn config_report(&self) -> AdcConfigReport {
+        use adc::ctrlb::RESSEL_A;
+        let ctrlb = self.adc.ctrlb.read();
+        let prescaler = 1 << (ctrlb.prescaler().bits() + 2);
+        let resolution = ctrlb.ressel().variant();
+        let resolution_bits = match resolution {
+            RESSEL_A::_12BIT | RESSEL_A::_16BIT => 12,
+            RESSEL_A::_10BIT => 10,
+            RESSEL_A::_8BIT => 8,
+        };
+        let sample_length = self.adc.sampctrl.read().samplen().bits();
+        let samples = 1 << self.adc.avgctrl.read().samplenum().bits().min(10);
+        let reference = match self.adc.refctrl.read().refsel().variant() {
+            Variant::Val(reference) => Some(reference),
+            Variant::Res(_) => None,
+        };
+        AdcConfigReport {
+            clock_freq: self.clock_freq,
+            prescaler,
+            resolution,
+            sample_length,
+            samples,
+            reference,
+            conversion_time_ns: conversion_time_ns(
+                self.clock_freq,
+                prescaler,
+                sample_length,
+                resolution_bits,
+                samples,
+            ),
+            max_sample_rate: max_sample_rate(
+                self.clock_freq,
+                prescaler,
+                sample_length,
+                resolution_bits,
+                samples,
+            ),
+        }
+    }
+
     fn power_up(&mut self) {
         while self.adc.status.read().syncbusy().bit_is_set() {}
         self.adc.ctrla.modify(|_, w| w.enable().set_bit());
@@ -191,3 +375,44 @@ adc_pins! {
     PB06: 14,
     PB07: 15
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion_time_default_config() {
+        // 48 MHz / 32, SAMPLEN = 5, 12 bits: 10 cycles of 1.5 MHz
+        assert_eq!(conversion_time_ns(Hertz(48_000_000), 32, 5, 12, 1), 6_667);
+        assert_eq!(
+            max_sample_rate(Hertz(48_000_000), 32, 5, 12, 1),
+            Hertz(150_000)
+        );
+    }
+
+    #[test]
+    fn conversion_time_scales_with_averaging() {
+        // 2.1 MHz ADC clock, SAMPLEN = 0, 12 bits: 7.5 cycles per result
+        assert_eq!(
+            max_sample_rate(Hertz(8_400_000), 4, 0, 12, 1),
+            Hertz(280_000)
+        );
+        assert_eq!(conversion_time_ns(Hertz(48_000_000), 4, 0, 12, 4), 2_500);
+    }
+
+    #[test]
+    fn conversion_half_cycles_per_resolution() {
+        assert_eq!(conversion_half_cycles(0, 12, 1), 15);
+        assert_eq!(conversion_half_cycles(0, 10, 1), 13);
+        assert_eq!(conversion_half_cycles(0, 8, 1), 11);
+    }
+
+    #[test]
+    fn result_event_keeps_the_other_events() {
+        assert_eq!(evctrl_result_event(0, true), 0x10);
+        assert_eq!(evctrl_result_event(0x10, false), 0);
+        // SYNCEI and WINMONEO stay as they were
+        assert_eq!(evctrl_result_event(0x22, true), 0x32);
+        assert_eq!(evctrl_result_event(0x32, false), 0x22);
+    }
+}
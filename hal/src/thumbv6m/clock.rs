@@ -6,19 +6,29 @@
 use crate::target_device::gclk::clkctrl::GEN_A::*;
 use crate::target_device::gclk::clkctrl::ID_A::*;
 use crate::target_device::gclk::genctrl::SRC_A::*;
+use crate::target_device::generic::Variant;
 use crate::target_device::{self, GCLK, NVMCTRL, PM, SYSCTRL};
 use crate::time::{Hertz, U32Ext};
 
+pub use crate::shared_pclk::{PclkToken, PclkUser, SharedPclk};
+
 pub type ClockId = target_device::gclk::clkctrl::ID_A;
 pub type ClockGenId = target_device::gclk::clkctrl::GEN_A;
 pub type ClockSource = target_device::gclk::genctrl::SRC_A;
 
+/// Number of clock generators: 6 on the SAMD11, 9 on the SAMD21
+#[cfg(feature = "samd11")]
+pub const NUM_GCLK_GENERATORS: usize = 6;
+/// Number of clock generators: 6 on the SAMD11, 9 on the SAMD21
+#[cfg(feature = "samd21")]
+pub const NUM_GCLK_GENERATORS: usize = 9;
+
 /// Represents a configured clock generator.
 /// Can be converted into the effective clock frequency.
 /// Its primary purpose is to be passed in to methods
 /// such as `GenericClockController::tcc2_tc3` to configure
 /// the clock for a peripheral.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct GClock {
     gclk: ClockGenId,
     freq: Hertz,
@@ -30,6 +40,380 @@ impl Into<Hertz> for GClock {
     }
 }
 
+/// The configuration of a clock generator, as read back from the hardware.
+///
+/// Unlike the frequencies tracked by `GenericClockController`, this reflects
+/// what is actually programmed into `GENCTRL` and `GENDIV`, which may differ
+/// if e.g. a bootloader left a generator configured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockConfig {
+    /// The selected clock source, or `None` if the field holds a reserved
+    /// value
+    pub src: Option<ClockSource>,
+    /// The effective linear division factor, taking `DIVSEL` into account
+    pub div: u32,
+    /// Whether the generator is enabled
+    pub enabled: bool,
+    /// Whether the 50/50 duty cycle correction for odd dividers is enabled
+    pub improve_duty_cycle: bool,
+    /// Whether the generator output is routed to its GCLK_IO pin
+    pub output_enable: bool,
+    /// The level driven on the GCLK_IO pin while the output is enabled but
+    /// the generator is turned off. The running clock itself can't be
+    /// inverted on the pin; an inverted clock can be output by a TCC
+    /// instead, see `clock_output` of the TCC types in `pwm`.
+    pub output_off_value: bool,
+}
+
+impl GClockConfig {
+    /// Returns the generator's output frequency, given the frequency of its
+    /// source.
+    pub fn freq(&self, source_freq: Hertz) -> Hertz {
+        Hertz(source_freq.0 / self.div)
+    }
+
+    /// Returns the nominal frequency of the generator's source, if it is a
+    /// fixed-frequency internal oscillator.
+    ///
+    /// This makes it easy to catch a generator that was left on a 32 kHz
+    /// oscillator while the code expects it to run at MHz speeds, e.g.
+    /// `debug_assert!(config.source_freq() != Some(OSC32K_FREQ))`. Sources
+    /// whose frequency depends on their configuration, like the crystal
+    /// oscillators, the DPLLs, `GCLKIN` and `GCLKGEN1`, return `None`.
+    pub fn source_freq(&self) -> Option<Hertz> {
+        match self.src? {
+            XOSC32K | OSC32K | OSCULP32K => Some(OSC32K_FREQ),
+            OSC8M => Some(OSC8M_FREQ),
+            DFLL48M => Some(OSC48M_FREQ),
+            XOSC | GCLKIN | GCLKGEN1 | DPLL96M => None,
+        }
+    }
+}
+
+/// A `Copy` snapshot of a clock generator, cheap to log and to compare
+/// against a later snapshot in order to detect unexpected clock changes.
+/// Returned by `GenericClockController::snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockSnapshot {
+    /// The clock generator in question
+    pub gclk: ClockGenId,
+    /// The configuration read back from the hardware
+    pub config: GClockConfig,
+    /// The generator's output frequency, or `None` if the frequency of its
+    /// source isn't known to the controller
+    pub freq: Option<Hertz>,
+}
+
+impl GClockSnapshot {
+    fn new(gclk: ClockGenId, config: GClockConfig, source_freq: Option<Hertz>) -> Self {
+        GClockSnapshot {
+            gclk,
+            config,
+            freq: source_freq.map(|freq| config.freq(freq)),
+        }
+    }
+
+    /// Returns the output frequency of the generator, or `None` if it is
+    /// disabled, or if the frequency of its source isn't known
+    pub fn running_freq(&self) -> Option<Hertz> {
+        if self.config.enabled {
+            self.freq
+        } else {
+            None
+        }
+    }
+}
+
+/// `CLKCTRL.CLKEN`: the peripheral channel is enabled
+const CLKCTRL_CLKEN: u16 = 1 << 14;
+
+/// Returns the `CLKCTRL` value `bits` with `CLKEN` set to `enable`, and the
+/// channel and generator selection kept
+fn clkctrl_gated(bits: u16, enable: bool) -> u16 {
+    let clken = if enable { CLKCTRL_CLKEN } else { 0 };
+    bits & !CLKCTRL_CLKEN | clken
+}
+
+/// The clock sources, indexed by their `GENCTRL.SRC` value
+const GCLK_SOURCES: [ClockSource; 9] = [
+    XOSC, GCLKIN, GCLKGEN1, OSCULP32K, OSC32K, XOSC32K, OSC8M, DFLL48M, DPLL96M,
+];
+
+/// Returns the source selected by the `GENCTRL.SRC` value `bits`, or `None`
+/// for a reserved value
+fn gclk_source_from_bits(bits: u8) -> Option<ClockSource> {
+    GCLK_SOURCES.get(bits as usize).copied()
+}
+
+/// `GENCTRL.OOV`: the level of the GCLK_IO pin while the generator is off
+const GENCTRL_OOV: u32 = 1 << 18;
+/// `GENCTRL.OE`: the generator output is routed to its GCLK_IO pin
+const GENCTRL_OE: u32 = 1 << 19;
+
+/// Returns `(output_enable, output_off_value)` from the bits of `GENCTRL`
+fn gclk_output_from_bits(genctrl: u32) -> (bool, bool) {
+    (genctrl & GENCTRL_OE != 0, genctrl & GENCTRL_OOV != 0)
+}
+
+/// Reported by `GenericClockController::reconcile` when a clock generator's
+/// hardware configuration doesn't match what the controller expects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockMismatch {
+    /// The clock generator in question
+    pub gclk: ClockGenId,
+    /// The frequency the controller believes the generator runs at;
+    /// `Hertz(0)` if it hasn't been configured through the controller
+    pub expected: Hertz,
+    /// The configuration read back from the hardware
+    pub actual: GClockConfig,
+}
+
+/// The divider chosen by `GenericClockController::div_to_reach`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockDivider {
+    /// The linear divider to pass to `configure_gclk_divider_and_source`
+    pub divider: u16,
+    /// The frequency achieved with `divider`
+    pub freq: Hertz,
+    /// The absolute difference between `freq` and the requested frequency
+    pub error: Hertz,
+}
+
+/// Returns whether `src` is the output of `gclk` itself: only `GCLKGEN1` can
+/// feed another generator, and GCLK1 would never start from it
+fn feeds_itself(gclk: ClockGenId, src: ClockSource) -> bool {
+    gclk == GCLK1 && src == GCLKGEN1
+}
+
+/// Returns the output frequency of a generator dividing `source` by the
+/// linear `divider`, where zero means no division like one
+fn divided_freq(source: Hertz, divider: u16) -> Hertz {
+    Hertz(source.0 / (divider as u32).max(1))
+}
+
+/// Picks the legal divider that brings `source` closest to `target`.
+fn closest_divider(source: Hertz, target: Hertz, max_divider: u16) -> GClockDivider {
+    let target = target.0.max(1);
+    let ideal = (source.0 / target).max(1).min(max_divider as u32);
+    let candidates = [ideal, (ideal + 1).min(max_divider as u32)];
+    let (divider, freq) = candidates
+        .iter()
+        .map(|&div| (div, source.0 / div))
+        .min_by_key(|&(_, freq)| (freq as i64 - target as i64).abs())
+        .unwrap();
+    GClockDivider {
+        divider: divider as u16,
+        freq: Hertz(freq),
+        error: Hertz((freq as i64 - target as i64).abs() as u32),
+    }
+}
+
+/// Frequencies of the clock generators, with every generator but GCLK0 and
+/// GCLK1 unconfigured
+fn gclk_freqs(gclk0: Hertz, gclk1: Hertz) -> [Hertz; NUM_GCLK_GENERATORS] {
+    let mut gclks = [Hertz(0); NUM_GCLK_GENERATORS];
+    gclks[0] = gclk0;
+    gclks[1] = gclk1;
+    gclks
+}
+
+/// The largest linear divider supported by a clock generator. `GCLK1` has a
+/// 16-bit `DIV` field, `GCLK2` a 5-bit one and the others 8 bits.
+fn max_divider(gclk: ClockGenId) -> u16 {
+    match gclk {
+        ClockGenId::GCLK1 => u16::MAX,
+        ClockGenId::GCLK2 => 31,
+        _ => u8::MAX as u16,
+    }
+}
+
+/// Reported by `GenericClockController::check_main_clock` when GCLK0 can't
+/// run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MainClockError {
+    /// `GENCTRL` of GCLK0, or of GCLK1 when GCLK0 runs from it, selects a
+    /// reserved source, a disabled GCLK1, or GCLK1 as its own source
+    InvalidSource,
+    /// The oscillator at the root of GCLK0 is not ready, or the DPLL is not
+    /// locked
+    NotReady(ClockSource),
+}
+
+/// Readiness of every source of a generator, read from the status registers
+#[derive(Debug, Clone, Copy, Default)]
+struct SourceStatus {
+    xosc: bool,
+    osc32k: bool,
+    xosc32k: bool,
+    osc8m: bool,
+    dfll: bool,
+    dpll: bool,
+    /// Source of GCLK1, if it is enabled
+    gclk1: Option<ClockSource>,
+}
+
+/// Check that `src` is ready to clock GCLK0
+fn check_source(src: ClockSource, status: &SourceStatus) -> Result<(), MainClockError> {
+    let ready = match src {
+        XOSC => status.xosc,
+        OSC32K => status.osc32k,
+        XOSC32K => status.xosc32k,
+        OSC8M => status.osc8m,
+        DFLL48M => status.dfll,
+        DPLL96M => status.dpll,
+        // Always running, or not monitored
+        OSCULP32K | GCLKIN => true,
+        GCLKGEN1 => {
+            return match status.gclk1 {
+                Some(GCLKGEN1) | None => Err(MainClockError::InvalidSource),
+                Some(src) => check_source(src, status),
+            }
+        }
+    };
+    if ready {
+        Ok(())
+    } else {
+        Err(MainClockError::NotReady(src))
+    }
+}
+
+/// Configuration of the external 32 kHz oscillator, as read back from
+/// `XOSC32K` by `GenericClockController::xosc32k_config`
+///
+/// This is meant to check the configuration made by
+/// `with_external_32kosc`, or left by a bootloader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xosc32kConfig {
+    /// Whether the oscillator is enabled (`ENABLE`)
+    pub enabled: bool,
+    /// Whether a crystal is connected between XIN32 and XOUT32, rather than
+    /// an external clock on XIN32 (`XTALEN`)
+    pub crystal: bool,
+    /// Whether the 32 kHz output is enabled (`EN32K`)
+    pub output_32k: bool,
+    /// Whether the 1 kHz output is enabled (`EN1K`)
+    pub output_1k: bool,
+    /// Whether the automatic amplitude control of the crystal is enabled
+    /// (`AAMPEN`)
+    pub amplitude_control: bool,
+    /// Whether the oscillator keeps running in standby (`RUNSTDBY`)
+    pub run_in_standby: bool,
+    /// Whether the oscillator only runs while a peripheral requests it
+    /// (`ONDEMAND`)
+    pub on_demand: bool,
+    /// Start-up time, in cycles of OSCULP32K (`STARTUP`), from 1 cycle for
+    /// 0 to 128k cycles for 7
+    pub startup: u8,
+    /// Whether the configuration is locked until the next reset
+    /// (`WRTLOCK`)
+    pub write_locked: bool,
+}
+
+impl Xosc32kConfig {
+    /// Decode the value of `XOSC32K`
+    fn from_bits(bits: u16) -> Self {
+        let bit = |n: u16| bits & 1 << n != 0;
+        Xosc32kConfig {
+            enabled: bit(1),
+            crystal: bit(2),
+            output_32k: bit(3),
+            output_1k: bit(4),
+            amplitude_control: bit(5),
+            run_in_standby: bit(6),
+            on_demand: bit(7),
+            startup: (bits >> 8 & 0x7) as u8,
+            write_locked: bit(12),
+        }
+    }
+
+    /// Encode as a value of `XOSC32K`
+    #[cfg(test)]
+    fn bits(&self) -> u16 {
+        (self.enabled as u16) << 1
+            | (self.crystal as u16) << 2
+            | (self.output_32k as u16) << 3
+            | (self.output_1k as u16) << 4
+            | (self.amplitude_control as u16) << 5
+            | (self.run_in_standby as u16) << 6
+            | (self.on_demand as u16) << 7
+            | (self.startup as u16 & 0x7) << 8
+            | (self.write_locked as u16) << 12
+    }
+}
+
+/// Gain of the external crystal oscillator
+pub type XoscGain = target_device::sysctrl::xosc::GAIN_A;
+
+/// Configuration of the external crystal oscillator.
+///
+/// The oscillator's gain must match the crystal, otherwise it may fail to
+/// start. `XoscConfig::crystal` picks the lowest gain setting whose
+/// frequency rating covers the crystal:
+///
+/// | Crystal frequency | `GAIN` |
+/// |-------------------|--------|
+/// | up to 2 MHz       | 0      |
+/// | 2 - 4 MHz         | 1      |
+/// | 4 - 8 MHz         | 2      |
+/// | 8 - 16 MHz        | 3      |
+/// | 16 - 32 MHz       | 4      |
+///
+/// Crystals with a high ESR or load capacitance may need a higher gain than
+/// recommended, which can be set with `XoscConfig::gain`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XoscConfig {
+    freq: Hertz,
+    gain: XoscGain,
+    amplitude_gain_control: bool,
+}
+
+impl XoscConfig {
+    /// Configuration for a crystal of the given frequency, which must be
+    /// between 0.4 and 32 MHz.
+    pub fn crystal<F: Into<Hertz>>(freq: F) -> Self {
+        let freq = freq.into();
+        Self {
+            freq,
+            gain: xosc_gain_for_freq(freq),
+            amplitude_gain_control: false,
+        }
+    }
+
+    /// Overrides the gain selected for the crystal frequency.
+    pub fn gain(mut self, gain: XoscGain) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Enables the automatic amplitude gain control, which lowers the gain
+    /// once the oscillations are stable, to reduce power consumption.
+    pub fn amplitude_gain_control(mut self, enable: bool) -> Self {
+        self.amplitude_gain_control = enable;
+        self
+    }
+
+    /// Returns the crystal frequency
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Returns the selected gain
+    pub fn selected_gain(&self) -> XoscGain {
+        self.gain
+    }
+}
+
+/// Returns the gain recommended for a crystal frequency. See `XoscConfig`.
+fn xosc_gain_for_freq(freq: Hertz) -> XoscGain {
+    match freq.0 {
+        0..=2_000_000 => XoscGain::_0,
+        2_000_001..=4_000_000 => XoscGain::_1,
+        4_000_001..=8_000_000 => XoscGain::_2,
+        8_000_001..=16_000_000 => XoscGain::_3,
+        _ => XoscGain::_4,
+    }
+}
+
 struct State {
     gclk: GCLK,
 }
@@ -55,21 +439,7 @@ impl State {
     ) {
         // validate the divisor factor based on gclk ID (samd21 see 15.8.5, for samd11
         // see 14.8.5)
-        let mut divisor_invalid = false;
-        if gclk == GCLK1 {
-            if divider as u32 >= 2_u32.pow(16) {
-                divisor_invalid = true;
-            }
-        } else if gclk == GCLK2 {
-            if divider >= 2_u16.pow(5) {
-                divisor_invalid = true;
-            }
-        } else {
-            if divider >= 2_u16.pow(8) {
-                divisor_invalid = true;
-            }
-        }
-        if divisor_invalid {
+        if divider > max_divider(gclk) {
             panic!("invalid divisor {} for GCLK {}", divider, gclk as u8);
         }
 
@@ -100,20 +470,101 @@ impl State {
         self.wait_for_sync();
     }
 
+    /// Returns the generator selected by a peripheral channel, or `None` if
+    /// the channel is disabled
+    fn read_pclk_gclk(&mut self, clock: ClockId) -> Option<ClockGenId> {
+        self.select_clkctrl(u8::from(clock));
+        let clkctrl = self.gclk.clkctrl.read();
+        match clkctrl.gen().variant() {
+            Variant::Val(gclk) if clkctrl.clken().bit_is_set() => Some(gclk),
+            _ => None,
+        }
+    }
+
+    /// Returns the generator of a peripheral channel, or `None` if the channel
+    /// is disabled
+    #[cfg(feature = "clock_map")]
+    fn read_pclk_generator(&mut self, clock: u8) -> Option<u8> {
+        self.select_clkctrl(clock);
+        let clkctrl = self.gclk.clkctrl.read();
+        if clkctrl.clken().bit_is_set() {
+            Some(clkctrl.gen().bits())
+        } else {
+            None
+        }
+    }
+
     fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
         // We must first read out the configuration of genctrl to read/modify/write it.
-        //   To do so, we must do an 8-bit write to GENCTRL.ID (ref 15.6.4.1 Indirect
+        self.select_genctrl(gclk);
+
+        // Now that the configuration is loaded, modify it
+        self.gclk.genctrl.modify(|_, w| w.runstdby().bit(enable));
+        self.wait_for_sync();
+    }
+
+    /// Loads the configuration of `gclk` into GENCTRL so that it can be read.
+    fn select_genctrl(&mut self, gclk: ClockGenId) {
+        // To do so, we must do an 8-bit write to GENCTRL.ID (ref 15.6.4.1 Indirect
         //   Access). 32-bit write did not work.
         unsafe {
             let genctrl_ptr_u8: *mut u8 = self.gclk.genctrl.as_ptr() as *mut u8;
             *genctrl_ptr_u8 = u8::from(gclk);
         }
         self.wait_for_sync();
+    }
 
-        // Now that the configuration is loaded, modify it
-        self.gclk.genctrl.modify(|_, w| w.runstdby().bit(enable));
+    /// Loads the configuration of `clock` into CLKCTRL so that it can be read.
+    fn select_clkctrl(&mut self, clock: u8) {
+        // Same indirect access as for GENCTRL
+        unsafe {
+            let clkctrl_ptr_u8: *mut u8 = self.gclk.clkctrl.as_ptr() as *mut u8;
+            *clkctrl_ptr_u8 = clock;
+        }
+    }
+
+    fn gate_clock(&mut self, clock: ClockId, enable: bool) {
+        self.select_clkctrl(u8::from(clock));
+        // SAFETY: The read-back ID and GEN are written again unchanged
+        self.gclk
+            .clkctrl
+            .modify(|r, w| unsafe { w.bits(clkctrl_gated(r.bits(), enable)) });
+        self.wait_for_sync();
+    }
+
+    /// Loads the divider of `gclk` into GENDIV so that it can be read.
+    fn select_gendiv(&mut self, gclk: ClockGenId) {
+        // Same indirect access as for GENCTRL
+        unsafe {
+            let gendiv_ptr_u8: *mut u8 = self.gclk.gendiv.as_ptr() as *mut u8;
+            *gendiv_ptr_u8 = u8::from(gclk);
+        }
         self.wait_for_sync();
     }
+
+    fn read_gclk_config(&mut self, gclk: ClockGenId) -> GClockConfig {
+        self.select_gendiv(gclk);
+        let div = self.gclk.gendiv.read().div().bits() as u32;
+        self.select_genctrl(gclk);
+        let genctrl = self.gclk.genctrl.read();
+        let div = if genctrl.divsel().bit_is_set() {
+            // exponential division by 2^(div + 1), at most 2^17 for GCLK1
+            1 << (div.min(16) + 1)
+        } else {
+            // zero and one both mean no division
+            div.max(1)
+        };
+        let src = gclk_source_from_bits(genctrl.src().bits());
+        let (output_enable, output_off_value) = gclk_output_from_bits(genctrl.bits());
+        GClockConfig {
+            src,
+            div,
+            enabled: genctrl.genen().bit_is_set(),
+            improve_duty_cycle: genctrl.idc().bit_is_set(),
+            output_enable,
+            output_off_value,
+        }
+    }
 }
 
 /// `GenericClockController` encapsulates the GCLK hardware.
@@ -124,8 +575,9 @@ impl State {
 /// gclk0 at 48Mhz.
 pub struct GenericClockController {
     state: State,
-    gclks: [Hertz; 8],
+    gclks: [Hertz; NUM_GCLK_GENERATORS],
     used_clocks: u64,
+    xosc: Hertz,
 }
 
 impl GenericClockController {
@@ -151,6 +603,48 @@ impl GenericClockController {
         Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, true)
     }
 
+    /// Like `with_external_32kosc`, but gives up waiting for the external
+    /// 32kHz crystal after polling its ready flag `timeout` times.
+    ///
+    /// `with_external_32kosc` spins on `XOSC32KRDY` forever, so a missing or
+    /// damaged crystal hangs the MCU at boot. On timeout, the oscillator is
+    /// disabled again and the untouched `GCLK` is handed back so that the
+    /// caller can fall back to the internal oscillator:
+    ///
+    /// ```ignore
+    /// let clocks = match GenericClockController::try_with_external_32kosc(
+    ///     peripherals.GCLK,
+    ///     &mut peripherals.PM,
+    ///     &mut peripherals.SYSCTRL,
+    ///     &mut peripherals.NVMCTRL,
+    ///     1_000_000,
+    /// ) {
+    ///     Ok(clocks) => clocks,
+    ///     Err(gclk) => GenericClockController::with_internal_32kosc(
+    ///         gclk,
+    ///         &mut peripherals.PM,
+    ///         &mut peripherals.SYSCTRL,
+    ///         &mut peripherals.NVMCTRL,
+    ///     ),
+    /// };
+    /// ```
+    ///
+    /// The crystal start-up time is in the order of a second, so `timeout`
+    /// should be generous; each poll is a single register read.
+    pub fn try_with_external_32kosc(
+        gclk: GCLK,
+        pm: &mut PM,
+        sysctrl: &mut SYSCTRL,
+        nvmctrl: &mut NVMCTRL,
+        timeout: u32,
+    ) -> Result<Self, GCLK> {
+        if try_enable_external_32kosc(sysctrl, timeout) {
+            Ok(Self::new_48mhz_from_32khz(gclk, pm, sysctrl, nvmctrl, true))
+        } else {
+            Err(gclk)
+        }
+    }
+
     fn new_48mhz_from_32khz(
         gclk: GCLK,
         pm: &mut PM,
@@ -199,17 +693,56 @@ impl GenericClockController {
 
         Self {
             state,
-            gclks: [
-                OSC48M_FREQ,
-                OSC32K_FREQ,
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-            ],
+            gclks: gclk_freqs(OSC48M_FREQ, OSC32K_FREQ),
             used_clocks: 1u64 << u8::from(ClockId::DFLL48),
+            xosc: Hertz(0),
+        }
+    }
+
+    /// Reset the clock controller, configure the system to run at 48Mhz from
+    /// the DFLL48 in open-loop mode and reset various clock dividers.
+    ///
+    /// The DFLL48 is started with the factory coarse calibration value from
+    /// the NVM software calibration area, and the middle of the fine range.
+    /// There is no reference clock and nothing to lock to, so the clock is
+    /// usable as soon as the DFLL48 reports ready. This makes for a fast boot
+    /// on boards without a crystal. The frequency is only accurate to about
+    /// 0.5-1% and drifts with temperature, which is too coarse for USB but
+    /// fine for most other peripherals.
+    pub fn with_open_loop_dfll(
+        gclk: GCLK,
+        pm: &mut PM,
+        sysctrl: &mut SYSCTRL,
+        nvmctrl: &mut NVMCTRL,
+    ) -> Self {
+        let mut state = State { gclk };
+
+        set_flash_to_half_auto_wait_state(nvmctrl);
+        #[cfg(feature = "samd21")]
+        set_flash_manual_write(nvmctrl);
+        enable_gclk_apb(pm);
+
+        state.reset_gclk();
+
+        enable_open_loop_dfll48m(sysctrl);
+        // Feed DFLL48 into the main clock
+        state.set_gclk_divider_and_source(GCLK0, 1, DFLL48M, true);
+
+        // Reset various dividers back to 1
+        sysctrl.osc8m.modify(|_, w| {
+            w.presc()._0();
+            w.ondemand().clear_bit()
+        });
+        pm.cpusel.write(|w| w.cpudiv().div1());
+        pm.apbasel.write(|w| w.apbadiv().div1());
+        pm.apbbsel.write(|w| w.apbbdiv().div1());
+        pm.apbcsel.write(|w| w.apbcdiv().div1());
+
+        Self {
+            state,
+            gclks: gclk_freqs(OSC48M_FREQ, Hertz(0)),
+            used_clocks: 0,
+            xosc: Hertz(0),
         }
     }
 
@@ -245,17 +778,9 @@ impl GenericClockController {
 
         Self {
             state,
-            gclks: [
-                OSC8M_FREQ,
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-                Hertz(0),
-            ],
+            gclks: gclk_freqs(OSC8M_FREQ, Hertz(0)),
             used_clocks: 0,
+            xosc: Hertz(0),
         }
     }
 
@@ -297,8 +822,17 @@ impl GenericClockController {
     /// this function doesn't expose that functionality at this time.
     /// `improve_duty_cycle` is a boolean that, when set to true, enables
     /// a 5o/50 duty cycle for odd divider values.
-    /// Returns a `GClock` for the configured clock generator.
+    /// Returns a `GClock` for the configured clock generator, with the
+    /// frequency of `src` divided by `divider`.
     /// Returns `None` if the clock generator has already been configured.
+    ///
+    /// `GCLKGEN1` can be the source of any generator but GCLK1 itself, which
+    /// would never start. `None` is returned for GCLK1 from `GCLKGEN1`, and
+    /// nothing is written to the hardware.
+    ///
+    /// `None` is also returned, before writing anything, if the frequency of
+    /// `src` isn't known to the controller: `GCLKIN`, or `XOSC` before
+    /// `configure_xosc`.
     pub fn configure_gclk_divider_and_source(
         &mut self,
         gclk: ClockGenId,
@@ -307,23 +841,187 @@ impl GenericClockController {
         improve_duty_cycle: bool,
     ) -> Option<GClock> {
         let idx = u8::from(gclk) as usize;
-        if self.gclks[idx].0 != 0 {
+        if self.gclks[idx].0 != 0 || feeds_itself(gclk, src) {
             return None;
         }
+        let freq = divided_freq(self.source_freq(src)?, divider);
         self.state
             .set_gclk_divider_and_source(gclk, divider, src, improve_duty_cycle);
-        let freq: Hertz = match src {
-            XOSC32K | OSC32K | OSCULP32K => OSC32K_FREQ,
-            GCLKGEN1 => self.gclks[1],
-            OSC8M => OSC8M_FREQ,
-            DFLL48M => OSC48M_FREQ,
-            DPLL96M => 96.mhz().into(),
-            GCLKIN | XOSC => unimplemented!(),
-        };
-        self.gclks[idx] = Hertz(freq.0 / divider as u32);
+        self.gclks[idx] = freq;
         Some(GClock { gclk, freq })
     }
 
+    /// Finds the divider that brings the frequency of `src` closest to
+    /// `target` on the given clock generator, without configuring anything.
+    /// The divider is limited to what the generator's `DIV` field can hold.
+    /// Pass the result to `configure_gclk_divider_and_source` to apply it.
+    /// Returns `None` if the frequency of `src` isn't known to the
+    /// controller.
+    pub fn div_to_reach<F: Into<Hertz>>(
+        &self,
+        gclk: ClockGenId,
+        src: ClockSource,
+        target: F,
+    ) -> Option<GClockDivider> {
+        let source = self.source_freq(src)?;
+        Some(closest_divider(source, target.into(), max_divider(gclk)))
+    }
+
+    /// Returns the frequency of a clock source, if it is known to the
+    /// controller.
+    fn source_freq(&self, src: ClockSource) -> Option<Hertz> {
+        match src {
+            XOSC32K | OSC32K | OSCULP32K => Some(OSC32K_FREQ),
+            GCLKGEN1 => Some(self.gclks[1]),
+            OSC8M => Some(OSC8M_FREQ),
+            DFLL48M => Some(OSC48M_FREQ),
+            DPLL96M => Some(96.mhz().into()),
+            XOSC if self.xosc.0 != 0 => Some(self.xosc),
+            GCLKIN | XOSC => None,
+        }
+    }
+
+    /// Enables the external crystal oscillator and waits for it to become
+    /// ready. Once enabled, it can be used as the source of a clock
+    /// generator with `configure_gclk_divider_and_source`.
+    pub fn configure_xosc(&mut self, sysctrl: &mut SYSCTRL, config: XoscConfig) {
+        enable_xosc(sysctrl, &config);
+        self.xosc = config.freq;
+    }
+
+    /// Reads back the configuration of a clock generator from the hardware.
+    /// This doesn't modify the generator, but it does use the indirect
+    /// access mechanism of `GENCTRL` and `GENDIV` to select it.
+    pub fn read_gclk_config(&mut self, gclk: ClockGenId) -> GClockConfig {
+        self.state.read_gclk_config(gclk)
+    }
+
+    /// Reads back the GCLK_IO pin configuration of a clock generator as
+    /// `(output_enable, output_off_value)`.
+    ///
+    /// Useful when a clock output pin appears stuck: with the output
+    /// enabled, the pin holds the output-off value whenever the generator
+    /// is disabled.
+    pub fn gclk_output_config(&mut self, gclk: ClockGenId) -> (bool, bool) {
+        let config = self.state.read_gclk_config(gclk);
+        (config.output_enable, config.output_off_value)
+    }
+
+    /// Takes a snapshot of a clock generator's hardware configuration and
+    /// resulting frequency.
+    pub fn snapshot(&mut self, gclk: ClockGenId) -> GClockSnapshot {
+        let config = self.state.read_gclk_config(gclk);
+        let source_freq = config.src.and_then(|src| self.source_freq(src));
+        GClockSnapshot::new(gclk, config, source_freq)
+    }
+
+    /// Returns the live frequency of a peripheral channel, read from the
+    /// hardware rather than from the controller's records.
+    ///
+    /// The channel's generator is looked up in `CLKCTRL`, then the source and
+    /// divider of that generator in `GENCTRL`. Returns `None` if the channel
+    /// or its generator is disabled, or if the frequency of the generator's
+    /// source isn't known to the controller. This doesn't modify the clocks
+    /// in any way.
+    pub fn live_pclk_freq(&mut self, clock: ClockId) -> Option<Hertz> {
+        let gclk = self.state.read_pclk_gclk(clock)?;
+        self.snapshot(gclk).running_freq()
+    }
+
+    /// Checks that the hardware configuration of a clock generator agrees
+    /// with the frequency this controller has recorded for it.
+    ///
+    /// A generator that hasn't been configured through the controller is
+    /// expected to be disabled. A configured generator is expected to be
+    /// enabled and, if its source frequency is known, to divide it down to
+    /// the recorded frequency.
+    /// Returns the read-back configuration if it matches, or a
+    /// `GClockMismatch` describing the discrepancy.
+    pub fn reconcile(&mut self, gclk: ClockGenId) -> Result<GClockConfig, GClockMismatch> {
+        let expected = self.gclks[u8::from(gclk) as usize];
+        let actual = self.state.read_gclk_config(gclk);
+        let matches = if expected.0 == 0 {
+            !actual.enabled
+        } else {
+            let freq = actual
+                .src
+                .and_then(|src| self.source_freq(src))
+                .map(|freq| actual.freq(freq));
+            actual.enabled && freq.map_or(true, |freq| freq == expected)
+        };
+        if matches {
+            Ok(actual)
+        } else {
+            Err(GClockMismatch {
+                gclk,
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Returns the source currently selected in `GENCTRL` for GCLK0, the
+    /// main clock, or `None` if the field holds a reserved value.
+    ///
+    /// Code switching the main clock to another source can use this to check
+    /// the starting state, and to avoid switching twice.
+    pub fn gclk0_source(&mut self) -> Option<ClockSource> {
+        self.state.read_gclk_config(GCLK0).src
+    }
+
+    /// Returns `true` if GCLK0, the main clock, currently runs from the DFLL.
+    pub fn gclk0_runs_from_dfll(&mut self) -> bool {
+        self.gclk0_source() == Some(DFLL48M)
+    }
+
+    /// Reads back the configuration of the external 32 kHz oscillator, e.g.
+    /// to check that it runs from a crystal with its 32 kHz output enabled.
+    pub fn xosc32k_config(&self, sysctrl: &SYSCTRL) -> Xosc32kConfig {
+        Xosc32kConfig::from_bits(sysctrl.xosc32k.read().bits())
+    }
+
+    /// Checks that the source of GCLK0, the main clock, is running
+    ///
+    /// GCLK0 stops while its source is not ready, and the CPU with it, e.g.
+    /// when a crystal fails to start or the DPLL loses its reference. Calling
+    /// this at boot, before relying on the main clock, reports such a
+    /// configuration instead. The source is ready when:
+    ///
+    /// - XOSC, OSC32K, XOSC32K, OSC8M and DFLL48M: their `RDY` bit is set in
+    ///   `SYSCTRL.PCLKSR`
+    /// - DPLL96M: both `DPLLSTATUS.LOCK` and `DPLLSTATUS.CLKRDY` are set
+    /// - OSCULP32K: always, as it can't be disabled
+    /// - GCLKIN: always, as the input pin is not monitored
+    /// - GCLKGEN1: GCLK1 is enabled, and its own source is ready
+    ///
+    /// The source which is not ready is returned, i.e. the source of GCLK1 if
+    /// GCLK0 runs from GCLK1.
+    pub fn check_main_clock(&mut self, sysctrl: &SYSCTRL) -> Result<(), MainClockError> {
+        let gclk1 = self.state.read_gclk_config(GCLK1);
+        let pclksr = sysctrl.pclksr.read();
+        let dpllstatus = sysctrl.dpllstatus.read();
+        let status = SourceStatus {
+            xosc: pclksr.xoscrdy().bit_is_set(),
+            osc32k: pclksr.osc32krdy().bit_is_set(),
+            xosc32k: pclksr.xosc32krdy().bit_is_set(),
+            osc8m: pclksr.osc8mrdy().bit_is_set(),
+            dfll: pclksr.dfllrdy().bit_is_set(),
+            dpll: dpllstatus.lock().bit_is_set() && dpllstatus.clkrdy().bit_is_set(),
+            gclk1: if gclk1.enabled { gclk1.src } else { None },
+        };
+        match self.state.read_gclk_config(GCLK0).src {
+            Some(src) => check_source(src, &status),
+            None => Err(MainClockError::InvalidSource),
+        }
+    }
+
+    /// Returns the generator of the peripheral channel with the given ID, or
+    /// `None` if the channel is disabled.
+    #[cfg(feature = "clock_map")]
+    pub(crate) fn pclk_generator(&mut self, clock: u8) -> Option<u8> {
+        self.state.read_pclk_generator(clock)
+    }
+
     /// Enables or disables the given GClk from operation in standby.
     pub fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
         self.state.configure_standby(gclk, enable)
@@ -351,12 +1049,24 @@ impl $Type {
     pub fn freq(&self) -> Hertz {
         self.freq
     }
+
+    /// Enable or disable the peripheral channel, e.g. to save power during
+    /// sleep. The clock generator selection is retained, so re-enabling the
+    /// channel restores the configured frequency.
+    pub fn gate(&mut self, clocks: &mut GenericClockController, enable: bool) {
+        clocks.state.gate_clock(ClockId::$clock, enable);
+    }
 }
 impl Into<Hertz> for $Type {
     fn into(self) -> Hertz {
         self.freq
     }
 }
+impl PclkToken for $Type {
+    fn pclk_freq(&self) -> Hertz {
+        self.freq
+    }
+}
 )+
 
 impl GenericClockController {
@@ -397,6 +1107,7 @@ clock_generator!(
     (sercom0_core, Sercom0CoreClock, SERCOM0_CORE),
     (sercom1_core, Sercom1CoreClock, SERCOM1_CORE),
     (sercom2_core, Sercom2CoreClock, SERCOM2_CORE),
+    (sercomx_slow, SercomxSlowClock, SERCOMX_SLOW),
     (rtc, RtcClock, RTC),
     (adc, AdcClock, ADC),
     (wdt, WdtClock, WDT),
@@ -424,6 +1135,7 @@ clock_generator!(
     (sercom3_core, Sercom3CoreClock, SERCOM3_CORE),
     (sercom4_core, Sercom4CoreClock, SERCOM4_CORE),
     (sercom5_core, Sercom5CoreClock, SERCOM5_CORE),
+    (sercomx_slow, SercomxSlowClock, SERCOMX_SLOW),
     (usb, UsbClock, USB),
     (rtc, RtcClock, RTC),
     (adc, AdcClock, ADC),
@@ -490,6 +1202,27 @@ pub fn enable_internal_32kosc(sysctrl: &mut SYSCTRL) {
 
 /// Turn on the external 32hkz oscillator
 pub fn enable_external_32kosc(sysctrl: &mut SYSCTRL) {
+    start_external_32kosc(sysctrl);
+    while sysctrl.pclksr.read().xosc32krdy().bit_is_clear() {
+        // Wait for the oscillator to stabilize
+    }
+}
+
+/// Turn on the external 32hkz oscillator, polling the ready flag at most
+/// `timeout` times. If the oscillator does not stabilize in time, it is
+/// disabled again and `false` is returned.
+pub fn try_enable_external_32kosc(sysctrl: &mut SYSCTRL, timeout: u32) -> bool {
+    start_external_32kosc(sysctrl);
+    for _ in 0..timeout {
+        if sysctrl.pclksr.read().xosc32krdy().bit_is_set() {
+            return true;
+        }
+    }
+    sysctrl.xosc32k.modify(|_, w| w.enable().clear_bit());
+    false
+}
+
+fn start_external_32kosc(sysctrl: &mut SYSCTRL) {
     sysctrl.xosc32k.modify(|_, w| {
         unsafe {
             // 6 here means: use 64k cycles of OSCULP32k to start up this oscillator
@@ -503,9 +1236,26 @@ pub fn enable_external_32kosc(sysctrl: &mut SYSCTRL) {
         w.runstdby().set_bit()
     });
     sysctrl.xosc32k.modify(|_, w| w.enable().set_bit());
-    while sysctrl.pclksr.read().xosc32krdy().bit_is_clear() {
+}
+
+/// Turn on the external crystal oscillator
+fn enable_xosc(sysctrl: &mut SYSCTRL, config: &XoscConfig) {
+    sysctrl.xosc.write(|w| {
+        w.gain().variant(config.gain);
+        w.ondemand().clear_bit();
+        // Crystal connected to xin/xout
+        w.xtalen().set_bit();
+        w.enable().set_bit()
+    });
+    while sysctrl.pclksr.read().xoscrdy().bit_is_clear() {
         // Wait for the oscillator to stabilize
     }
+
+    // The amplitude gain control must only be enabled once the oscillator is
+    // ready (see the silicon errata)
+    if config.amplitude_gain_control {
+        sysctrl.xosc.modify(|_, w| w.ampgc().set_bit());
+    }
 }
 
 fn wait_for_dfllrdy(sysctrl: &mut SYSCTRL) {
@@ -597,3 +1347,280 @@ fn configure_and_enable_dfll48m(sysctrl: &mut SYSCTRL, use_external_crystal: boo
 
     wait_for_dfllrdy(sysctrl);
 }
+
+/// `DFLLVAL.FINE` used in open-loop mode, the middle of its 10-bit range
+const DFLL_OPEN_LOOP_FINE: u16 = 0x200;
+
+/// Value of `DFLLVAL` for the given calibration values
+fn dfllval_bits(coarse: u8, fine: u16) -> u32 {
+    ((coarse as u32 & 0x3f) << 10) | (fine as u32 & 0x3ff)
+}
+
+/// Start the dfll48m in open-loop mode, from the factory calibration
+fn enable_open_loop_dfll48m(sysctrl: &mut SYSCTRL) {
+    // Same as in closed-loop mode, the configuration only sticks while
+    // on-demand mode is off
+    sysctrl.dfllctrl.write(|w| w.ondemand().clear_bit());
+    wait_for_dfllrdy(sysctrl);
+
+    let coarse = super::calibration::dfll48m_coarse_cal();
+    sysctrl
+        .dfllval
+        .write(|w| unsafe { w.bits(dfllval_bits(coarse, DFLL_OPEN_LOOP_FINE)) });
+    wait_for_dfllrdy(sysctrl);
+
+    // MODE cleared selects open-loop operation
+    sysctrl.dfllctrl.write(|w| {
+        w.ondemand().clear_bit();
+        w.enable().set_bit()
+    });
+    wait_for_dfllrdy(sysctrl);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_loop_dfllval_packs_calibration() {
+        // Errata default coarse value, middle of the fine range
+        assert_eq!(dfllval_bits(0x1f, DFLL_OPEN_LOOP_FINE), 0x7e00);
+        assert_eq!(dfllval_bits(0x3f, 0x3ff), 0xffff);
+        assert_eq!(dfllval_bits(0, 0), 0);
+    }
+
+    #[test]
+    fn gclk_snapshot_tracks_divider() {
+        let config = GClockConfig {
+            src: Some(DFLL48M),
+            div: 2,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        let before = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        let again = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        assert_eq!(before, again);
+        assert_eq!(before.freq, Some(Hertz(24_000_000)));
+
+        let config = GClockConfig { div: 4, ..config };
+        let after = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        assert_ne!(before, after);
+        assert_eq!(after.freq, Some(Hertz(12_000_000)));
+    }
+
+    #[test]
+    fn live_freq_of_a_running_generator() {
+        // A SERCOM core clock fed from GCLK3, running from the DFLL / 2
+        let config = GClockConfig {
+            src: Some(DFLL48M),
+            div: 2,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, Some(OSC48M_FREQ));
+        assert_eq!(gclk3.running_freq(), Some(Hertz(24_000_000)));
+
+        let config = GClockConfig {
+            enabled: false,
+            ..config
+        };
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, Some(OSC48M_FREQ));
+        assert_eq!(gclk3.running_freq(), None);
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, None);
+        assert_eq!(gclk3.running_freq(), None);
+    }
+
+    #[test]
+    fn gclk_output_bits_are_decoded() {
+        // OE is bit 19 and OOV bit 18, next to IDC and DIVSEL
+        assert_eq!(gclk_output_from_bits(0x0008_0000), (true, false));
+        assert_eq!(gclk_output_from_bits(0x0004_0000), (false, true));
+        assert_eq!(gclk_output_from_bits(0x000C_0000), (true, true));
+        // ID, SRC, GENEN, IDC, DIVSEL and RUNSTDBY don't read as output bits
+        assert_eq!(gclk_output_from_bits(0xFFF3_FFFF), (false, false));
+    }
+
+    #[test]
+    fn gating_a_channel_keeps_its_generator() {
+        // CLKEN set, GEN = 3, ID = 0x14 (SERCOM0_CORE on SAMD21)
+        let clkctrl = 0x4314;
+        let gated = clkctrl_gated(clkctrl, false);
+        assert_eq!(gated, 0x0314);
+        assert_eq!(clkctrl_gated(gated, false), 0x0314);
+        // Re-enabling restores the channel as it was configured
+        assert_eq!(clkctrl_gated(gated, true), clkctrl);
+        // WRTLOCK is kept as well
+        assert_eq!(clkctrl_gated(0xC314, false), 0x8314);
+    }
+
+    #[test]
+    fn gclk0_source_is_read_back() {
+        for &src in GCLK_SOURCES.iter() {
+            assert_eq!(gclk_source_from_bits(u8::from(src)), Some(src));
+        }
+        assert_eq!(gclk_source_from_bits(9), None);
+        assert_eq!(gclk_source_from_bits(31), None);
+
+        // GCLK0 out of reset: SRC = 6, OSC8M
+        let config = GClockConfig {
+            src: gclk_source_from_bits(6),
+            div: 1,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        assert_eq!(config.src, Some(OSC8M));
+        assert_eq!(config.source_freq(), Some(OSC8M_FREQ));
+
+        // GCLK0 after `new`: SRC = 7, the DFLL
+        let config = GClockConfig {
+            src: gclk_source_from_bits(7),
+            ..config
+        };
+        assert_eq!(config.src, Some(DFLL48M));
+        assert_eq!(config.source_freq(), Some(OSC48M_FREQ));
+        assert_eq!(config.freq(OSC48M_FREQ), OSC48M_FREQ);
+    }
+
+    #[test]
+    fn gclk1_cant_feed_itself() {
+        assert!(feeds_itself(GCLK1, GCLKGEN1));
+        assert!(!feeds_itself(GCLK2, GCLKGEN1));
+        assert!(!feeds_itself(GCLK1, OSC32K));
+    }
+
+    #[test]
+    fn configured_gclk_runs_at_the_divided_freq() {
+        // The RTC clock of the feather_m0 `clock` example: 32 kHz / 32
+        assert_eq!(divided_freq(OSC32K_FREQ, 32), Hertz(1024));
+        assert_eq!(divided_freq(OSC48M_FREQ, 48), Hertz(1_000_000));
+        // Zero and one both leave the source undivided
+        assert_eq!(divided_freq(OSC48M_FREQ, 0), OSC48M_FREQ);
+        assert_eq!(divided_freq(OSC48M_FREQ, 1), OSC48M_FREQ);
+    }
+
+    #[test]
+    fn div_to_reach_picks_closest_legal_divider() {
+        // 48 MHz / 7 is closer to 7 MHz than 48 MHz / 6
+        let div = closest_divider(Hertz(48_000_000), Hertz(7_000_000), 255);
+        assert_eq!(div.divider, 7);
+        assert_eq!(div.freq, Hertz(6_857_142));
+        assert_eq!(div.error, Hertz(142_858));
+
+        let div = closest_divider(Hertz(48_000_000), Hertz(1_000_000), 255);
+        assert_eq!((div.divider, div.error), (48, Hertz(0)));
+
+        // GCLK2 only has a 5-bit divider
+        let div = closest_divider(Hertz(48_000_000), Hertz(1_000), max_divider(GCLK2));
+        assert_eq!(div.divider, 31);
+        assert_eq!(div.freq, Hertz(1_548_387));
+
+        let div = closest_divider(OSC32K_FREQ, Hertz(1_000_000), 255);
+        assert_eq!(div.divider, 1);
+    }
+
+    #[test]
+    fn gclk_config_reports_source_freq() {
+        let config = GClockConfig {
+            src: Some(OSCULP32K),
+            div: 1,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        assert_eq!(config.source_freq(), Some(Hertz(32_768)));
+        assert_eq!(config.freq(config.source_freq().unwrap()), Hertz(32_768));
+
+        let config = GClockConfig {
+            src: Some(GCLKIN),
+            ..config
+        };
+        assert_eq!(config.source_freq(), None);
+        let config = GClockConfig {
+            src: None,
+            ..config
+        };
+        assert_eq!(config.source_freq(), None);
+    }
+
+    #[test]
+    fn xosc_gain_follows_crystal_frequency() {
+        assert_eq!(xosc_gain_for_freq(Hertz(2_000_000)), XoscGain::_0);
+        assert_eq!(xosc_gain_for_freq(Hertz(3_686_400)), XoscGain::_1);
+        assert_eq!(xosc_gain_for_freq(Hertz(8_000_000)), XoscGain::_2);
+        assert_eq!(xosc_gain_for_freq(Hertz(12_000_000)), XoscGain::_3);
+        assert_eq!(xosc_gain_for_freq(Hertz(32_000_000)), XoscGain::_4);
+    }
+
+    #[test]
+    fn xosc_gain_can_be_overridden() {
+        let config = XoscConfig::crystal(8.mhz()).gain(XoscGain::_4);
+        assert_eq!(config.selected_gain(), XoscGain::_4);
+        assert_eq!(config.freq(), Hertz(8_000_000));
+    }
+
+    #[test]
+    fn main_clock_source_readiness() {
+        let mut status = SourceStatus {
+            osc8m: true,
+            ..SourceStatus::default()
+        };
+        assert_eq!(check_source(OSC8M, &status), Ok(()));
+        assert_eq!(check_source(OSCULP32K, &status), Ok(()));
+        assert_eq!(
+            check_source(DFLL48M, &status),
+            Err(MainClockError::NotReady(DFLL48M))
+        );
+        status.dfll = true;
+        assert_eq!(check_source(DFLL48M, &status), Ok(()));
+
+        // Through GCLK1, the root source is checked
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::InvalidSource)
+        );
+        status.gclk1 = Some(XOSC32K);
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::NotReady(XOSC32K))
+        );
+        status.xosc32k = true;
+        assert_eq!(check_source(GCLKGEN1, &status), Ok(()));
+        status.gclk1 = Some(GCLKGEN1);
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::InvalidSource)
+        );
+    }
+
+    #[test]
+    fn every_generator_is_tracked() {
+        #[cfg(feature = "samd11")]
+        let last = ClockGenId::GCLK5;
+        #[cfg(feature = "samd21")]
+        let last = ClockGenId::GCLK8;
+        assert_eq!(u8::from(last) as usize, NUM_GCLK_GENERATORS - 1);
+        let gclks = gclk_freqs(Hertz(48_000_000), Hertz(32_768));
+        assert_eq!(gclks.len(), NUM_GCLK_GENERATORS);
+        assert_eq!(gclks[u8::from(last) as usize], Hertz(0));
+    }
+
+    #[test]
+    fn xosc32k_config_round_trip() {
+        // As configured by `with_external_32kosc`
+        let config = Xosc32kConfig::from_bits(0x064e);
+        assert!(config.enabled && config.crystal && config.output_32k);
+        assert_eq!(config.startup, 6);
+        for bits in 0..1u16 << 13 {
+            // Bits 0 and 11 are reserved
+            let bits = bits & 0x17fe;
+            assert_eq!(Xosc32kConfig::from_bits(bits).bits(), bits);
+        }
+    }
+}
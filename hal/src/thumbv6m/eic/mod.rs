@@ -1,3 +1,12 @@
+//! # External Interrupt Controller
+//!
+//! On SAMD11/21, the EIC is always clocked by the generic clock `GCLK_EIC`.
+//! It is needed to synchronize the registers, for edge detection and for the
+//! input filter. Only level detection keeps working while the clock is
+//! stopped, e.g. to wake up from standby. [`EIC::init`] therefore requires an
+//! [`EicClock`] as proof that the clock has been set up, which also makes the
+//! filter available on every line.
+
 use crate::clock::EicClock;
 use crate::target_device;
 
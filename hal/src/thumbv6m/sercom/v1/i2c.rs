@@ -1,7 +1,13 @@
 // Note: section 7.2.3 shows which pins support I2C Hs mode
 
-use crate::clock;
+use crate::clock::{self, PclkUser};
 use crate::hal::blocking::i2c::{Read, Write, WriteRead};
+use crate::sercom::v1::i2c_scan::{self, AddressSet, ProbeStrategy};
+use crate::sercom::v1::i2c_slave::{
+    ctrla_with_mode, next_slave_flag, I2CListener, SlaveEvent, SlaveFlag, MODE_I2C_MASTER,
+    MODE_I2C_SLAVE, SLAVE_CMD_COMPLETE, SLAVE_CMD_CONTINUE,
+};
+use crate::sercom::v1::self_test::{SelfTest, SelfTestError, HS_MASTER_CODE};
 use crate::target_device::sercom0::I2CM;
 use crate::target_device::{PM, SERCOM0, SERCOM1};
 #[cfg(feature = "samd21")]
@@ -9,6 +15,7 @@ use crate::target_device::{SERCOM2, SERCOM3};
 #[cfg(feature = "min-samd21g")]
 use crate::target_device::{SERCOM4, SERCOM5};
 use crate::time::Hertz;
+use core::ops::RangeInclusive;
 
 const BUS_STATE_UNKNOWN: u8 = 0;
 const BUS_STATE_IDLE: u8 = 1;
@@ -29,6 +36,7 @@ pub struct $Type<$pad0, $pad1> {
     sda: $pad0,
     scl: $pad1,
     sercom: $SERCOM,
+    slow: Option<PclkUser<clock::SercomxSlowClock>>,
 }
 
 impl<$pad0, $pad1> $Type<$pad0, $pad1> {
@@ -96,7 +104,12 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
             while sercom.i2cm().syncbusy.read().sysop().bit_is_set() {}
         }
 
-        Self { sda, scl, sercom }
+        Self {
+            sda,
+            scl,
+            sercom,
+            slow: None,
+        }
     }
 
     /// Breaks the sercom device up into its constituent pins and the SERCOM
@@ -105,6 +118,39 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         (self.sda, self.scl, self.sercom)
     }
 
+    /// Abort transfers when SCL is held low for 25 to 35 ms, with a
+    /// [`I2CError::Timeout`]
+    ///
+    /// The timeout is timed by the SERCOM slow clock, shared by every SERCOM,
+    /// which must run at 32 kHz. `slow` is a user of that clock, from a
+    /// [`SharedPclk`](clock::SharedPclk) of `clocks.sercomx_slow()`. It is held
+    /// until the timeout is disabled.
+    pub fn enable_scl_low_timeout(&mut self, slow: PclkUser<clock::SercomxSlowClock>) {
+        self.set_scl_low_timeout(true);
+        self.slow = Some(slow);
+    }
+
+    /// Stop aborting transfers on SCL low timeouts, and return the user of
+    /// the slow clock, if the timeout was enabled
+    pub fn disable_scl_low_timeout(&mut self) -> Option<PclkUser<clock::SercomxSlowClock>> {
+        self.set_scl_low_timeout(false);
+        self.slow.take()
+    }
+
+    fn set_scl_low_timeout(&mut self, enable: bool) {
+        unsafe {
+            self.i2cm().ctrla.modify(|_, w| w.enable().clear_bit());
+            while self.i2cm().syncbusy.read().enable().bit_is_set() {}
+            self.i2cm().ctrla.modify(|_, w| w.lowtouten().bit(enable));
+            self.i2cm().ctrla.modify(|_, w| w.enable().set_bit());
+            while self.i2cm().syncbusy.read().enable().bit_is_set() {}
+            self.i2cm()
+                .status
+                .modify(|_, w| w.busstate().bits(BUS_STATE_IDLE));
+            while self.i2cm().syncbusy.read().sysop().bit_is_set() {}
+        }
+    }
+
     fn start_tx_write(&mut self, addr: u8) -> Result<(), I2CError> {
         let status = self.i2cm().status.read();
         if status.busstate().bits() == BUS_STATE_BUSY
@@ -169,9 +215,10 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         // wait for transmission to complete
         loop {
             let intflag = self.i2cm().intflag.read();
-            // If arbitration was lost, it will be signalled via the mb bit
+            // If arbitration was lost, or if the address was not
+            // acknowledged, it will be signalled via the mb bit
             if intflag.mb().bit_is_set() {
-                return Err(I2CError::ArbitrationLost);
+                return self.status_to_err().and(Err(I2CError::ArbitrationLost));
             }
             if intflag.sb().bit_is_set() || intflag.error().bit_is_set() {
                 break;
@@ -272,6 +319,63 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         self.start_tx_read(addr)?;
         self.fill_buffer(buffer)
     }
+
+    /// Address `addr` without transferring any data, and return whether a
+    /// device acknowledged
+    ///
+    /// The probe always ends with a STOP, and the bus state is forced back
+    /// to idle after a bus error, an arbitration loss or a timeout. See the
+    /// [`i2c_scan`](crate::sercom::v1::i2c_scan) module.
+    pub fn probe(&mut self, addr: u8, strategy: ProbeStrategy) -> Result<(), I2CError> {
+        let result = match strategy {
+            ProbeStrategy::Write => self.write(addr, &[]),
+            ProbeStrategy::Read => self.read(addr, &mut [0]),
+        };
+        if matches!(
+            result,
+            Err(I2CError::ArbitrationLost) | Err(I2CError::BusError) | Err(I2CError::Timeout)
+        ) {
+            self.recover_bus();
+        }
+        result
+    }
+
+    /// Probe every address of `range`, and return the addresses which
+    /// acknowledged
+    ///
+    /// Addresses above `0x7F` are skipped.
+    /// [`SCAN_RANGE`](crate::sercom::v1::SCAN_RANGE) holds the addresses
+    /// which are not reserved.
+    pub fn scan(&mut self, range: RangeInclusive<u8>, strategy: ProbeStrategy) -> AddressSet {
+        self.scan_verbose(range, strategy, |_, _| ())
+    }
+
+    /// Probe every address of `range` like [`scan`](Self::scan), and also
+    /// pass the outcome of every probe to `report`
+    pub fn scan_verbose<F>(
+        &mut self,
+        range: RangeInclusive<u8>,
+        strategy: ProbeStrategy,
+        report: F,
+    ) -> AddressSet
+    where
+        F: FnMut(u8, Result<(), I2CError>),
+    {
+        i2c_scan::scan(range, |addr| self.probe(addr, strategy), report)
+    }
+
+    /// Clear the error flags, and force the bus state to idle
+    fn recover_bus(&mut self) {
+        unsafe {
+            self.i2cm().status.write(|w| {
+                w.arblost().set_bit();
+                w.buserr().set_bit();
+                w.lowtout().set_bit();
+                w.busstate().bits(BUS_STATE_IDLE)
+            });
+        }
+        self.wait_sync();
+    }
 }
 impl<$pad0, $pad1> Write for $Type<$pad0, $pad1> {
     type Error = I2CError;
@@ -303,6 +407,117 @@ impl<$pad0, $pad1> WriteRead for $Type<$pad0, $pad1> {
         res
     }
 }
+
+impl<$pad0, $pad1> SelfTest for $Type<$pad0, $pad1> {
+    /// Address the Hs-mode master code, which must not be acknowledged. See
+    /// the [`self_test`](crate::sercom::v1::self_test) module.
+    fn run(&mut self) -> Result<(), SelfTestError> {
+        match self.write(HS_MASTER_CODE, &[]) {
+            Err(I2CError::Nack) => Ok(()),
+            _ => Err(SelfTestError::Bus),
+        }
+    }
+}
+
+impl<$pad0, $pad1> $Type<$pad0, $pad1> {
+    /// Switch to slave mode on the same pads after losing arbitration,
+    /// answering to the 7-bit address `addr`
+    ///
+    /// See the [`i2c_slave`](crate::sercom::v1::i2c_slave) module for more
+    /// details.
+    pub fn into_listen_after_arbitration_loss(self, addr: u8) -> I2CListener<Self> {
+        let baud = self.sercom.i2cm().baud.read().baud().bits();
+        let i2cm = self.sercom.i2cm();
+        i2cm.ctrla.modify(|_, w| w.enable().clear_bit());
+        while i2cm.syncbusy.read().enable().bit_is_set() {}
+        i2cm.ctrla
+            .modify(|r, w| unsafe { w.bits(ctrla_with_mode(r.bits(), MODE_I2C_SLAVE)) });
+
+        let i2cs = self.sercom.i2cs();
+        i2cs.addr.write(|w| unsafe { w.addr().bits(addr as u16) });
+        // Discard the flags left over from master mode
+        i2cs.intflag.write(|w| unsafe { w.bits(0xFF) });
+        i2cs.ctrla.modify(|_, w| w.enable().set_bit());
+        while i2cs.syncbusy.read().enable().bit_is_set() {}
+
+        I2CListener { master: self, baud }
+    }
+}
+
+impl<$pad0, $pad1> I2CListener<$Type<$pad0, $pad1>> {
+    /// Handle the next slave event, if any
+    ///
+    /// Addresses and received bytes are acknowledged before returning. When
+    /// the master NACKs a byte sent by us, the transfer is completed and no
+    /// event is returned.
+    pub fn poll(&mut self) -> Option<SlaveEvent> {
+        let i2cs = self.master.sercom.i2cs();
+        let status = i2cs.status.read();
+        let read = status.dir().bit_is_set();
+        match next_slave_flag(i2cs.intflag.read().bits())? {
+            SlaveFlag::Error => {
+                i2cs.intflag.write(|w| w.error().set_bit());
+                Some(SlaveEvent::Error)
+            }
+            SlaveFlag::Stop => {
+                i2cs.intflag.write(|w| w.prec().set_bit());
+                Some(SlaveEvent::Stop)
+            }
+            SlaveFlag::AddressMatch => {
+                self.command(SLAVE_CMD_CONTINUE);
+                Some(SlaveEvent::AddressMatch { read })
+            }
+            SlaveFlag::DataReady if read => {
+                if status.rxnack().bit_is_set() {
+                    self.command(SLAVE_CMD_COMPLETE);
+                    None
+                } else {
+                    Some(SlaveEvent::ByteRequested)
+                }
+            }
+            SlaveFlag::DataReady => {
+                let byte = i2cs.data.read().bits();
+                self.command(SLAVE_CMD_CONTINUE);
+                Some(SlaveEvent::Received(byte))
+            }
+        }
+    }
+
+    /// Send a byte after a [`SlaveEvent::ByteRequested`] event
+    pub fn respond(&mut self, byte: u8) {
+        let i2cs = self.master.sercom.i2cs();
+        i2cs.data.write(|w| unsafe { w.bits(byte) });
+        self.command(SLAVE_CMD_CONTINUE);
+    }
+
+    fn command(&mut self, cmd: u8) {
+        self.master.sercom.i2cs().ctrlb.modify(|_, w| unsafe {
+            // clear bit means send ack
+            w.ackact().clear_bit();
+            w.cmd().bits(cmd)
+        });
+    }
+
+    /// Switch back to master mode, with the bus frequency used before
+    pub fn into_master(self) -> $Type<$pad0, $pad1> {
+        let i2cs = self.master.sercom.i2cs();
+        i2cs.ctrla.modify(|_, w| w.enable().clear_bit());
+        while i2cs.syncbusy.read().enable().bit_is_set() {}
+        i2cs.ctrla
+            .modify(|r, w| unsafe { w.bits(ctrla_with_mode(r.bits(), MODE_I2C_MASTER)) });
+
+        let i2cm = self.master.sercom.i2cm();
+        i2cm.baud.write(|w| unsafe { w.baud().bits(self.baud) });
+        i2cm.ctrla.modify(|_, w| w.enable().set_bit());
+        while i2cm.syncbusy.read().enable().bit_is_set() {}
+        // set the bus idle
+        i2cm.status
+            .modify(|_, w| unsafe { w.busstate().bits(BUS_STATE_IDLE) });
+        while i2cm.syncbusy.read().sysop().bit_is_set() {}
+
+        self.master
+    }
+}
         )+
     };
 }
@@ -1,6 +1,9 @@
 use crate::clock;
 use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use crate::sercom::pads::*;
+use crate::sercom::v1::self_test::{
+    exchange_pattern, spi_data_out_pad, wait, SelfTest, SelfTestError,
+};
 use crate::spi_common::CommonSpi;
 use crate::target_device::sercom0::SPI;
 use crate::target_device::{PM, SERCOM0, SERCOM1};
@@ -241,6 +244,41 @@ macro_rules! spi_master {
             }
         }
 
+        impl<MISO, MOSI, SCK> SelfTest for $Type<MISO, MOSI, SCK> {
+            /// Shift the pattern back in through the data output pad.
+            /// See the [`self_test`](crate::sercom::v1::self_test) module.
+            fn run(&mut self) -> Result<(), SelfTestError> {
+                let ctrla = self.spi().ctrla.read();
+                let out_pad = spi_data_out_pad(ctrla.dopo().bits());
+                let ctrla = ctrla.bits();
+
+                // DIPO is enable-protected
+                self.disable();
+                self.spi_mut().ctrla.modify(|_, w| unsafe { w.dipo().bits(out_pad) });
+                self.enable();
+
+                let spi = self.spi();
+                while spi.intflag.read().rxc().bit_is_set() {
+                    spi.data.read();
+                }
+                let result = exchange_pattern(|byte| {
+                    wait(|| spi.intflag.read().dre().bit_is_set())?;
+                    spi.data.write(|w| unsafe { w.data().bits(byte as u16) });
+                    wait(|| spi.intflag.read().rxc().bit_is_set())?;
+                    if spi.status.read().bufovf().bit_is_set() {
+                        spi.status.write(|w| w.bufovf().set_bit());
+                        return Err(SelfTestError::Overrun);
+                    }
+                    Ok(spi.data.read().data().bits() as u8)
+                });
+
+                self.disable();
+                self.spi_mut().ctrla.write(|w| unsafe { w.bits(ctrla).enable().clear_bit() });
+                self.enable();
+                result
+            }
+        }
+
         impl<MISO, MOSI, SCK> ::hal::blocking::spi::transfer::Default<u8> for $Type<MISO, MOSI, SCK> {}
         impl<MISO, MOSI, SCK> ::hal::blocking::spi::write::Default<u8> for $Type<MISO, MOSI, SCK> {}
         #[cfg(feature = "unproven")]
@@ -2,6 +2,8 @@ use crate::clock;
 use crate::hal::blocking::serial::{write::Default, Write};
 use crate::hal::serial;
 use crate::sercom::pads::*;
+use crate::sercom::v1::self_test::{exchange_pattern, uart_tx_pad, wait, SelfTest, SelfTestError};
+use crate::sercom::v1::uart_frame::{data_mask, FrameFormat};
 use crate::target_device::sercom0::USART;
 use crate::target_device::{PM, SERCOM0, SERCOM1};
 #[cfg(feature = "samd21")]
@@ -9,6 +11,7 @@ use crate::target_device::{SERCOM2, SERCOM3};
 #[cfg(feature = "min-samd21g")]
 use crate::target_device::{SERCOM4, SERCOM5};
 use crate::time::Hertz;
+use bitflags::bitflags;
 use core::fmt;
 use core::marker::PhantomData;
 
@@ -20,6 +23,68 @@ pub trait RxpoTxpo {
     fn rxpo_txpo(&self) -> (u8, u8);
 }
 
+bitflags! {
+    /// Interrupt bit flags for UART transactions
+    ///
+    /// The available interrupt flags are `DRE`, `TXC`, `RXC`, `RXS`, `CTSIC`,
+    /// `RXBRK` and `ERROR`. The binary format of the underlying bits exactly
+    /// matches the INTFLAG register.
+    ///
+    /// INTFLAG is write-one-to-clear: writing a 1 to a flag clears it, and
+    /// writing a 0 has no effect. `DRE` and `RXC` cannot be cleared this way;
+    /// they are cleared by writing and reading the DATA register respectively.
+    pub struct Flags: u8 {
+        const DRE = 0x01;
+        const TXC = 0x02;
+        const RXC = 0x04;
+        const RXS = 0x08;
+        const CTSIC = 0x10;
+        const RXBRK = 0x20;
+        const ERROR = 0x80;
+    }
+}
+
+bitflags! {
+    /// Status bit flags for UART transactions
+    ///
+    /// The available status flags are `PERR`, `FERR`, `BUFOVF`, `CTS`, `ISF`
+    /// and `COLL`. The binary format of the underlying bits exactly matches
+    /// the STATUS register.
+    ///
+    /// Like INTFLAG, STATUS is write-one-to-clear. `CTS` reflects the state of
+    /// the CTS pin and cannot be cleared.
+    pub struct Status: u16 {
+        const PERR = 0x01;
+        const FERR = 0x02;
+        const BUFOVF = 0x04;
+        const CTS = 0x08;
+        const ISF = 0x10;
+        const COLL = 0x20;
+    }
+}
+
+/// `INTFLAG` bits written to clear `flags`. `DRE` and `RXC` are left out, as
+/// writing ones doesn't clear them, and every other flag is left untouched.
+fn intflag_clear_bits(flags: Flags) -> u8 {
+    (flags - (Flags::DRE | Flags::RXC)).bits()
+}
+
+/// `STATUS` bits written to clear `status`. `CTS` is left out, as it follows
+/// the CTS pin.
+fn status_clear_bits(status: Status) -> u16 {
+    (status - Status::CTS).bits()
+}
+
+/// The flags to clear once `flags` report a complete transmission: `TXC`
+/// only, or `None` while the transmission is in progress
+fn transmission_complete(flags: Flags) -> Option<Flags> {
+    if flags.contains(Flags::TXC) {
+        Some(Flags::TXC)
+    } else {
+        None
+    }
+}
+
 /// Define a UARTX type for the given Sercom.
 ///
 /// Also defines the valid "pad to uart function" mappings for this instance so
@@ -314,6 +379,69 @@ macro_rules! uart {
                         self.usart().status.read()
                     }
                 }
+
+                /// Read the interrupt flags
+                pub fn read_flags(&self) -> Flags {
+                    let bits = unsafe { self.usart() }.intflag.read().bits();
+                    Flags::from_bits_truncate(bits)
+                }
+
+                /// Clear the given interrupt flags
+                ///
+                /// Only the flags in `flags` are written, so e.g. clearing
+                /// `ERROR` while handling a receive error leaves a pending
+                /// `TXC` untouched. `DRE` and `RXC` are not affected.
+                pub fn clear_flags(&mut self, flags: Flags) {
+                    let bits = intflag_clear_bits(flags);
+                    unsafe { self.usart().intflag.write(|w| w.bits(bits)) };
+                }
+
+                /// Read the status flags
+                pub fn read_status(&self) -> Status {
+                    let bits = unsafe { self.usart() }.status.read().bits();
+                    Status::from_bits_truncate(bits)
+                }
+
+                /// Clear the given status flags
+                pub fn clear_status(&mut self, status: Status) {
+                    let bits = status_clear_bits(status);
+                    unsafe { self.usart().status.write(|w| w.bits(bits)) };
+                }
+
+                /// Check whether the transmission is complete, i.e. the last
+                /// character has been shifted out and no new data is pending.
+                ///
+                /// When complete, the `TXC` flag is cleared so that the next
+                /// transmission can be detected. No other flag is touched.
+                /// This is typically used to release the driver-enable line of
+                /// an RS-485 transceiver.
+                pub fn poll_transmission_complete(&mut self) -> nb::Result<(), ()> {
+                    [<$Type Tx>]::<TX, RTS>::do_transmission_complete(unsafe { self.usart() })
+                }
+
+                /// Block until the transmission is complete. See
+                /// `poll_transmission_complete`.
+                pub fn wait_transmission_complete(&mut self) {
+                    let _ = nb::block!(self.poll_transmission_complete());
+                }
+
+                /// Change the frame format, 8N1 by default. See the
+                /// [`uart_frame`](crate::sercom::v1::uart_frame) module.
+                pub fn set_frame_format(&mut self, frame: FrameFormat) {
+                    let usart = unsafe { self.usart() };
+                    // FORM, CHSIZE, SBMODE and PMODE are enable-protected
+                    usart.ctrla.modify(|_, w| w.enable().clear_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                    usart.ctrla.modify(|_, w| unsafe { w.form().bits(frame.form()) });
+                    usart.ctrlb.modify(|_, w| {
+                        unsafe { w.chsize().bits(frame.chsize()) };
+                        w.sbmode().bit(frame.sbmode());
+                        w.pmode().bit(frame.pmode())
+                    });
+                    while usart.syncbusy.read().ctrlb().bit_is_set() {}
+                    usart.ctrla.modify(|_, w| w.enable().set_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                }
             }
 
             /// The transmitting half of the corresponding UARTX instance (as returned by `UARTX::split`)
@@ -354,6 +482,26 @@ macro_rules! uart {
 
                     Ok(())
                 }
+
+                fn do_transmission_complete(usart: &USART) -> nb::Result<(), ()> {
+                    let flags = Flags::from_bits_truncate(usart.intflag.read().bits());
+                    let clear = transmission_complete(flags).ok_or(nb::Error::WouldBlock)?;
+                    let bits = intflag_clear_bits(clear);
+                    unsafe { usart.intflag.write(|w| w.bits(bits)) };
+                    Ok(())
+                }
+
+                /// Check whether the transmission is complete, clearing only
+                /// the `TXC` flag when it is.
+                pub fn poll_transmission_complete(&mut self) -> nb::Result<(), ()> {
+                    Self::do_transmission_complete(unsafe { self.usart() })
+                }
+
+                /// Block until the transmission is complete. See
+                /// `poll_transmission_complete`.
+                pub fn wait_transmission_complete(&mut self) {
+                    let _ = nb::block!(self.poll_transmission_complete());
+                }
             }
 
             impl<TX, RTS> serial::Write<u8> for [<$Type Tx>]<TX, RTS> {
@@ -402,8 +550,9 @@ macro_rules! uart {
                     }
 
                     let data = usart.data.read().bits();
+                    let mask = data_mask(usart.ctrlb.read().chsize().bits());
 
-                    Ok(data as u8)
+                    Ok(data as u8 & mask)
                 }
             }
 
@@ -427,6 +576,54 @@ macro_rules! uart {
 
             impl<RX, TX, RTS, CTS> Default<u8> for $Type<RX, TX, RTS, CTS> {}
 
+            impl<RX, TX, RTS, CTS> SelfTest for $Type<RX, TX, RTS, CTS> {
+                /// Receive the pattern back through the TX pad. See the
+                /// [`self_test`](crate::sercom::v1::self_test) module.
+                fn run(&mut self) -> Result<(), SelfTestError> {
+                    let usart = self.sercom.usart();
+                    let ctrla = usart.ctrla.read();
+                    let tx_pad = uart_tx_pad(ctrla.txpo().bits());
+                    let ctrla = ctrla.bits();
+
+                    // RXPO is enable-protected
+                    usart.ctrla.modify(|_, w| w.enable().clear_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                    usart.ctrla.modify(|_, w| unsafe { w.rxpo().bits(tx_pad) });
+                    usart.ctrla.modify(|_, w| w.enable().set_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                    while usart.intflag.read().rxc().bit_is_set() {
+                        usart.data.read();
+                    }
+
+                    let result = exchange_pattern(|byte| {
+                        wait(|| usart.intflag.read().dre().bit_is_set())?;
+                        usart.data.write(|w| unsafe { w.bits(byte as u16) });
+                        wait(|| usart.intflag.read().rxc().bit_is_set())?;
+                        let status = usart.status.read();
+                        let received = usart.data.read().bits() as u8;
+                        if status.bufovf().bit_is_set() {
+                            Err(SelfTestError::Overrun)
+                        } else if status.ferr().bit_is_set() || status.perr().bit_is_set() {
+                            Err(SelfTestError::Framing)
+                        } else {
+                            Ok(received)
+                        }
+                    });
+
+                    usart.ctrla.modify(|_, w| w.enable().clear_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                    usart.ctrla.write(|w| unsafe { w.bits(ctrla).enable().clear_bit() });
+                    usart.ctrla.modify(|_, w| w.enable().set_bit());
+                    while usart.syncbusy.read().enable().bit_is_set() {}
+                    while usart.intflag.read().rxc().bit_is_set() {
+                        usart.data.read();
+                    }
+                    let status = usart.status.read().bits();
+                    usart.status.write(|w| unsafe { w.bits(status) });
+                    result
+                }
+            }
+
             impl<TX, RTS> fmt::Write for [<$Type Tx>]<TX, RTS> {
                 fn write_str(&mut self, s: &str) -> fmt::Result {
                     self.bwrite_all(s.as_bytes()).map_err(|_| fmt::Error)
@@ -463,3 +660,35 @@ fn calculate_baud_value(baudrate: u32, clk_freq: u32, n_samples: u8) -> u16 {
 
     baud_calculated as u16
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rx_error_handling_keeps_a_pending_txc() {
+        // A receive error while the last character is shifted out
+        let pending = Flags::TXC | Flags::RXC | Flags::ERROR;
+        let written = intflag_clear_bits(Flags::ERROR | Flags::RXC);
+        assert_eq!(written, Flags::ERROR.bits());
+        let left = Flags::from_bits_truncate(pending.bits() & !written);
+        assert_eq!(left, Flags::TXC | Flags::RXC);
+
+        // The flags that can't be cleared by writing ones are never written
+        assert_eq!(
+            intflag_clear_bits(Flags::all()),
+            (Flags::all() - Flags::DRE - Flags::RXC).bits()
+        );
+        assert_eq!(
+            status_clear_bits(Status::FERR | Status::CTS),
+            Status::FERR.bits()
+        );
+    }
+
+    #[test]
+    fn transmission_complete_only_clears_txc() {
+        assert_eq!(transmission_complete(Flags::DRE | Flags::RXC), None);
+        let clear = transmission_complete(Flags::all()).unwrap();
+        assert_eq!(intflag_clear_bits(clear), Flags::TXC.bits());
+    }
+}
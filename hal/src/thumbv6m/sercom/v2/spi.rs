@@ -54,6 +54,28 @@
 //! `static` variables. In these cases, the [`pads_alias`] macro can help
 //! simplify the declaration.
 //!
+//! ## Receive-only and transmit-only
+//!
+//! Devices that only ever send or only ever receive data, like displays or
+//! ADCs, don't need both data lines. Leave the unused data [`Pad`] as [`NoneT`]
+//! and the peripheral is configured for one direction only.
+//!
+//! - Receive-only [`Pads`] ([`Rx`] but [`NotTx`]) have no Data Out [`Pad`].
+//!   The peripheral still shifts out a dummy word to clock each word in, but
+//!   the pin is not driven and remains free for other uses.
+//! - Transmit-only [`Pads`] ([`Tx`] but [`NotRx`]) have no Data In [`Pad`], so
+//!   that pin is free. The receiver (`CTRLB.RXEN`) is left disabled, so the
+//!   DATA register never has to be read and no buffer overflow can occur.
+//!
+//! The driver only implements the traits of the available direction.
+//! Receive-only [`Spi`]s implement [`Read`] and the blocking [`Transfer`],
+//! whose words are shifted out as dummy words. Transmit-only [`Spi`]s implement
+//! [`Write`] and the blocking [`blocking::spi::Write`]. Neither implements
+//! [`FullDuplex`].
+//!
+//! In [`Master`] [`Mode`], the SS [`Pad`] is optional and can be freed as
+//! well.
+//!
 //! # [`Config`]
 //!
 //! Use the [`Pads`] struct to create a [`Config`] struct, which represents the
@@ -110,6 +132,7 @@
 //! [`enable`]: Config::enable
 //! [`Pin`]: crate::gpio::v2::pin::Pin
 //! [`PinId`]: crate::gpio::v2::pin::PinId
+//! [`Transfer`]: blocking::spi::Transfer
 
 use core::convert::{TryFrom, TryInto};
 use core::marker::PhantomData;
@@ -133,7 +156,7 @@ use crate::gpio::v2::{AnyPin, SpecificPin};
 use crate::sercom::v2::pads::{Map, Pad0, Pad1, Pad2, Pad3, PadNum};
 use crate::sercom::v2::pads::{OptionalPad, Pad, SomePad};
 use crate::sercom::v2::Sercom;
-use crate::time::Hertz;
+use crate::time::{Hertz, Nanoseconds};
 use crate::typelevel::{Is, NoneT, Sealed};
 
 //=============================================================================
@@ -265,9 +288,14 @@ macro_rules! impl_dipo_dopo {
 // completeness, to help verify that all possible combinations have been
 // covered. Only combinations with a valid pin for `CK` and either `DI` or `DO`
 // have been considered. Other combinations would have no practical use.
-
-impl_dipo_dopo!((Pad0, NoneT, Pad1, Pad2): (0, 0));
-impl_dipo_dopo!((Pad0, NoneT, Pad1, NoneT): (0, 0));
+//
+// Receive-only combinations must not place `DO` on the `DI` pad. The
+// peripheral still shifts out dummy words, and would drive them onto the
+// Data In pin. Of the equivalent encodings, the one routing `DO` to an unused
+// pad is selected.
+
+//impl_dipo_dopo!((Pad0, NoneT, Pad1, Pad2): (0, 0));
+//impl_dipo_dopo!((Pad0, NoneT, Pad1, NoneT): (0, 0));
 impl_dipo_dopo!((NoneT, Pad0, Pad1, Pad2): (0, 0));
 impl_dipo_dopo!((NoneT, Pad0, Pad1, NoneT): (0, 0));
 
@@ -280,8 +308,8 @@ impl_dipo_dopo!((NoneT, Pad2, Pad3, NoneT): (0, 1));
 
 impl_dipo_dopo!((Pad0, Pad3, Pad1, Pad2): (0, 2));
 impl_dipo_dopo!((Pad0, Pad3, Pad1, NoneT): (0, 2));
-//impl_dipo_dopo!((Pad0, NoneT, Pad1, Pad2): (0, 2));
-//impl_dipo_dopo!((Pad0, NoneT, Pad1, NoneT): (0, 2));
+impl_dipo_dopo!((Pad0, NoneT, Pad1, Pad2): (0, 2));
+impl_dipo_dopo!((Pad0, NoneT, Pad1, NoneT): (0, 2));
 impl_dipo_dopo!((NoneT, Pad3, Pad1, Pad2): (0, 2));
 impl_dipo_dopo!((NoneT, Pad3, Pad1, NoneT): (0, 2));
 
@@ -311,8 +339,8 @@ impl_dipo_dopo!((Pad2, NoneT, Pad1, NoneT): (2, 0));
 //impl_dipo_dopo!((NoneT, Pad0, Pad1, Pad2): (2, 0));
 //impl_dipo_dopo!((NoneT, Pad0, Pad1, NoneT): (2, 0));
 
-impl_dipo_dopo!((Pad2, NoneT, Pad3, Pad1): (2, 1));
-impl_dipo_dopo!((Pad2, NoneT, Pad3, NoneT): (2, 1));
+//impl_dipo_dopo!((Pad2, NoneT, Pad3, Pad1): (2, 1));
+//impl_dipo_dopo!((Pad2, NoneT, Pad3, NoneT): (2, 1));
 //impl_dipo_dopo!((NoneT, Pad2, Pad3, Pad1): (2, 1));
 //impl_dipo_dopo!((NoneT, Pad2, Pad3, NoneT): (2, 1));
 
@@ -323,8 +351,8 @@ impl_dipo_dopo!((Pad2, Pad3, Pad1, NoneT): (2, 2));
 
 impl_dipo_dopo!((Pad2, Pad0, Pad3, Pad1): (2, 3));
 impl_dipo_dopo!((Pad2, Pad0, Pad3, NoneT): (2, 3));
-//impl_dipo_dopo!((Pad2, NoneT, Pad3, Pad1): (2, 3));
-//impl_dipo_dopo!((Pad2, NoneT, Pad3, NoneT): (2, 3));
+impl_dipo_dopo!((Pad2, NoneT, Pad3, Pad1): (2, 3));
+impl_dipo_dopo!((Pad2, NoneT, Pad3, NoneT): (2, 3));
 //impl_dipo_dopo!((NoneT, Pad0, Pad3, Pad1): (2, 3));
 //impl_dipo_dopo!((NoneT, Pad0, Pad3, NoneT): (2, 3));
 
@@ -658,6 +686,12 @@ pub trait AnyPads: Sealed + Is<Type = SpecificPads<Self>> {
 
     /// SS [`Pad`] from the corresponding [`Pads`]
     type SS: OptionalPad;
+
+    /// Whether the receiver is enabled
+    ///
+    /// `CTRLB.RXEN` is only set when there is a Data In [`Pad`], so
+    /// transmit-only [`Pads`] never receive data that would have to be read.
+    const RXEN: bool = <Self::DataIn as OptionalPad>::IS_SOME;
 }
 
 /// Type alias to recover the specific [`Pads`] type from an implementation of
@@ -1002,6 +1036,26 @@ where
     mode: PhantomData<M>,
     chsize: PhantomData<C>,
     freq: Hertz,
+    baud: Option<Hertz>,
+    char_delay: u32,
+}
+
+/// Compute the BAUD register value for a baud rate, given the GCLK frequency
+///
+/// Values outside the achievable range saturate at the extremes.
+fn baud_value(freq: Hertz, baud: Hertz) -> u8 {
+    let baud = (freq.0 / 2 / baud.0).saturating_sub(1);
+    if baud <= u8::MAX as u32 {
+        baud as u8
+    } else {
+        u8::MAX
+    }
+}
+
+/// Convert a delay into a number of CPU cycles, rounding up
+fn delay_cycles(delay: Nanoseconds, cpu_freq: Hertz) -> u32 {
+    let cycles = (delay.0 as u64 * cpu_freq.0 as u64 + 999_999_999) / 1_000_000_000;
+    cycles.min(u32::MAX as u64) as u32
 }
 
 impl<P: DipoDopo> Config<P> {
@@ -1017,6 +1071,8 @@ impl<P: DipoDopo> Config<P> {
             mode: PhantomData,
             chsize: PhantomData,
             freq: freq.into(),
+            baud: None,
+            char_delay: 0,
         }
     }
 
@@ -1072,6 +1128,8 @@ where
             mode: PhantomData,
             chsize: PhantomData,
             freq: self.freq,
+            baud: self.baud,
+            char_delay: self.char_delay,
         }
     }
 
@@ -1170,14 +1228,10 @@ where
     /// half the GCLK frequency. The minimum baud rate is the GCLK frequency /
     /// 512. Values outside this range will saturate at the extremes.
     #[inline]
-    pub fn baud<B: Into<Hertz>>(self, baud: B) -> Self {
+    pub fn baud<B: Into<Hertz>>(mut self, baud: B) -> Self {
         let baud: Hertz = baud.into();
-        let baud = (self.freq.0 / 2 / baud.0).saturating_sub(1);
-        let baud = if baud <= u8::MAX as u32 {
-            baud as u8
-        } else {
-            u8::MAX
-        };
+        self.baud = Some(baud);
+        let baud = baud_value(self.freq, baud);
         self.sercom
             .spi()
             .baud
@@ -1185,6 +1239,44 @@ where
         self
     }
 
+    /// Change the stored GCLK frequency
+    ///
+    /// If a baud rate was previously set with [`Config::baud`], the BAUD
+    /// register is recomputed for the new frequency, so that the baud rate
+    /// stays the same. Use this after changing the GCLK feeding the
+    /// [`Sercom`].
+    #[inline]
+    pub fn gclk_freq<F: Into<Hertz>>(mut self, freq: F) -> Self {
+        self.freq = freq.into();
+        match self.baud {
+            Some(baud) => self.baud(baud),
+            None => self,
+        }
+    }
+
+    /// Insert a delay before each character of a [`FullDuplex`] transaction
+    ///
+    /// The SAMD11/21 SERCOM has no hardware support for inter-character
+    /// spacing, so the delay is inserted in software. [`FullDuplex::send`]
+    /// and the [`blocking::spi`] traits busy-wait for `delay` before writing
+    /// each word to the DATA register. `cpu_freq` is the frequency of the CPU
+    /// clock, used to convert the delay into cycles, so the granularity is one
+    /// CPU cycle. The actual delay is longer by the overhead of the
+    /// surrounding code, and may be stretched by interrupts.
+    ///
+    /// The delay only results in a gap on the bus if the previous word was
+    /// read before the next one is sent, as the [`blocking::spi`] traits do.
+    /// A delay of zero disables the feature, which is the default.
+    #[inline]
+    pub fn inter_char_delay<D, F>(mut self, delay: D, cpu_freq: F) -> Self
+    where
+        D: Into<Nanoseconds>,
+        F: Into<Hertz>,
+    {
+        self.char_delay = delay_cycles(delay.into(), cpu_freq.into());
+        self
+    }
+
     /// Control the buffer overflow notification
     ///
     /// If set to true, an [`Error::Overflow`] will be issued as soon as an
@@ -1229,7 +1321,7 @@ where
     where
         Self: ValidConfig,
     {
-        self.sercom.spi().ctrlb.modify(|_, w| w.rxen().set_bit());
+        self.sercom.spi().ctrlb.modify(|_, w| w.rxen().bit(P::RXEN));
         while self.sercom.spi().syncbusy.read().ctrlb().bit_is_set() {}
         self.sercom.spi().ctrla.modify(|_, w| w.enable().set_bit());
         while self.sercom.spi().syncbusy.read().enable().bit_is_set() {}
@@ -1402,6 +1494,63 @@ where
 {
 }
 
+//=============================================================================
+// Suspended
+//=============================================================================
+
+/// A suspended SPI peripheral
+///
+/// Returned by [`Spi::suspend`], for duty-cycled applications that only need
+/// the peripheral from time to time. The peripheral is disabled, but it keeps
+/// its full configuration, so it can be brought back with
+/// [`Suspended::resume`] instead of going through [`Config`] again. No
+/// transactions are possible while it is suspended.
+///
+/// To save more power, the APB clock of the [`Sercom`] can be stopped as well,
+/// using [`Suspended::disable_apb_clock`]. The registers keep their values
+/// while it is stopped, and [`Suspended::resume`] restarts it. The generic
+/// clock is not managed here, as it may be shared with other peripherals.
+///
+/// Resuming only sets the RXEN (if [`AnyPads::RXEN`]) and ENABLE bits. Each
+/// of them must synchronize to the generic clock domain, which takes a few
+/// periods of the generic and APB clocks, so the latency is a small number of
+/// clock cycles rather than a full reconfiguration.
+pub struct Suspended<C: ValidConfig> {
+    config: C,
+}
+
+impl<C: ValidConfig> Suspended<C> {
+    /// Stop the APB clock of the [`Sercom`] while suspended
+    #[inline]
+    pub fn disable_apb_clock(&mut self, pm: &PM) {
+        self.config.as_mut().sercom.disable_apb_clock(pm);
+    }
+
+    /// Re-enable the APB clock, if needed, and the SPI peripheral
+    #[inline]
+    pub fn resume(mut self, pm: &PM) -> Spi<C> {
+        let config = self.config.as_mut();
+        config.sercom.enable_apb_clock(pm);
+        config
+            .sercom
+            .spi()
+            .ctrlb
+            .modify(|_, w| w.rxen().bit(SpiPads::<C>::RXEN));
+        while config.sercom.spi().syncbusy.read().ctrlb().bit_is_set() {}
+        config.enable_peripheral(true);
+        Spi {
+            config: self.config,
+        }
+    }
+
+    /// Return the disabled [`Config`], e.g. to change it or free the
+    /// [`Sercom`]. The APB clock must be running.
+    #[inline]
+    pub fn into_config(self) -> C {
+        self.config
+    }
+}
+
 //=============================================================================
 // Spi
 //=============================================================================
@@ -1460,6 +1609,17 @@ impl<C: ValidConfig> Spi<C> {
         self.config.as_mut().enable_peripheral(true);
     }
 
+    /// Recompute the baud rate after a change of the GCLK frequency
+    ///
+    /// BAUD can only be written while the peripheral is disabled, so this
+    /// calls [`Spi::reconfigure`] with [`Config::gclk_freq`], which may
+    /// interrupt any ongoing transactions.
+    #[inline]
+    pub fn update_gclk_freq<F: Into<Hertz>>(&mut self, freq: F) {
+        let freq = freq.into();
+        self.reconfigure(|config| config.gclk_freq(freq));
+    }
+
     /// Enable interrupts for the specified flags
     #[inline]
     pub fn enable_interrupts(&mut self, flags: Flags) {
@@ -1546,6 +1706,17 @@ impl<C: ValidConfig> Spi<C> {
         self.config.as_mut().enable_peripheral(false);
         self.config
     }
+
+    /// Suspend the SPI peripheral
+    ///
+    /// The peripheral is disabled, but it keeps its full configuration. See
+    /// [`Suspended`] for more details.
+    #[inline]
+    pub fn suspend(self) -> Suspended<C> {
+        Suspended {
+            config: self.disable(),
+        }
+    }
 }
 
 impl<C> Spi<C>
@@ -1781,6 +1952,10 @@ where
     fn send(&mut self, word: SpiWord<C>) -> nb::Result<(), Error> {
         let flags = self.read_flags_errors()?;
         if flags.contains(Flags::DRE) {
+            let char_delay = self.config.as_ref().char_delay;
+            if char_delay != 0 {
+                cortex_m::asm::delay(char_delay);
+            }
             unsafe { self.write_data(word.as_()) };
             Ok(())
         } else {
@@ -1789,24 +1964,196 @@ where
     }
 }
 
-impl<C> blocking::spi::transfer::Default<SpiWord<C>> for Spi<C>
+impl<P, M, C> Spi<Config<P, M, C>>
 where
-    C: ValidConfig,
-    Self: FullDuplex<SpiWord<C>>,
+    Config<P, M, C>: ValidConfig,
+    P: DipoDopo + Tx,
+    M: MasterMode,
+    C: CharSize,
 {
+    /// Send a word for the [`blocking::spi`] traits, after the inter-character
+    /// delay
+    ///
+    /// If the receiver is enabled, wait for the word received in exchange and
+    /// discard it, so that the DATA register never overflows.
+    #[inline]
+    fn write_word(&mut self, word: u16) -> Result<(), Error> {
+        while !self.read_flags_errors()?.contains(Flags::DRE) {}
+        let char_delay = self.config.char_delay;
+        if char_delay != 0 {
+            cortex_m::asm::delay(char_delay);
+        }
+        unsafe { self.write_data(word) };
+        if P::RXEN {
+            while !self.read_flags_errors()?.contains(Flags::RXC) {}
+            unsafe { self.read_data() };
+        }
+        Ok(())
+    }
 }
 
-impl<C> blocking::spi::write::Default<SpiWord<C>> for Spi<C>
-where
-    C: ValidConfig,
-    Self: FullDuplex<SpiWord<C>>,
-{
+macro_rules! impl_blocking_traits {
+    ( $($CharSize:ident: $Word:ident),+ ) => {
+        $(
+            /// Implement [`Transfer`] for [`Spi`]s that can receive
+            ///
+            /// [`Transfer`] is implemented for [`Pads`] that are [`Rx`] in a
+            /// [`MasterMode`]. Each word of the slice is sent and then
+            /// overwritten by the word received in exchange. For receive-only
+            /// [`Pads`], the words are shifted out as dummy words to clock in
+            /// the response, but the Data Out pin is not driven.
+            ///
+            /// [`Transfer`]: blocking::spi::Transfer
+            impl<P, M> blocking::spi::Transfer<$Word> for Spi<Config<P, M, $CharSize>>
+            where
+                Config<P, M, $CharSize>: ValidConfig,
+                P: DipoDopo + Rx,
+                M: MasterMode,
+            {
+                type Error = Error;
+
+                #[inline]
+                fn transfer<'w>(&mut self, words: &'w mut [$Word]) -> Result<&'w [$Word], Error> {
+                    let char_delay = self.config.char_delay;
+                    for word in words.iter_mut() {
+                        while !self.read_flags_errors()?.contains(Flags::DRE) {}
+                        if char_delay != 0 {
+                            cortex_m::asm::delay(char_delay);
+                        }
+                        unsafe { self.write_data(*word as u16) };
+                        while !self.read_flags_errors()?.contains(Flags::RXC) {}
+                        *word = unsafe { self.read_data() as $Word };
+                    }
+                    Ok(words)
+                }
+            }
+
+            /// Implement [`Write`] for [`Spi`]s that can transmit
+            ///
+            /// [`Write`] is implemented for [`Pads`] that are [`Tx`] in a
+            /// [`MasterMode`]. If the [`Pads`] are also [`Rx`], the received
+            /// words are read and discarded. Otherwise, the receiver is
+            /// disabled, so the DATA register is never read and the write
+            /// returns as soon as the last word is written to it. Use
+            /// [`serial::Write::flush`] to wait until it is shifted out, e.g.
+            /// before latching a shift register.
+            ///
+            /// [`Write`]: blocking::spi::Write
+            /// [`serial::Write::flush`]: embedded_hal::serial::Write::flush
+            impl<P, M> blocking::spi::Write<$Word> for Spi<Config<P, M, $CharSize>>
+            where
+                Config<P, M, $CharSize>: ValidConfig,
+                P: DipoDopo + Tx,
+                M: MasterMode,
+            {
+                type Error = Error;
+
+                #[inline]
+                fn write(&mut self, words: &[$Word]) -> Result<(), Error> {
+                    for word in words {
+                        self.write_word(*word as u16)?;
+                    }
+                    Ok(())
+                }
+            }
+
+            /// Implement [`WriteIter`] for [`Spi`]s that can transmit
+            ///
+            /// See the implementation of [`Write`](blocking::spi::Write) for
+            /// more details.
+            ///
+            /// [`WriteIter`]: blocking::spi::WriteIter
+            #[cfg(feature = "unproven")]
+            impl<P, M> blocking::spi::WriteIter<$Word> for Spi<Config<P, M, $CharSize>>
+            where
+                Config<P, M, $CharSize>: ValidConfig,
+                P: DipoDopo + Tx,
+                M: MasterMode,
+            {
+                type Error = Error;
+
+                #[inline]
+                fn write_iter<WI>(&mut self, words: WI) -> Result<(), Error>
+                where
+                    WI: IntoIterator<Item = $Word>,
+                {
+                    for word in words.into_iter() {
+                        self.write_word(word as u16)?;
+                    }
+                    Ok(())
+                }
+            }
+        )+
+    };
 }
 
-#[cfg(feature = "unproven")]
-impl<C> blocking::spi::write_iter::Default<SpiWord<C>> for Spi<C>
-where
-    C: ValidConfig,
-    Self: FullDuplex<SpiWord<C>>,
-{
+impl_blocking_traits!(EightBit: u8, NineBit: u16);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::v2::{PA04, PA05};
+    use crate::sercom::v2::Sercom0;
+
+    type DataIn = Pad<Sercom0, Pad0, PA04>;
+    #[cfg(feature = "samd11")]
+    type DataOut = Pad<Sercom0, Pad2, crate::gpio::v2::PA08>;
+    #[cfg(feature = "samd21")]
+    type DataOut = Pad<Sercom0, Pad2, crate::gpio::v2::PA06>;
+    type Sclk = Pad<Sercom0, Pad1, PA05>;
+
+    #[test]
+    fn rxen_follows_data_in() {
+        type Duplex = Pads<Sercom0, DataIn, DataOut, Sclk>;
+        type RxOnly = Pads<Sercom0, DataIn, NoneT, Sclk>;
+        type TxOnly = Pads<Sercom0, NoneT, DataOut, Sclk>;
+        assert!(<Duplex as AnyPads>::RXEN);
+        assert!(<RxOnly as AnyPads>::RXEN);
+        assert!(!<TxOnly as AnyPads>::RXEN);
+    }
+
+    /// Data Out pad selected by a `DOPO` value, from the datasheet table
+    fn data_out_pad(dopo: u8) -> u8 {
+        [0, 2, 3, 0][dopo as usize]
+    }
+
+    #[test]
+    fn rx_only_data_out_avoids_data_in() {
+        type RxOnly = Pads<Sercom0, DataIn, NoneT, Sclk>;
+        assert_eq!(<RxOnly as DipoDopo>::DIPO, 0);
+        assert_ne!(data_out_pad(<RxOnly as DipoDopo>::DOPO), 0);
+
+        #[cfg(feature = "samd11")]
+        type Pad2In = Pad<Sercom0, Pad2, crate::gpio::v2::PA08>;
+        #[cfg(feature = "samd11")]
+        type Pad3Sclk = Pad<Sercom0, Pad3, crate::gpio::v2::PA09>;
+        #[cfg(feature = "samd21")]
+        type Pad2In = Pad<Sercom0, Pad2, crate::gpio::v2::PA06>;
+        #[cfg(feature = "samd21")]
+        type Pad3Sclk = Pad<Sercom0, Pad3, crate::gpio::v2::PA07>;
+        type RxOnlyPad2 = Pads<Sercom0, Pad2In, NoneT, Pad3Sclk>;
+        assert_eq!(<RxOnlyPad2 as DipoDopo>::DIPO, 2);
+        assert_ne!(data_out_pad(<RxOnlyPad2 as DipoDopo>::DOPO), 2);
+    }
+
+    #[test]
+    fn baud_value_follows_gclk_freq() {
+        let baud = Hertz(1_000_000);
+        assert_eq!(baud_value(Hertz(48_000_000), baud), 23);
+        assert_eq!(baud_value(Hertz(12_000_000), baud), 5);
+    }
+
+    #[test]
+    fn baud_value_saturates() {
+        assert_eq!(baud_value(Hertz(48_000_000), Hertz(48_000_000)), 0);
+        assert_eq!(baud_value(Hertz(48_000_000), Hertz(10_000)), u8::MAX);
+    }
+
+    #[test]
+    fn inter_char_delay_in_cpu_cycles() {
+        assert_eq!(delay_cycles(Nanoseconds(10_000), Hertz(48_000_000)), 480);
+        // Rounds up to the next whole cycle
+        assert_eq!(delay_cycles(Nanoseconds(30), Hertz(48_000_000)), 2);
+        assert_eq!(delay_cycles(Nanoseconds(0), Hertz(48_000_000)), 0);
+    }
 }
@@ -20,6 +20,68 @@ pub enum WatchdogTimeout {
     Cycles16K,
 }
 
+impl WatchdogTimeout {
+    /// Number of cycles of the watchdog clock, at 1.024 kHz
+    #[inline]
+    pub fn cycles(self) -> u32 {
+        8 << self as u8
+    }
+}
+
+/// Frequency of the watchdog clock, the 1.024 kHz output of OSCULP32K
+const WDT_CLOCK_HZ: u32 = 1024;
+
+/// Errors of the watchdog configuration
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum WatchdogError {
+    /// The watchdog runs, and its timing can't change until it is disabled
+    Enabled,
+    /// The watchdog is in always-on mode, set by `ALWAYSON` or by the user
+    /// row fuses, until the next power-on reset
+    AlwaysOn,
+}
+
+/// Bits of `CTRL` which decide the changes allowed by the hardware
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+struct Control {
+    enabled: bool,
+    window_mode: bool,
+    always_on: bool,
+}
+
+/// Change of the watchdog configuration
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Change {
+    /// Period or closed window (`CONFIG`)
+    Timing,
+    /// Window mode (`WEN`)
+    WindowMode,
+    /// Stopping the watchdog (`ENABLE`)
+    Disable,
+}
+
+/// Check that the hardware allows `change` in the state of `control`
+///
+/// `CONFIG` and `WEN` are enable-protected, and `CONFIG` and `ENABLE` are
+/// read-only in always-on mode. Only the window mode can still be switched in
+/// always-on mode.
+fn check_change(control: Control, change: Change) -> Result<(), WatchdogError> {
+    match change {
+        Change::WindowMode if control.always_on => Ok(()),
+        _ if control.always_on => Err(WatchdogError::AlwaysOn),
+        Change::Disable => Ok(()),
+        _ if control.enabled => Err(WatchdogError::Enabled),
+        _ => Ok(()),
+    }
+}
+
+/// Number of watchdog clock cycles before the first feed is due, after the
+/// watchdog is started or fed. In window mode, the closed window comes first.
+fn feed_deadline(period: u8, window: Option<u8>) -> u32 {
+    let cycles = |field: u8| 8 << field.min(WatchdogTimeout::Cycles16K as u8);
+    window.map_or(0, cycles) + cycles(period)
+}
+
 pub struct Watchdog {
     wdt: WDT,
 }
@@ -28,6 +90,91 @@ impl Watchdog {
     pub fn new(wdt: WDT) -> Self {
         Self { wdt }
     }
+
+    fn control(&self) -> Control {
+        let ctrl = self.wdt.ctrl.read();
+        Control {
+            enabled: ctrl.enable().bit_is_set(),
+            window_mode: ctrl.wen().bit_is_set(),
+            always_on: ctrl.alwayson().bit_is_set(),
+        }
+    }
+
+    /// Returns `true` if the watchdog is in always-on mode, in which it can't
+    /// be disabled nor have its timing changed until the next power-on reset
+    pub fn is_always_on(&self) -> bool {
+        self.control().always_on
+    }
+
+    /// Configure the watchdog, without starting it
+    ///
+    /// The watchdog resets the processor if it is not fed within `period`.
+    /// With a `window`, it is in window mode, and also resets the processor
+    /// if fed within `window` of the previous feed. Start it with
+    /// [`Watchdog::arm`] once the slow initialization, like the clock tree
+    /// bring-up, is done.
+    ///
+    /// The timing can't be changed while the watchdog runs, or in always-on
+    /// mode, e.g. when enabled by the user row fuses.
+    pub fn configure_without_start(
+        &mut self,
+        period: WatchdogTimeout,
+        window: Option<WatchdogTimeout>,
+    ) -> Result<(), WatchdogError> {
+        check_change(self.control(), Change::Timing)?;
+        self.wdt.config.write(|w| unsafe {
+            w.per().bits(period as u8);
+            w.window().bits(window.map_or(0, |window| window as u8))
+        });
+        self.wdt.ctrl.modify(|_, w| w.wen().bit(window.is_some()));
+        self.sync();
+        Ok(())
+    }
+
+    /// Start the watchdog, as configured by
+    /// [`Watchdog::configure_without_start`]
+    pub fn arm(&mut self) {
+        self.wdt.ctrl.modify(|_, w| w.enable().set_bit());
+        self.sync();
+    }
+
+    /// Switch the window mode, keeping the period and the closed window
+    ///
+    /// Unlike the timing, the window mode can still be switched in
+    /// always-on mode, but not while the watchdog runs otherwise.
+    pub fn set_window_mode(&mut self, enable: bool) -> Result<(), WatchdogError> {
+        check_change(self.control(), Change::WindowMode)?;
+        self.wdt.ctrl.modify(|_, w| w.wen().bit(enable));
+        self.sync();
+        Ok(())
+    }
+
+    /// Disable the watchdog, unless it is in always-on mode
+    pub fn try_disable(&mut self) -> Result<(), WatchdogError> {
+        check_change(self.control(), Change::Disable)?;
+        watchdog::WatchdogDisable::disable(self);
+        Ok(())
+    }
+
+    /// Time before the first feed is due, in milliseconds, counted from the
+    /// start of the watchdog or from the last feed
+    ///
+    /// A watchdog enabled by the user row fuses starts at reset, so this is
+    /// the budget of the whole bring-up before the first feed. In window mode,
+    /// it includes the closed window.
+    pub fn max_bringup_budget(&self) -> u32 {
+        let config = self.wdt.config.read();
+        let window = if self.control().window_mode {
+            Some(config.window().bits())
+        } else {
+            None
+        };
+        feed_deadline(config.per().bits(), window) * 1000 / WDT_CLOCK_HZ
+    }
+
+    fn sync(&self) {
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+    }
 }
 
 impl watchdog::Watchdog for Watchdog {
@@ -67,3 +214,44 @@ impl watchdog::WatchdogEnable for Watchdog {
         while self.wdt.status.read().syncbusy().bit_is_set() {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_changes() {
+        use Change::*;
+        use WatchdogError::*;
+        let control = |enabled, always_on| Control {
+            enabled,
+            window_mode: false,
+            always_on,
+        };
+        // Enabled, always-on, and the result of each change
+        let matrix = [
+            (false, false, [Ok(()), Ok(()), Ok(())]),
+            (true, false, [Err(Enabled), Err(Enabled), Ok(())]),
+            (true, true, [Err(AlwaysOn), Ok(()), Err(AlwaysOn)]),
+            // ALWAYSON forces ENABLE, but don't rely on it
+            (false, true, [Err(AlwaysOn), Ok(()), Err(AlwaysOn)]),
+        ];
+        for &(enabled, always_on, results) in matrix.iter() {
+            let control = control(enabled, always_on);
+            for (&change, &result) in [Timing, WindowMode, Disable].iter().zip(results.iter()) {
+                assert_eq!(check_change(control, change), result);
+            }
+        }
+    }
+
+    #[test]
+    fn feed_deadlines() {
+        assert_eq!(WatchdogTimeout::Cycles8.cycles(), 8);
+        assert_eq!(WatchdogTimeout::Cycles16K.cycles(), 16384);
+        assert_eq!(feed_deadline(WatchdogTimeout::Cycles1K as u8, None), 1024);
+        let window = Some(WatchdogTimeout::Cycles512 as u8);
+        assert_eq!(feed_deadline(WatchdogTimeout::Cycles1K as u8, window), 1536);
+        // Reserved values count as the longest period
+        assert_eq!(feed_deadline(0xF, None), 16384);
+    }
+}
@@ -5,9 +5,21 @@ use crate::gpio::v2::*;
 use crate::hal::adc::{Channel, OneShot};
 use crate::target_device::gclk::genctrl::SRC_A::DFLL;
 use crate::target_device::gclk::pchctrl::GEN_A;
+use crate::target_device::generic::Variant;
 use crate::target_device::{adc0, ADC0, ADC1, MCLK};
+use crate::time::Hertz;
 
 use crate::calibration;
+use crate::supc::VrefUser;
+
+#[cfg(all(feature = "unproven", feature = "dma"))]
+use crate::dmac::transfer::BufferPair;
+#[cfg(all(feature = "unproven", feature = "dma"))]
+use crate::dmac::{
+    channel::{Busy, Channel as DmaChannel, Ready},
+    dma_controller::ChId,
+    Buffer, BufferError, DmaController, Transfer, TriggerAction, TriggerSource,
+};
 
 /// An ADC where results are accessible via interrupt servicing.
 pub struct InterruptAdc<ADC, C>
@@ -20,6 +32,78 @@ where
 
 pub struct Adc<ADC> {
     adc: ADC,
+    clock_freq: Hertz,
+    vref: Option<VrefUser>,
+}
+
+/// A snapshot of an ADC's configuration, along with the resulting conversion
+/// timing. Returned by `Adc::config_report`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcConfigReport {
+    /// Frequency of the generic clock feeding the ADC
+    pub clock_freq: Hertz,
+    /// Division factor of the ADC prescaler
+    pub prescaler: u16,
+    /// Result resolution
+    pub resolution: adc0::ctrlb::RESSEL_A,
+    /// Sampling time, in ADC clock cycles minus one (`SAMPLEN`)
+    pub sample_length: u8,
+    /// Number of conversions accumulated into one result
+    pub samples: u16,
+    /// Selected reference, or `None` if the field holds a reserved value
+    pub reference: Option<adc0::refctrl::REFSEL_A>,
+    /// Time taken by one result, in nanoseconds
+    pub conversion_time_ns: u32,
+    /// Highest achievable rate of results
+    pub max_sample_rate: Hertz,
+}
+
+/// Returns the number of ADC clock cycles needed for one result.
+///
+/// Each conversion takes `SAMPLEN + 1` cycles of sampling plus one cycle
+/// per bit of resolution; the 16-bit averaging mode converts at 12 bits.
+/// When averaging, one result is made of `samples` conversions.
+pub const fn conversion_cycles(sample_length: u8, resolution_bits: u8, samples: u16) -> u32 {
+    (sample_length as u32 + 1 + resolution_bits as u32) * samples as u32
+}
+
+/// Returns the time taken by one result, in nanoseconds, rounded up.
+/// `prescaler` is the division factor between the generic clock and the ADC
+/// clock. See `conversion_cycles` for the other parameters.
+pub const fn conversion_time_ns(
+    clock_freq: Hertz,
+    prescaler: u16,
+    sample_length: u8,
+    resolution_bits: u8,
+    samples: u16,
+) -> u32 {
+    let cycles = conversion_cycles(sample_length, resolution_bits, samples) as u64;
+    let ns = cycles * prescaler as u64 * 1_000_000_000;
+    ((ns + clock_freq.0 as u64 - 1) / clock_freq.0 as u64) as u32
+}
+
+/// Returns the highest achievable rate of results, rounded down. See
+/// `conversion_time_ns` for the parameters.
+pub const fn max_sample_rate(
+    clock_freq: Hertz,
+    prescaler: u16,
+    sample_length: u8,
+    resolution_bits: u8,
+    samples: u16,
+) -> Hertz {
+    let cycles = conversion_cycles(sample_length, resolution_bits, samples);
+    Hertz(clock_freq.0 / (prescaler as u32 * cycles))
+}
+
+/// `EVCTRL.RESRDYEO`: an event is emitted on every result ready, the same
+/// bit on all chips
+const EVCTRL_RESRDYEO: u8 = 1 << 4;
+
+/// Returns the `EVCTRL` value `bits` with `RESRDYEO` set to `enable`, and the
+/// other event settings kept
+fn evctrl_result_event(bits: u8, enable: bool) -> u8 {
+    let resrdyeo = if enable { EVCTRL_RESRDYEO } else { 0 };
+    bits & !EVCTRL_RESRDYEO | resrdyeo
 }
 
 /// Describes how an interrupt-driven ADC should finalize the peripheral
@@ -33,6 +117,125 @@ pub trait ConversionMode<ADC> {
 pub struct SingleConversion;
 pub struct FreeRunning;
 
+/// `INPUTCTRL.MUXNEG` value selecting the internal ground, i.e. a
+/// single-ended conversion
+pub const MUXNEG_GND: u8 = 0x18;
+
+/// One conversion of an [`AdcSequence`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeqStep {
+    /// Positive input (`INPUTCTRL.MUXPOS`)
+    pub muxpos: u8,
+    /// Negative input (`INPUTCTRL.MUXNEG`), [`MUXNEG_GND`] for a
+    /// single-ended conversion
+    pub muxneg: u8,
+    /// Reference (`REFCTRL.REFSEL`)
+    pub refsel: adc0::refctrl::REFSEL_A,
+}
+
+impl SeqStep {
+    /// Value written to `INPUTCTRL` for this step
+    ///
+    /// Differential mode is selected unless `muxneg` is [`MUXNEG_GND`], and
+    /// the last step of a sequence sets `DSEQSTOP`.
+    pub fn inputctrl_bits(&self, last: bool) -> u32 {
+        let mut bits = (self.muxpos as u32 & 0x1F) | ((self.muxneg as u32 & 0x1F) << 8);
+        if self.muxneg != MUXNEG_GND {
+            bits |= 1 << 7;
+        }
+        if last {
+            bits |= 1 << 15;
+        }
+        bits
+    }
+
+    /// Value written to `REFCTRL` for this step
+    pub fn refctrl_bits(&self) -> u32 {
+        self.refsel as u32
+    }
+}
+
+/// DMA sequence of ADC conversions
+///
+/// The SAMD5x/E5x ADCs can have their registers rewritten by the DMAC between
+/// conversions, through the `DSEQDATA` register. The registers enabled in
+/// `DSEQCTRL` are written in order, one DMA beat each, and a conversion is
+/// started automatically once the last one is written. This allows scanning
+/// channels in an arbitrary order, with a different reference for each,
+/// without involving the CPU.
+///
+/// An [`AdcSequence`] holds the `INPUTCTRL` and `REFCTRL` values of each
+/// [`SeqStep`], in the order the ADC expects them. It is used as the source
+/// buffer of the sequencing DMA transfer, see `Adc::start_sequence`. The
+/// `DSEQSTOP` bit of the last step ends the sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct AdcSequence<const STEPS: usize> {
+    words: [[u32; 2]; STEPS],
+}
+
+impl<const STEPS: usize> AdcSequence<STEPS> {
+    /// Build the DMA data for a sequence of conversions
+    pub fn new(steps: &[SeqStep; STEPS]) -> Self {
+        let mut words = [[0; 2]; STEPS];
+        for (i, (words, step)) in words.iter_mut().zip(steps.iter()).enumerate() {
+            *words = [step.inputctrl_bits(i == STEPS - 1), step.refctrl_bits()];
+        }
+        AdcSequence { words }
+    }
+
+    /// Return the values written to `DSEQDATA`, in order
+    pub fn words(&self) -> &[[u32; 2]; STEPS] {
+        &self.words
+    }
+}
+
+/// The sequence is transferred one 32-bit word at a time to the fixed
+/// `DSEQDATA` register
+#[cfg(all(feature = "unproven", feature = "dma"))]
+unsafe impl<const STEPS: usize> Buffer for &mut AdcSequence<STEPS> {
+    type Beat = u32;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut u32 {
+        let start = self.words.as_mut_ptr() as *mut u32;
+        // SAFETY: The words are contiguous, and this points one past the end,
+        // as expected for an incrementing buffer
+        unsafe { start.add(2 * STEPS) }
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        2 * STEPS
+    }
+}
+
+/// Running transfer writing an [`AdcSequence`] to `DSEQDATA`
+#[cfg(all(feature = "unproven", feature = "dma"))]
+pub type SequenceTransfer<Id, const STEPS: usize> =
+    Transfer<DmaChannel<Id, Busy>, BufferPair<&'static mut AdcSequence<STEPS>, &'static mut u32>>;
+
+/// Running transfer reading the result of each step of an [`AdcSequence`]
+#[cfg(all(feature = "unproven", feature = "dma"))]
+pub type ResultTransfer<Id, const STEPS: usize> =
+    Transfer<DmaChannel<Id, Busy>, BufferPair<&'static mut u16, &'static mut [u16; STEPS]>>;
+
+/// Channels and buffers of an [`AdcSequence`] which was rejected by the DMAC,
+/// along with the reason
+#[cfg(all(feature = "unproven", feature = "dma"))]
+pub type RejectedSequence<S, R, const STEPS: usize> = (
+    DmaChannel<S, Ready>,
+    DmaChannel<R, Ready>,
+    &'static mut AdcSequence<STEPS>,
+    &'static mut [u16; STEPS],
+    BufferError,
+);
+
 macro_rules! adc_hal {
     ($($ADC:ident: ($init:ident, $mclk:ident, $apmask:ident, $compcal:ident, $refcal:ident, $r2rcal:ident),)+) => {
         $(
@@ -42,7 +245,7 @@ impl Adc<$ADC> {
         // set to 1/(1/(48000000/32) * 6) = 250000 SPS
         let adc_clock = clocks.configure_gclk_divider_and_source(gclk, 1, DFLL, false)
             .expect("adc clock setup failed");
-        clocks.$init(&adc_clock).expect("adc clock setup failed");
+        let clock_freq = clocks.$init(&adc_clock).expect("adc clock setup failed").freq();
         adc.ctrla.modify(|_, w| w.prescaler().div32());
         adc.ctrlb.modify(|_, w| w.ressel()._12bit());
         while adc.syncbusy.read().ctrlb().bit_is_set() {}
@@ -57,7 +260,11 @@ impl Adc<$ADC> {
             w.biasr2r().bits(calibration::$r2rcal())
         });
 
-        let mut newadc = Self { adc };
+        let mut newadc = Self {
+            adc,
+            clock_freq,
+            vref: None,
+        };
         newadc.samples(adc0::avgctrl::SAMPLENUM_A::_1);
         newadc.reference(adc0::refctrl::REFSEL_A::INTVCC1);
 
@@ -90,6 +297,129 @@ impl Adc<$ADC> {
         while self.adc.syncbusy.read().refctrl().bit_is_set() {}
     }
 
+    /// Use the internal voltage reference of the SUPC (`INTREF`)
+    ///
+    /// The ADC holds `vref` while it uses the reference, so that its level
+    /// can't be changed. The user held before, if any, is returned.
+    pub fn use_internal_reference(&mut self, vref: VrefUser) -> Option<VrefUser> {
+        self.reference(adc0::refctrl::REFSEL_A::INTREF);
+        self.vref.replace(vref)
+    }
+
+    /// Stop using the internal voltage reference, and return its user, to be
+    /// released with `Vref::release`
+    ///
+    /// The ADC is referenced to `VDDANA / 2` again (`INTVCC1`), as after
+    /// `Adc::adcN`.
+    pub fn release_internal_reference(&mut self) -> Option<VrefUser> {
+        let vref = self.vref.take()?;
+        self.reference(adc0::refctrl::REFSEL_A::INTVCC1);
+        Some(vref)
+    }
+
+    /// Emit an event on every result ready (`EVCTRL.RESRDYEO`)
+    ///
+    /// Through EVSYS, this lets other peripherals react to each conversion
+    /// without involving the CPU. Select the ADC's `RESRDY` event generator
+    /// in an EVSYS `CHANNEL` register, then select that channel as the `USER`
+    /// of the consumer, e.g. the DAC start event or a DMAC channel event
+    /// input. To copy each result with the DMAC, configure the channel's
+    /// event input action as a trigger (`CHEVCTRL.EVACT`) and point its
+    /// source at the `RESULT` register. This HAL does not abstract EVSYS
+    /// yet.
+    pub fn enable_result_event(&mut self) {
+        // SAFETY: Only RESRDYEO changes, the other event settings are kept
+        self.adc
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(evctrl_result_event(r.bits(), true)) });
+    }
+
+    /// Stop emitting result ready events
+    pub fn disable_result_event(&mut self) {
+        // SAFETY: Only RESRDYEO changes, the other event settings are kept
+        self.adc
+            .evctrl
+            .modify(|r, w| unsafe { w.bits(evctrl_result_event(r.bits(), false)) });
+    }
+
+    /// Start a conversion on every incoming event (`EVCTRL.STARTEI`)
+    ///
+    /// The ADC must be enabled for the events to start conversions, and the
+    /// input selected, e.g. with [`Adc::enable_for_events`]. Route the event
+    /// generator, like an RTC periodic event, to the ADC's `START` event user
+    /// through EVSYS.
+    pub fn enable_start_event(&mut self) {
+        self.adc.evctrl.modify(|_, w| w.startei().set_bit());
+    }
+
+    /// Stop starting conversions on incoming events
+    pub fn disable_start_event(&mut self) {
+        self.adc.evctrl.modify(|_, w| w.startei().clear_bit());
+    }
+
+    /// Keep the ADC running in standby sleep (`CTRLA.RUNSTDBY`)
+    ///
+    /// This is needed for event-started conversions to complete while the
+    /// CPU sleeps. The ADC clock generator must also run in standby.
+    pub fn run_in_standby(&mut self, enable: bool) {
+        // CTRLA.RUNSTDBY is enable-protected; the ADC is only enabled during
+        // conversions
+        self.adc.ctrla.modify(|_, w| w.runstdby().bit(enable));
+    }
+
+    /// Select `pin` as the input and keep the ADC enabled, so that
+    /// conversions can be started by events, see [`Adc::enable_start_event`]
+    pub fn enable_for_events<PIN: Channel<$ADC, ID=u8>>(&mut self, pin: &mut PIN) {
+        self.mux(pin);
+        self.power_up();
+    }
+
+    /// Disable the ADC after [`Adc::enable_for_events`]
+    pub fn disable_for_events(&mut self) {
+        self.power_down();
+    }
+
+    /// Reads back the ADC configuration and computes the resulting
+    /// conversion time and maximum sample rate.
+    pub fn config_report(&self) -> AdcConfigReport {
+        use adc0::ctrlb::RESSEL_A;
+        let prescaler = 1 << (self.adc.ctrla.read().prescaler().bits() + 1);
+        let resolution = self.adc.ctrlb.read().ressel().variant();
+        let resolution_bits = match resolution {
+            RESSEL_A::_12BIT | RESSEL_A::_16BIT => 12,
+            RESSEL_A::_10BIT => 10,
+            RESSEL_A::_8BIT => 8,
+        };
+        let sample_length = self.adc.sampctrl.read().samplen().bits();
+        let samples = 1 << self.adc.avgctrl.read().samplenum().bits().min(10);
+        let reference = match self.adc.refctrl.read().refsel().variant() {
+            Variant::Val(reference) => Some(reference),
+            Variant::Res(_) => None,
+        };
+        AdcConfigReport {
+            clock_freq: self.clock_freq,
+            prescaler,
+            resolution,
+            sample_length,
+            samples,
+            reference,
+            conversion_time_ns: conversion_time_ns(
+                self.clock_freq,
+                prescaler,
+                sample_length,
+                resolution_bits,
+                samples,
+            ),
+            max_sample_rate: max_sample_rate(
+                self.clock_freq,
+                prescaler,
+                sample_length,
+                resolution_bits,
+                samples,
+            ),
+        }
+    }
+
     fn power_up(&mut self) {
         while self.adc.syncbusy.read().enable().bit_is_set() {}
         self.adc.ctrla.modify(|_, w| w.enable().set_bit());
@@ -157,6 +487,89 @@ impl Adc<$ADC> {
     }
 }
 
+#[cfg(all(feature = "unproven", feature = "dma"))]
+paste::paste! {
+impl Adc<$ADC> {
+    /// Start a DMA sequence of conversions
+    ///
+    /// `seq_chan` writes the `sequence` to `DSEQDATA`, triggered by the ADC
+    /// each time it is ready for the next register value, and the conversion
+    /// of each step starts automatically. `result_chan` copies each result to
+    /// the slot of its step in `results`. The result transfer is started
+    /// first, so no result can be missed.
+    ///
+    /// The sequence is done when the result transfer completes. Its channel
+    /// can raise the DMAC transfer-complete interrupt if it was initialized
+    /// with interrupts enabled. Then call [`Adc::finish_sequence`].
+    ///
+    /// The sequence takes two DMA beats per step, so it can't have more
+    /// steps than half of [`MAX_BEATS`](crate::dmac::MAX_BEATS). A longer
+    /// sequence is handed back with both channels and buffers, before the ADC
+    /// is touched.
+    pub fn start_sequence<S: ChId, R: ChId, const STEPS: usize>(
+        &mut self,
+        dmac: &mut DmaController,
+        seq_chan: DmaChannel<S, Ready>,
+        result_chan: DmaChannel<R, Ready>,
+        sequence: &'static mut AdcSequence<STEPS>,
+        results: &'static mut [u16; STEPS],
+    ) -> Result<
+        (SequenceTransfer<S, STEPS>, ResultTransfer<R, STEPS>),
+        RejectedSequence<S, R, STEPS>,
+    > {
+        // SAFETY: The registers are only accessed by the DMAC until the
+        // sequence is finished
+        let result_reg = unsafe { &mut *self.adc.result.as_ptr() };
+        let dseqdata = unsafe { &mut *self.adc.dseqdata.as_ptr() };
+
+        let results = match Transfer::try_new(result_chan, result_reg, results, false) {
+            Ok(results) => results,
+            Err((result_chan, _, results, e)) => {
+                return Err((seq_chan, result_chan, sequence, results, e))
+            }
+        };
+        let sequence = match Transfer::try_new(seq_chan, sequence, dseqdata, false) {
+            Ok(sequence) => sequence,
+            Err((seq_chan, sequence, _, e)) => {
+                let (result_chan, _, results, _) = results.free();
+                return Err((seq_chan, result_chan, sequence, results, e));
+            }
+        };
+
+        self.adc.dseqctrl.write(|w| {
+            w.inputctrl().set_bit();
+            w.refctrl().set_bit();
+            w.autostart().set_bit()
+        });
+        self.power_up();
+
+        let results = results.begin(
+            dmac,
+            TriggerSource::[<$ADC _RESRDY>],
+            TriggerAction::BURST,
+        );
+        let sequence = sequence.begin(
+            dmac,
+            TriggerSource::[<$ADC _SEQ>],
+            TriggerAction::BURST,
+        );
+        Ok((sequence, results))
+    }
+
+    /// Whether a DMA sequence is still updating the registers
+    pub fn sequence_busy(&self) -> bool {
+        self.adc.dseqstat.read().busy().bit_is_set()
+    }
+
+    /// Stop DMA sequencing and power down the ADC, once the result transfer
+    /// of a sequence has completed
+    pub fn finish_sequence(&mut self) {
+        self.adc.dseqctrl.write(|w| unsafe { w.bits(0) });
+        self.power_down();
+    }
+}
+}
+
 impl ConversionMode<$ADC> for SingleConversion  {
     fn on_start(_adc: &mut Adc<$ADC>) {
     }
@@ -317,3 +730,70 @@ adc_pins! {
     PD00: (ADC1, 14),
     PD01: (ADC1, 15),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversion_time_default_config() {
+        // 48 MHz / 32, SAMPLEN = 5, 12 bits: 18 cycles of 1.5 MHz
+        assert_eq!(conversion_time_ns(Hertz(48_000_000), 32, 5, 12, 1), 12_000);
+        assert_eq!(
+            max_sample_rate(Hertz(48_000_000), 32, 5, 12, 1),
+            Hertz(83_333)
+        );
+    }
+
+    #[test]
+    fn conversion_time_scales_with_averaging() {
+        let single = conversion_time_ns(Hertz(48_000_000), 4, 63, 12, 1);
+        let averaged = conversion_time_ns(Hertz(48_000_000), 4, 63, 12, 16);
+        assert_eq!(single, 6_334);
+        assert_eq!(averaged, 101_334);
+        assert_eq!(
+            max_sample_rate(Hertz(48_000_000), 4, 63, 12, 16),
+            Hertz(9_868)
+        );
+    }
+
+    #[test]
+    fn sequence_words_in_register_order() {
+        use adc0::refctrl::REFSEL_A;
+        let sequence = AdcSequence::new(&[
+            SeqStep {
+                muxpos: 3,
+                muxneg: MUXNEG_GND,
+                refsel: REFSEL_A::INTVCC1,
+            },
+            SeqStep {
+                muxpos: 0,
+                muxneg: 1,
+                refsel: REFSEL_A::AREFA,
+            },
+        ]);
+        assert_eq!(
+            sequence.words(),
+            &[
+                [0x1803, REFSEL_A::INTVCC1 as u32],
+                [0x8000 | 0x0100 | 0x80, REFSEL_A::AREFA as u32],
+            ]
+        );
+    }
+
+    #[test]
+    fn conversion_cycles_per_resolution() {
+        assert_eq!(conversion_cycles(0, 12, 1), 13);
+        assert_eq!(conversion_cycles(0, 10, 1), 11);
+        assert_eq!(conversion_cycles(0, 8, 1), 9);
+    }
+
+    #[test]
+    fn result_event_keeps_the_other_events() {
+        assert_eq!(evctrl_result_event(0, true), 0x10);
+        assert_eq!(evctrl_result_event(0x10, false), 0);
+        // STARTEI and WINMONEO stay as they were
+        assert_eq!(evctrl_result_event(0x22, true), 0x32);
+        assert_eq!(evctrl_result_event(0x32, false), 0x22);
+    }
+}
@@ -0,0 +1,245 @@
+//! # Monotonic timestamps for received CAN frames
+//!
+//! The MCAN stores a 16-bit timestamp in every received frame, taken from a
+//! counter which wraps around after 65536 ticks, e.g. every 65 ms at 1
+//! Mbit/s. [`TimestampCorrelator`] converts these timestamps to the 64-bit
+//! monotonic timebase of the application, like the RTC or a `Monotonic` of
+//! RTIC.
+//!
+//! [`configure_timestamp_counter`] makes the counter tick every `prescaler`
+//! nominal bit times, and [`TimestampCorrelator::sample`] records the
+//! counter and the monotonic time together. A frame timestamp is then dated
+//! by its age relative to the latest sample.
+//!
+//! The age of a frame is only known modulo the counter period. It is
+//! unambiguous for frames received after the previous sample, if the
+//! samples are less than one counter period apart. So:
+//!
+//! - Sample once when the CAN is started, before any frame is received
+//! - Sample each time the RX FIFOs are drained, right before reading them,
+//!   and at least once per [`TimestampCorrelator::wrap_period`] while frames
+//!   can be waiting in the FIFOs
+//!
+//! Frames received before the previous sample, and frames dated across
+//! samples too far apart, are rejected with a [`TimestampError`] rather than
+//! dated one counter period off.
+//!
+//! ```
+//! configure_timestamp_counter(&can0, 1)?;
+//! // 500 kbit/s, and a 1 MHz monotonic timer
+//! let mut correlator = TimestampCorrelator::new(500_000, 1_000_000);
+//! correlator.sample(read_timestamp_counter(&can0), mono.now());
+//! // On every RX FIFO interrupt
+//! correlator.sample(read_timestamp_counter(&can0), mono.now());
+//! for frame in fifo.drain() {
+//!     let received_at = correlator.to_monotonic(frame.timestamp())?;
+//! }
+//! ```
+
+use crate::target_device::can0;
+
+/// Number of ticks of the timestamp counter, before it wraps around
+const COUNTER_PERIOD: u64 = 1 << 16;
+
+/// Samples further apart than this number of ticks can't be trusted to be
+/// less than a counter period apart, leaving a margin for the drift between
+/// the CAN clock and the monotonic timebase
+const MAX_SAMPLE_INTERVAL: u64 = COUNTER_PERIOD - COUNTER_PERIOD / 16;
+
+/// Errors of the timestamp counter configuration and conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampError {
+    /// The prescaler is not within 1 to 16
+    InvalidPrescaler,
+    /// The CAN is not in configuration mode, with `CCCR.INIT` and
+    /// `CCCR.CCE` set
+    NotInConfiguration,
+    /// Less than two samples were taken
+    NotSampled,
+    /// The latest samples are too far apart to tell how many times the
+    /// counter wrapped around
+    SamplesTooFarApart,
+    /// The frame was received before the previous sample
+    Stale,
+}
+
+/// Make the timestamp counter of `can` count every `prescaler` nominal bit
+/// times, from 1 to 16 (`TSCC.TSS = INC`, `TSCC.TCP = prescaler - 1`)
+///
+/// `TSCC` can only be written in configuration mode.
+pub fn configure_timestamp_counter(
+    can: &can0::RegisterBlock,
+    prescaler: u8,
+) -> Result<(), TimestampError> {
+    if !(1..=16).contains(&prescaler) {
+        return Err(TimestampError::InvalidPrescaler);
+    }
+    let cccr = can.cccr.read();
+    if cccr.init().bit_is_clear() || cccr.cce().bit_is_clear() {
+        return Err(TimestampError::NotInConfiguration);
+    }
+    can.tscc.write(|w| unsafe {
+        w.tss().inc();
+        w.tcp().bits(prescaler - 1)
+    });
+    Ok(())
+}
+
+/// Read the timestamp counter of `can` (`TSCV`)
+#[inline]
+pub fn read_timestamp_counter(can: &can0::RegisterBlock) -> u16 {
+    can.tscv.read().tsc().bits()
+}
+
+/// Timestamp counter and monotonic time, read together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sample {
+    counter: u16,
+    monotonic: u64,
+}
+
+/// Converts frame timestamps to a monotonic timebase
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Clone)]
+pub struct TimestampCorrelator {
+    counter_hz: u32,
+    monotonic_hz: u32,
+    previous: Option<Sample>,
+    latest: Option<Sample>,
+}
+
+impl TimestampCorrelator {
+    /// Create a correlator for a timestamp counter ticking at `counter_hz`,
+    /// the bit rate divided by the prescaler, and a monotonic timebase
+    /// ticking at `monotonic_hz`
+    #[inline]
+    pub fn new(counter_hz: u32, monotonic_hz: u32) -> Self {
+        TimestampCorrelator {
+            counter_hz,
+            monotonic_hz,
+            previous: None,
+            latest: None,
+        }
+    }
+
+    /// Period of the timestamp counter, in monotonic ticks. Samples must be
+    /// taken at least this often while frames are received.
+    #[inline]
+    pub fn wrap_period(&self) -> u64 {
+        self.to_monotonic_ticks(MAX_SAMPLE_INTERVAL)
+    }
+
+    /// Record the timestamp `counter` read at the `monotonic` time
+    #[inline]
+    pub fn sample(&mut self, counter: u16, monotonic: u64) {
+        self.previous = self.latest;
+        self.latest = Some(Sample { counter, monotonic });
+    }
+
+    /// Monotonic time at which a frame with the given `timestamp` was
+    /// received
+    pub fn to_monotonic(&self, timestamp: u16) -> Result<u64, TimestampError> {
+        let (previous, latest) = match (self.previous, self.latest) {
+            (Some(previous), Some(latest)) => (previous, latest),
+            _ => return Err(TimestampError::NotSampled),
+        };
+        let elapsed = latest.monotonic.saturating_sub(previous.monotonic);
+        if self.to_counter_ticks(elapsed) >= MAX_SAMPLE_INTERVAL {
+            return Err(TimestampError::SamplesTooFarApart);
+        }
+        // Both are less than a counter period, so they are exact
+        let window = latest.counter.wrapping_sub(previous.counter);
+        let age = latest.counter.wrapping_sub(timestamp);
+        if age > window {
+            return Err(TimestampError::Stale);
+        }
+        Ok(latest
+            .monotonic
+            .saturating_sub(self.to_monotonic_ticks(age as u64)))
+    }
+
+    /// Convert counter ticks to monotonic ticks, rounded to the nearest
+    fn to_monotonic_ticks(&self, ticks: u64) -> u64 {
+        let counter_hz = self.counter_hz as u64;
+        (ticks * self.monotonic_hz as u64 + counter_hz / 2) / counter_hz
+    }
+
+    /// Convert monotonic ticks to counter ticks, rounded down
+    fn to_counter_ticks(&self, ticks: u64) -> u64 {
+        (ticks as u128 * self.counter_hz as u128 / self.monotonic_hz as u128) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 Mbit/s without prescaler, and a 1 MHz monotonic timebase: one
+    /// counter tick per microsecond
+    fn correlator() -> TimestampCorrelator {
+        TimestampCorrelator::new(1_000_000, 1_000_000)
+    }
+
+    #[test]
+    fn needs_two_samples() {
+        let mut correlator = correlator();
+        assert_eq!(correlator.to_monotonic(0), Err(TimestampError::NotSampled));
+        correlator.sample(100, 5_000);
+        assert_eq!(correlator.to_monotonic(0), Err(TimestampError::NotSampled));
+        correlator.sample(1_100, 6_000);
+        assert_eq!(correlator.to_monotonic(600), Ok(5_500));
+    }
+
+    #[test]
+    fn frames_across_a_counter_wrap() {
+        let mut correlator = correlator();
+        correlator.sample(65_000, 1_000_000);
+        correlator.sample(40_000, 1_040_536);
+        // Received before the wrap, and right after the previous sample
+        assert_eq!(correlator.to_monotonic(65_500), Ok(1_000_500));
+        assert_eq!(correlator.to_monotonic(65_000), Ok(1_000_000));
+        // Received after the wrap
+        assert_eq!(correlator.to_monotonic(100), Ok(1_000_636));
+        assert_eq!(correlator.to_monotonic(40_000), Ok(1_040_536));
+    }
+
+    #[test]
+    fn stale_frames_are_rejected() {
+        let mut correlator = correlator();
+        correlator.sample(10_000, 2_000_000);
+        correlator.sample(20_000, 2_010_000);
+        // Received before the previous sample. It could be older than a
+        // counter period.
+        assert_eq!(correlator.to_monotonic(9_999), Err(TimestampError::Stale));
+        // Would be read as 10 us old, but is from the future: a frame from a
+        // counter period ago
+        assert_eq!(correlator.to_monotonic(20_010), Err(TimestampError::Stale));
+    }
+
+    #[test]
+    fn samples_too_far_apart_are_rejected() {
+        let mut correlator = correlator();
+        correlator.sample(0, 0);
+        // One wrap and 1000 ticks later, the counters look 1000 ticks apart
+        correlator.sample(1_000, 66_536);
+        assert_eq!(
+            correlator.to_monotonic(500),
+            Err(TimestampError::SamplesTooFarApart)
+        );
+        correlator.sample(2_000, 67_536);
+        assert_eq!(correlator.to_monotonic(1_500), Ok(67_036));
+    }
+
+    #[test]
+    fn timebase_conversion() {
+        // 500 kbit/s, prescaler 1: 2 us per tick, with a 32.768 kHz RTC
+        let mut correlator = TimestampCorrelator::new(500_000, 32_768);
+        correlator.sample(0, 1_000);
+        correlator.sample(30_000, 1_000 + 1_966);
+        // 15_000 ticks of 2 us are 30 ms, 983 RTC ticks
+        assert_eq!(correlator.to_monotonic(15_000), Ok(1_983));
+        // 61_440 ticks are 122.88 ms, 4026.6 RTC ticks
+        assert_eq!(correlator.wrap_period(), 4_027);
+    }
+}
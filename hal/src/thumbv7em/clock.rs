@@ -5,12 +5,38 @@
 //! that the peripherals have been correctly configured.
 use crate::target_device::gclk::genctrl::SRC_A::*;
 use crate::target_device::gclk::pchctrl::GEN_A::*;
+use crate::target_device::generic::Variant;
+use crate::target_device::oscctrl::dpll::dpllctrlb::REFCLK_A;
 use crate::target_device::{self, GCLK, MCLK, NVMCTRL, OSC32KCTRL, OSCCTRL};
 use crate::time::{Hertz, MegaHertz};
 
+pub use crate::shared_pclk::{PclkToken, PclkUser, SharedPclk};
+
+pub mod debug_out;
+pub mod dyn_gclk;
+pub mod gclk_in;
+pub mod gclk_out;
+pub mod presets;
+pub mod watch;
+
+pub use debug_out::DebugClock;
+pub use dyn_gclk::{DynGclk, DynGclkId};
+pub use gclk_in::GclkIn;
+pub use gclk_out::{GclkId, GclkIo, GclkOut};
+pub use watch::PclkWatch;
+use watch::PclkWatches;
+
 pub type ClockGenId = target_device::gclk::pchctrl::GEN_A;
 pub type ClockSource = target_device::gclk::genctrl::SRC_A;
 
+/// Number of clock generators, 12 on every SAMD5x and SAME5x
+pub const NUM_GCLK_GENERATORS: usize = 12;
+
+/// Every clock generator, in order
+const GCLK_IDS: [ClockGenId; NUM_GCLK_GENERATORS] = [
+    GCLK0, GCLK1, GCLK2, GCLK3, GCLK4, GCLK5, GCLK6, GCLK7, GCLK8, GCLK9, GCLK10, GCLK11,
+];
+
 #[allow(non_camel_case_types)]
 pub enum ClockId {
     DFLL48 = 0,
@@ -75,6 +101,7 @@ impl From<ClockId> for u8 {
 /// such as `GenericClockController::tcc2_tc3` to configure
 /// the clock for a peripheral.
 //#[derive(Clone, Copy)]
+#[derive(Debug)]
 pub struct GClock {
     gclk: ClockGenId,
     freq: Hertz,
@@ -86,6 +113,912 @@ impl Into<Hertz> for GClock {
     }
 }
 
+/// The configuration of a clock generator, as read back from the hardware.
+///
+/// Unlike the frequencies tracked by `GenericClockController`, this reflects
+/// what is actually programmed into `GENCTRL`, which may differ if e.g. a
+/// bootloader left a generator configured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockConfig {
+    /// The selected clock source, or `None` if the field holds a reserved
+    /// value
+    pub src: Option<ClockSource>,
+    /// The effective linear division factor, taking `DIVSEL` into account
+    pub div: u32,
+    /// Whether the generator is enabled
+    pub enabled: bool,
+    /// Whether the 50/50 duty cycle correction for odd dividers is enabled
+    pub improve_duty_cycle: bool,
+    /// Whether the generator output is routed to its GCLK_IO pin
+    pub output_enable: bool,
+    /// The level driven on the GCLK_IO pin while the output is enabled but
+    /// the generator is turned off. The running clock itself can't be
+    /// inverted on the pin; an inverted clock can be output by a TCC
+    /// instead, see `clock_output` of the TCC types in `pwm`.
+    pub output_off_value: bool,
+}
+
+impl GClockConfig {
+    /// Returns the generator's output frequency, given the frequency of its
+    /// source.
+    pub fn freq(&self, source_freq: Hertz) -> Hertz {
+        Hertz(source_freq.0 / self.div)
+    }
+
+    /// Returns the nominal frequency of the generator's source, if it is a
+    /// fixed-frequency internal oscillator.
+    ///
+    /// This makes it easy to catch a generator that was left on a 32 kHz
+    /// oscillator while the code expects it to run at MHz speeds, e.g.
+    /// `debug_assert!(config.source_freq() != Some(OSC32K_FREQ))`. Sources
+    /// whose frequency depends on their configuration, like the crystal
+    /// oscillators, the DPLLs, `GCLKIN` and `GCLKGEN1`, return `None`.
+    pub fn source_freq(&self) -> Option<Hertz> {
+        match self.src? {
+            XOSC32K | OSCULP32K => Some(OSC32K_FREQ),
+            DFLL => Some(OSC48M_FREQ),
+            XOSC0 | XOSC1 | GCLKIN | GCLKGEN1 | DPLL0 | DPLL1 => None,
+        }
+    }
+}
+
+/// A `Copy` snapshot of a clock generator, cheap to log and to compare
+/// against a later snapshot in order to detect unexpected clock changes.
+/// Returned by `GenericClockController::snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockSnapshot {
+    /// The clock generator in question
+    pub gclk: ClockGenId,
+    /// The configuration read back from the hardware
+    pub config: GClockConfig,
+    /// The generator's output frequency, or `None` if the frequency of its
+    /// source isn't known to the controller
+    pub freq: Option<Hertz>,
+}
+
+impl GClockSnapshot {
+    fn new(gclk: ClockGenId, config: GClockConfig, source_freq: Option<Hertz>) -> Self {
+        GClockSnapshot {
+            gclk,
+            config,
+            freq: source_freq.map(|freq| config.freq(freq)),
+        }
+    }
+
+    /// Returns the output frequency of the generator, or `None` if it is
+    /// disabled, or if the frequency of its source isn't known
+    pub fn running_freq(&self) -> Option<Hertz> {
+        if self.config.enabled {
+            self.freq
+        } else {
+            None
+        }
+    }
+}
+
+/// `PCHCTRL.CHEN`: the peripheral channel is enabled
+const PCHCTRL_CHEN: u32 = 1 << 6;
+
+/// Returns the `PCHCTRL` value `bits` with `CHEN` set to `enable`, and the
+/// generator selection kept
+fn pchctrl_gated(bits: u32, enable: bool) -> u32 {
+    let chen = if enable { PCHCTRL_CHEN } else { 0 };
+    bits & !PCHCTRL_CHEN | chen
+}
+
+/// Waits until `chen` reads back `enable`, which is when a change of `CHEN`
+/// took effect
+fn wait_for_chen(enable: bool, mut chen: impl FnMut() -> bool) {
+    while chen() != enable {}
+}
+
+/// The clock sources, indexed by their `GENCTRL.SRC` value
+const GCLK_SOURCES: [ClockSource; 9] = [
+    XOSC0, XOSC1, GCLKIN, GCLKGEN1, OSCULP32K, XOSC32K, DFLL, DPLL0, DPLL1,
+];
+
+/// Returns the source selected by the `GENCTRL.SRC` value `bits`, or `None`
+/// for a reserved value
+fn gclk_source_from_bits(bits: u8) -> Option<ClockSource> {
+    GCLK_SOURCES.get(bits as usize).copied()
+}
+
+/// `GENCTRL.OOV`: the level of the GCLK_IO pin while the generator is off
+const GENCTRL_OOV: u32 = 1 << 10;
+/// `GENCTRL.OE`: the generator output is routed to its GCLK_IO pin
+const GENCTRL_OE: u32 = 1 << 11;
+
+/// Returns the `GENCTRL` bits of the GCLK_IO pin configuration
+fn gclk_output_bits(output_enable: bool, output_off_value: bool) -> u32 {
+    let oe = if output_enable { GENCTRL_OE } else { 0 };
+    let oov = if output_off_value { GENCTRL_OOV } else { 0 };
+    oe | oov
+}
+
+/// Returns `(output_enable, output_off_value)` from the bits of `GENCTRL`
+fn gclk_output_from_bits(genctrl: u32) -> (bool, bool) {
+    (genctrl & GENCTRL_OE != 0, genctrl & GENCTRL_OOV != 0)
+}
+
+/// `GENCTRL.GENEN`: the generator is enabled
+const GENCTRL_GENEN: u32 = 1 << 8;
+
+/// `GENCTRL0` as written by `GenericClockController::reset`: the DFLL,
+/// undivided and enabled, like out of reset
+const GENCTRL0_RESET: u32 = 1 << 16 | GENCTRL_GENEN | DFLL as u32;
+
+/// Updates the frequencies `gclks` of the configured generators running from
+/// `src`, after it changed to `source`, and returns those which changed, one
+/// bit each
+///
+/// `inputs` holds the frequency of the GCLK_IO input of each generator, which
+/// is their source for `GCLKIN`. A change of GCLK1 is passed on to the
+/// generators it feeds.
+fn propagate_source_change(
+    gclks: &mut [Hertz; NUM_GCLK_GENERATORS],
+    inputs: &[Hertz; NUM_GCLK_GENERATORS],
+    src: ClockSource,
+    source: Option<Hertz>,
+    read_config: &mut dyn FnMut(ClockGenId) -> GClockConfig,
+) -> u16 {
+    let mut changed = 0;
+    for &gclk in GCLK_IDS.iter() {
+        let idx = u8::from(gclk) as usize;
+        if gclks[idx].0 == 0 {
+            continue;
+        }
+        let config = read_config(gclk);
+        if config.src != Some(src) {
+            continue;
+        }
+        let source = match src {
+            GCLKIN => Some(inputs[idx]).filter(|freq| freq.0 != 0),
+            _ => source,
+        };
+        let source = match source {
+            Some(source) => source,
+            None => continue,
+        };
+        // Kept at 1 Hz at least, like `configure_two_stage_divider`
+        let freq = Hertz(config.freq(source).0.max(1));
+        if freq != gclks[idx] {
+            gclks[idx] = freq;
+            changed |= 1 << idx;
+            if gclk == GCLK1 {
+                changed |=
+                    propagate_source_change(gclks, inputs, GCLKGEN1, Some(freq), read_config);
+            }
+        }
+    }
+    changed
+}
+
+/// Returns whether an enabled generator runs from `src`
+fn source_in_use(
+    src: ClockSource,
+    read_config: &mut dyn FnMut(ClockGenId) -> GClockConfig,
+) -> bool {
+    GCLK_IDS.iter().any(|&gclk| {
+        let config = read_config(gclk);
+        config.enabled && config.src == Some(src)
+    })
+}
+
+/// Reported by `GenericClockController::reconcile` when a clock generator's
+/// hardware configuration doesn't match what the controller expects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockMismatch {
+    /// The clock generator in question
+    pub gclk: ClockGenId,
+    /// The frequency the controller believes the generator runs at;
+    /// `Hertz(0)` if it hasn't been configured through the controller
+    pub expected: Hertz,
+    /// The configuration read back from the hardware
+    pub actual: GClockConfig,
+}
+
+/// The divider chosen by `GenericClockController::div_to_reach`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GClockDivider {
+    /// The linear divider to pass to `configure_gclk_divider_and_source`
+    pub divider: u16,
+    /// The frequency achieved with `divider`
+    pub freq: Hertz,
+    /// The absolute difference between `freq` and the requested frequency
+    pub error: Hertz,
+}
+
+/// Division factor of a clock generator, as written to `GENCTRL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GClockDiv {
+    /// Linear division by the value. Zero and one both mean no division.
+    /// Only GCLK1 accepts values above 255.
+    Div(u16),
+    /// Exponential division by 2^n (`DIVSEL`), from 2^1 to 2^9, or to 2^17
+    /// for GCLK1
+    Div2Pow(u8),
+}
+
+impl GClockDiv {
+    /// Returns the effective division factor
+    pub fn factor(&self) -> u32 {
+        match *self {
+            GClockDiv::Div(div) => (div as u32).max(1),
+            GClockDiv::Div2Pow(n) => 1 << n,
+        }
+    }
+
+    /// Returns whether the generator can represent this division factor
+    pub fn is_valid_for(&self, gclk: ClockGenId) -> bool {
+        match *self {
+            GClockDiv::Div(div) => div <= max_divider(gclk),
+            GClockDiv::Div2Pow(n) => n >= 1 && n <= max_div2_pow(gclk),
+        }
+    }
+
+    /// Returns the values of the `DIVSEL` and `DIV` fields
+    fn genctrl_fields(&self) -> (bool, u16) {
+        match *self {
+            GClockDiv::Div(div) => (false, div),
+            GClockDiv::Div2Pow(n) => (true, n as u16 - 1),
+        }
+    }
+}
+
+/// Reported by `GenericClockController::try_configure_gclk_div` when a clock
+/// generator can't be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GClockError {
+    /// The clock generator has already been configured
+    AlreadyConfigured,
+    /// The division factor can't be represented by the clock generator
+    InvalidDivider(GClockDiv),
+    /// The frequency of the source isn't known to the controller
+    UnknownSourceFreq,
+    /// GCLK1 can't be its own source, through `GCLKGEN1`
+    SelfSourced,
+}
+
+/// Returns whether `src` is the output of `gclk` itself: only `GCLKGEN1` can
+/// feed another generator, and GCLK1 would never start from it
+fn feeds_itself(gclk: ClockGenId, src: ClockSource) -> bool {
+    gclk == GCLK1 && src == GCLKGEN1
+}
+
+/// Returns the output frequency of a generator dividing `source` by the
+/// linear `divider`, where zero means no division like one
+fn divided_freq(source: Hertz, divider: u16) -> Hertz {
+    Hertz(source.0 / GClockDiv::Div(divider).factor())
+}
+
+/// Picks the legal divider that brings `source` closest to `target`.
+fn closest_divider(source: Hertz, target: Hertz, max_divider: u16) -> GClockDivider {
+    let target = target.0.max(1);
+    let ideal = (source.0 / target).max(1).min(max_divider as u32);
+    let candidates = [ideal, (ideal + 1).min(max_divider as u32)];
+    let (divider, freq) = candidates
+        .iter()
+        .map(|&div| (div, source.0 / div))
+        .min_by_key(|&(_, freq)| (freq as i64 - target as i64).abs())
+        .unwrap();
+    GClockDivider {
+        divider: divider as u16,
+        freq: Hertz(freq),
+        error: Hertz((freq as i64 - target as i64).abs() as u32),
+    }
+}
+
+/// The largest linear divider supported by a clock generator. Only `GCLK1`
+/// has a 16-bit `DIV` field.
+fn max_divider(gclk: ClockGenId) -> u16 {
+    match gclk {
+        ClockGenId::GCLK1 => u16::MAX,
+        _ => u8::MAX as u16,
+    }
+}
+
+/// The largest exponent of the exponential divider of a clock generator. The
+/// hardware clamps larger values of `DIV` while `DIVSEL` is set.
+fn max_div2_pow(gclk: ClockGenId) -> u8 {
+    match gclk {
+        ClockGenId::GCLK1 => 17,
+        _ => 9,
+    }
+}
+
+/// Picks the division factor, linear or exponential, that brings `source`
+/// closest to `target` on the given clock generator, and returns it along
+/// with the frequency achieved.
+///
+/// The exponential divider reaches further than the linear one: down to
+/// 1/512 of the source on most generators, instead of 1/255. Linear division
+/// is preferred when both are as close.
+pub fn gclk_div_for_freq<S, T>(gclk: ClockGenId, source: S, target: T) -> (GClockDiv, Hertz)
+where
+    S: Into<Hertz>,
+    T: Into<Hertz>,
+{
+    let (source, target) = (source.into(), target.into());
+    let linear = closest_divider(source, target, max_divider(gclk));
+    let ideal = (source.0 / target.0.max(1)).max(1);
+    let floor_log2 = (31 - ideal.leading_zeros()) as u8;
+    let max = max_div2_pow(gclk);
+    let candidates = [floor_log2.max(1).min(max), (floor_log2 + 1).min(max)];
+    let distance = |freq: u32| freq.max(target.0) - freq.min(target.0);
+    candidates.iter().map(|&n| GClockDiv::Div2Pow(n)).fold(
+        (GClockDiv::Div(linear.divider), linear.freq),
+        |best, div| {
+            let freq = Hertz(source.0 / div.factor());
+            if distance(freq.0) < distance(best.1 .0) {
+                (div, freq)
+            } else {
+                best
+            }
+        },
+    )
+}
+
+/// The largest combined division factor of GCLK1 and a generator fed by it
+const MAX_TWO_STAGE_FACTOR: u32 = u16::MAX as u32 * u8::MAX as u32;
+
+/// The dividers chosen by `two_stage_divider`
+///
+/// GCLK1 divides its source by `gclk1`, and feeds a second generator through
+/// `GCLKGEN1`, which divides again by `second`. The frequencies are in
+/// millihertz, since the output can be far below 1 Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoStageDivider {
+    /// The linear divider of GCLK1
+    pub gclk1: u16,
+    /// The linear divider of the generator fed by GCLK1
+    pub second: u8,
+    /// The output frequency achieved, in millihertz
+    pub freq_millihertz: u64,
+    /// The absolute difference between `freq_millihertz` and the requested
+    /// frequency, in millihertz
+    pub error_millihertz: u64,
+}
+
+impl TwoStageDivider {
+    /// Returns the combined division factor of both generators
+    pub fn factor(&self) -> u32 {
+        self.gclk1 as u32 * self.second as u32
+    }
+}
+
+/// Reported by `two_stage_divider` when a frequency can't be reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoStageError {
+    /// The requested frequency is above the source frequency
+    TooFast,
+    /// The requested frequency is zero, or below the source frequency divided
+    /// by 65535 × 255
+    TooSlow,
+}
+
+/// Picks the dividers of GCLK1 and of a generator fed by GCLK1 that bring
+/// `source` closest to `target_millihertz`.
+///
+/// GCLK1 is the only generator that can feed others, and the only one with a
+/// 16-bit `DIV` field. Chaining it with another generator multiplies the
+/// dividers, up to 65535 × 255, which reaches sub-Hz frequencies: about 2 mHz
+/// from a 32.768 kHz source. Of the pairs with the closest combined factor,
+/// the one with the smallest second divider is picked. Pass the result to
+/// `GenericClockController::configure_two_stage_divider` to apply it.
+pub fn two_stage_divider(
+    source: Hertz,
+    target_millihertz: u64,
+) -> Result<TwoStageDivider, TwoStageError> {
+    let source = source.0 as u64 * 1000;
+    if target_millihertz > source {
+        return Err(TwoStageError::TooFast);
+    }
+    if target_millihertz.saturating_mul(MAX_TWO_STAGE_FACTOR as u64) < source {
+        return Err(TwoStageError::TooSlow);
+    }
+    let target = target_millihertz;
+    // The pair whose factor times the target is closest to the source
+    let (gclk1, second) = (1..=u8::MAX as u64)
+        .map(|second| {
+            let gclk1 = (2 * source + target * second) / (2 * target * second);
+            (gclk1.max(1).min(u16::MAX as u64), second)
+        })
+        .min_by_key(|&(gclk1, second)| {
+            let product = (gclk1 * second).saturating_mul(target);
+            product.max(source) - product.min(source)
+        })
+        .unwrap();
+    let factor = gclk1 * second;
+    let freq = (source + factor / 2) / factor;
+    Ok(TwoStageDivider {
+        gclk1: gclk1 as u16,
+        second: second as u8,
+        freq_millihertz: freq,
+        error_millihertz: freq.max(target) - freq.min(target),
+    })
+}
+
+/// Selects one of the two digital phase-locked loops
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DpllId {
+    Dpll0 = 0,
+    Dpll1 = 1,
+}
+
+/// The loop divider ratio chosen by `dpll_loop_div_for_target`
+///
+/// The DPLL output frequency is the reference frequency multiplied by
+/// `int + frac / 32`. The `LDR` field of `DPLLRATIO` holds `int - 1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DpllRatio {
+    /// The integer part of the multiplier
+    pub int: u16,
+    /// The fractional part of the multiplier, in 1/32 steps
+    pub frac: u8,
+    /// The output frequency achieved with this ratio
+    pub freq: Hertz,
+    /// The absolute difference between `freq` and the requested frequency
+    pub error: Hertz,
+}
+
+impl DpllRatio {
+    /// Returns the ratio that brings `reference` closest to `target`,
+    /// rounded to the nearest 1/32 step, along with the frequency achieved
+    ///
+    /// Unlike `dpll_loop_div_for_target`, the limits of the DPLL are not
+    /// checked. The integer part is only kept within 1 and 8192, the range
+    /// of the 13-bit `LDR` field, so the ratio saturates at 8192 + 31/32.
+    pub fn nearest(reference: Hertz, target: Hertz) -> Self {
+        let steps = (target.0 as u64 * 64 / reference.0.max(1) as u64 + 1) / 2;
+        let steps = steps.max(32).min(MAX_DPLL_INT as u64 * 32 + 31);
+        Self::from_steps(reference, target, steps)
+    }
+
+    /// Returns the ratio of `steps` 1/32 steps, and the frequency achieved
+    /// with it
+    fn from_steps(reference: Hertz, target: Hertz, steps: u64) -> Self {
+        let mut ratio = DpllRatio {
+            int: (steps / 32) as u16,
+            frac: (steps % 32) as u8,
+            freq: Hertz(0),
+            error: Hertz(0),
+        };
+        ratio.freq = ratio.output_freq(reference);
+        ratio.error = Hertz(ratio.freq.0.max(target.0) - ratio.freq.0.min(target.0));
+        ratio
+    }
+
+    /// Returns the difference between the frequency achieved and `target`,
+    /// in Hz: positive if the DPLL runs faster than `target`
+    pub fn freq_error(&self, target: Hertz) -> i32 {
+        let error = self.freq.0 as i64 - target.0 as i64;
+        error.max(i32::MIN as i64).min(i32::MAX as i64) as i32
+    }
+
+    /// Returns the output frequency of a DPLL with this ratio, given the
+    /// frequency of its reference
+    pub fn output_freq(&self, reference: Hertz) -> Hertz {
+        dpll_output_freq(reference, self.int, self.frac)
+    }
+}
+
+/// Returns the output frequency of a DPLL multiplying `reference` by
+/// `int + frac / 32`
+fn dpll_output_freq(reference: Hertz, int: u16, frac: u8) -> Hertz {
+    let steps = int as u64 * 32 + frac as u64;
+    Hertz((reference.0 as u64 * steps / 32).min(u32::MAX as u64) as u32)
+}
+
+/// Reported when a frequency is outside the limits of the DPLL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DpllError {
+    /// The reference frequency is outside the 32 kHz - 3.2 MHz input range
+    InputFreqOutOfRange { freq: Hertz },
+    /// The output frequency is outside the 96 - 200 MHz output range
+    OutputFreqOutOfRange { freq: Hertz },
+    /// The integer part of the multiplier is outside 1 - 8192, or the
+    /// fractional part is not below 32
+    InvalidRatio { int: u16, frac: u8 },
+    /// An enabled clock generator runs from the DPLL, which can't be stopped
+    /// to be reconfigured
+    InUse,
+}
+
+/// The DPLL input frequency range
+const DPLL_REF_RANGE: (u32, u32) = (32_000, 3_200_000);
+/// The DPLL output frequency range
+const DPLL_OUT_RANGE: (u32, u32) = (96_000_000, 200_000_000);
+/// The largest integer part of the DPLL multiplier, as `LDR` holds `int - 1`
+const MAX_DPLL_INT: u16 = 8192;
+
+/// Picks the DPLL multiplier that brings `reference` closest to `target`,
+/// within the output range of the DPLL.
+///
+/// The multiplier is rounded to the nearest 1/32 step, so the error is at
+/// most 1/64 of the reference frequency. Pass the result to
+/// `GenericClockController::set_dpll_ratio` to apply it.
+pub fn dpll_loop_div_for_target<R, T>(reference: R, target: T) -> Result<DpllRatio, DpllError>
+where
+    R: Into<Hertz>,
+    T: Into<Hertz>,
+{
+    let (reference, target) = (reference.into(), target.into());
+    check_dpll_input(reference)?;
+    check_dpll_output(target)?;
+    let ratio = DpllRatio::nearest(reference, target);
+    let steps = ratio.int as u64 * 32 + ratio.frac as u64;
+    // Rounding may step just outside of the output range
+    if ratio.freq.0 > DPLL_OUT_RANGE.1 {
+        Ok(DpllRatio::from_steps(reference, target, steps - 1))
+    } else if ratio.freq.0 < DPLL_OUT_RANGE.0 {
+        Ok(DpllRatio::from_steps(reference, target, steps + 1))
+    } else {
+        Ok(ratio)
+    }
+}
+
+/// Checks that `freq` is within the input range of the DPLL
+fn check_dpll_input(freq: Hertz) -> Result<(), DpllError> {
+    if freq.0 < DPLL_REF_RANGE.0 || freq.0 > DPLL_REF_RANGE.1 {
+        Err(DpllError::InputFreqOutOfRange { freq })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that `freq` is within the output range of the DPLL
+fn check_dpll_output(freq: Hertz) -> Result<(), DpllError> {
+    if freq.0 < DPLL_OUT_RANGE.0 || freq.0 > DPLL_OUT_RANGE.1 {
+        Err(DpllError::OutputFreqOutOfRange { freq })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that `ratio` fits the `LDR` and `LDRFRAC` fields of `DPLLRATIO`
+fn check_dpll_ratio_fields(ratio: &DpllRatio) -> Result<(), DpllError> {
+    if (1..=MAX_DPLL_INT).contains(&ratio.int) && ratio.frac < 32 {
+        Ok(())
+    } else {
+        Err(DpllError::InvalidRatio {
+            int: ratio.int,
+            frac: ratio.frac,
+        })
+    }
+}
+
+/// Checks that a DPLL referenced to `reference` runs within its limits with
+/// `ratio`, and returns its output frequency
+fn check_dpll_ratio(reference: Hertz, ratio: &DpllRatio) -> Result<Hertz, DpllError> {
+    check_dpll_ratio_fields(ratio)?;
+    check_dpll_input(reference)?;
+    let freq = ratio.output_freq(reference);
+    check_dpll_output(freq)?;
+    Ok(freq)
+}
+
+/// Reference of a DPLL, selected by `DPLLCTRLB.REFCLK`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DpllReference {
+    /// A clock generator, through the `FDPLLn` peripheral channel
+    Gclk(ClockGenId),
+    /// XOSC32K, which must be running, e.g. after `with_external_32kosc`
+    Xosc32k,
+    /// A crystal oscillator enabled by `configure_xosc`, divided by
+    /// `DPLLCTRLB.DIV` into the input range of the DPLL
+    Xosc(XoscId),
+}
+
+/// The configuration of a DPLL, read back from the hardware by
+/// `GenericClockController::dpll_config`, e.g. to log the clock tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DpllConfig {
+    /// The reference, or `None` if `REFCLK` holds a reserved value, or if
+    /// the peripheral channel of a GCLK reference is disabled
+    pub reference: Option<DpllReference>,
+    /// The `DIV` field, which divides a crystal oscillator reference by
+    /// `2 × (prediv + 1)`
+    pub prediv: u16,
+    /// The integer part of the multiplier
+    pub int: u16,
+    /// The fractional part of the multiplier, in 1/32 steps
+    pub frac: u8,
+    /// Whether the DPLL is enabled
+    pub enabled: bool,
+    /// Whether the DPLL is locked
+    pub locked: bool,
+    /// The output frequency, or `None` if the frequency of the reference
+    /// isn't known to the controller
+    pub freq: Option<Hertz>,
+}
+
+impl DpllConfig {
+    /// Returns the output frequency of the DPLL, given the frequency of its
+    /// reference, before the division of a crystal oscillator reference
+    pub fn output_freq(&self, reference: Hertz) -> Hertz {
+        let input = match self.reference {
+            Some(DpllReference::Xosc(_)) => Hertz(reference.0 / (2 * (self.prediv as u32 + 1))),
+            _ => reference,
+        };
+        dpll_output_freq(input, self.int, self.frac)
+    }
+}
+
+/// The largest value of the 11-bit `DPLLCTRLB.DIV` field
+const MAX_DPLL_XOSC_DIV: u16 = 0x7FF;
+
+/// Returns the `DPLLCTRLB.DIV` value which brings the frequency of a crystal
+/// oscillator within the input range of the DPLL, dividing it the least,
+/// along with the resulting reference frequency, `xosc / (2 × (DIV + 1))`
+fn xosc_dpll_div(xosc: Hertz) -> (u16, Hertz) {
+    let max = DPLL_REF_RANGE.1 as u64 * 2;
+    let div = ((xosc.0 as u64 + max - 1) / max).max(1) - 1;
+    let div = div.min(MAX_DPLL_XOSC_DIV as u64) as u16;
+    (div, Hertz(xosc.0 / (2 * (div as u32 + 1))))
+}
+
+/// Returns the `INTFLAG` bits of a DPLL in OSCCTRL: `LCKR`, `LCKF`, `LTO` and
+/// `LDRTO`
+fn dpll_intflag_mask(dpll: DpllId) -> u32 {
+    match dpll {
+        DpllId::Dpll0 => 0xF << 16,
+        DpllId::Dpll1 => 0xF << 24,
+    }
+}
+
+/// A clock that depends on another clock of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockNode {
+    /// A clock generator, by number
+    Gclk(u8),
+    /// The DFLL, which depends on its reference in closed-loop mode
+    Dfll,
+    /// DPLL0, when its reference is a generic clock
+    Dpll0,
+    /// DPLL1, when its reference is a generic clock
+    Dpll1,
+}
+
+/// Number of clocks tracked by `find_cycle`: the generators, the DFLL and
+/// both DPLLs
+const CLOCK_NODES: usize = NUM_GCLK_GENERATORS + 3;
+
+impl ClockNode {
+    fn index(self) -> usize {
+        match self {
+            ClockNode::Gclk(n) => n as usize,
+            ClockNode::Dfll => NUM_GCLK_GENERATORS,
+            ClockNode::Dpll0 => NUM_GCLK_GENERATORS + 1,
+            ClockNode::Dpll1 => NUM_GCLK_GENERATORS + 2,
+        }
+    }
+}
+
+/// Reported by `GenericClockController::check_cycles` when a clock depends
+/// on itself, holding one of the clocks of the cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockCycle(pub ClockNode);
+
+/// Find a clock that depends on itself, given the source of every clock
+///
+/// Every clock has a single source, so the sources are followed from each
+/// clock for at most as many steps as there are clocks. A walk ending on a
+/// clock without a tracked source is acyclic; a walk coming back to its start
+/// is a cycle.
+fn find_cycle(sources: &[Option<ClockNode>; CLOCK_NODES]) -> Option<ClockNode> {
+    // Every clock of a cycle is the source of another clock, so the walks
+    // only start from sources
+    for start in sources.iter().filter_map(|&source| source) {
+        let mut node = start;
+        for _ in 0..CLOCK_NODES {
+            match sources[node.index()] {
+                Some(source) if source == start => return Some(start),
+                Some(source) => node = source,
+                None => break,
+            }
+        }
+    }
+    None
+}
+
+/// Reported by `GenericClockController::check_main_clock` when GCLK0 can't
+/// run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MainClockError {
+    /// `GENCTRL` of GCLK0, or of GCLK1 when GCLK0 runs from it, selects a
+    /// reserved source, a disabled GCLK1, or GCLK1 as its own source
+    InvalidSource,
+    /// The oscillator at the root of GCLK0 is not ready, or the DPLL is not
+    /// locked
+    NotReady(ClockSource),
+}
+
+/// Readiness of every source of a generator, read from the status registers
+#[derive(Debug, Clone, Copy, Default)]
+struct SourceStatus {
+    xosc: [bool; 2],
+    xosc32k: bool,
+    dfll: bool,
+    dpll: [bool; 2],
+    /// Source of GCLK1, if it is enabled
+    gclk1: Option<ClockSource>,
+}
+
+/// Check that `src` is ready to clock GCLK0
+fn check_source(src: ClockSource, status: &SourceStatus) -> Result<(), MainClockError> {
+    let ready = match src {
+        XOSC0 => status.xosc[0],
+        XOSC1 => status.xosc[1],
+        XOSC32K => status.xosc32k,
+        DFLL => status.dfll,
+        DPLL0 => status.dpll[0],
+        DPLL1 => status.dpll[1],
+        // Always running, or not monitored
+        OSCULP32K | GCLKIN => true,
+        GCLKGEN1 => {
+            return match status.gclk1 {
+                Some(GCLKGEN1) | None => Err(MainClockError::InvalidSource),
+                Some(src) => check_source(src, status),
+            }
+        }
+    };
+    if ready {
+        Ok(())
+    } else {
+        Err(MainClockError::NotReady(src))
+    }
+}
+
+/// Configuration of the external 32 kHz oscillator, as read back from
+/// `XOSC32K` by `GenericClockController::xosc32k_config`
+///
+/// This is meant to check the configuration made by
+/// `with_external_32kosc`, or left by a bootloader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xosc32kConfig {
+    /// Whether the oscillator is enabled (`ENABLE`)
+    pub enabled: bool,
+    /// Whether a crystal is connected between XIN32 and XOUT32, rather than
+    /// an external clock on XIN32 (`XTALEN`)
+    pub crystal: bool,
+    /// Whether the 32 kHz output is enabled (`EN32K`)
+    pub output_32k: bool,
+    /// Whether the 1 kHz output is enabled (`EN1K`)
+    pub output_1k: bool,
+    /// Whether the oscillator keeps running in standby (`RUNSTDBY`)
+    pub run_in_standby: bool,
+    /// Whether the oscillator only runs while a peripheral requests it
+    /// (`ONDEMAND`)
+    pub on_demand: bool,
+    /// Start-up time (`STARTUP`), from 62.6 ms for 0 to 8 s for 6
+    pub startup: u8,
+    /// Whether the configuration is locked until the next reset
+    /// (`WRTLOCK`)
+    pub write_locked: bool,
+    /// Gain mode of the crystal (`CGM`): 1 for the standard mode, 2 for the
+    /// high speed mode
+    pub gain_mode: u8,
+}
+
+impl Xosc32kConfig {
+    /// Decode the value of `XOSC32K`
+    fn from_bits(bits: u16) -> Self {
+        let bit = |n: u16| bits & 1 << n != 0;
+        Xosc32kConfig {
+            enabled: bit(1),
+            crystal: bit(2),
+            output_32k: bit(3),
+            output_1k: bit(4),
+            run_in_standby: bit(6),
+            on_demand: bit(7),
+            startup: (bits >> 8 & 0x7) as u8,
+            write_locked: bit(12),
+            gain_mode: (bits >> 13 & 0x3) as u8,
+        }
+    }
+
+    /// Encode as a value of `XOSC32K`
+    #[cfg(test)]
+    fn bits(&self) -> u16 {
+        (self.enabled as u16) << 1
+            | (self.crystal as u16) << 2
+            | (self.output_32k as u16) << 3
+            | (self.output_1k as u16) << 4
+            | (self.run_in_standby as u16) << 6
+            | (self.on_demand as u16) << 7
+            | (self.startup as u16 & 0x7) << 8
+            | (self.write_locked as u16) << 12
+            | (self.gain_mode as u16 & 0x3) << 13
+    }
+}
+
+/// Selects one of the two external crystal oscillators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XoscId {
+    Xosc0 = 0,
+    Xosc1 = 1,
+}
+
+/// Configuration of an external crystal oscillator.
+///
+/// The oscillator's drive current must match the crystal, otherwise it may
+/// fail to start. `XoscConfig::crystal` picks the current multiplier
+/// (`IMULT`) and reference current (`IPTAT`) recommended by the datasheet
+/// for the crystal frequency:
+///
+/// | Crystal frequency | `IPTAT` | `IMULT` |
+/// |-------------------|---------|---------|
+/// | 8 MHz             | 2       | 3       |
+/// | 8 - 16 MHz        | 3       | 4       |
+/// | 16 - 24 MHz       | 3       | 5       |
+/// | 24 - 48 MHz       | 3       | 6       |
+///
+/// Crystals with a high ESR or load capacitance may need more current than
+/// recommended, which can be set with `XoscConfig::current`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XoscConfig {
+    freq: Hertz,
+    imult: u8,
+    iptat: u8,
+    loop_control: bool,
+}
+
+impl XoscConfig {
+    /// Configuration for a crystal of the given frequency, which must be
+    /// between 8 and 48 MHz.
+    pub fn crystal<F: Into<Hertz>>(freq: F) -> Self {
+        let freq = freq.into();
+        let (imult, iptat) = xosc_current_for_freq(freq);
+        Self {
+            freq,
+            imult,
+            iptat,
+            loop_control: false,
+        }
+    }
+
+    /// Overrides the current multiplier and reference current selected for
+    /// the crystal frequency.
+    pub fn current(mut self, imult: u8, iptat: u8) -> Self {
+        self.imult = imult;
+        self.iptat = iptat;
+        self
+    }
+
+    /// Enables the automatic loop control, which lowers the drive current
+    /// once the oscillations are stable, to reduce power consumption.
+    pub fn automatic_loop_control(mut self, enable: bool) -> Self {
+        self.loop_control = enable;
+        self
+    }
+
+    /// Returns the crystal frequency
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Returns the selected current multiplier (`IMULT`)
+    pub fn imult(&self) -> u8 {
+        self.imult
+    }
+
+    /// Returns the selected reference current (`IPTAT`)
+    pub fn iptat(&self) -> u8 {
+        self.iptat
+    }
+}
+
+/// Returns the `(IMULT, IPTAT)` pair recommended for a crystal frequency.
+/// See `XoscConfig`.
+fn xosc_current_for_freq(freq: Hertz) -> (u8, u8) {
+    match freq.0 {
+        0..=8_000_000 => (3, 2),
+        8_000_001..=16_000_000 => (4, 3),
+        16_000_001..=24_000_000 => (5, 3),
+        _ => (6, 3),
+    }
+}
+
 struct State {
     gclk: GCLK,
 }
@@ -109,25 +1042,26 @@ impl State {
         improve_duty_cycle: bool,
     ) {
         // validate the divisor factor based on gclk ID (see 14.8.3)
-        let mut divisor_invalid = false;
-        if gclk == GCLK1 {
-            if divider as u32 >= 2_u32.pow(16) {
-                divisor_invalid = true;
-            }
-        } else {
-            if divider >= 2_u16.pow(8) {
-                divisor_invalid = true;
-            }
-        }
-        if divisor_invalid {
+        let div = GClockDiv::Div(divider);
+        if !div.is_valid_for(gclk) {
             panic!("invalid divisor {} for GCLK {}", divider, gclk as u8);
         }
+        self.set_gclk_div_and_source(gclk, div, src, improve_duty_cycle);
+    }
 
+    /// Writes `GENCTRL`, once `div` is known to be valid for `gclk`
+    fn set_gclk_div_and_source(
+        &mut self,
+        gclk: ClockGenId,
+        div: GClockDiv,
+        src: ClockSource,
+        improve_duty_cycle: bool,
+    ) {
+        let (divsel, div) = div.genctrl_fields();
         self.gclk.genctrl[u8::from(gclk) as usize].write(|w| unsafe {
             w.src().variant(src);
-            w.div().bits(divider);
-            // divide directly by divider, rather than 2^(n+1)
-            w.divsel().clear_bit();
+            w.div().bits(div);
+            w.divsel().bit(divsel);
             w.idc().bit(improve_duty_cycle);
             w.genen().set_bit();
             w.oe().set_bit()
@@ -136,6 +1070,63 @@ impl State {
         self.wait_for_sync();
     }
 
+    fn read_gclk_config(&mut self, gclk: ClockGenId) -> GClockConfig {
+        let genctrl = self.gclk.genctrl[u8::from(gclk) as usize].read();
+        let div = genctrl.div().bits() as u32;
+        let div = if genctrl.divsel().bit_is_set() {
+            // exponential division by 2^(div + 1), at most 2^9, or 2^17 for
+            // GCLK1
+            1 << (div.min(max_div2_pow(gclk) as u32 - 1) + 1)
+        } else {
+            // zero and one both mean no division
+            div.max(1)
+        };
+        let src = gclk_source_from_bits(genctrl.src().bits());
+        let (output_enable, output_off_value) = gclk_output_from_bits(genctrl.bits());
+        GClockConfig {
+            src,
+            div,
+            enabled: genctrl.genen().bit_is_set(),
+            improve_duty_cycle: genctrl.idc().bit_is_set(),
+            output_enable,
+            output_off_value,
+        }
+    }
+
+    fn set_gclk_output(&mut self, gclk: ClockGenId, enable: bool, off_value: bool) {
+        let bits = gclk_output_bits(enable, off_value);
+        self.gclk.genctrl[u8::from(gclk) as usize]
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(GENCTRL_OE | GENCTRL_OOV) | bits) });
+        self.wait_for_sync();
+    }
+
+    fn disable_gclk(&mut self, gclk: ClockGenId) {
+        self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| w.genen().clear_bit());
+        self.wait_for_sync();
+    }
+
+    fn enable_gclk(&mut self, gclk: ClockGenId) {
+        self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| w.genen().set_bit());
+        self.wait_for_sync();
+    }
+
+    /// Modifies the division factor of `gclk` only, once `div` is known to be
+    /// valid for it
+    fn set_gclk_div(&mut self, gclk: ClockGenId, div: GClockDiv) {
+        let (divsel, div) = div.genctrl_fields();
+        self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| unsafe {
+            w.div().bits(div);
+            w.divsel().bit(divsel)
+        });
+        self.wait_for_sync();
+    }
+
+    /// Modifies the source of `gclk` only
+    fn set_gclk_source(&mut self, gclk: ClockGenId, src: ClockSource) {
+        self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| w.src().variant(src));
+        self.wait_for_sync();
+    }
+
     fn enable_clock_generator(&mut self, clock: ClockId, generator: ClockGenId) {
         self.gclk.pchctrl[u8::from(clock) as usize].write(|w| unsafe {
             w.gen().bits(generator.into());
@@ -144,6 +1135,34 @@ impl State {
         self.wait_for_sync();
     }
 
+    fn gate_clock(&mut self, clock: ClockId, enable: bool) {
+        let pchctrl = &self.gclk.pchctrl[u8::from(clock) as usize];
+        // SAFETY: Only CHEN changes, GEN is written back unchanged
+        pchctrl.modify(|r, w| unsafe { w.bits(pchctrl_gated(r.bits(), enable)) });
+        wait_for_chen(enable, || pchctrl.read().chen().bit());
+    }
+
+    /// Returns the generator selected by a peripheral channel, or `None` if
+    /// the channel is disabled
+    fn read_pclk_gclk(&mut self, clock: ClockId) -> Option<ClockGenId> {
+        let pchctrl = self.gclk.pchctrl[u8::from(clock) as usize].read();
+        match pchctrl.gen().variant() {
+            Variant::Val(gclk) if pchctrl.chen().bit_is_set() => Some(gclk),
+            _ => None,
+        }
+    }
+
+    /// Returns the generator of a peripheral channel, or `None` if the channel
+    /// is disabled
+    fn read_pclk_generator(&mut self, clock: u8) -> Option<u8> {
+        let pchctrl = self.gclk.pchctrl[clock as usize].read();
+        if pchctrl.chen().bit_is_set() {
+            Some(pchctrl.gen().bits())
+        } else {
+            None
+        }
+    }
+
     fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
         self.gclk.genctrl[u8::from(gclk) as usize].modify(|_, w| w.runstdby().bit(enable));
         self.wait_for_sync();
@@ -158,8 +1177,14 @@ impl State {
 /// signal by 2.5x.
 pub struct GenericClockController {
     state: State,
-    gclks: [Hertz; 12],
+    gclks: [Hertz; NUM_GCLK_GENERATORS],
     used_clocks: u64,
+    xoscs: [Hertz; 2],
+    dpll0: Hertz,
+    dpll1: Hertz,
+    gclk_inputs: [Hertz; NUM_GCLK_GENERATORS],
+    watches: PclkWatches,
+    gclk_outputs: [u8; 8],
 }
 
 impl GenericClockController {
@@ -187,6 +1212,51 @@ impl GenericClockController {
         Self::new(gclk, mclk, osc32kctrl, oscctrl, nvmctrl, true)
     }
 
+    /// Like `with_external_32kosc`, but gives up waiting for the external
+    /// 32kHz crystal after polling its ready flag `timeout` times.
+    ///
+    /// `with_external_32kosc` spins on `XOSC32KRDY` forever, so a missing or
+    /// damaged crystal hangs the MCU at boot. On timeout, the oscillator is
+    /// disabled again and the untouched `GCLK` is handed back so that the
+    /// caller can fall back to the internal oscillator:
+    ///
+    /// ```ignore
+    /// let clocks = match GenericClockController::try_with_external_32kosc(
+    ///     peripherals.GCLK,
+    ///     &mut peripherals.MCLK,
+    ///     &mut peripherals.OSC32KCTRL,
+    ///     &mut peripherals.OSCCTRL,
+    ///     &mut peripherals.NVMCTRL,
+    ///     1_000_000,
+    /// ) {
+    ///     Ok(clocks) => clocks,
+    ///     Err(gclk) => GenericClockController::with_internal_32kosc(
+    ///         gclk,
+    ///         &mut peripherals.MCLK,
+    ///         &mut peripherals.OSC32KCTRL,
+    ///         &mut peripherals.OSCCTRL,
+    ///         &mut peripherals.NVMCTRL,
+    ///     ),
+    /// };
+    /// ```
+    ///
+    /// The crystal start-up time is in the order of a second, so `timeout`
+    /// should be generous; each poll is a single register read.
+    pub fn try_with_external_32kosc(
+        gclk: GCLK,
+        mclk: &mut MCLK,
+        osc32kctrl: &mut OSC32KCTRL,
+        oscctrl: &mut OSCCTRL,
+        nvmctrl: &mut NVMCTRL,
+        timeout: u32,
+    ) -> Result<Self, GCLK> {
+        if try_enable_external_32kosc(osc32kctrl, timeout) {
+            Ok(Self::new(gclk, mclk, osc32kctrl, oscctrl, nvmctrl, true))
+        } else {
+            Err(gclk)
+        }
+    }
+
     fn new(
         gclk: GCLK,
         mclk: &mut MCLK,
@@ -227,7 +1297,7 @@ impl GenericClockController {
         while state.gclk.syncbusy.read().genctrl().is_gclk5() {}
 
         configure_and_enable_dpll0(oscctrl, &mut state.gclk);
-        wait_for_dpllrdy(oscctrl);
+        wait_for_dpllrdy(oscctrl, DpllId::Dpll0);
 
         unsafe {
             // GCLK0 set to DPLL0 (120MHz)
@@ -260,6 +1330,12 @@ impl GenericClockController {
                 Hertz(0),
             ],
             used_clocks: 1u64 << u8::from(ClockId::FDPLL0),
+            xoscs: [Hertz(0); 2],
+            dpll0: OSC120M_FREQ,
+            dpll1: Hertz(0),
+            gclk_inputs: [Hertz(0); NUM_GCLK_GENERATORS],
+            watches: PclkWatches::new(),
+            gclk_outputs: [0; 8],
         }
     }
 
@@ -294,6 +1370,11 @@ impl GenericClockController {
         }
     }
 
+    /// Returns the frequency of a configured clock generator
+    pub fn gclk_freq(&self, generator: &GClock) -> Hertz {
+        self.gclks[u8::from(generator.gclk) as usize]
+    }
+
     /// Configures a clock generator with the specified divider and
     /// source.
     /// `divider` is a linear divider to be applied to the clock
@@ -301,8 +1382,18 @@ impl GenericClockController {
     /// this function doesn't expose that functionality at this time.
     /// `improve_duty_cycle` is a boolean that, when set to true, enables
     /// a 50/50 duty cycle for odd divider values.
-    /// Returns a `GClock` for the configured clock generator.
+    /// Returns a `GClock` for the configured clock generator, with the
+    /// frequency of `src` divided by `divider`.
     /// Returns `None` if the clock generator has already been configured.
+    ///
+    /// `GCLKGEN1` can be the source of any generator but GCLK1 itself, which
+    /// would never start. `None` is returned for GCLK1 from `GCLKGEN1`, and
+    /// nothing is written to the hardware.
+    ///
+    /// `None` is also returned, before writing anything, if the frequency of
+    /// `src` isn't known to the controller, e.g. `XOSC0` before
+    /// `configure_xosc`. `GCLKIN` is only known for generators configured
+    /// with `configure_gclk_in`, so use that method for an external input.
     pub fn configure_gclk_divider_and_source(
         &mut self,
         gclk: ClockGenId,
@@ -311,26 +1402,735 @@ impl GenericClockController {
         improve_duty_cycle: bool,
     ) -> Option<GClock> {
         let idx = u8::from(gclk) as usize;
-        if self.gclks[idx].0 != 0 {
+        if self.gclks[idx].0 != 0 || feeds_itself(gclk, src) {
             return None;
         }
+        let freq = divided_freq(self.gclk_source_freq(gclk, src)?, divider);
         self.state
             .set_gclk_divider_and_source(gclk, divider, src, improve_duty_cycle);
-        let freq: Hertz = match src {
-            XOSC32K | OSCULP32K => OSC32K_FREQ,
-            GCLKGEN1 => self.gclks[1],
-            DFLL => OSC48M_FREQ,
-            DPLL0 => OSC120M_FREQ,
-            XOSC0 | XOSC1 | GCLKIN | DPLL1 => unimplemented!(),
-        };
-        self.gclks[idx] = Hertz(freq.0 / divider as u32);
+        self.gclks[idx] = freq;
         Some(GClock { gclk, freq })
     }
 
+    /// Configures a clock generator with the specified division factor and
+    /// source, like `configure_gclk_divider_and_source`, but also accepts
+    /// exponential division factors, and fails instead of panicking.
+    ///
+    /// Nothing is written to the hardware unless the division factor can be
+    /// represented by the generator, and the frequency of `src` is known to
+    /// the controller, so the frequency tracked for the generator always
+    /// matches `GENCTRL`. Use `gclk_div_for_freq` to pick the division
+    /// factor.
+    pub fn try_configure_gclk_div(
+        &mut self,
+        gclk: ClockGenId,
+        div: GClockDiv,
+        src: ClockSource,
+        improve_duty_cycle: bool,
+    ) -> Result<GClock, GClockError> {
+        if feeds_itself(gclk, src) {
+            return Err(GClockError::SelfSourced);
+        }
+        let idx = u8::from(gclk) as usize;
+        if self.gclks[idx].0 != 0 {
+            return Err(GClockError::AlreadyConfigured);
+        }
+        if !div.is_valid_for(gclk) {
+            return Err(GClockError::InvalidDivider(div));
+        }
+        let source = self
+            .source_freq(src)
+            .ok_or(GClockError::UnknownSourceFreq)?;
+        self.state
+            .set_gclk_div_and_source(gclk, div, src, improve_duty_cycle);
+        let freq = Hertz(source.0 / div.factor());
+        self.gclks[idx] = freq;
+        Ok(GClock { gclk, freq })
+    }
+
+    /// Finds the divider that brings the frequency of `src` closest to
+    /// `target` on the given clock generator, without configuring anything.
+    /// The divider is limited to what the generator's `DIV` field can hold.
+    /// Pass the result to `configure_gclk_divider_and_source` to apply it.
+    /// Returns `None` if the frequency of `src` isn't known to the
+    /// controller.
+    pub fn div_to_reach<F: Into<Hertz>>(
+        &self,
+        gclk: ClockGenId,
+        src: ClockSource,
+        target: F,
+    ) -> Option<GClockDivider> {
+        let source = self.source_freq(src)?;
+        Some(closest_divider(source, target.into(), max_divider(gclk)))
+    }
+
+    /// Configures `gclk` to divide the output of GCLK1 again, as chosen by
+    /// `two_stage_divider`, for frequencies far below those of a single
+    /// generator.
+    ///
+    /// GCLK1 keeps its source, its GCLK_IO output and standby settings and
+    /// its duty cycle correction, but its divider is replaced by
+    /// `dividers.gclk1`, which also slows down every other user of GCLK1.
+    /// `Hertz` can't hold sub-Hz frequencies, so the frequencies tracked by
+    /// the controller are rounded down, but kept at 1 Hz at least, so that
+    /// the generators read as configured. `dividers.freq_millihertz` holds
+    /// the exact output frequency. The frequencies tracked for the other
+    /// generators fed by GCLK1 are updated, and the watches of the channels
+    /// running from GCLK1 or from those generators are notified, see the
+    /// [`watch`] module.
+    /// Returns `None` if `gclk` has already been configured, if it is GCLK1,
+    /// or if the frequency of the source of GCLK1 isn't known to the
+    /// controller.
+    ///
+    /// ```no_run
+    /// // A 0.1 Hz clock on GCLK3, from the 32.768 kHz source of GCLK1
+    /// let dividers = two_stage_divider(OSC32K_FREQ, 100).unwrap();
+    /// assert_eq!(dividers.factor(), 327_680);
+    /// let gclk3 = clocks
+    ///     .configure_two_stage_divider(ClockGenId::GCLK3, dividers)
+    ///     .unwrap();
+    /// ```
+    pub fn configure_two_stage_divider(
+        &mut self,
+        gclk: ClockGenId,
+        dividers: TwoStageDivider,
+    ) -> Option<GClock> {
+        let idx = u8::from(gclk) as usize;
+        if self.gclks[idx].0 != 0 || feeds_itself(gclk, GCLKGEN1) {
+            return None;
+        }
+        let src = self.state.read_gclk_config(GCLK1).src?;
+        let source = self.source_freq(src)?;
+        self.state
+            .set_gclk_div(GCLK1, GClockDiv::Div(dividers.gclk1));
+        let gclk1 = Hertz((source.0 / dividers.gclk1 as u32).max(1));
+        if gclk1 != self.gclks[1] {
+            self.gclks[1] = gclk1;
+            // `gclk` isn't configured yet, so it isn't updated twice
+            self.gclk_changed(GCLK1);
+        }
+        self.state
+            .set_gclk_divider_and_source(gclk, dividers.second as u16, GCLKGEN1, false);
+        let freq = Hertz((source.0 / dividers.factor()).max(1));
+        self.gclks[idx] = freq;
+        Some(GClock { gclk, freq })
+    }
+
+    /// Returns the frequency of a clock source, if it is known to the
+    /// controller.
+    fn source_freq(&self, src: ClockSource) -> Option<Hertz> {
+        match src {
+            XOSC32K | OSCULP32K => Some(OSC32K_FREQ),
+            GCLKGEN1 => Some(self.gclks[1]),
+            DFLL => Some(OSC48M_FREQ),
+            DPLL0 => Some(self.dpll0),
+            XOSC0 if self.xoscs[0].0 != 0 => Some(self.xoscs[0]),
+            XOSC1 if self.xoscs[1].0 != 0 => Some(self.xoscs[1]),
+            DPLL1 if self.dpll1.0 != 0 => Some(self.dpll1),
+            XOSC0 | XOSC1 | GCLKIN | DPLL1 => None,
+        }
+    }
+
+    /// Registers `watch` for the peripheral channel `clock`, to be notified
+    /// whenever the generator of the channel changes frequency through the
+    /// controller. Hands `watch` back if the channel already has a watch.
+    /// See the [`watch`] module.
+    pub fn watch_pclk(
+        &mut self,
+        clock: ClockId,
+        watch: &'static PclkWatch,
+    ) -> Result<(), &'static PclkWatch> {
+        self.watches.register(u8::from(clock), watch)
+    }
+
+    /// Removes the watch of a peripheral channel, and returns it
+    pub fn unwatch_pclk(&mut self, clock: ClockId) -> Option<&'static PclkWatch> {
+        self.watches.remove(u8::from(clock))
+    }
+
+    /// Returns the frequency of `src` as the source of `gclk`, which differs
+    /// between the generators for `GCLKIN`, the input of their own GCLK_IO
+    /// pin
+    fn gclk_source_freq(&self, gclk: ClockGenId, src: ClockSource) -> Option<Hertz> {
+        match src {
+            GCLKIN => Some(self.gclk_inputs[u8::from(gclk) as usize]).filter(|freq| freq.0 != 0),
+            _ => self.source_freq(src),
+        }
+    }
+
+    /// Notifies the watches of the channels running from `gclk`, after it
+    /// changed frequency, and those of the generators it feeds
+    fn gclk_changed(&mut self, gclk: ClockGenId) {
+        self.notify_watches(gclk);
+        if gclk == GCLK1 {
+            self.source_changed(GCLKGEN1);
+        }
+    }
+
+    /// Notifies the watches of the channels running from `gclk`
+    fn notify_watches(&mut self, gclk: ClockGenId) {
+        let freq = self.gclks[u8::from(gclk) as usize];
+        for (channel, watch) in self.watches.iter() {
+            if self.state.read_pclk_generator(channel) == Some(u8::from(gclk)) {
+                watch.notify(freq);
+            }
+        }
+    }
+
+    /// Updates the frequencies tracked for the generators fed by `src`, after
+    /// it changed frequency, and notifies the watches of their channels
+    fn source_changed(&mut self, src: ClockSource) {
+        let source = self.source_freq(src);
+        let state = &mut self.state;
+        let changed = propagate_source_change(
+            &mut self.gclks,
+            &self.gclk_inputs,
+            src,
+            source,
+            &mut |gclk| state.read_gclk_config(gclk),
+        );
+        for &gclk in GCLK_IDS.iter() {
+            if changed & 1 << u8::from(gclk) != 0 {
+                self.notify_watches(gclk);
+            }
+        }
+    }
+
+    /// Enables an external crystal oscillator and waits for it to become
+    /// ready. Once enabled, it can be used as the source of a clock
+    /// generator with `configure_gclk_divider_and_source`.
+    pub fn configure_xosc(&mut self, oscctrl: &mut OSCCTRL, xosc: XoscId, config: XoscConfig) {
+        enable_xosc(oscctrl, xosc, &config);
+        self.xoscs[xosc as usize] = config.freq;
+    }
+
+    /// Configures DPLL1 to bring `reference` closest to `target`, enables it
+    /// and waits for it to lock, for a second high-frequency clock domain
+    /// independent of the 120 MHz of DPLL0 and GCLK0. Once locked, DPLL1 can
+    /// be the source of a clock generator with
+    /// `configure_gclk_divider_and_source`. Returns the ratio chosen, which
+    /// holds the frequency achieved.
+    ///
+    /// The reference must be running, otherwise the DPLL never locks. A
+    /// `DpllReference::Gclk` must have been configured through the
+    /// controller, and a `DpllReference::Xosc` enabled with
+    /// `configure_xosc`.
+    ///
+    /// DPLL1 is disabled while it is reconfigured, which would stop the
+    /// generators running from it. So if DPLL1 is already running, disable
+    /// those generators first, e.g. with [`DynGclk::disable`]: while any of
+    /// them is enabled, [`DpllError::InUse`] is returned. The other settings
+    /// of `DPLLCTRLA`, such as `RUNSTDBY`, are kept.
+    ///
+    /// Nothing is written if DPLL1 is in use, or if the reference or `target`
+    /// is out of the range of the DPLL. DPLL0 can't be reconfigured this way, as it feeds the
+    /// CPU; use `try_set_dpll_ratio` to change its frequency.
+    ///
+    /// ```no_run
+    /// // CPU at 120 MHz from DPLL0, peripherals at 100 MHz from an 8 MHz
+    /// // crystal, through DPLL1 and GCLK2
+    /// clocks.configure_xosc(&mut oscctrl, XoscId::Xosc1, XoscConfig::crystal(8.mhz()));
+    /// let ratio = clocks
+    ///     .configure_dpll1(&mut oscctrl, DpllReference::Xosc(XoscId::Xosc1), 100.mhz())
+    ///     .unwrap();
+    /// assert_eq!(ratio.freq, Hertz(100_000_000));
+    /// let gclk2 = clocks
+    ///     .configure_gclk_divider_and_source(ClockGenId::GCLK2, 1, ClockSource::DPLL1, false)
+    ///     .unwrap();
+    /// ```
+    pub fn configure_dpll1<F: Into<Hertz>>(
+        &mut self,
+        oscctrl: &mut OSCCTRL,
+        reference: DpllReference,
+        target: F,
+    ) -> Result<DpllRatio, DpllError> {
+        let (refclk, div, reference_freq) = match reference {
+            DpllReference::Gclk(gclk) => (REFCLK_A::GCLK, 0, self.gclks[u8::from(gclk) as usize]),
+            DpllReference::Xosc32k => (REFCLK_A::XOSC32, 0, OSC32K_FREQ),
+            DpllReference::Xosc(xosc) => {
+                let (div, freq) = xosc_dpll_div(self.xoscs[xosc as usize]);
+                let refclk = match xosc {
+                    XoscId::Xosc0 => REFCLK_A::XOSC0,
+                    XoscId::Xosc1 => REFCLK_A::XOSC1,
+                };
+                (refclk, div, freq)
+            }
+        };
+        let ratio = dpll_loop_div_for_target(reference_freq, target)?;
+        let state = &mut self.state;
+        if source_in_use(DPLL1, &mut |gclk| state.read_gclk_config(gclk)) {
+            return Err(DpllError::InUse);
+        }
+
+        let dpll = &oscctrl.dpll[DpllId::Dpll1 as usize];
+        dpll.dpllctrla.modify(|_, w| w.enable().clear_bit());
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        self.dpll1 = Hertz(0);
+        if let DpllReference::Gclk(gclk) = reference {
+            self.state.enable_clock_generator(ClockId::FDPLL1, gclk);
+            self.used_clocks |= 1u64 << u8::from(ClockId::FDPLL1);
+        }
+        self.set_dpll_ratio(oscctrl, DpllId::Dpll1, ratio)?;
+        let dpll = &oscctrl.dpll[DpllId::Dpll1 as usize];
+        dpll.dpllctrlb.write(|w| unsafe {
+            w.refclk().variant(refclk);
+            w.div().bits(div)
+        });
+        // The lock flags of the previous configuration don't apply anymore
+        self.clear_dpll_flags(oscctrl, DpllId::Dpll1);
+        let dpll = &oscctrl.dpll[DpllId::Dpll1 as usize];
+        dpll.dpllctrla.modify(|_, w| {
+            w.enable().set_bit();
+            w.ondemand().clear_bit()
+        });
+        while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        wait_for_dpllrdy(oscctrl, DpllId::Dpll1);
+        self.dpll1 = ratio.freq;
+        Ok(ratio)
+    }
+
+    /// Clears the lock, lock fail and timeout interrupt flags of a DPLL
+    ///
+    /// Both DPLLs share the `INTFLAG` register of OSCCTRL with the other
+    /// oscillators. Its flags are cleared by writing ones, so only the
+    /// flags of `dpll` are written: a read-modify-write would also clear
+    /// every other pending flag, e.g. those of the other DPLL, from under
+    /// its interrupt handler.
+    pub fn clear_dpll_flags(&mut self, oscctrl: &mut OSCCTRL, dpll: DpllId) {
+        oscctrl
+            .intflag
+            .write(|w| unsafe { w.bits(dpll_intflag_mask(dpll)) });
+    }
+
+    /// Reads back the configuration of a clock generator from the hardware.
+    /// This doesn't modify the generator in any way.
+    pub fn read_gclk_config(&mut self, gclk: ClockGenId) -> GClockConfig {
+        self.state.read_gclk_config(gclk)
+    }
+
+    /// Reads back the GCLK_IO pin configuration of a clock generator as
+    /// `(output_enable, output_off_value)`.
+    ///
+    /// Useful when a clock output pin appears stuck: with the output
+    /// enabled, the pin holds the output-off value whenever the generator
+    /// is disabled.
+    pub fn gclk_output_config(&mut self, gclk: ClockGenId) -> (bool, bool) {
+        let config = self.state.read_gclk_config(gclk);
+        (config.output_enable, config.output_off_value)
+    }
+
+    /// Reads back the configuration of a DPLL from the hardware, along with
+    /// its output frequency, if the frequency of its reference is known to
+    /// the controller. This doesn't modify the DPLL in any way.
+    pub fn dpll_config(&mut self, oscctrl: &OSCCTRL, dpll: DpllId) -> DpllConfig {
+        let channel = match dpll {
+            DpllId::Dpll0 => ClockId::FDPLL0,
+            DpllId::Dpll1 => ClockId::FDPLL1,
+        };
+        let regs = &oscctrl.dpll[dpll as usize];
+        let ctrlb = regs.dpllctrlb.read();
+        let ratio = regs.dpllratio.read();
+        let reference = match ctrlb.refclk().variant() {
+            Variant::Val(REFCLK_A::GCLK) => {
+                self.state.read_pclk_gclk(channel).map(DpllReference::Gclk)
+            }
+            Variant::Val(REFCLK_A::XOSC32) => Some(DpllReference::Xosc32k),
+            Variant::Val(REFCLK_A::XOSC0) => Some(DpllReference::Xosc(XoscId::Xosc0)),
+            Variant::Val(REFCLK_A::XOSC1) => Some(DpllReference::Xosc(XoscId::Xosc1)),
+            Variant::Res(_) => None,
+        };
+        let mut config = DpllConfig {
+            reference,
+            prediv: ctrlb.div().bits(),
+            int: ratio.ldr().bits() + 1,
+            frac: ratio.ldrfrac().bits(),
+            enabled: regs.dpllctrla.read().enable().bit_is_set(),
+            locked: regs.dpllstatus.read().lock().bit_is_set(),
+            freq: None,
+        };
+        let reference_freq = match reference {
+            Some(DpllReference::Gclk(gclk)) => self.gclks[u8::from(gclk) as usize],
+            Some(DpllReference::Xosc32k) => OSC32K_FREQ,
+            Some(DpllReference::Xosc(xosc)) => self.xoscs[xosc as usize],
+            None => Hertz(0),
+        };
+        if reference_freq.0 != 0 {
+            config.freq = Some(config.output_freq(reference_freq));
+        }
+        config
+    }
+
+    /// Takes a snapshot of a clock generator's hardware configuration and
+    /// resulting frequency.
+    pub fn snapshot(&mut self, gclk: ClockGenId) -> GClockSnapshot {
+        let config = self.state.read_gclk_config(gclk);
+        let source_freq = config.src.and_then(|src| self.gclk_source_freq(gclk, src));
+        GClockSnapshot::new(gclk, config, source_freq)
+    }
+
+    /// Returns the live frequency of a peripheral channel, read from the
+    /// hardware rather than from the controller's records.
+    ///
+    /// The channel's generator is looked up in `PCHCTRL`, then the source and
+    /// divider of that generator in `GENCTRL`. Returns `None` if the channel
+    /// or its generator is disabled, or if the frequency of the generator's
+    /// source isn't known to the controller. This doesn't modify the clocks
+    /// in any way.
+    pub fn live_pclk_freq(&mut self, clock: ClockId) -> Option<Hertz> {
+        let gclk = self.state.read_pclk_gclk(clock)?;
+        self.snapshot(gclk).running_freq()
+    }
+
+    /// Checks that the hardware configuration of a clock generator agrees
+    /// with the frequency this controller has recorded for it.
+    ///
+    /// A generator that hasn't been configured through the controller is
+    /// expected to be disabled. A configured generator is expected to be
+    /// enabled and, if its source frequency is known, to divide it down to
+    /// the recorded frequency.
+    /// Returns the read-back configuration if it matches, or a
+    /// `GClockMismatch` describing the discrepancy.
+    pub fn reconcile(&mut self, gclk: ClockGenId) -> Result<GClockConfig, GClockMismatch> {
+        let expected = self.gclks[u8::from(gclk) as usize];
+        let actual = self.state.read_gclk_config(gclk);
+        let matches = if expected.0 == 0 {
+            !actual.enabled
+        } else {
+            let freq = actual
+                .src
+                .and_then(|src| self.gclk_source_freq(gclk, src))
+                .map(|freq| actual.freq(freq));
+            actual.enabled && freq.map_or(true, |freq| freq == expected)
+        };
+        if matches {
+            Ok(actual)
+        } else {
+            Err(GClockMismatch {
+                gclk,
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Returns the source currently selected in `GENCTRL` for GCLK0, the
+    /// main clock, or `None` if the field holds a reserved value.
+    ///
+    /// Code switching the main clock to another source can use this to check
+    /// the starting state, and to avoid switching twice.
+    pub fn gclk0_source(&mut self) -> Option<ClockSource> {
+        self.state.read_gclk_config(GCLK0).src
+    }
+
+    /// Returns `true` if GCLK0, the main clock, currently runs from the DFLL.
+    pub fn gclk0_runs_from_dfll(&mut self) -> bool {
+        self.gclk0_source() == Some(DFLL)
+    }
+
+    /// Reads back the configuration of the external 32 kHz oscillator, e.g.
+    /// to check that it runs from a crystal with its 32 kHz output enabled.
+    pub fn xosc32k_config(&self, osc32kctrl: &OSC32KCTRL) -> Xosc32kConfig {
+        Xosc32kConfig::from_bits(osc32kctrl.xosc32k.read().bits())
+    }
+
+    /// Returns the generator of the peripheral channel with the given ID, or
+    /// `None` if the channel is disabled.
+    #[cfg(feature = "clock_map")]
+    pub(crate) fn pclk_generator(&mut self, clock: u8) -> Option<u8> {
+        self.state.read_pclk_generator(clock)
+    }
+
     /// Enables or disables the given GClk from operation in standby.
     pub fn configure_standby(&mut self, gclk: ClockGenId, enable: bool) {
         self.state.configure_standby(gclk, enable)
     }
+
+    /// Sets whether DPLL0 runs on demand, i.e. only while a clock generator
+    /// or peripheral requests it. Returns the resulting `ONDEMAND` setting.
+    ///
+    /// On-demand operation saves power when DPLL0 only feeds peripherals that
+    /// are idle some of the time. It must not be used while DPLL0 is the
+    /// source of GCLK0, which is the case after `with_internal_32kosc` or
+    /// `with_external_32kosc`: the main clock would stop whenever DPLL0 powers
+    /// down, and DPLL0 would have to relock every time the CPU wakes up. In
+    /// that case `on_demand` is ignored and `ONDEMAND` stays cleared.
+    pub fn set_dpll0_on_demand(&mut self, oscctrl: &mut OSCCTRL, on_demand: bool) -> bool {
+        let gclk0_src = self.state.gclk.genctrl[0].read().src().variant();
+        let on_demand = dpll_on_demand(on_demand, gclk0_src == Variant::Val(DPLL0));
+        oscctrl.dpll[0]
+            .dpllctrla
+            .modify(|_, w| w.ondemand().bit(on_demand));
+        on_demand
+    }
+
+    /// Changes the loop divider ratio of a DPLL, e.g. to one computed by
+    /// `dpll_loop_div_for_target`. The ratio can be changed while the DPLL
+    /// runs, and the DPLL relocks to the new frequency. The frequencies
+    /// known to the controller are not updated.
+    ///
+    /// Nothing is written if the integer part of `ratio` is outside 1 - 8192,
+    /// or its fractional part is not below 32, as they don't fit `DPLLRATIO`.
+    /// The frequencies are not checked against the limits of the DPLL. Use
+    /// `try_set_dpll_ratio` unless they are known to be valid.
+    pub fn set_dpll_ratio(
+        &mut self,
+        oscctrl: &mut OSCCTRL,
+        dpll: DpllId,
+        ratio: DpllRatio,
+    ) -> Result<(), DpllError> {
+        check_dpll_ratio_fields(&ratio)?;
+        let dpll = &oscctrl.dpll[dpll as usize];
+        dpll.dpllratio.write(|w| unsafe {
+            w.ldr().bits(ratio.int - 1);
+            w.ldrfrac().bits(ratio.frac)
+        });
+        while dpll.dpllsyncbusy.read().dpllratio().bit_is_set() {}
+        Ok(())
+    }
+
+    /// Changes the loop divider ratio of a DPLL like `set_dpll_ratio`, once
+    /// the frequency of its reference, `reference`, and the resulting output
+    /// frequency are known to be within the limits of the DPLL. Returns the
+    /// output frequency.
+    ///
+    /// Nothing is written if either frequency is out of range, so that a
+    /// ratio derived from run-time values can be rejected and recovered
+    /// from. The frequencies tracked for DPLL0, or for DPLL1 if it was
+    /// configured with `configure_dpll1`, and for the generators it feeds
+    /// are updated, and the watches of their channels notified, see the
+    /// [`watch`] module. DPLL0 feeds GCLK0, so `gclk0` returns the new
+    /// frequency of the CPU afterwards.
+    pub fn try_set_dpll_ratio(
+        &mut self,
+        oscctrl: &mut OSCCTRL,
+        dpll: DpllId,
+        reference: Hertz,
+        ratio: DpllRatio,
+    ) -> Result<Hertz, DpllError> {
+        let freq = check_dpll_ratio(reference, &ratio)?;
+        self.set_dpll_ratio(oscctrl, dpll, ratio)?;
+        match dpll {
+            DpllId::Dpll0 => {
+                self.dpll0 = freq;
+                self.source_changed(DPLL0);
+            }
+            DpllId::Dpll1 if self.dpll1.0 != 0 => {
+                self.dpll1 = freq;
+                self.source_changed(DPLL1);
+            }
+            DpllId::Dpll1 => (),
+        }
+        Ok(freq)
+    }
+
+    /// Returns the calibration value of OSCULP32K, loaded from the NVM
+    /// software calibration area at reset.
+    pub fn osculp32k_calibration(&self, osc32kctrl: &OSC32KCTRL) -> u8 {
+        osc32kctrl.osculp32k.read().calib().bits()
+    }
+
+    /// Sets the 6-bit calibration value of OSCULP32K, e.g. one found by
+    /// measuring it against a crystal. Returns `false`, leaving the
+    /// calibration unchanged, if the configuration is write-locked.
+    pub fn set_osculp32k_calibration(&mut self, osc32kctrl: &mut OSC32KCTRL, calib: u8) -> bool {
+        debug_assert!(calib <= 0x3f, "OSCULP32K calibration is 6 bits");
+        if self.osculp32k_locked(osc32kctrl) {
+            return false;
+        }
+        osc32kctrl
+            .osculp32k
+            .modify(|_, w| unsafe { w.calib().bits(calib) });
+        true
+    }
+
+    /// Write-locks the configuration of OSCULP32K until the next power-on
+    /// reset, like `XOSC32K.WRTLOCK` does for XOSC32K
+    ///
+    /// OSCULP32K is the timebase of the RTC after `with_internal_32kosc`.
+    /// Locking it guarantees that no code can slow the RTC down by changing
+    /// the calibration, or stop the 32 kHz and 1 kHz outputs, which matters
+    /// for tamper detection and for timestamps kept in the backup domain.
+    /// Set the calibration first: it can't be changed afterwards, and
+    /// `set_osculp32k_calibration` returns `false`.
+    pub fn lock_osculp32k(&mut self, osc32kctrl: &mut OSC32KCTRL) {
+        osc32kctrl.osculp32k.modify(|_, w| w.wrtlock().set_bit());
+    }
+
+    /// Returns `true` if the configuration of OSCULP32K is write-locked.
+    pub fn osculp32k_locked(&self, osc32kctrl: &OSC32KCTRL) -> bool {
+        osc32kctrl.osculp32k.read().wrtlock().bit_is_set()
+    }
+
+    /// Returns `true` if the source of GCLK0, the main clock, is configured
+    /// to run on demand.
+    ///
+    /// An on-demand main clock source can stop in standby and has to restart
+    /// before the CPU can run again. This is rarely intended, so this can be
+    /// used as a sanity check after changing the clock tree, e.g.
+    /// `debug_assert!(!clocks.main_clock_on_demand(&oscctrl))`.
+    pub fn main_clock_on_demand(&mut self, oscctrl: &OSCCTRL) -> bool {
+        match self.state.gclk.genctrl[0].read().src().variant() {
+            Variant::Val(DFLL) => oscctrl.dfllctrla.read().ondemand().bit_is_set(),
+            Variant::Val(DPLL0) => oscctrl.dpll[0].dpllctrla.read().ondemand().bit_is_set(),
+            Variant::Val(DPLL1) => oscctrl.dpll[1].dpllctrla.read().ondemand().bit_is_set(),
+            Variant::Val(XOSC0) => oscctrl.xoscctrl[0].read().ondemand().bit_is_set(),
+            Variant::Val(XOSC1) => oscctrl.xoscctrl[1].read().ondemand().bit_is_set(),
+            _ => false,
+        }
+    }
+
+    /// Checks that the source of GCLK0, the main clock, is running
+    ///
+    /// GCLK0 stops while its source is not ready, and the CPU with it, e.g.
+    /// when a crystal fails to start or a DPLL loses its reference. Calling
+    /// this at boot, before relying on the main clock, reports such a
+    /// configuration instead. The source is ready when:
+    ///
+    /// - XOSC0 and XOSC1: `OSCCTRL.STATUS.XOSCRDYn` is set
+    /// - XOSC32K: `OSC32KCTRL.STATUS.XOSC32KRDY` is set
+    /// - DFLL: `OSCCTRL.STATUS.DFLLRDY` is set
+    /// - DPLL0 and DPLL1: both `DPLLSTATUS.LOCK` and `DPLLSTATUS.CLKRDY` are
+    ///   set
+    /// - OSCULP32K: always, as it can't be disabled
+    /// - GCLKIN: always, as the input pin is not monitored
+    /// - GCLKGEN1: GCLK1 is enabled, and its own source is ready
+    ///
+    /// The source which is not ready is returned, i.e. the source of GCLK1 if
+    /// GCLK0 runs from GCLK1.
+    pub fn check_main_clock(
+        &mut self,
+        oscctrl: &OSCCTRL,
+        osc32kctrl: &OSC32KCTRL,
+    ) -> Result<(), MainClockError> {
+        let gclk1 = self.state.read_gclk_config(GCLK1);
+        let status = oscctrl.status.read();
+        let dpll_ready = |n: usize| {
+            let status = oscctrl.dpll[n].dpllstatus.read();
+            status.lock().bit_is_set() && status.clkrdy().bit_is_set()
+        };
+        let status = SourceStatus {
+            xosc: [
+                status.xoscrdy0().bit_is_set(),
+                status.xoscrdy1().bit_is_set(),
+            ],
+            xosc32k: osc32kctrl.status.read().xosc32krdy().bit_is_set(),
+            dfll: status.dfllrdy().bit_is_set(),
+            dpll: [dpll_ready(0), dpll_ready(1)],
+            gclk1: if gclk1.enabled { gclk1.src } else { None },
+        };
+        match self.state.read_gclk_config(GCLK0).src {
+            Some(src) => check_source(src, &status),
+            None => Err(MainClockError::InvalidSource),
+        }
+    }
+
+    /// Checks that no clock depends on itself, according to the hardware
+    /// configuration
+    ///
+    /// The typed clock tokens can't express every configuration, e.g. a
+    /// generator sourced from DPLL0 while DPLL0 takes its reference from that
+    /// same generator. Such a tree never starts, or stops as soon as the
+    /// cycle is closed. This is a check for configurations made or adopted at
+    /// run-time.
+    ///
+    /// The dependencies are read back from the registers: the source of each
+    /// enabled generator (`GCLKGEN1` being GCLK1), the generic clock
+    /// reference of each enabled DPLL, and the reference of the DFLL in
+    /// closed-loop mode, except for the USB clock recovery. The oscillators
+    /// have no dependencies. One of the clocks of the first cycle found is
+    /// returned.
+    pub fn check_cycles(&mut self, oscctrl: &OSCCTRL) -> Result<(), ClockCycle> {
+        let gclk = &self.state.gclk;
+        let reference = |channel: ClockId| {
+            let pchctrl = gclk.pchctrl[u8::from(channel) as usize].read();
+            // Generators 12 to 15 don't exist
+            match pchctrl.gen().bits() {
+                n if (n as usize) < NUM_GCLK_GENERATORS && pchctrl.chen().bit_is_set() => {
+                    Some(ClockNode::Gclk(n))
+                }
+                _ => None,
+            }
+        };
+
+        let mut sources = [None; CLOCK_NODES];
+        for (n, genctrl) in gclk.genctrl.iter().enumerate() {
+            let genctrl = genctrl.read();
+            if genctrl.genen().bit_is_clear() {
+                continue;
+            }
+            sources[n] = match genctrl.src().variant() {
+                Variant::Val(GCLKGEN1) => Some(ClockNode::Gclk(1)),
+                Variant::Val(DFLL) => Some(ClockNode::Dfll),
+                Variant::Val(DPLL0) => Some(ClockNode::Dpll0),
+                Variant::Val(DPLL1) => Some(ClockNode::Dpll1),
+                _ => None,
+            };
+        }
+        let dfllctrlb = oscctrl.dfllctrlb.read();
+        if dfllctrlb.mode().bit_is_set() && dfllctrlb.usbcrm().bit_is_clear() {
+            sources[ClockNode::Dfll.index()] = reference(ClockId::DFLL48);
+        }
+        for (n, dpll) in oscctrl.dpll.iter().enumerate() {
+            if dpll.dpllctrla.read().enable().bit_is_set()
+                && dpll.dpllctrlb.read().refclk().is_gclk()
+            {
+                let (node, channel) = match n {
+                    0 => (ClockNode::Dpll0, ClockId::FDPLL0),
+                    _ => (ClockNode::Dpll1, ClockId::FDPLL1),
+                };
+                sources[node.index()] = reference(channel);
+            }
+        }
+
+        match find_cycle(&sources) {
+            Some(node) => Err(ClockCycle(node)),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the clock tree to its reset state, and hand back the `GCLK`
+    ///
+    /// GCLK0 runs from the DFLL again, at 48 MHz in open-loop mode. The DFLL
+    /// is enabled first, if it was stopped, and GCLK0 only moves to it once it
+    /// is ready. Every other clock generator and every peripheral channel is
+    /// disabled, then both DPLLs and both crystal oscillators. Consumers are
+    /// stopped before their sources, so no clock loses its source while in
+    /// use. The 32 kHz oscillators are left running, since the RTC may depend
+    /// on them.
+    ///
+    /// This is needed before jumping to a bootloader, which expects the reset
+    /// configuration, or to run the clock setup again, e.g. with
+    /// `with_internal_32kosc`. The clock tokens returned by the controller
+    /// are stale afterwards, so the peripherals using them must be stopped
+    /// first. The flash wait states are left as configured, which is safe at
+    /// a lower frequency.
+    pub fn reset(self, oscctrl: &mut OSCCTRL) -> GCLK {
+        let mut state = self.state;
+
+        // The DFLL, running, without the USB correction
+        oscctrl.dfllctrla.modify(|_, w| {
+            w.enable().set_bit();
+            w.ondemand().clear_bit()
+        });
+        while oscctrl.dfllsync.read().enable().bit_is_set() {}
+        oscctrl.dfllctrlb.write(|w| unsafe { w.bits(0) });
+        while oscctrl.dfllsync.read().dfllctrlb().bit_is_set() {}
+        while oscctrl.status.read().dfllrdy().bit_is_clear() {}
+
+        // GCLK0 from the DFLL, then the consumers of the other clocks
+        state.gclk.genctrl[0].write(|w| unsafe { w.bits(GENCTRL0_RESET) });
+        while state.gclk.syncbusy.read().genctrl().is_gclk0() {}
+        state.reset_gclk();
+
+        for dpll in oscctrl.dpll.iter() {
+            dpll.dpllctrla.modify(|_, w| w.enable().clear_bit());
+            while dpll.dpllsyncbusy.read().enable().bit_is_set() {}
+        }
+        for xosc in oscctrl.xoscctrl.iter() {
+            xosc.modify(|_, w| w.enable().clear_bit());
+        }
+        state.gclk
+    }
 }
 
 macro_rules! clock_generator {
@@ -362,6 +2162,13 @@ impl $Type {
     pub fn freq(&self) -> Hertz {
         self.freq
     }
+
+    /// Enable or disable the peripheral channel, e.g. to save power during
+    /// sleep. The clock generator selection is retained, so re-enabling the
+    /// channel restores the configured frequency.
+    pub fn gate(&mut self, clocks: &mut GenericClockController, enable: bool) {
+        clocks.state.gate_clock(ClockId::$clock, enable);
+    }
 }
 $(#[$attr])*
 impl Into<Hertz> for $Type {
@@ -369,6 +2176,12 @@ impl Into<Hertz> for $Type {
         self.freq
     }
 }
+$(#[$attr])*
+impl PclkToken for $Type {
+    fn pclk_freq(&self) -> Hertz {
+        self.freq
+    }
+}
 )+
 
 impl GenericClockController {
@@ -423,6 +2236,7 @@ clock_generator!(
     (usb, UsbClock, USB),
     (adc0, Adc0Clock, ADC0),
     (adc1, Adc1Clock, ADC1),
+    (slow_32k, Slow32kClock, SLOW_32K),
     (eic, EicClock, EIC),
     (freq_m_msr, FreqmMsrClock, FREQM_MSR),
     (freq_m_ref, FreqmRefClock, FREQM_REF),
@@ -479,6 +2293,36 @@ fn enable_internal_32kosc(osc32kctrl: &mut OSC32KCTRL) {
 
 /// Turn on the external 32hkz oscillator
 fn enable_external_32kosc(osc32kctrl: &mut OSC32KCTRL) {
+    start_external_32kosc(osc32kctrl);
+
+    // Wait for the oscillator to stabilize
+    while osc32kctrl.status.read().xosc32krdy().bit_is_clear() {}
+}
+
+/// Turn on the external 32hkz oscillator, polling the ready flag at most
+/// `timeout` times. If the oscillator does not stabilize in time, it is
+/// disabled again and `false` is returned.
+fn try_enable_external_32kosc(osc32kctrl: &mut OSC32KCTRL, timeout: u32) -> bool {
+    start_external_32kosc(osc32kctrl);
+
+    if poll_ready(timeout, || {
+        osc32kctrl.status.read().xosc32krdy().bit_is_set()
+    }) {
+        return true;
+    }
+
+    osc32kctrl.xosc32k.modify(|_, w| w.enable().clear_bit());
+    osc32kctrl.rtcctrl.reset();
+    false
+}
+
+/// Polls `ready` at most `timeout` times, and returns whether it returned
+/// `true`
+fn poll_ready(timeout: u32, mut ready: impl FnMut() -> bool) -> bool {
+    (0..timeout).any(|_| ready())
+}
+
+fn start_external_32kosc(osc32kctrl: &mut OSC32KCTRL) {
     osc32kctrl.xosc32k.modify(|_, w| {
         w.ondemand().clear_bit();
         // Enable 32khz output
@@ -492,14 +2336,39 @@ fn enable_external_32kosc(osc32kctrl: &mut OSC32KCTRL) {
     });
 
     osc32kctrl.rtcctrl.write(|w| w.rtcsel().xosc1k());
+}
+
+/// Turn on an external crystal oscillator
+fn enable_xosc(oscctrl: &mut OSCCTRL, xosc: XoscId, config: &XoscConfig) {
+    oscctrl.xoscctrl[xosc as usize].write(|w| {
+        unsafe {
+            w.imult().bits(config.imult);
+            w.iptat().bits(config.iptat);
+        }
+        w.enalc().bit(config.loop_control);
+        w.ondemand().clear_bit();
+        // Crystal connected to xin/xout
+        w.xtalen().set_bit();
+        w.enable().set_bit()
+    });
 
     // Wait for the oscillator to stabilize
-    while osc32kctrl.status.read().xosc32krdy().bit_is_clear() {}
+    match xosc {
+        XoscId::Xosc0 => while oscctrl.status.read().xoscrdy0().bit_is_clear() {},
+        XoscId::Xosc1 => while oscctrl.status.read().xoscrdy1().bit_is_clear() {},
+    }
 }
 
-fn wait_for_dpllrdy(oscctrl: &mut OSCCTRL) {
-    while oscctrl.dpll[0].dpllstatus.read().lock().bit_is_clear()
-        || oscctrl.dpll[0].dpllstatus.read().clkrdy().bit_is_clear()
+/// The `ONDEMAND` setting to use for a DPLL. A DPLL feeding GCLK0 must keep
+/// running, regardless of what was requested.
+fn dpll_on_demand(requested: bool, feeds_gclk0: bool) -> bool {
+    requested && !feeds_gclk0
+}
+
+fn wait_for_dpllrdy(oscctrl: &mut OSCCTRL, dpll: DpllId) {
+    let dpll = &oscctrl.dpll[dpll as usize];
+    while dpll.dpllstatus.read().lock().bit_is_clear()
+        || dpll.dpllstatus.read().clkrdy().bit_is_clear()
     {}
 }
 
@@ -543,3 +2412,666 @@ fn configure_usb_correction(oscctrl: &mut OSCCTRL) {
     });
     while oscctrl.dfllsync.read().dfllctrlb().bit_is_set() {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpll_ratio_for_target() {
+        let ratio = dpll_loop_div_for_target(Hertz(2_000_000), Hertz(120_000_000));
+        assert_eq!(
+            ratio,
+            Ok(DpllRatio {
+                int: 60,
+                frac: 0,
+                freq: Hertz(120_000_000),
+                error: Hertz(0),
+            })
+        );
+
+        // 100 MHz from the 32 kHz crystal needs a fractional multiplier
+        let ratio = dpll_loop_div_for_target(Hertz(32_768), Hertz(100_000_000)).unwrap();
+        assert_eq!((ratio.int, ratio.frac), (3051, 24));
+        assert_eq!(ratio.freq, Hertz(99_999_744));
+        assert_eq!(ratio.error, Hertz(256));
+
+        // Rounding up would exceed 200 MHz
+        let ratio = dpll_loop_div_for_target(Hertz(3_001_000), Hertz(200_000_000)).unwrap();
+        assert_eq!((ratio.int, ratio.frac), (66, 20));
+        assert_eq!(ratio.freq, Hertz(199_941_625));
+
+        assert_eq!(
+            dpll_loop_div_for_target(Hertz(4_000_000), Hertz(120_000_000)),
+            Err(DpllError::InputFreqOutOfRange {
+                freq: Hertz(4_000_000)
+            })
+        );
+        assert_eq!(
+            dpll_loop_div_for_target(Hertz(2_000_000), Hertz(48_000_000)),
+            Err(DpllError::OutputFreqOutOfRange {
+                freq: Hertz(48_000_000)
+            })
+        );
+    }
+
+    #[test]
+    fn independent_dpll_domains() {
+        // DPLL0 from GCLK5, at 2 MHz from the DFLL: the CPU at 120 MHz
+        let dpll0 = dpll_loop_div_for_target(Hertz(2_000_000), OSC120M_FREQ).unwrap();
+        assert_eq!((dpll0.int, dpll0.frac, dpll0.freq), (60, 0, OSC120M_FREQ));
+
+        // DPLL1 from an 8 MHz crystal, divided by 4: peripherals at 100 MHz
+        let (div, reference) = xosc_dpll_div(Hertz(8_000_000));
+        assert_eq!((div, reference), (1, Hertz(2_000_000)));
+        let dpll1 = dpll_loop_div_for_target(reference, Hertz(100_000_000)).unwrap();
+        assert_eq!(
+            (dpll1.int, dpll1.frac, dpll1.freq),
+            (50, 0, Hertz(100_000_000))
+        );
+
+        // Crystals up to 6.4 MHz are only divided by 2
+        assert_eq!(xosc_dpll_div(Hertz(6_400_000)), (0, Hertz(3_200_000)));
+        assert_eq!(xosc_dpll_div(Hertz(12_000_000)), (1, Hertz(3_000_000)));
+        // Unconfigured crystals are rejected by the range check
+        assert_eq!(xosc_dpll_div(Hertz(0)), (0, Hertz(0)));
+
+        // The flags of each DPLL are disjoint
+        assert_eq!(dpll_intflag_mask(DpllId::Dpll0), 0x000F_0000);
+        assert_eq!(dpll_intflag_mask(DpllId::Dpll1), 0x0F00_0000);
+    }
+
+    #[test]
+    fn dpll1_in_use_by_an_enabled_generator() {
+        let gclk2 = GClockConfig {
+            src: Some(DPLL1),
+            div: 1,
+            enabled: false,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        let other = GClockConfig {
+            src: Some(DFLL),
+            enabled: true,
+            ..gclk2
+        };
+        let mut configs = [other; NUM_GCLK_GENERATORS];
+        configs[2] = gclk2;
+        let mut read_config = |gclk: ClockGenId| configs[u8::from(gclk) as usize];
+        // A disabled generator doesn't hold DPLL1
+        assert!(!source_in_use(DPLL1, &mut read_config));
+        assert!(source_in_use(DFLL, &mut read_config));
+
+        configs[2].enabled = true;
+        let mut read_config = |gclk: ClockGenId| configs[u8::from(gclk) as usize];
+        assert!(source_in_use(DPLL1, &mut read_config));
+    }
+
+    #[test]
+    fn dpll_config_output_freq() {
+        // DPLL0 after reset of the controller: GCLK5 at 2 MHz, times 60
+        let mut config = DpllConfig {
+            reference: Some(DpllReference::Gclk(ClockGenId::GCLK5)),
+            prediv: 0,
+            int: 60,
+            frac: 0,
+            enabled: true,
+            locked: true,
+            freq: None,
+        };
+        assert_eq!(config.output_freq(Hertz(2_000_000)), OSC120M_FREQ);
+        // A 12 MHz crystal, divided by 4, times 33 + 10/32
+        config.reference = Some(DpllReference::Xosc(XoscId::Xosc0));
+        config.prediv = 1;
+        config.int = 33;
+        config.frac = 10;
+        assert_eq!(config.output_freq(Hertz(12_000_000)), Hertz(99_937_500));
+        // The divider only applies to crystal oscillators
+        config.reference = Some(DpllReference::Xosc32k);
+        config.prediv = 0x7FF;
+        assert_eq!(config.output_freq(OSC32K_FREQ), Hertz(1_091_584));
+    }
+
+    #[test]
+    fn nearest_dpll_ratio() {
+        // Exact multiples
+        let ratio = DpllRatio::nearest(Hertz(2_000_000), Hertz(120_000_000));
+        assert_eq!((ratio.int, ratio.frac), (60, 0));
+        assert_eq!(ratio.freq, Hertz(120_000_000));
+        assert_eq!(ratio.freq_error(Hertz(120_000_000)), 0);
+
+        // Fractional targets: 48 MHz from 32.768 kHz is exactly
+        // 1464 + 27/32, 100 MHz is rounded to the nearest 1/32 step
+        let ratio = DpllRatio::nearest(Hertz(32_768), Hertz(48_000_000));
+        assert_eq!((ratio.int, ratio.frac), (1464, 27));
+        assert_eq!(ratio.freq_error(Hertz(48_000_000)), 0);
+        let ratio = DpllRatio::nearest(Hertz(32_768), Hertz(100_000_000));
+        assert_eq!((ratio.int, ratio.frac), (3051, 24));
+        assert_eq!(ratio.freq_error(Hertz(100_000_000)), -256);
+        assert_eq!(ratio.error, Hertz(256));
+        // 1.25 MHz from 1 MHz is 1 + 8/32
+        let ratio = DpllRatio::nearest(Hertz(1_000_000), Hertz(1_250_000));
+        assert_eq!((ratio.int, ratio.frac), (1, 8));
+        assert_eq!(ratio.freq_error(Hertz(1_260_000)), -10_000);
+
+        // Saturated at the LDR ceiling, and at a multiplier of one
+        let ratio = DpllRatio::nearest(Hertz(32_768), Hertz(400_000_000));
+        assert_eq!((ratio.int, ratio.frac), (MAX_DPLL_INT, 31));
+        assert_eq!(ratio.freq, Hertz(268_467_200));
+        assert_eq!(ratio.freq_error(Hertz(400_000_000)), -131_532_800);
+        let ratio = DpllRatio::nearest(Hertz(2_000_000), Hertz(1_000_000));
+        assert_eq!((ratio.int, ratio.frac), (1, 0));
+    }
+
+    #[test]
+    fn dpll_ratio_is_checked_at_the_limits() {
+        let ratio = |int, frac| DpllRatio {
+            int,
+            frac,
+            freq: Hertz(0),
+            error: Hertz(0),
+        };
+        // 96 and 200 MHz from 2 MHz are both valid
+        assert_eq!(
+            check_dpll_ratio(Hertz(2_000_000), &ratio(48, 0)),
+            Ok(Hertz(96_000_000))
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(2_000_000), &ratio(100, 0)),
+            Ok(Hertz(200_000_000))
+        );
+        // One 1/32 step beyond either end is not
+        assert_eq!(
+            check_dpll_ratio(Hertz(2_000_000), &ratio(47, 31)),
+            Err(DpllError::OutputFreqOutOfRange {
+                freq: Hertz(95_937_500)
+            })
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(2_000_000), &ratio(100, 1)),
+            Err(DpllError::OutputFreqOutOfRange {
+                freq: Hertz(200_062_500)
+            })
+        );
+
+        // 32 kHz and 3.2 MHz are the limits of the reference
+        assert_eq!(
+            check_dpll_ratio(Hertz(32_000), &ratio(3000, 0)),
+            Ok(Hertz(96_000_000))
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(3_200_000), &ratio(40, 0)),
+            Ok(Hertz(128_000_000))
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(31_999), &ratio(3001, 0)),
+            Err(DpllError::InputFreqOutOfRange {
+                freq: Hertz(31_999)
+            })
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(3_200_001), &ratio(40, 0)),
+            Err(DpllError::InputFreqOutOfRange {
+                freq: Hertz(3_200_001)
+            })
+        );
+
+        // `LDR` holds `int - 1`, so zero would underflow
+        assert_eq!(
+            check_dpll_ratio_fields(&ratio(0, 0)),
+            Err(DpllError::InvalidRatio { int: 0, frac: 0 })
+        );
+        assert_eq!(check_dpll_ratio_fields(&ratio(1, 31)), Ok(()));
+        assert_eq!(check_dpll_ratio_fields(&ratio(MAX_DPLL_INT, 0)), Ok(()));
+        assert_eq!(
+            check_dpll_ratio_fields(&ratio(MAX_DPLL_INT + 1, 0)),
+            Err(DpllError::InvalidRatio {
+                int: MAX_DPLL_INT + 1,
+                frac: 0
+            })
+        );
+        assert_eq!(
+            check_dpll_ratio_fields(&ratio(48, 32)),
+            Err(DpllError::InvalidRatio { int: 48, frac: 32 })
+        );
+        assert_eq!(
+            check_dpll_ratio(Hertz(2_000_000), &ratio(0, 0)),
+            Err(DpllError::InvalidRatio { int: 0, frac: 0 })
+        );
+    }
+
+    #[test]
+    fn clock_cycles_are_found() {
+        // The default tree: GCLK0 <- DPLL0 <- GCLK5 <- DFLL, GCLK1 on a
+        // 32 kHz oscillator
+        let mut sources = [None; CLOCK_NODES];
+        sources[0] = Some(ClockNode::Dpll0);
+        sources[5] = Some(ClockNode::Dfll);
+        sources[ClockNode::Dpll0.index()] = Some(ClockNode::Gclk(5));
+        assert_eq!(find_cycle(&sources), None);
+
+        // GCLK2 <- DPLL1 <- GCLK2
+        let mut cyclic = sources;
+        cyclic[2] = Some(ClockNode::Dpll1);
+        cyclic[ClockNode::Dpll1.index()] = Some(ClockNode::Gclk(2));
+        assert_eq!(find_cycle(&cyclic), Some(ClockNode::Dpll1));
+
+        // GCLK1 <- DPLL1 <- GCLK3 <- GCLKGEN1, with GCLK4 hanging off it
+        let mut cyclic = sources;
+        cyclic[1] = Some(ClockNode::Dpll1);
+        cyclic[ClockNode::Dpll1.index()] = Some(ClockNode::Gclk(3));
+        cyclic[3] = Some(ClockNode::Gclk(1));
+        cyclic[4] = Some(ClockNode::Gclk(1));
+        assert!(find_cycle(&cyclic).is_some());
+
+        // The DFLL in closed-loop mode, referenced to the GCLK0 it feeds
+        // through DPLL0
+        let mut cyclic = sources;
+        cyclic[ClockNode::Dfll.index()] = Some(ClockNode::Gclk(0));
+        assert!(find_cycle(&cyclic).is_some());
+    }
+
+    #[test]
+    fn gclk_snapshot_tracks_divider() {
+        let config = GClockConfig {
+            src: Some(DFLL),
+            div: 2,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        let before = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        let again = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        assert_eq!(before, again);
+        assert_eq!(before.freq, Some(Hertz(24_000_000)));
+
+        let config = GClockConfig { div: 4, ..config };
+        let after = GClockSnapshot::new(ClockGenId::GCLK2, config, Some(Hertz(48_000_000)));
+        assert_ne!(before, after);
+        assert_eq!(after.freq, Some(Hertz(12_000_000)));
+    }
+
+    #[test]
+    fn live_freq_of_a_running_generator() {
+        // A SERCOM core clock fed from GCLK3, running from the DFLL / 2
+        let config = GClockConfig {
+            src: Some(DFLL),
+            div: 2,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, Some(OSC48M_FREQ));
+        assert_eq!(gclk3.running_freq(), Some(Hertz(24_000_000)));
+
+        let config = GClockConfig {
+            enabled: false,
+            ..config
+        };
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, Some(OSC48M_FREQ));
+        assert_eq!(gclk3.running_freq(), None);
+        let gclk3 = GClockSnapshot::new(ClockGenId::GCLK3, config, None);
+        assert_eq!(gclk3.running_freq(), None);
+    }
+
+    #[test]
+    fn div_to_reach_picks_closest_legal_divider() {
+        // 48 MHz / 7 is closer to 7 MHz than 48 MHz / 6
+        let div = closest_divider(Hertz(48_000_000), Hertz(7_000_000), 255);
+        assert_eq!(div.divider, 7);
+        assert_eq!(div.freq, Hertz(6_857_142));
+        assert_eq!(div.error, Hertz(142_858));
+
+        let div = closest_divider(Hertz(48_000_000), Hertz(1_000_000), 255);
+        assert_eq!((div.divider, div.error), (48, Hertz(0)));
+
+        // Only GCLK1 can divide by more than 255
+        let div = closest_divider(OSC48M_FREQ, Hertz(1_000), max_divider(ClockGenId::GCLK2));
+        assert_eq!(div.divider, 255);
+        let div = closest_divider(OSC48M_FREQ, Hertz(1_000), max_divider(ClockGenId::GCLK1));
+        assert_eq!((div.divider, div.error), (48_000, Hertz(0)));
+
+        let div = closest_divider(OSC32K_FREQ, Hertz(1_000_000), 255);
+        assert_eq!(div.divider, 1);
+    }
+
+    #[test]
+    fn gclk_div_validity() {
+        use GClockDiv::*;
+        let (gclk1, gclk2) = (ClockGenId::GCLK1, ClockGenId::GCLK2);
+        // 256 and 512 are only reachable exponentially, outside of GCLK1
+        assert!(Div(255).is_valid_for(gclk2));
+        assert!(!Div(256).is_valid_for(gclk2));
+        assert!(Div(256).is_valid_for(gclk1));
+        assert_eq!(Div2Pow(8).factor(), 256);
+        assert_eq!(Div2Pow(8).genctrl_fields(), (true, 7));
+        assert!(Div2Pow(8).is_valid_for(gclk2));
+        assert!(Div2Pow(9).is_valid_for(gclk2));
+        assert!(!Div2Pow(10).is_valid_for(gclk2));
+        assert!(Div2Pow(17).is_valid_for(gclk1));
+        assert!(!Div2Pow(18).is_valid_for(gclk1));
+        assert!(!Div2Pow(0).is_valid_for(gclk1));
+
+        // Zero passes the source through, like one
+        assert!(Div(0).is_valid_for(gclk2));
+        assert_eq!(Div(0).factor(), 1);
+        assert_eq!(Div(0).genctrl_fields(), (false, 0));
+    }
+
+    #[test]
+    fn gclk_output_bits_round_trip() {
+        for &(oe, oov) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+            assert_eq!(gclk_output_from_bits(gclk_output_bits(oe, oov)), (oe, oov));
+        }
+        // OE is bit 11 and OOV bit 10, next to IDC and DIVSEL
+        assert_eq!(gclk_output_bits(true, false), 0x0800);
+        assert_eq!(gclk_output_bits(false, true), 0x0400);
+        // GENEN, IDC, DIVSEL and RUNSTDBY don't read as output bits
+        assert_eq!(gclk_output_from_bits(0xFFFF_F3FF), (false, false));
+    }
+
+    #[test]
+    fn gating_a_channel_keeps_its_generator() {
+        // CHEN set, GEN = 5
+        let pchctrl = 0x45;
+        let gated = pchctrl_gated(pchctrl, false);
+        assert_eq!(gated, 0x05);
+        assert_eq!(pchctrl_gated(gated, false), 0x05);
+        // Re-enabling restores the channel as it was configured
+        assert_eq!(pchctrl_gated(gated, true), pchctrl);
+    }
+
+    #[test]
+    fn chen_is_polled_until_it_reads_back() {
+        // The channel is disabled on the third read
+        let mut reads = 0;
+        wait_for_chen(false, || {
+            reads += 1;
+            reads < 3
+        });
+        assert_eq!(reads, 3);
+
+        // Nothing to wait for once it reads back the requested value
+        let mut reads = 0;
+        wait_for_chen(true, || {
+            reads += 1;
+            true
+        });
+        assert_eq!(reads, 1);
+    }
+
+    #[test]
+    fn gclk0_source_is_read_back() {
+        for &src in GCLK_SOURCES.iter() {
+            assert_eq!(gclk_source_from_bits(u8::from(src)), Some(src));
+        }
+        assert_eq!(gclk_source_from_bits(9), None);
+        assert_eq!(gclk_source_from_bits(15), None);
+
+        // GCLK0 out of reset, or after `reset`: SRC = 6, the DFLL
+        let config = GClockConfig {
+            src: gclk_source_from_bits(6),
+            div: 1,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        assert_eq!(config.src, Some(DFLL));
+        assert_eq!(config.source_freq(), Some(OSC48M_FREQ));
+        assert_eq!(config.freq(OSC48M_FREQ), OSC48M_FREQ);
+
+        // GCLK0 after `with_external_32kosc`: SRC = 7, DPLL0, whose frequency
+        // depends on its ratio
+        let config = GClockConfig {
+            src: gclk_source_from_bits(7),
+            ..config
+        };
+        assert_eq!(config.src, Some(DPLL0));
+        assert_eq!(config.source_freq(), None);
+        assert_eq!(config.freq(OSC120M_FREQ), OSC120M_FREQ);
+    }
+
+    #[test]
+    fn ready_flag_is_polled_until_the_timeout() {
+        // The crystal becomes ready on the third poll
+        let mut polls = 0;
+        let mut ready = || {
+            polls += 1;
+            polls == 3
+        };
+        assert!(poll_ready(5, &mut ready));
+        assert_eq!(polls, 3);
+
+        // It never does: the flag is polled `timeout` times, then given up
+        let mut polls = 0;
+        assert!(!poll_ready(1000, || {
+            polls += 1;
+            false
+        }));
+        assert_eq!(polls, 1000);
+        assert!(!poll_ready(0, || true));
+    }
+
+    #[test]
+    fn gclk1_cant_feed_itself() {
+        assert!(feeds_itself(GCLK1, GCLKGEN1));
+        assert!(!feeds_itself(GCLK2, GCLKGEN1));
+        assert!(!feeds_itself(GCLK1, XOSC32K));
+    }
+
+    #[test]
+    fn configured_gclk_runs_at_the_divided_freq() {
+        // The pygamer `clock_out` example: 120 MHz / 40 on GCLK2
+        assert_eq!(divided_freq(OSC120M_FREQ, 40), Hertz(3_000_000));
+        assert_eq!(divided_freq(OSC48M_FREQ, 48), Hertz(1_000_000));
+        // Zero and one both leave the source undivided
+        assert_eq!(divided_freq(OSC48M_FREQ, 0), OSC48M_FREQ);
+        assert_eq!(divided_freq(OSC48M_FREQ, 1), OSC48M_FREQ);
+    }
+
+    #[test]
+    fn gclk_div_for_freq_uses_the_exponential_divider() {
+        use GClockDiv::*;
+        let gclk2 = ClockGenId::GCLK2;
+        assert_eq!(
+            gclk_div_for_freq(gclk2, OSC48M_FREQ, Hertz(1_000_000)),
+            (Div(48), Hertz(1_000_000))
+        );
+        assert_eq!(
+            gclk_div_for_freq(gclk2, OSC48M_FREQ, Hertz(187_500)),
+            (Div2Pow(8), Hertz(187_500))
+        );
+        assert_eq!(
+            gclk_div_for_freq(gclk2, OSC48M_FREQ, Hertz(93_750)),
+            (Div2Pow(9), Hertz(93_750))
+        );
+        // Clamped to the slowest division
+        assert_eq!(
+            gclk_div_for_freq(gclk2, OSC48M_FREQ, Hertz(1_000)),
+            (Div2Pow(9), Hertz(93_750))
+        );
+        // Linear division is preferred when both are exact
+        assert_eq!(
+            gclk_div_for_freq(ClockGenId::GCLK1, OSC48M_FREQ, Hertz(187_500)),
+            (Div(256), Hertz(187_500))
+        );
+        assert_eq!(
+            gclk_div_for_freq(gclk2, OSC48M_FREQ, Hertz(96_000_000)),
+            (Div(1), OSC48M_FREQ)
+        );
+    }
+
+    #[cfg(feature = "fugit")]
+    #[test]
+    fn fugit_rates_pick_the_same_dividers() {
+        use fugit::HertzU32;
+        assert_eq!(
+            gclk_div_for_freq(ClockGenId::GCLK2, HertzU32::MHz(48), HertzU32::kHz(1_000)),
+            gclk_div_for_freq(ClockGenId::GCLK2, OSC48M_FREQ, Hertz(1_000_000))
+        );
+        let ratio = dpll_loop_div_for_target(HertzU32::kHz(2_000), HertzU32::MHz(100)).unwrap();
+        assert_eq!(
+            (ratio.int, ratio.frac, ratio.freq),
+            (50, 0, Hertz(100_000_000))
+        );
+    }
+
+    #[test]
+    fn two_stage_division_reaches_sub_hz() {
+        // 1 Hz needs no second stage
+        let div = two_stage_divider(OSC32K_FREQ, 1_000).unwrap();
+        assert_eq!((div.gclk1, div.second), (32_768, 1));
+        assert_eq!((div.freq_millihertz, div.error_millihertz), (1_000, 0));
+
+        // 0.1 Hz is 32768 × 10, which doesn't fit in GCLK1 alone
+        let div = two_stage_divider(OSC32K_FREQ, 100).unwrap();
+        assert_eq!((div.gclk1, div.second), (40_960, 8));
+        assert_eq!(div.factor(), 327_680);
+        assert_eq!((div.freq_millihertz, div.error_millihertz), (100, 0));
+
+        // 12 MHz / 12e6 with the smallest second divider
+        let div = two_stage_divider(Hertz(12_000_000), 1_000).unwrap();
+        assert_eq!((div.gclk1, div.second), (62_500, 192));
+
+        // Closest to 3 mHz: 32.768 kHz / 10922667 is not a legal factor
+        let div = two_stage_divider(OSC32K_FREQ, 3).unwrap();
+        assert!(div.factor() <= MAX_TWO_STAGE_FACTOR);
+        assert_eq!(div.freq_millihertz, 3);
+    }
+
+    #[test]
+    fn two_stage_division_limits() {
+        // The slowest clock from 32.768 kHz is about 1.96 mHz
+        assert_eq!(
+            two_stage_divider(OSC32K_FREQ, 1),
+            Err(TwoStageError::TooSlow)
+        );
+        assert_eq!(
+            two_stage_divider(OSC32K_FREQ, 0),
+            Err(TwoStageError::TooSlow)
+        );
+        assert!(two_stage_divider(OSC32K_FREQ, 2).is_ok());
+        // 48 MHz can't be divided down to 1 Hz
+        assert_eq!(
+            two_stage_divider(OSC48M_FREQ, 1_000),
+            Err(TwoStageError::TooSlow)
+        );
+        assert_eq!(
+            two_stage_divider(OSC32K_FREQ, 32_768_001),
+            Err(TwoStageError::TooFast)
+        );
+        let div = two_stage_divider(OSC32K_FREQ, 32_768_000).unwrap();
+        assert_eq!(div.factor(), 1);
+    }
+
+    #[test]
+    fn gclk_config_reports_source_freq() {
+        let config = GClockConfig {
+            src: Some(OSCULP32K),
+            div: 1,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        };
+        assert_eq!(config.source_freq(), Some(Hertz(32_768)));
+        assert_eq!(config.freq(config.source_freq().unwrap()), Hertz(32_768));
+
+        let config = GClockConfig {
+            src: Some(GCLKIN),
+            ..config
+        };
+        assert_eq!(config.source_freq(), None);
+        let config = GClockConfig {
+            src: None,
+            ..config
+        };
+        assert_eq!(config.source_freq(), None);
+    }
+
+    #[test]
+    fn dpll_feeding_main_clock_is_never_on_demand() {
+        assert!(!dpll_on_demand(true, true));
+        assert!(!dpll_on_demand(false, true));
+        assert!(dpll_on_demand(true, false));
+        assert!(!dpll_on_demand(false, false));
+    }
+
+    #[test]
+    fn xosc_current_follows_crystal_frequency() {
+        assert_eq!(xosc_current_for_freq(Hertz(8_000_000)), (3, 2));
+        assert_eq!(xosc_current_for_freq(Hertz(12_000_000)), (4, 3));
+        assert_eq!(xosc_current_for_freq(Hertz(16_000_000)), (4, 3));
+        assert_eq!(xosc_current_for_freq(Hertz(20_000_000)), (5, 3));
+        assert_eq!(xosc_current_for_freq(Hertz(32_000_000)), (6, 3));
+    }
+
+    #[test]
+    fn xosc_current_can_be_overridden() {
+        let config = XoscConfig::crystal(MegaHertz(12)).current(6, 3);
+        assert_eq!(config.imult(), 6);
+        assert_eq!(config.iptat(), 3);
+        assert_eq!(config.freq(), Hertz(12_000_000));
+    }
+
+    #[test]
+    fn main_clock_source_readiness() {
+        let mut status = SourceStatus {
+            dfll: true,
+            ..SourceStatus::default()
+        };
+        assert_eq!(check_source(DFLL, &status), Ok(()));
+        assert_eq!(check_source(OSCULP32K, &status), Ok(()));
+        assert_eq!(
+            check_source(DPLL0, &status),
+            Err(MainClockError::NotReady(DPLL0))
+        );
+        status.dpll[0] = true;
+        assert_eq!(check_source(DPLL0, &status), Ok(()));
+
+        // Through GCLK1, the root source is checked
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::InvalidSource)
+        );
+        status.gclk1 = Some(XOSC32K);
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::NotReady(XOSC32K))
+        );
+        status.xosc32k = true;
+        assert_eq!(check_source(GCLKGEN1, &status), Ok(()));
+        status.gclk1 = Some(GCLKGEN1);
+        assert_eq!(
+            check_source(GCLKGEN1, &status),
+            Err(MainClockError::InvalidSource)
+        );
+    }
+
+    #[test]
+    fn every_generator_is_tracked() {
+        assert_eq!(
+            u8::from(ClockGenId::GCLK11) as usize,
+            NUM_GCLK_GENERATORS - 1
+        );
+        assert!(ClockNode::Gclk(11).index() < ClockNode::Dfll.index());
+    }
+
+    #[test]
+    fn xosc32k_config_round_trip() {
+        // As configured by `with_external_32kosc`
+        let config = Xosc32kConfig::from_bits(0x205e);
+        assert!(config.enabled && config.crystal && config.output_32k);
+        assert!(config.output_1k && config.run_in_standby && !config.on_demand);
+        assert_eq!(config.gain_mode, 1);
+        for bits in 0..1u16 << 15 {
+            // Bits 0, 5 and 11 are reserved
+            let bits = bits & 0x77de;
+            assert_eq!(Xosc32kConfig::from_bits(bits).bits(), bits);
+        }
+    }
+}
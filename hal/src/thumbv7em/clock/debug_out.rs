@@ -0,0 +1,223 @@
+//! # Debug clock output
+//!
+//! Board bring-up often starts by putting a known clock on a pin, to check the
+//! layout, or to measure the accuracy of a crystal with a scope or a frequency
+//! counter. A [`DebugClock`] dedicates a clock generator to this. It outputs
+//! the generator on one of its GCLK_IO pins, like a [`GclkOut`], and changes
+//! its divider at run time, like a [`DynGclk`]:
+//! [`DebugClock::set_output_freq`] brings the generator closest to a
+//! requested frequency and returns the frequency achieved, and
+//! [`DebugClock::sweep`] steps through a list of frequencies.
+//!
+//! Only a generator without any other user can become a debug clock: not
+//! GCLK0, which runs the CPU, nor a generator which runs a peripheral channel
+//! or feeds another generator. GCLK8 to GCLK11 have no GCLK_IO pin, so pick
+//! one of GCLK2 to GCLK7 that the application doesn't need, e.g. GCLK7 on
+//! PB13:
+//!
+//! ```
+//! let pb13 = pins.pb13.into_function_m(&mut pins.port);
+//! let gclk7 = clocks
+//!     .configure_gclk_divider_and_source(ClockGenId::GCLK7, 1, ClockSource::DFLL, false)
+//!     .unwrap();
+//! let mut debug: DebugClock<Gclk7Id, _> = DebugClock::new(&mut clocks, gclk7, pb13).unwrap();
+//! assert_eq!(debug.set_output_freq(&mut clocks, 3.mhz())?, Hertz(3_000_000));
+//! let freqs = [1.mhz().into(), 2.mhz().into(), 4.mhz().into()];
+//! debug.sweep(&mut clocks, &freqs, 500, &mut delay)?;
+//! let (gclk7, pb13) = debug.free(&mut clocks);
+//! ```
+
+use hal::blocking::delay::DelayMs;
+
+use crate::gpio::v2::*;
+use crate::time::Hertz;
+
+use super::{gclk_div_for_freq, ClockGenId, GClock, GClockError, GenericClockController};
+use super::{DynGclk, GclkId, GclkIo, GclkOut};
+
+/// Clock generator `G`, output on its GCLK_IO pin `I` at a frequency chosen at
+/// run time
+///
+/// See the [module-level documentation](self) for more details.
+pub struct DebugClock<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    out: GclkOut<G, I>,
+    gclk: DynGclk,
+}
+
+impl<G, I> DebugClock<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    /// Outputs `gclk` on `pin`, to be used as a debug clock
+    ///
+    /// Hands `gclk` and `pin` back if `gclk` isn't the generator `G`, if it is
+    /// GCLK0, if a peripheral channel or another generator runs from it, or if
+    /// its output can't be enabled, see
+    /// [`GenericClockController::enable_gclk_out`].
+    pub fn new<P>(
+        clocks: &mut GenericClockController,
+        gclk: GClock,
+        pin: P,
+    ) -> Result<Self, (GClock, P)>
+    where
+        P: AnyPin<Id = I, Mode = AlternateM>,
+    {
+        if gclk.gclk != G::ID || G::ID == ClockGenId::GCLK0 || clocks.gclk_in_use(G::ID) {
+            return Err((gclk, pin));
+        }
+        match clocks.enable_gclk_out(pin, false) {
+            Ok(out) => Ok(DebugClock {
+                out,
+                gclk: gclk.into(),
+            }),
+            Err(pin) => Err((gclk, pin)),
+        }
+    }
+
+    /// Frequency of the generator
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.gclk.freq()
+    }
+
+    /// Sets the division factor which brings the generator closest to
+    /// `target`, and returns the frequency achieved
+    ///
+    /// Both linear and exponential division are considered, see
+    /// [`gclk_div_for_freq`]. Nothing is written to the hardware unless the
+    /// frequency of the source of the generator is known to the controller.
+    pub fn set_output_freq<F: Into<Hertz>>(
+        &mut self,
+        clocks: &mut GenericClockController,
+        target: F,
+    ) -> Result<Hertz, GClockError> {
+        let src = clocks.state.read_gclk_config(G::ID).src;
+        let source = src
+            .and_then(|src| clocks.gclk_source_freq(G::ID, src))
+            .ok_or(GClockError::UnknownSourceFreq)?;
+        let (div, _) = gclk_div_for_freq(G::ID, source, target);
+        self.gclk.set_div(clocks, div)
+    }
+
+    /// Sets each frequency of `freqs` in turn, see
+    /// [`DebugClock::set_output_freq`], and holds it for `dwell_ms`
+    /// milliseconds
+    ///
+    /// The sweep stops at the first frequency which can't be set.
+    pub fn sweep<D: DelayMs<u32>>(
+        &mut self,
+        clocks: &mut GenericClockController,
+        freqs: &[Hertz],
+        dwell_ms: u32,
+        delay: &mut D,
+    ) -> Result<(), GClockError> {
+        sweep_with(freqs, dwell_ms, delay, |freq| {
+            self.set_output_freq(clocks, freq)
+        })
+    }
+
+    /// Stops the output, and returns the generator, still running at its last
+    /// frequency, along with the pin
+    pub fn free(self, clocks: &mut GenericClockController) -> (DynGclk, Pin<I, AlternateM>) {
+        let pin = clocks.disable_gclk_out(self.out);
+        (self.gclk, pin)
+    }
+}
+
+/// Calls `set` with each frequency of `freqs`, and waits `dwell_ms` after
+/// each, until `set` fails
+fn sweep_with<D, S>(
+    freqs: &[Hertz],
+    dwell_ms: u32,
+    delay: &mut D,
+    mut set: S,
+) -> Result<(), GClockError>
+where
+    D: DelayMs<u32>,
+    S: FnMut(Hertz) -> Result<Hertz, GClockError>,
+{
+    for &freq in freqs {
+        set(freq)?;
+        delay.delay_ms(dwell_ms);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{GClockDiv, OSC48M_FREQ};
+    use core::cell::Cell;
+
+    /// Records the frequency output during each delay
+    struct MockDelay<'a> {
+        output: &'a Cell<Hertz>,
+        held: [(Hertz, u32); 4],
+        delays: usize,
+    }
+
+    impl DelayMs<u32> for MockDelay<'_> {
+        fn delay_ms(&mut self, ms: u32) {
+            self.held[self.delays] = (self.output.get(), ms);
+            self.delays += 1;
+        }
+    }
+
+    #[test]
+    fn sweep_holds_each_freq() {
+        // GCLK7 from the 48 MHz DFLL
+        let output = Cell::new(Hertz(0));
+        let mut delay = MockDelay {
+            output: &output,
+            held: [(Hertz(0), 0); 4],
+            delays: 0,
+        };
+        let set = |target: Hertz| {
+            let (_, freq) = gclk_div_for_freq(ClockGenId::GCLK7, OSC48M_FREQ, target);
+            output.set(freq);
+            Ok(freq)
+        };
+        let freqs = [Hertz(1_000_000), Hertz(7_000_000), Hertz(100_000)];
+        assert_eq!(sweep_with(&freqs, 500, &mut delay, set), Ok(()));
+        assert_eq!(delay.delays, 3);
+        assert_eq!(delay.held[0], (Hertz(1_000_000), 500));
+        // 48 MHz / 7, the closest to 7 MHz
+        assert_eq!(delay.held[1], (Hertz(6_857_142), 500));
+        // 48 MHz / 2^9, as the linear divider stops at 255
+        assert_eq!(delay.held[2], (Hertz(93_750), 500));
+        assert_eq!(
+            gclk_div_for_freq(ClockGenId::GCLK7, OSC48M_FREQ, Hertz(100_000)).0,
+            GClockDiv::Div2Pow(9)
+        );
+    }
+
+    #[test]
+    fn sweep_stops_at_the_first_failure() {
+        let output = Cell::new(Hertz(0));
+        let mut delay = MockDelay {
+            output: &output,
+            held: [(Hertz(0), 0); 4],
+            delays: 0,
+        };
+        let mut calls = 0;
+        let set = |freq: Hertz| {
+            calls += 1;
+            if calls == 2 {
+                return Err(GClockError::UnknownSourceFreq);
+            }
+            output.set(freq);
+            Ok(freq)
+        };
+        let freqs = [Hertz(1_000_000), Hertz(2_000_000), Hertz(4_000_000)];
+        let result = sweep_with(&freqs, 10, &mut delay, set);
+        assert_eq!(result, Err(GClockError::UnknownSourceFreq));
+        assert_eq!(calls, 2);
+        assert_eq!(delay.delays, 1);
+        assert_eq!(delay.held[0], (Hertz(1_000_000), 10));
+    }
+}
@@ -0,0 +1,294 @@
+//! # Generators selected at run time
+//!
+//! The clock generators are usually picked when writing the code, with
+//! [`ClockGenId`] constants or the [`GclkId`] marker types. [`DynGclkId`]
+//! selects one from a value only known at run time, e.g. a calibration
+//! setting read from flash at boot, and [`DynGclk`] reconfigures the
+//! generator it names: [`DynGclk::set_div`], [`DynGclk::set_source`],
+//! [`DynGclk::enable`] and [`DynGclk::disable`] all index `GENCTRL` with the
+//! number of the generator.
+//!
+//! ```
+//! let id = DynGclkId::try_from(settings.calibration_gclk).unwrap();
+//! let mut gclk = clocks.dyn_gclk(id);
+//! gclk.set_source(&mut clocks, ClockSource::XOSC32K)?;
+//! gclk.set_div(&mut clocks, GClockDiv::Div(32))?;
+//! gclk.enable(&mut clocks)?;
+//! assert_eq!(gclk.freq(), Hertz(1024));
+//! ```
+//!
+//! This gives up the checks of the usual configuration methods, which only
+//! configure a generator once. A `DynGclk` can change the frequency of a
+//! generator which is in use: the CPU for GCLK0, peripheral channels, or other
+//! generators for GCLK1. The frequencies tracked by the controller are
+//! updated and the watches of the affected channels are notified, see the
+//! [`watch`](super::watch) module, but the [`GClock`] tokens and the
+//! peripheral channel tokens created before keep their old frequency, and the
+//! drivers built from them keep their old timing.
+
+use core::convert::TryFrom;
+
+use crate::time::Hertz;
+
+use super::{feeds_itself, GenericClockController, GCLK_IDS};
+use super::{ClockGenId, ClockSource, GClock, GClockDiv, GClockError, GclkId};
+
+/// Clock generator, selected at run time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DynGclkId {
+    Gen0 = 0,
+    Gen1,
+    Gen2,
+    Gen3,
+    Gen4,
+    Gen5,
+    Gen6,
+    Gen7,
+    Gen8,
+    Gen9,
+    Gen10,
+    Gen11,
+}
+
+const DYN_GCLK_IDS: [DynGclkId; 12] = [
+    DynGclkId::Gen0,
+    DynGclkId::Gen1,
+    DynGclkId::Gen2,
+    DynGclkId::Gen3,
+    DynGclkId::Gen4,
+    DynGclkId::Gen5,
+    DynGclkId::Gen6,
+    DynGclkId::Gen7,
+    DynGclkId::Gen8,
+    DynGclkId::Gen9,
+    DynGclkId::Gen10,
+    DynGclkId::Gen11,
+];
+
+impl DynGclkId {
+    /// The generator of the marker type `G`
+    #[inline]
+    pub fn of<G: GclkId>() -> Self {
+        Self::from(G::ID)
+    }
+
+    /// Returns whether this is the generator of the marker type `G`
+    #[inline]
+    pub fn is<G: GclkId>(self) -> bool {
+        self == Self::of::<G>()
+    }
+}
+
+impl TryFrom<u8> for DynGclkId {
+    type Error = u8;
+
+    /// Converts the number of a generator, or hands it back if there is no
+    /// such generator
+    #[inline]
+    fn try_from(num: u8) -> Result<Self, u8> {
+        DYN_GCLK_IDS.get(num as usize).copied().ok_or(num)
+    }
+}
+
+impl From<ClockGenId> for DynGclkId {
+    #[inline]
+    fn from(gclk: ClockGenId) -> Self {
+        DYN_GCLK_IDS[u8::from(gclk) as usize]
+    }
+}
+
+impl From<DynGclkId> for ClockGenId {
+    #[inline]
+    fn from(id: DynGclkId) -> Self {
+        GCLK_IDS[id as usize]
+    }
+}
+
+/// A clock generator selected at run time, along with its frequency
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug)]
+pub struct DynGclk {
+    id: DynGclkId,
+    freq: Hertz,
+}
+
+impl From<GClock> for DynGclk {
+    #[inline]
+    fn from(gclk: GClock) -> Self {
+        DynGclk {
+            id: gclk.gclk.into(),
+            freq: gclk.freq,
+        }
+    }
+}
+
+impl DynGclk {
+    /// The generator
+    #[inline]
+    pub fn id(&self) -> DynGclkId {
+        self.id
+    }
+
+    /// Frequency of the generator, or zero if it is disabled or wasn't
+    /// configured through the controller
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Returns the [`GClock`] of the generator if it is the generator of the
+    /// marker type `G`, or hands `self` back otherwise
+    pub fn try_into_typed<G: GclkId>(self) -> Result<GClock, Self> {
+        if self.id.is::<G>() && self.freq.0 != 0 {
+            Ok(GClock {
+                gclk: G::ID,
+                freq: self.freq,
+            })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Sets the division factor of the generator, and returns its new
+    /// frequency
+    ///
+    /// Nothing is written to the hardware unless `div` can be represented by
+    /// the generator, and the frequency of its source is known to the
+    /// controller.
+    pub fn set_div(
+        &mut self,
+        clocks: &mut GenericClockController,
+        div: GClockDiv,
+    ) -> Result<Hertz, GClockError> {
+        let gclk = self.id.into();
+        if !div.is_valid_for(gclk) {
+            return Err(GClockError::InvalidDivider(div));
+        }
+        let src = clocks.state.read_gclk_config(gclk).src;
+        src.and_then(|src| clocks.gclk_source_freq(gclk, src))
+            .ok_or(GClockError::UnknownSourceFreq)?;
+        clocks.state.set_gclk_div(gclk, div);
+        Ok(self.update(clocks))
+    }
+
+    /// Sets the source of the generator, and returns its new frequency
+    ///
+    /// Nothing is written to the hardware unless the frequency of `src` is
+    /// known to the controller. `GCLKGEN1` can't be the source of GCLK1,
+    /// which is reported as [`GClockError::SelfSourced`].
+    pub fn set_source(
+        &mut self,
+        clocks: &mut GenericClockController,
+        src: ClockSource,
+    ) -> Result<Hertz, GClockError> {
+        let gclk = self.id.into();
+        if feeds_itself(gclk, src) {
+            return Err(GClockError::SelfSourced);
+        }
+        clocks
+            .gclk_source_freq(gclk, src)
+            .ok_or(GClockError::UnknownSourceFreq)?;
+        clocks.state.set_gclk_source(gclk, src);
+        Ok(self.update(clocks))
+    }
+
+    /// Enables the generator, and returns its frequency
+    ///
+    /// Nothing is written to the hardware unless the frequency of its source
+    /// is known to the controller.
+    pub fn enable(&mut self, clocks: &mut GenericClockController) -> Result<Hertz, GClockError> {
+        let gclk = self.id.into();
+        let src = clocks.state.read_gclk_config(gclk).src;
+        src.and_then(|src| clocks.gclk_source_freq(gclk, src))
+            .ok_or(GClockError::UnknownSourceFreq)?;
+        clocks.state.enable_gclk(gclk);
+        Ok(self.update(clocks))
+    }
+
+    /// Disables the generator
+    ///
+    /// The peripheral channels and generators running from it stop, and the
+    /// controller tracks it as not configured, so that it can be configured
+    /// again. GCLK0 runs the CPU, and can't be disabled, which is checked in
+    /// debug builds.
+    pub fn disable(&mut self, clocks: &mut GenericClockController) {
+        debug_assert!(self.id != DynGclkId::Gen0, "GCLK0 can't be disabled");
+        clocks.state.disable_gclk(self.id.into());
+        clocks.gclks[self.id as usize] = Hertz(0);
+        self.freq = Hertz(0);
+    }
+
+    /// Updates the frequency tracked for the generator from the hardware,
+    /// and notifies the watches if it changed
+    fn update(&mut self, clocks: &mut GenericClockController) -> Hertz {
+        let gclk = self.id.into();
+        let config = clocks.state.read_gclk_config(gclk);
+        let source = config
+            .src
+            .and_then(|src| clocks.gclk_source_freq(gclk, src));
+        let freq = match source {
+            // Kept at 1 Hz at least, like `configure_two_stage_divider`
+            Some(source) if config.enabled => Hertz(config.freq(source).0.max(1)),
+            _ => Hertz(0),
+        };
+        let idx = self.id as usize;
+        if freq != clocks.gclks[idx] {
+            clocks.gclks[idx] = freq;
+            if freq.0 != 0 {
+                clocks.gclk_changed(gclk);
+            }
+        }
+        self.freq = freq;
+        freq
+    }
+}
+
+impl GenericClockController {
+    /// Returns a [`DynGclk`] for the generator `id`, with the frequency
+    /// tracked for it, which is zero if it wasn't configured
+    pub fn dyn_gclk(&mut self, id: DynGclkId) -> DynGclk {
+        DynGclk {
+            id,
+            freq: self.gclks[id as usize],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::gclk_out::{Gclk2Id, Gclk3Id};
+
+    #[test]
+    fn ids_convert_from_numbers() {
+        for num in 0..12 {
+            let id = DynGclkId::try_from(num).unwrap();
+            assert_eq!(id as u8, num);
+            assert_eq!(u8::from(ClockGenId::from(id)), num);
+            assert_eq!(DynGclkId::from(ClockGenId::from(id)), id);
+        }
+        assert_eq!(DynGclkId::try_from(12), Err(12));
+        assert_eq!(DynGclkId::of::<Gclk2Id>(), DynGclkId::Gen2);
+    }
+
+    #[test]
+    fn typed_conversion_checks_the_generator() {
+        let gclk = DynGclk::from(GClock {
+            gclk: ClockGenId::GCLK3,
+            freq: Hertz(1_000_000),
+        });
+        let gclk = gclk.try_into_typed::<Gclk2Id>().unwrap_err();
+        let gclk = gclk.try_into_typed::<Gclk3Id>().unwrap();
+        assert_eq!(gclk.gclk, ClockGenId::GCLK3);
+        assert_eq!(gclk.freq, Hertz(1_000_000));
+
+        // Disabled generators have no `GClock`
+        let disabled = DynGclk {
+            id: DynGclkId::Gen3,
+            freq: Hertz(0),
+        };
+        assert!(disabled.try_into_typed::<Gclk3Id>().is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! # Clock generator inputs
+//!
+//! Instead of an internal source, a clock generator can divide an external
+//! clock applied to one of its GCLK_IO pins, e.g. a 10 MHz lab reference. The
+//! frequency of the input isn't measured, so it is given by the user, and the
+//! controller tracks the frequency of the generator from it, like for any
+//! other source.
+//!
+//! [`GenericClockController::configure_gclk_in`] takes a pin in
+//! [`AlternateM`] mode, configures its generator with the `GCLKIN` source,
+//! and returns a [`GclkIn`] along with the [`GClock`] of the generator. The
+//! [`GclkIn`] keeps the pin, so that it can't be used for anything else while
+//! the generator runs from it. [`GenericClockController::disable_gclk_in`]
+//! hands the pin back, once no peripheral channel uses the generator anymore:
+//!
+//! ```
+//! // GCLK3 at 1 MHz, from a 10 MHz reference on PA17
+//! let pa17 = pins.pa17.into_function_m(&mut pins.port);
+//! let (input, gclk3) = clocks
+//!     .configure_gclk_in(pa17, 10.mhz(), GClockDiv::Div(10))
+//!     .unwrap();
+//! let tc_clock = clocks.tc2_tc3(&gclk3).unwrap();
+//! assert_eq!(tc_clock.freq(), Hertz(1_000_000));
+//! ```
+//!
+//! If the external clock changes frequency, [`GclkIn::set_freq`] updates the
+//! frequencies tracked for the generator and, for GCLK1, for the generators
+//! running from it.
+//!
+//! The generator stays on `GCLKIN` until it is disabled, so it can't output
+//! its clock on another of its GCLK_IO pins with
+//! [`GenericClockController::enable_gclk_out`] meanwhile.
+
+use core::marker::PhantomData;
+
+use crate::gpio::v2::*;
+use crate::time::Hertz;
+
+use super::watch::NUM_PCLK_CHANNELS;
+use super::{source_in_use, GclkId, GclkIo, GenericClockController};
+use super::{ClockGenId, ClockSource, GClock, GClockDiv, GClockError};
+
+/// The external clock applied to the GCLK_IO pin `I`, used as the source of
+/// generator `G`
+///
+/// See the [module-level documentation](self) for more details.
+pub struct GclkIn<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    gclk: PhantomData<G>,
+    pin: Pin<I, AlternateM>,
+    freq: Hertz,
+}
+
+impl<G, I> GclkIn<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    /// Frequency of the external clock, as given to
+    /// [`GenericClockController::configure_gclk_in`]
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Updates the frequency of the external clock, e.g. after the reference
+    /// was retuned
+    ///
+    /// The frequencies tracked for the generator and, for GCLK1, the
+    /// generators it feeds are updated, and the watches of their channels are
+    /// notified, see the [`watch`](super::watch) module. The [`GClock`]
+    /// tokens created before keep their old frequency.
+    pub fn set_freq<F: Into<Hertz>>(&mut self, clocks: &mut GenericClockController, freq: F) {
+        self.freq = freq.into();
+        clocks.gclk_inputs[u8::from(G::ID) as usize] = self.freq;
+        clocks.source_changed(ClockSource::GCLKIN);
+    }
+}
+
+impl GenericClockController {
+    /// Configures the generator of `pin` to divide the clock applied to
+    /// `pin`, which runs at `freq`
+    ///
+    /// The output of the generator is disabled, as the pin is an input.
+    /// Nothing is written to the hardware if the generator is already
+    /// configured, or if `div` can't be represented by the generator, and
+    /// `pin` is handed back along with the error.
+    #[allow(clippy::type_complexity)]
+    pub fn configure_gclk_in<G, I, P, F>(
+        &mut self,
+        pin: P,
+        freq: F,
+        div: GClockDiv,
+    ) -> Result<(GclkIn<G, I>, GClock), (P, GClockError)>
+    where
+        G: GclkId,
+        I: GclkIo<Gclk = G>,
+        P: AnyPin<Id = I, Mode = AlternateM>,
+        F: Into<Hertz>,
+    {
+        let idx = u8::from(G::ID) as usize;
+        if self.gclks[idx].0 != 0 {
+            return Err((pin, GClockError::AlreadyConfigured));
+        }
+        if !div.is_valid_for(G::ID) {
+            return Err((pin, GClockError::InvalidDivider(div)));
+        }
+        let freq = freq.into();
+        self.state
+            .set_gclk_div_and_source(G::ID, div, ClockSource::GCLKIN, false);
+        self.state.set_gclk_output(G::ID, false, false);
+        let gclk_freq = Hertz(freq.0 / div.factor());
+        self.gclks[idx] = gclk_freq;
+        self.gclk_inputs[idx] = freq;
+        let input = GclkIn {
+            gclk: PhantomData,
+            pin: pin.into(),
+            freq,
+        };
+        let gclk = GClock {
+            gclk: G::ID,
+            freq: gclk_freq,
+        };
+        Ok((input, gclk))
+    }
+
+    /// Disables the generator of `input`, and returns its pin
+    ///
+    /// Hands `input` back if a peripheral channel still runs from the
+    /// generator, or, for GCLK1, if another generator is fed by it. Once
+    /// disabled, the generator can be configured again, and the [`GClock`]
+    /// returned by [`GenericClockController::configure_gclk_in`] must not be
+    /// used anymore.
+    pub fn disable_gclk_in<G, I>(
+        &mut self,
+        input: GclkIn<G, I>,
+    ) -> Result<Pin<I, AlternateM>, GclkIn<G, I>>
+    where
+        G: GclkId,
+        I: GclkIo<Gclk = G>,
+    {
+        if self.gclk_in_use(G::ID) {
+            return Err(input);
+        }
+        let idx = u8::from(G::ID) as usize;
+        self.state.disable_gclk(G::ID);
+        self.gclks[idx] = Hertz(0);
+        self.gclk_inputs[idx] = Hertz(0);
+        Ok(input.pin)
+    }
+
+    /// Returns whether an enabled peripheral channel or generator runs from
+    /// `gclk`
+    pub(super) fn gclk_in_use(&mut self, gclk: ClockGenId) -> bool {
+        let id = u8::from(gclk);
+        for channel in 0..NUM_PCLK_CHANNELS as u8 {
+            if self.state.read_pclk_generator(channel) == Some(id) {
+                return true;
+            }
+        }
+        let state = &mut self.state;
+        gclk == ClockGenId::GCLK1
+            && source_in_use(ClockSource::GCLKGEN1, &mut |other| {
+                state.read_gclk_config(other)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{propagate_source_change, GClockConfig, NUM_GCLK_GENERATORS};
+
+    fn config(src: ClockSource, div: u32) -> GClockConfig {
+        GClockConfig {
+            src: Some(src),
+            div,
+            enabled: true,
+            improve_duty_cycle: false,
+            output_enable: false,
+            output_off_value: false,
+        }
+    }
+
+    #[test]
+    fn input_freq_propagates_to_the_fed_generators() {
+        // GCLK1 divides a 10 MHz reference by 10, and feeds GCLK3. GCLK4 has
+        // an input of its own, at 8 MHz. GCLK5 runs from GCLK1 as well, but
+        // wasn't configured through the controller.
+        let mut configs = [config(ClockSource::DPLL0, 1); NUM_GCLK_GENERATORS];
+        configs[1] = config(ClockSource::GCLKIN, 10);
+        configs[3] = config(ClockSource::GCLKGEN1, 4);
+        configs[4] = config(ClockSource::GCLKIN, 8);
+        configs[5] = config(ClockSource::GCLKGEN1, 2);
+        let mut gclks = [Hertz(0); NUM_GCLK_GENERATORS];
+        gclks[0] = Hertz(120_000_000);
+        gclks[1] = Hertz(1_000_000);
+        gclks[3] = Hertz(250_000);
+        gclks[4] = Hertz(1_000_000);
+        let mut inputs = [Hertz(0); NUM_GCLK_GENERATORS];
+        inputs[1] = Hertz(10_000_000);
+        inputs[4] = Hertz(8_000_000);
+
+        // The reference of GCLK1 is retuned to 12 MHz
+        inputs[1] = Hertz(12_000_000);
+        let mut read_config = |gclk: ClockGenId| configs[u8::from(gclk) as usize];
+        let changed = propagate_source_change(
+            &mut gclks,
+            &inputs,
+            ClockSource::GCLKIN,
+            None,
+            &mut read_config,
+        );
+        assert_eq!(changed, 0b1010);
+        assert_eq!(gclks[1], Hertz(1_200_000));
+        assert_eq!(gclks[3], Hertz(300_000));
+        assert_eq!(gclks[4], Hertz(1_000_000));
+        assert_eq!(gclks[5], Hertz(0));
+        assert_eq!(gclks[0], Hertz(120_000_000));
+
+        // Nothing changes without a new frequency
+        let changed = propagate_source_change(
+            &mut gclks,
+            &inputs,
+            ClockSource::GCLKIN,
+            None,
+            &mut read_config,
+        );
+        assert_eq!(changed, 0);
+    }
+}
@@ -0,0 +1,217 @@
+//! # Clock generator outputs
+//!
+//! Clock generators GCLK0 to GCLK7 can drive their clock on GCLK_IO pins,
+//! through peripheral function M. Each pin is wired to a single generator:
+//! PB14 can only output GCLK0, PA16 only GCLK2, and so on. [`GclkIo`] maps
+//! each of these pins to its generator, so that routing a generator to a pin
+//! which can't carry it doesn't compile.
+//!
+//! [`GenericClockController::enable_gclk_out`] takes a pin in [`AlternateM`]
+//! mode, enables the output of its generator, and returns a [`GclkOut`].
+//! [`GenericClockController::disable_gclk_out`] hands the pin back. Several
+//! pins can output the same generator, which keeps its output enabled until
+//! the last one is given back:
+//!
+//! ```
+//! let pa16 = pins.d5.into_function_m(&mut pins.port);
+//! let gclk2 = clocks
+//!     .configure_gclk_divider_and_source(ClockGenId::GCLK2, 48, ClockSource::DFLL, false)
+//!     .unwrap();
+//! let out: GclkOut<Gclk2Id, _> = clocks.enable_gclk_out(pa16, false).unwrap();
+//! assert_eq!(out.freq(), Hertz(1_000_000));
+//! let pa16 = clocks.disable_gclk_out(out);
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::gpio::v2::*;
+use crate::time::Hertz;
+use crate::typelevel::Sealed;
+
+use super::{ClockGenId, ClockSource, GenericClockController};
+
+/// Type-level clock generator, for the generators which have GCLK_IO pins
+pub trait GclkId: Sealed {
+    /// The corresponding [`ClockGenId`]
+    const ID: ClockGenId;
+}
+
+macro_rules! gclk_ids {
+    ( $( $(#[$attr:meta])* $Id:ident => $GCLK:ident, )+ ) => {
+        $(
+            $(#[$attr])*
+            pub enum $Id {}
+
+            impl Sealed for $Id {}
+
+            impl GclkId for $Id {
+                const ID: ClockGenId = ClockGenId::$GCLK;
+            }
+        )+
+    };
+}
+
+gclk_ids!(
+    /// Type-level GCLK0
+    Gclk0Id => GCLK0,
+    /// Type-level GCLK1
+    Gclk1Id => GCLK1,
+    /// Type-level GCLK2
+    Gclk2Id => GCLK2,
+    /// Type-level GCLK3
+    Gclk3Id => GCLK3,
+    /// Type-level GCLK4
+    Gclk4Id => GCLK4,
+    /// Type-level GCLK5
+    Gclk5Id => GCLK5,
+    /// Type-level GCLK6
+    Gclk6Id => GCLK6,
+    /// Type-level GCLK7
+    Gclk7Id => GCLK7,
+);
+
+/// A pin which can output the clock of a generator, or feed it an external
+/// clock, in [`AlternateM`] mode
+///
+/// This trait is only implemented for the GCLK_IO pins of the package. See
+/// also the [`gclk_in`](super::gclk_in) module.
+pub trait GclkIo: PinId {
+    /// The only generator the pin is wired to
+    type Gclk: GclkId;
+}
+
+macro_rules! gclk_io {
+    ( $( $(#[$attr:meta])* $Pin:ident => $Id:ident, )+ ) => {
+        $(
+            $(#[$attr])*
+            impl GclkIo for $Pin {
+                type Gclk = $Id;
+            }
+        )+
+    };
+}
+
+gclk_io!(
+    PA10 => Gclk4Id,
+    PA11 => Gclk5Id,
+    PA14 => Gclk0Id,
+    PA15 => Gclk1Id,
+    PA16 => Gclk2Id,
+    PA17 => Gclk3Id,
+    PA27 => Gclk1Id,
+    PA30 => Gclk0Id,
+    PB10 => Gclk4Id,
+    PB11 => Gclk5Id,
+    #[cfg(feature = "min-samd51j")]
+    PB12 => Gclk6Id,
+    #[cfg(feature = "min-samd51j")]
+    PB13 => Gclk7Id,
+    #[cfg(feature = "min-samd51j")]
+    PB14 => Gclk0Id,
+    #[cfg(feature = "min-samd51j")]
+    PB15 => Gclk1Id,
+    #[cfg(feature = "min-samd51j")]
+    PB16 => Gclk2Id,
+    #[cfg(feature = "min-samd51j")]
+    PB17 => Gclk3Id,
+    #[cfg(feature = "min-samd51n")]
+    PB18 => Gclk4Id,
+    #[cfg(feature = "min-samd51n")]
+    PB19 => Gclk5Id,
+    #[cfg(feature = "min-samd51n")]
+    PB20 => Gclk6Id,
+    #[cfg(feature = "min-samd51n")]
+    PB21 => Gclk7Id,
+    PB22 => Gclk0Id,
+    PB23 => Gclk1Id,
+);
+
+/// The clock of generator `G`, output on the GCLK_IO pin `I`
+///
+/// See the [module-level documentation](self) for more details.
+pub struct GclkOut<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    gclk: PhantomData<G>,
+    pin: Pin<I, AlternateM>,
+    freq: Hertz,
+}
+
+impl<G, I> GclkOut<G, I>
+where
+    G: GclkId,
+    I: GclkIo<Gclk = G>,
+{
+    /// Frequency of the generator when its output was enabled
+    #[inline]
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+}
+
+impl GenericClockController {
+    /// Enables the output of the generator of `pin`, and routes it to `pin`
+    ///
+    /// While the generator is turned off, the pin is driven at `off_value`.
+    /// The `GENCTRL` register holds a single off value for every pin of the
+    /// generator, so the last one written applies. Hands `pin` back if the
+    /// generator isn't configured, or if it runs from an external clock,
+    /// see [`GclkIn`](super::GclkIn).
+    pub fn enable_gclk_out<G, I, P>(&mut self, pin: P, off_value: bool) -> Result<GclkOut<G, I>, P>
+    where
+        G: GclkId,
+        I: GclkIo<Gclk = G>,
+        P: AnyPin<Id = I, Mode = AlternateM>,
+    {
+        let idx = u8::from(G::ID) as usize;
+        let freq = self.gclks[idx];
+        if freq.0 == 0 || self.state.read_gclk_config(G::ID).src == Some(ClockSource::GCLKIN) {
+            return Err(pin);
+        }
+        self.gclk_outputs[idx] += 1;
+        self.state.set_gclk_output(G::ID, true, off_value);
+        Ok(GclkOut {
+            gclk: PhantomData,
+            pin: pin.into(),
+            freq,
+        })
+    }
+
+    /// Stops routing the generator to the pin of `out`, and returns the pin
+    ///
+    /// The output of the generator is disabled once no pin outputs it
+    /// anymore.
+    pub fn disable_gclk_out<G, I>(&mut self, out: GclkOut<G, I>) -> Pin<I, AlternateM>
+    where
+        G: GclkId,
+        I: GclkIo<Gclk = G>,
+    {
+        let idx = u8::from(G::ID) as usize;
+        self.gclk_outputs[idx] -= 1;
+        if self.gclk_outputs[idx] == 0 {
+            let (_, off_value) = self.gclk_output_config(G::ID);
+            self.state.set_gclk_output(G::ID, false, off_value);
+        }
+        out.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gclk_of<I: GclkIo>() -> ClockGenId {
+        <I::Gclk as GclkId>::ID
+    }
+
+    #[test]
+    fn pins_output_a_single_generator() {
+        assert_eq!(gclk_of::<PA14>(), ClockGenId::GCLK0);
+        assert_eq!(gclk_of::<PA16>(), ClockGenId::GCLK2);
+        assert_eq!(gclk_of::<PA27>(), ClockGenId::GCLK1);
+        assert_eq!(gclk_of::<PB11>(), ClockGenId::GCLK5);
+        assert_eq!(gclk_of::<PB22>(), ClockGenId::GCLK0);
+    }
+}
@@ -0,0 +1,264 @@
+//! # Clock tree presets
+//!
+//! ## Without any crystal
+//!
+//! Low-cost boards may omit both the 32.768 kHz crystal and the main crystal.
+//! [`no_crystal`] sets up the clock tree from the internal oscillators only,
+//! like [`GenericClockController::with_internal_32kosc`]:
+//!
+//! - OSCULP32K feeds GCLK1 at 32.768 kHz, and the RTC through its 1.024 kHz
+//!   output
+//! - The DFLL runs at 48 MHz in open loop, or in USB clock recovery mode,
+//!   locked to the start-of-frame packets of the host, with the `usb` feature
+//! - GCLK5 divides the DFLL down to 2 MHz, as the reference of DPLL0
+//! - DPLL0 multiplies it up to 120 MHz, for GCLK0 and the CPU
+//!
+//! Along with the controller, it returns an [`AccuracyReport`], the expected
+//! worst-case error of the RTC and of the main clock. The DPLL tracks its
+//! reference, so the main clock is as accurate as the DFLL.
+//!
+//! ## Trimming OSCULP32K
+//!
+//! The factory calibration of OSCULP32K leaves the RTC far less accurate than
+//! a crystal. While an accurate reference is available, e.g. the DFLL in USB
+//! clock recovery mode during enumeration, OSCULP32K can be trimmed against
+//! it with the FREQM. OSCULP32K is used as the *reference* clock of the FREQM
+//! (through GCLK1), and GCLK0 as the *measured* clock: the 120 MHz counted
+//! during 255 periods of OSCULP32K resolve it to about 1 ppm.
+//! [`reference_freq_millihertz`] converts the measurement, and
+//! [`trim_osculp32k`] searches the calibration closest to 32.768 kHz.
+//! [`AccuracyReport::with_rtc_trim`] accounts for the result:
+//!
+//! ```
+//! let (mut clocks, report) = presets::no_crystal(gclk, &mut mclk, &mut osc32kctrl, &mut oscctrl, &mut nvmctrl);
+//! // Once the USB host sends start-of-frame packets, with FREQM configured
+//! // with REFNUM = 255
+//! let start = clocks.osculp32k_calibration(&osc32kctrl);
+//! let trim = presets::trim_osculp32k(start, |calib| {
+//!     clocks.set_osculp32k_calibration(&mut osc32kctrl, calib);
+//!     freqm.ctrlb.write(|w| w.start().set_bit());
+//!     while freqm.status.read().busy().bit_is_set() {}
+//!     let value = freqm.value.read().value().bits();
+//!     presets::reference_freq_millihertz(OSC120M_FREQ, 255, value)
+//! });
+//! let report = report.with_rtc_trim(&trim, 2);
+//! ```
+//!
+//! The trimmed accuracy only holds around the temperature and voltage of the
+//! trim. Trim again whenever the reference is available.
+//!
+//! ## Accuracy budget
+//!
+//! The tolerances below are conservative budgets over temperature and
+//! voltage. Check them against the electrical characteristics for the
+//! operating range of the application, and adjust the [`AccuracyReport`]
+//! fields if needed.
+//!
+//! | Source                             | Budget         |
+//! |------------------------------------|----------------|
+//! | OSCULP32K, factory calibration     | ±20 %          |
+//! | DFLL, open loop                    | ±3 %           |
+//! | DFLL, USB clock recovery           | ±500 ppm       |
+//! | OSCULP32K, trimmed                 | trim residual, measurement resolution and reference error |
+
+use crate::target_device::{GCLK, MCLK, NVMCTRL, OSC32KCTRL, OSCCTRL};
+use crate::time::Hertz;
+
+use super::{GenericClockController, OSC32K_FREQ};
+
+/// Budgeted error of OSCULP32K with its factory calibration, in ppm
+pub const OSCULP32K_FACTORY_PPM: u32 = 200_000;
+
+/// Budgeted error of the DFLL in open loop, in ppm
+pub const DFLL_OPEN_LOOP_PPM: u32 = 30_000;
+
+/// Error of the DFLL in USB clock recovery mode, in ppm: the tolerance of the
+/// 1 ms start-of-frame interval of full-speed USB, ±500 ns
+pub const DFLL_USB_RECOVERY_PPM: u32 = 500;
+
+/// Largest value of the 6-bit `OSCULP32K.CALIB` field
+const MAX_CALIB: u8 = 0x3f;
+
+/// Expected worst-case error of the clocks of a preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccuracyReport {
+    /// Error of the RTC timebase, in ppm
+    pub rtc_ppm: u32,
+    /// Error of the main clock, GCLK0, in ppm
+    pub main_clock_ppm: u32,
+}
+
+impl AccuracyReport {
+    /// Accounts for OSCULP32K trimmed against the main clock by
+    /// [`trim_osculp32k`], with measurements resolved to `resolution_ppm`
+    ///
+    /// The RTC error is then the residual error of the trim, plus the
+    /// resolution of the measurements, plus the error of the main clock.
+    pub fn with_rtc_trim(self, trim: &Osculp32kTrim, resolution_ppm: u32) -> Self {
+        AccuracyReport {
+            rtc_ppm: trim.error_ppm + resolution_ppm + self.main_clock_ppm,
+            ..self
+        }
+    }
+
+    /// Returns the worst-case drift of the RTC, in seconds per day, rounded
+    /// up
+    pub fn rtc_drift_per_day(&self) -> u32 {
+        ((self.rtc_ppm as u64 * 86_400 + 999_999) / 1_000_000) as u32
+    }
+}
+
+/// Sets up the clock tree without any crystal, and reports its accuracy
+///
+/// See the [module-level documentation](self) for the resulting tree. The
+/// DFLL runs in USB clock recovery mode with the `usb` feature, and in open
+/// loop otherwise.
+pub fn no_crystal(
+    gclk: GCLK,
+    mclk: &mut MCLK,
+    osc32kctrl: &mut OSC32KCTRL,
+    oscctrl: &mut OSCCTRL,
+    nvmctrl: &mut NVMCTRL,
+) -> (GenericClockController, AccuracyReport) {
+    let clocks =
+        GenericClockController::with_internal_32kosc(gclk, mclk, osc32kctrl, oscctrl, nvmctrl);
+    let main_clock_ppm = if cfg!(feature = "usb") {
+        DFLL_USB_RECOVERY_PPM
+    } else {
+        DFLL_OPEN_LOOP_PPM
+    };
+    let report = AccuracyReport {
+        rtc_ppm: OSCULP32K_FACTORY_PPM,
+        main_clock_ppm,
+    };
+    (clocks, report)
+}
+
+/// Returns the frequency of the FREQM reference clock, in millihertz, given
+/// the frequency of the measured clock, `REFNUM` and the measured `VALUE`
+///
+/// This is the measurement used the other way around: `VALUE` periods of the
+/// measured clock last `refnum` periods of the reference clock. Returns zero
+/// if `value` is zero, i.e. if the measured clock is stopped.
+pub fn reference_freq_millihertz(measured: Hertz, refnum: u8, value: u32) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    let value = value as u64;
+    (measured.0 as u64 * 1000 * refnum as u64 + value / 2) / value
+}
+
+/// The calibration chosen by [`trim_osculp32k`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Osculp32kTrim {
+    /// The calibration value, for `set_osculp32k_calibration`
+    pub calib: u8,
+    /// The frequency measured with `calib`, in millihertz
+    pub freq_millihertz: u64,
+    /// The error of the measured frequency, in ppm, rounded up
+    pub error_ppm: u32,
+}
+
+/// Error of `freq_millihertz` from 32.768 kHz, in ppm, rounded up
+fn osculp32k_error_ppm(freq_millihertz: u64) -> u32 {
+    let nominal = OSC32K_FREQ.0 as u64 * 1000;
+    let diff = freq_millihertz.max(nominal) - freq_millihertz.min(nominal);
+    ((diff * 1_000_000 + nominal - 1) / nominal) as u32
+}
+
+/// Finds the calibration of OSCULP32K closest to 32.768 kHz, starting from
+/// `start`
+///
+/// `measure` applies a calibration and returns the resulting frequency of
+/// OSCULP32K, in millihertz, e.g. measured by the FREQM. The calibration
+/// moves by one step towards the closest of its neighbours, until neither
+/// is closer. This doesn't depend on the direction or the size of the
+/// calibration steps, which vary between parts. `measure` is called last
+/// with a neighbour of the result, so apply `calib` again afterwards.
+pub fn trim_osculp32k(start: u8, mut measure: impl FnMut(u8) -> u64) -> Osculp32kTrim {
+    let mut trial = |calib: u8| {
+        let freq_millihertz = measure(calib);
+        Osculp32kTrim {
+            calib,
+            freq_millihertz,
+            error_ppm: osculp32k_error_ppm(freq_millihertz),
+        }
+    };
+    let mut best = trial(start.min(MAX_CALIB));
+    // Each step improves the error, so there are at most 63 of them
+    for _ in 0..MAX_CALIB {
+        let lower = best.calib.checked_sub(1).map(&mut trial);
+        let higher = Some(best.calib + 1)
+            .filter(|&calib| calib <= MAX_CALIB)
+            .map(&mut trial);
+        let closer = [lower, higher]
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|neighbour| neighbour.error_ppm < best.error_ppm)
+            .min_by_key(|neighbour| neighbour.error_ppm);
+        match closer {
+            Some(neighbour) => best = neighbour,
+            None => break,
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An OSCULP32K whose frequency falls by 0.8 % per calibration step,
+    /// reaching 32.768 kHz between 19 and 20
+    fn osculp32k(calib: u8) -> u64 {
+        36_000_000 - calib as u64 * 164_000
+    }
+
+    #[test]
+    fn trim_finds_closest_calibration() {
+        // 19 gives 32.884 kHz, 20 gives 32.720 kHz
+        let trim = trim_osculp32k(0, osculp32k);
+        assert_eq!(trim.calib, 20);
+        assert_eq!(trim.freq_millihertz, 32_720_000);
+        assert_eq!(trim.error_ppm, 1465);
+        // From either side
+        assert_eq!(trim_osculp32k(63, osculp32k), trim);
+        assert_eq!(trim_osculp32k(20, osculp32k), trim);
+
+        // The opposite direction, stuck at the end of the range
+        let trim = trim_osculp32k(40, |calib| 30_000_000 + calib as u64 * 10_000);
+        assert_eq!(trim.calib, MAX_CALIB);
+    }
+
+    #[test]
+    fn freqm_measures_the_reference() {
+        // 120 MHz counted during 255 periods of 32.768 kHz
+        assert_eq!(
+            reference_freq_millihertz(Hertz(120_000_000), 255, 933_838),
+            32_767_996
+        );
+        assert_eq!(reference_freq_millihertz(Hertz(120_000_000), 255, 0), 0);
+        assert_eq!(osculp32k_error_ppm(32_767_995), 1);
+        assert_eq!(osculp32k_error_ppm(32_768_000), 0);
+    }
+
+    #[test]
+    fn accuracy_accounting() {
+        let report = AccuracyReport {
+            rtc_ppm: OSCULP32K_FACTORY_PPM,
+            main_clock_ppm: DFLL_USB_RECOVERY_PPM,
+        };
+        assert_eq!(report.rtc_drift_per_day(), 17_280);
+        let trim = Osculp32kTrim {
+            calib: 20,
+            freq_millihertz: 32_720_000,
+            error_ppm: 1465,
+        };
+        let report = report.with_rtc_trim(&trim, 2);
+        assert_eq!(report.rtc_ppm, 1967);
+        assert_eq!(report.main_clock_ppm, 500);
+        // 169.95 s
+        assert_eq!(report.rtc_drift_per_day(), 170);
+    }
+}
@@ -0,0 +1,173 @@
+//! # Generator change notifications
+//!
+//! Drivers compute their timing, like the BAUD register of a SERCOM, from the
+//! frequency of their peripheral channel when they are created. A few
+//! controller APIs change the frequency of a clock which may already be in
+//! use:
+//!
+//! - `configure_two_stage_divider` replaces the divider of GCLK1
+//! - `configure_dpll1` changes the frequency of DPLL1, and
+//!   `try_set_dpll_ratio` the frequency of either DPLL
+//!
+//! The controller then updates the frequencies it tracks for every generator
+//! fed by the changed clock, directly or through GCLK1. A driver running from
+//! one of them keeps its old timing, unless it is told.
+//!
+//! A [`PclkWatch`] is a flag for one peripheral channel, registered with
+//! `GenericClockController::watch_pclk`. The controller keeps a fixed-size
+//! array of one slot per channel, so a channel has at most one watch. Whenever
+//! the generator of a watched channel changes frequency, the controller stores
+//! the new frequency in its watch. Drivers take it with their
+//! `refresh_timing` method, which recomputes the baud rate registers if the
+//! frequency changed, and does nothing otherwise:
+//!
+//! ```
+//! static SPI_CLOCK: PclkWatch = PclkWatch::new();
+//!
+//! let sercom_clock = clocks.sercom4_core(&gclk1).unwrap();
+//! clocks.watch_pclk(ClockId::SERCOM4_CORE, &SPI_CLOCK).unwrap();
+//! // Build the SPI from `sercom_clock.freq()`, then slow down GCLK1
+//! let gclk3 = clocks
+//!     .configure_two_stage_divider(ClockGenId::GCLK3, dividers)
+//!     .unwrap();
+//! assert!(spi.refresh_timing(&SPI_CLOCK));
+//! ```
+//!
+//! Watches only see the changes made through the controller. The tokens of
+//! the peripheral channels, like `Sercom4CoreClock`, still hold the frequency
+//! they were created with, and so does a [`PclkUser`](super::PclkUser).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::time::Hertz;
+
+/// Number of peripheral channels, 48 on every SAMD5x and SAME5x
+pub(super) const NUM_PCLK_CHANNELS: usize = 48;
+
+/// Flag set with the new frequency of a peripheral channel, whenever its
+/// generator changes frequency
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Default)]
+pub struct PclkWatch {
+    /// New frequency of the channel, or zero if it didn't change
+    freq: AtomicU32,
+}
+
+impl PclkWatch {
+    /// Create a watch, with no change pending
+    pub const fn new() -> Self {
+        PclkWatch {
+            freq: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns whether the channel changed frequency since the last call to
+    /// [`PclkWatch::take`]
+    #[inline]
+    pub fn is_dirty(&self) -> bool {
+        self.freq.load(Ordering::Relaxed) != 0
+    }
+
+    /// Returns the new frequency of the channel, if it changed since the last
+    /// call, and clears the flag
+    #[inline]
+    pub fn take(&self) -> Option<Hertz> {
+        match self.freq.swap(0, Ordering::Relaxed) {
+            0 => None,
+            freq => Some(Hertz(freq)),
+        }
+    }
+
+    /// Record that the channel now runs at `freq`
+    #[inline]
+    pub(crate) fn notify(&self, freq: Hertz) {
+        // Zero means no change, and the controller never tracks a running
+        // generator below 1 Hz
+        self.freq.store(freq.0.max(1), Ordering::Relaxed);
+    }
+}
+
+/// The watches registered with the controller, one slot per peripheral
+/// channel
+pub(crate) struct PclkWatches([Option<&'static PclkWatch>; NUM_PCLK_CHANNELS]);
+
+impl PclkWatches {
+    pub(crate) const fn new() -> Self {
+        PclkWatches([None; NUM_PCLK_CHANNELS])
+    }
+
+    /// Register `watch` for `channel`, or hand it back if the channel already
+    /// has a watch
+    pub(crate) fn register(
+        &mut self,
+        channel: u8,
+        watch: &'static PclkWatch,
+    ) -> Result<(), &'static PclkWatch> {
+        let slot = &mut self.0[channel as usize];
+        match slot {
+            Some(_) => Err(watch),
+            None => {
+                *slot = Some(watch);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the watch of `channel`, if any
+    pub(crate) fn remove(&mut self, channel: u8) -> Option<&'static PclkWatch> {
+        self.0[channel as usize].take()
+    }
+
+    /// Iterate over the watched channels
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u8, &'static PclkWatch)> + '_ {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, watch)| watch.map(|watch| (channel as u8, watch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_holds_the_latest_frequency() {
+        let watch = PclkWatch::new();
+        assert!(!watch.is_dirty());
+        assert_eq!(watch.take(), None);
+
+        watch.notify(Hertz(32_768));
+        watch.notify(Hertz(16_384));
+        assert!(watch.is_dirty());
+        assert_eq!(watch.take(), Some(Hertz(16_384)));
+        assert!(!watch.is_dirty());
+        assert_eq!(watch.take(), None);
+
+        // A change to 0 Hz must still be seen
+        watch.notify(Hertz(0));
+        assert_eq!(watch.take(), Some(Hertz(1)));
+    }
+
+    #[test]
+    fn one_watch_per_channel() {
+        static FIRST: PclkWatch = PclkWatch::new();
+        static SECOND: PclkWatch = PclkWatch::new();
+        let mut watches = PclkWatches::new();
+        assert!(watches.register(7, &FIRST).is_ok());
+        assert!(watches.register(34, &SECOND).is_ok());
+        let rejected = watches.register(7, &SECOND).unwrap_err();
+        assert!(core::ptr::eq(rejected, &SECOND));
+
+        let mut iter = watches.iter();
+        assert!(matches!(iter.next(), Some((7, w)) if core::ptr::eq(w, &FIRST)));
+        assert!(matches!(iter.next(), Some((34, w)) if core::ptr::eq(w, &SECOND)));
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        assert!(watches.remove(7).is_some());
+        assert!(watches.remove(7).is_none());
+        assert!(watches.register(7, &SECOND).is_ok());
+    }
+}
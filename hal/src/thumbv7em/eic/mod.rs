@@ -1,18 +1,83 @@
+//! # External Interrupt Controller
+//!
+//! The EIC can detect edges and levels with or without a clock:
+//!
+//! - A clocked EIC samples its inputs with either the generic clock
+//!   `GCLK_EIC` ([`init_with_gclk`]) or the always-on `CLK_ULP32K`
+//!   ([`init_with_ulp32k`]). The input filter ([`filter`]) and the debouncer
+//!   ([`button_debounce_pins`]) only work with such a clock, and both
+//!   constructors require an [`EicClock`] as proof that it has been set up.
+//! - A clockless EIC ([`init_clockless`]) uses asynchronous edge detection,
+//!   which keeps working when every clock is stopped, e.g. in standby. The
+//!   filter and the debouncer are bypassed in this mode, so they are not
+//!   available on a [`ConfigurableEIC<Clockless>`].
+//!
+//! [`filter`]: pin::ExtInt0::filter
+//! [`button_debounce_pins`]: ConfigurableEIC::button_debounce_pins
+
 use crate::clock::EicClock;
 use crate::target_device;
+use crate::typelevel::Sealed;
+use core::marker::PhantomData;
 
 pub mod pin;
 
+/// Type-level `enum` for the clocking of the EIC
+///
+/// See the [module-level documentation](self) for more details.
+pub trait ClockMode: Sealed {}
+
+/// The EIC is clocked, so the filter and debouncer are available
+pub enum Clocked {}
+impl Sealed for Clocked {}
+impl ClockMode for Clocked {}
+
+/// The EIC uses asynchronous edge detection, without a clock
+pub enum Clockless {}
+impl Sealed for Clockless {}
+impl ClockMode for Clockless {}
+
 /// An External Interrupt Controller which is being configured.
-pub struct ConfigurableEIC {
+///
+/// The filter and the debouncer of a clockless EIC are rejected at compile
+/// time:
+///
+/// ```compile_fail
+/// use atsamd_hal::eic::{Clockless, ConfigurableEIC};
+///
+/// fn debounce(eic: &mut ConfigurableEIC<Clockless>) {
+///     eic.button_debounce_pins(&[0]);
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use atsamd_hal::eic::pin::ExtInt0;
+/// use atsamd_hal::eic::{Clockless, ConfigurableEIC};
+///
+/// fn filter<GPIO>(eic: &mut ConfigurableEIC<Clockless>, pin: &mut ExtInt0<GPIO>) {
+///     pin.filter(eic, true);
+/// }
+/// ```
+pub struct ConfigurableEIC<C: ClockMode = Clocked> {
     eic: target_device::EIC,
+    mode: PhantomData<C>,
 }
 
-impl ConfigurableEIC {
+impl<C: ClockMode> ConfigurableEIC<C> {
     fn new(eic: target_device::EIC) -> Self {
-        Self { eic }
+        Self {
+            eic,
+            mode: PhantomData,
+        }
+    }
+
+    /// finalize enables the EIC.
+    pub fn finalize(self) -> EIC {
+        self.into()
     }
+}
 
+impl ConfigurableEIC<Clocked> {
     /// button_debounce_pins enables debouncing for the
     /// specified pins, with a configuration appropriate
     /// for debouncing physical buttons.
@@ -31,10 +96,15 @@ impl ConfigurableEIC {
         }
         self.eic.debouncen.write(|w| unsafe { w.bits(debounceen) });
     }
+}
 
-    /// finalize enables the EIC.
-    pub fn finalize(self) -> EIC {
-        self.into()
+/// Enable the APB clock of the EIC and reset it
+fn reset(mclk: &mut target_device::MCLK, eic: &target_device::EIC) {
+    mclk.apbamask.modify(|_, w| w.eic_().set_bit());
+
+    eic.ctrla.modify(|_, w| w.swrst().set_bit());
+    while eic.syncbusy.read().swrst().bit_is_set() {
+        cortex_m::asm::nop();
     }
 }
 
@@ -46,12 +116,7 @@ pub fn init_with_ulp32k(
     _clock: EicClock,
     eic: target_device::EIC,
 ) -> ConfigurableEIC {
-    mclk.apbamask.modify(|_, w| w.eic_().set_bit());
-
-    eic.ctrla.modify(|_, w| w.swrst().set_bit());
-    while eic.syncbusy.read().swrst().bit_is_set() {
-        cortex_m::asm::nop();
-    }
+    reset(mclk, &eic);
 
     // Use the low-power 32k clock.
     eic.ctrla.modify(|_, w| w.cksel().set_bit());
@@ -59,13 +124,45 @@ pub fn init_with_ulp32k(
     ConfigurableEIC::new(eic)
 }
 
+/// init_with_gclk initializes the EIC and wires it up to the generic clock
+/// `GCLK_EIC`, whose generator was selected when creating the [`EicClock`].
+/// finalize() must be called before the EIC is ready for use.
+pub fn init_with_gclk(
+    mclk: &mut target_device::MCLK,
+    _clock: EicClock,
+    eic: target_device::EIC,
+) -> ConfigurableEIC {
+    reset(mclk, &eic);
+
+    // Use GCLK_EIC.
+    eic.ctrla.modify(|_, w| w.cksel().clear_bit());
+
+    ConfigurableEIC::new(eic)
+}
+
+/// init_clockless initializes the EIC for asynchronous edge detection on every
+/// line, so that no clock is needed to detect edges. The always-on 32kHz
+/// clock is only used to synchronize the registers. finalize() must be called
+/// before the EIC is ready for use.
+pub fn init_clockless(
+    mclk: &mut target_device::MCLK,
+    eic: target_device::EIC,
+) -> ConfigurableEIC<Clockless> {
+    reset(mclk, &eic);
+
+    eic.ctrla.modify(|_, w| w.cksel().set_bit());
+    eic.asynch.write(|w| unsafe { w.asynch().bits(0xFFFF) });
+
+    ConfigurableEIC::new(eic)
+}
+
 /// A configured External Interrupt Controller.
 pub struct EIC {
     _eic: target_device::EIC,
 }
 
-impl From<ConfigurableEIC> for EIC {
-    fn from(eic: ConfigurableEIC) -> Self {
+impl<C: ClockMode> From<ConfigurableEIC<C>> for EIC {
+    fn from(eic: ConfigurableEIC<C>) -> Self {
         eic.eic.ctrla.modify(|_, w| w.enable().set_bit());
         while eic.eic.syncbusy.read().enable().bit_is_set() {
             cortex_m::asm::nop();
@@ -74,3 +171,49 @@ impl From<ConfigurableEIC> for EIC {
         Self { _eic: eic.eic }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::pin::{ExtInt0, Sense};
+    use super::*;
+
+    /// Every configuration is available on a clocked EIC
+    fn clocked<GPIO>(eic: &mut ConfigurableEIC<Clocked>, pin: &mut ExtInt0<GPIO>) {
+        pin.sense(eic, Sense::RISE);
+        pin.filter(eic, true);
+        pin.enable_event(eic);
+        pin.enable_interrupt(eic);
+        pin.disable_interrupt(eic);
+        eic.button_debounce_pins(&[0]);
+    }
+
+    /// Everything but the filter and the debouncer is available on a
+    /// clockless EIC, see the `compile_fail` examples of `ConfigurableEIC`
+    fn clockless<GPIO>(eic: &mut ConfigurableEIC<Clockless>, pin: &mut ExtInt0<GPIO>) {
+        pin.sense(eic, Sense::BOTH);
+        pin.enable_event(eic);
+        pin.enable_interrupt(eic);
+        pin.disable_interrupt(eic);
+    }
+
+    /// Both modes can be finalized
+    fn finalize<C: ClockMode>(eic: ConfigurableEIC<C>) -> EIC {
+        eic.finalize()
+    }
+
+    /// An EIC without a mode is clocked
+    fn default_mode(eic: ConfigurableEIC) -> ConfigurableEIC<Clocked> {
+        eic
+    }
+
+    #[test]
+    fn typestates_allow_their_configurations() {
+        // The EIC can't be accessed on the host, so these only have to type
+        // check
+        let _: fn(&mut ConfigurableEIC<Clocked>, &mut ExtInt0<()>) = clocked;
+        let _: fn(&mut ConfigurableEIC<Clockless>, &mut ExtInt0<()>) = clockless;
+        let _: fn(ConfigurableEIC<Clocked>) -> EIC = finalize;
+        let _: fn(ConfigurableEIC<Clockless>) -> EIC = finalize;
+        let _: fn(ConfigurableEIC) -> ConfigurableEIC<Clocked> = default_mode;
+    }
+}
@@ -47,19 +47,19 @@ crate::paste::item! {
             [<$PadType $num>](pin)
         }
 
-        pub fn enable_event(&mut self, eic: &mut super::ConfigurableEIC) {
+        pub fn enable_event<C: super::ClockMode>(&mut self, eic: &mut super::ConfigurableEIC<C>) {
             eic.eic.evctrl.modify(|_, w| unsafe {
                 w.bits(1 << $num)
             });
         }
 
-        pub fn enable_interrupt(&mut self, eic: &mut super::ConfigurableEIC) {
+        pub fn enable_interrupt<C: super::ClockMode>(&mut self, eic: &mut super::ConfigurableEIC<C>) {
             eic.eic.intenset.write(|w| unsafe {
                 w.bits(1 << $num)
             })
         }
 
-        pub fn disable_interrupt(&mut self, eic: &mut super::ConfigurableEIC) {
+        pub fn disable_interrupt<C: super::ClockMode>(&mut self, eic: &mut super::ConfigurableEIC<C>) {
             eic.eic.intenclr.write(|w| unsafe {
                 w.bits(1 << $num)
             })
@@ -83,7 +83,7 @@ crate::paste::item! {
             }
         }
 
-        pub fn sense(&mut self, _eic: &mut super::ConfigurableEIC, sense: Sense) {
+        pub fn sense<C: super::ClockMode>(&mut self, _eic: &mut super::ConfigurableEIC<C>, sense: Sense) {
             // Which of the two config blocks this eic config is in
             let offset = ($num >> 3) & 0b0001;
             let config = unsafe { &(*target_device::EIC::ptr()).config[offset] };
@@ -104,7 +104,9 @@ crate::paste::item! {
             });
         }
 
-        pub fn filter(&mut self, _eic: &mut super::ConfigurableEIC, filter: bool) {
+        /// Enable or disable the majority-vote input filter. It samples the
+        /// input with the EIC clock, so it requires a clocked EIC.
+        pub fn filter(&mut self, _eic: &mut super::ConfigurableEIC<super::Clocked>, filter: bool) {
             // Which of the two config blocks this eic config is in
             let offset = ($num >> 3) & 0b0001;
             let config = unsafe { &(*target_device::EIC::ptr()).config[offset] };
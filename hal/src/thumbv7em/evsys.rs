@@ -0,0 +1,281 @@
+//! # Event system tracing
+//!
+//! Event pipelines, like an EIC pin starting an ADC conversion, run without
+//! the CPU, so nothing shows when they are wired wrong. A [`Tracer`] records
+//! when events go through selected EVSYS channels, using their event detected
+//! (`EVD`) interrupts, to check that the generator fires, and how often.
+//!
+//! The EVSYS channels are still configured through the PAC, which this HAL
+//! does not abstract yet. [`Tracer::attach`] only enables the interrupts of a
+//! configured channel, and [`Tracer::on_interrupt`] timestamps its events
+//! into a ring buffer of the last `N` events. Only channels 0 to 11 have
+//! interrupts, and the event must go through the synchronous or
+//! resynchronized path, with the channel's GCLK (`evsysN`) running.
+//!
+//! The timestamps come from a function, typically the DWT cycle counter or
+//! the RTC counter. As every event interrupts the CPU, the tracer is meant for
+//! debugging, and misses events which come faster than the interrupt handler.
+//! An event detected while the previous one is still flagged counts as an
+//! overrun.
+//!
+//! ```
+//! static TRACER: Mutex<RefCell<Option<Tracer<16>>>> = Mutex::new(RefCell::new(None));
+//!
+//! let mut tracer = Tracer::new(DWT::get_cycle_count);
+//! tracer.attach(2, "adc start")?;
+//! free(|cs| TRACER.borrow(cs).replace(Some(tracer)));
+//! unsafe { NVIC::unmask(interrupt::EVSYS_2) };
+//!
+//! #[interrupt]
+//! fn EVSYS_2() {
+//!     free(|cs| TRACER.borrow(cs).borrow_mut().as_mut().unwrap().on_interrupt());
+//! }
+//!
+//! // Later, from the main loop
+//! free(|cs| hprintln!("{:?}", TRACER.borrow(cs).borrow().as_ref().unwrap().dump()));
+//! ```
+//!
+//! The `EVSYS_0` to `EVSYS_3` interrupts belong to channels 0 to 3, and
+//! `EVSYS_4` to channels 4 to 11.
+
+use core::fmt;
+
+use crate::target_device::{evsys, EVSYS};
+
+/// Number of EVSYS channels with interrupts, which can be traced
+pub const TRACEABLE_CHANNELS: usize = 12;
+
+/// Errors of [`Tracer::attach`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceError {
+    /// The channel has no interrupts
+    NotTraceable(u8),
+    /// The channel uses the asynchronous path, which detects no events
+    Asynchronous(u8),
+    /// The channel is already traced
+    AlreadyAttached(u8),
+}
+
+/// Ring buffer of the timestamps of the last `N` events of a channel
+#[derive(Clone, Copy)]
+pub struct EventLog<const N: usize> {
+    timestamps: [u32; N],
+    next: usize,
+    len: usize,
+    events: u32,
+    overruns: u32,
+}
+
+impl<const N: usize> EventLog<N> {
+    /// Create an empty log
+    #[inline]
+    pub const fn new() -> Self {
+        EventLog {
+            timestamps: [0; N],
+            next: 0,
+            len: 0,
+            events: 0,
+            overruns: 0,
+        }
+    }
+
+    /// Record an event, overwriting the oldest one if the log is full
+    #[inline]
+    pub fn record(&mut self, timestamp: u32) {
+        self.events = self.events.wrapping_add(1);
+        if N == 0 {
+            return;
+        }
+        self.timestamps[self.next] = timestamp;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Count an event lost because the previous one was not handled yet
+    #[inline]
+    pub fn overrun(&mut self) {
+        self.overruns = self.overruns.wrapping_add(1);
+    }
+
+    /// Number of events recorded since the log was created, including the
+    /// ones overwritten since
+    #[inline]
+    pub fn events(&self) -> u32 {
+        self.events
+    }
+
+    /// Number of overruns
+    #[inline]
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Timestamps of the events still in the log, oldest first
+    #[inline]
+    pub fn timestamps(&self) -> impl Iterator<Item = u32> + '_ {
+        let start = (self.next + N - self.len) % N.max(1);
+        (0..self.len).map(move |i| self.timestamps[(start + i) % N])
+    }
+}
+
+impl<const N: usize> Default for EventLog<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Debug for EventLog<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} events, {} overruns: ", self.events, self.overruns)?;
+        f.debug_list().entries(self.timestamps()).finish()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TracedChannel<const N: usize> {
+    label: &'static str,
+    log: EventLog<N>,
+}
+
+/// Records the events of EVSYS channels
+///
+/// See the [module-level documentation](self) for more details.
+pub struct Tracer<const N: usize> {
+    timestamp: fn() -> u32,
+    channels: [Option<TracedChannel<N>>; TRACEABLE_CHANNELS],
+}
+
+impl<const N: usize> Tracer<N> {
+    /// Create a tracer, with no channels attached, timestamping the events
+    /// with `timestamp`
+    #[inline]
+    pub fn new(timestamp: fn() -> u32) -> Self {
+        Tracer {
+            timestamp,
+            channels: [None; TRACEABLE_CHANNELS],
+        }
+    }
+
+    #[inline]
+    fn channel(channel: u8) -> &'static evsys::CHANNEL {
+        // SAFETY: The tracer only accesses the interrupt registers of the
+        // channels attached to it, which no other code uses
+        unsafe { &(*EVSYS::ptr()).channel[channel as usize] }
+    }
+
+    /// Trace the events of `channel`, which must already be configured, and
+    /// name them `label` in the [dump](Tracer::dump)
+    pub fn attach(&mut self, channel: u8, label: &'static str) -> Result<(), TraceError> {
+        let slot = self
+            .channels
+            .get_mut(channel as usize)
+            .ok_or(TraceError::NotTraceable(channel))?;
+        if slot.is_some() {
+            return Err(TraceError::AlreadyAttached(channel));
+        }
+        let regs = Self::channel(channel);
+        if regs.channel.read().path().is_asynchronous() {
+            return Err(TraceError::Asynchronous(channel));
+        }
+        *slot = Some(TracedChannel {
+            label,
+            log: EventLog::new(),
+        });
+        regs.chintflag.write(|w| w.evd().set_bit().ovr().set_bit());
+        regs.chintenset.write(|w| w.evd().set_bit().ovr().set_bit());
+        Ok(())
+    }
+
+    /// Stop tracing `channel`, and return its log
+    pub fn detach(&mut self, channel: u8) -> Option<EventLog<N>> {
+        let traced = self.channels.get_mut(channel as usize)?.take()?;
+        Self::channel(channel)
+            .chintenclr
+            .write(|w| w.evd().set_bit().ovr().set_bit());
+        Some(traced.log)
+    }
+
+    /// Record the events detected on the attached channels
+    ///
+    /// Call this from the `EVSYS_n` interrupts of the attached channels.
+    pub fn on_interrupt(&mut self) {
+        let now = (self.timestamp)();
+        for (channel, traced) in self.channels.iter_mut().enumerate() {
+            if let Some(traced) = traced {
+                let regs = Self::channel(channel as u8);
+                let flags = regs.chintflag.read();
+                if flags.evd().bit_is_set() {
+                    traced.log.record(now);
+                }
+                if flags.ovr().bit_is_set() {
+                    traced.log.overrun();
+                }
+                regs.chintflag.write(|w| unsafe { w.bits(flags.bits()) });
+            }
+        }
+    }
+
+    /// Log of `channel`, if it is attached
+    #[inline]
+    pub fn log(&self, channel: u8) -> Option<&EventLog<N>> {
+        self.channels
+            .get(channel as usize)?
+            .as_ref()
+            .map(|traced| &traced.log)
+    }
+
+    /// Logs of every attached channel, formatted by their `Debug`
+    /// implementation, one channel per line
+    #[inline]
+    pub fn dump(&self) -> Dump<'_, N> {
+        Dump { tracer: self }
+    }
+}
+
+/// Logs of a [`Tracer`], see [`Tracer::dump`]
+pub struct Dump<'a, const N: usize> {
+    tracer: &'a Tracer<N>,
+}
+
+impl<const N: usize> fmt::Debug for Dump<'_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (channel, traced) in self.tracer.channels.iter().enumerate() {
+            if let Some(traced) = traced {
+                writeln!(
+                    f,
+                    "{} (channel {}): {:?}",
+                    traced.label, channel, traced.log
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_keeps_the_last_events() {
+        let mut log = EventLog::<3>::new();
+        assert_eq!(log.timestamps().count(), 0);
+        log.record(10);
+        log.record(20);
+        assert!(log.timestamps().eq([10, 20].iter().copied()));
+        log.record(30);
+        log.record(40);
+        log.overrun();
+        assert!(log.timestamps().eq([20, 30, 40].iter().copied()));
+        assert_eq!(log.events(), 4);
+        assert_eq!(log.overruns(), 1);
+    }
+
+    #[test]
+    fn empty_log() {
+        let mut log = EventLog::<0>::new();
+        log.record(10);
+        assert_eq!(log.timestamps().count(), 0);
+    }
+}
@@ -0,0 +1,327 @@
+//! # Frequency and duty cycle measurement
+//!
+//! [`FrequencyMeter`] measures the frequency and duty cycle of a pulse train,
+//! like a fan tachometer or a flow sensor output, with the period and pulse
+//! width capture of a TCC (`EVCTRL.EVACT1 = PPW`). On every rising edge of
+//! the input, the TCC captures the period into `CC0` and restarts its
+//! counter; on every falling edge, it captures the pulse width into `CC1`.
+//!
+//! The input reaches the TCC as its `EV1` event. As for [`TccCapture`], EVSYS
+//! is not abstracted by this HAL yet:
+//!
+//! - Enable event generation for the input pin in the EIC (`EVCTRL.EXTINTEO`),
+//!   detecting both edges
+//! - Configure an EVSYS channel with the EIC as its generator
+//! - Select that channel in the `USER` register of the TCC's `EV1` event user
+//!
+//! ## Auto-ranging
+//!
+//! The counter only spans `2^16` or `2^24` ticks, so the prescaler of the TCC
+//! is adjusted as the input frequency changes:
+//!
+//! - The prescaler is increased when the counter overflows, or when a period
+//!   takes more than 7/8 of the counter range
+//! - The prescaler is decreased when the period would take less than half of
+//!   the counter range with the next lower prescaler
+//!
+//! The gap between both thresholds keeps the prescaler from toggling at a
+//! range boundary. Changing the prescaler restarts the counter, so the first
+//! capture afterwards is discarded, along with the periods accumulated for
+//! the average.
+//!
+//! ## Example
+//!
+//! ```
+//! let capture = TccCapture::tcc1(&tcc1_clock, peripherals.TCC1, &mut mclk, 0);
+//! let mut meter = FrequencyMeter::new(capture, 4);
+//! loop {
+//!     match meter.read() {
+//!         Ok(m) => rpm = m.freq.0 * 60 / PULSES_PER_REVOLUTION,
+//!         Err(nb::Error::Other(MeasureError::BelowRange)) => rpm = 0,
+//!         _ => (),
+//!     }
+//! }
+//! ```
+//!
+//! [`TccCapture`]: crate::tcc_capture::TccCapture
+
+use crate::tcc_capture::{CaptureTcc, TccCapture};
+use crate::time::Hertz;
+
+/// Division factors of the `CTRLA.PRESCALER` values
+const DIVISIONS: [u32; 8] = [1, 2, 4, 8, 16, 64, 256, 1024];
+
+/// Shortest measurable period with the prescaler disabled, in counter ticks
+const MIN_PERIOD_TICKS: u32 = 8;
+
+/// A frequency and duty cycle measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// Frequency of the input, rounded to the nearest hertz
+    pub freq: Hertz,
+    /// Portion of the period the input spends high, in thousandths
+    pub duty_permille: u16,
+}
+
+/// Error returned by [`FrequencyMeter::read`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasureError {
+    /// The input is too slow, or stuck, for the counter range at the largest
+    /// prescaler
+    BelowRange,
+    /// The input is too fast for the counter clock
+    AboveRange,
+}
+
+/// Prescaler adjustment decided by [`range_step`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeStep {
+    Keep,
+    Up,
+    Down,
+}
+
+/// Decide the prescaler adjustment, given the index of the current prescaler
+/// and the captured period in ticks, or `None` if the counter overflowed
+fn range_step(prescaler: usize, period: Option<u32>, max: u32) -> RangeStep {
+    let top = DIVISIONS.len() - 1;
+    match period {
+        None if prescaler < top => RangeStep::Up,
+        None => RangeStep::Keep,
+        Some(p) if p > max - max / 8 && prescaler < top => RangeStep::Up,
+        Some(p) if prescaler > 0 => {
+            let ratio = DIVISIONS[prescaler] / DIVISIONS[prescaler - 1];
+            if (p as u64) * (ratio as u64) < (max / 2) as u64 {
+                RangeStep::Down
+            } else {
+                RangeStep::Keep
+            }
+        }
+        Some(_) => RangeStep::Keep,
+    }
+}
+
+/// Compute the measurement of `periods` periods and pulse widths accumulated
+/// at a counter frequency of `clock / division`
+fn measurement(
+    clock: Hertz,
+    division: u32,
+    periods: u32,
+    period_sum: u64,
+    width_sum: u64,
+) -> Measurement {
+    let ticks = period_sum * division as u64;
+    let freq = (clock.0 as u64 * periods as u64 + ticks / 2) / ticks;
+    let duty = (width_sum * 1000 + period_sum / 2) / period_sum;
+    Measurement {
+        freq: Hertz(freq as u32),
+        duty_permille: duty.min(1000) as u16,
+    }
+}
+
+/// An auto-ranging frequency and duty cycle meter
+///
+/// See the [module-level documentation](self) for more details.
+pub struct FrequencyMeter<TCC> {
+    tcc: TCC,
+    clock: Hertz,
+    prescaler: usize,
+    periods: u8,
+    count: u8,
+    period_sum: u64,
+    width_sum: u64,
+    settling: bool,
+}
+
+impl<TCC: CaptureTcc> FrequencyMeter<TCC> {
+    /// Reconfigure a TCC for period and pulse width capture, and average
+    /// every measurement over `periods` periods
+    ///
+    /// The counter clock is the frequency of the capture. The channels
+    /// selected for the capture don't matter, since channels 0 and 1 are
+    /// used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `periods` is zero.
+    pub fn new(capture: TccCapture<TCC>, periods: u8) -> Self {
+        assert!(periods > 0, "at least one period must be averaged");
+        let clock = capture.freq();
+        let tcc = capture.free();
+        tcc.evctrl.write(|w| w.evact1().ppw().tcei1().set_bit());
+        let mut meter = FrequencyMeter {
+            tcc,
+            clock,
+            prescaler: 0,
+            periods,
+            count: 0,
+            period_sum: 0,
+            width_sum: 0,
+            settling: true,
+        };
+        meter.set_prescaler(0);
+        meter
+    }
+
+    /// Division factor of the current prescaler
+    pub fn prescaler_division(&self) -> u32 {
+        DIVISIONS[self.prescaler]
+    }
+
+    /// Return a measurement, once enough periods were captured
+    ///
+    /// This must be polled at least once per input period, so that no
+    /// capture is missed.
+    pub fn read(&mut self) -> nb::Result<Measurement, MeasureError> {
+        let max = (1u32 << TCC::COUNTER_BITS) - 1;
+        let flags = self.tcc.intflag.read();
+
+        if flags.ovf().bit_is_set() {
+            self.tcc.intflag.write(|w| w.ovf().set_bit());
+            return match range_step(self.prescaler, None, max) {
+                RangeStep::Up => {
+                    self.set_prescaler(self.prescaler + 1);
+                    Err(nb::Error::WouldBlock)
+                }
+                _ => {
+                    self.reset_average();
+                    Err(nb::Error::Other(MeasureError::BelowRange))
+                }
+            };
+        }
+        if flags.mc0().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Reading the capture registers clears the MC0 and MC1 flags
+        let period = self.tcc.cc()[0].read().bits();
+        let width = self.tcc.cc()[1].read().bits();
+        if self.settling {
+            self.settling = false;
+            return Err(nb::Error::WouldBlock);
+        }
+        if self.prescaler == 0 && period < MIN_PERIOD_TICKS {
+            self.reset_average();
+            return Err(nb::Error::Other(MeasureError::AboveRange));
+        }
+
+        match range_step(self.prescaler, Some(period), max) {
+            RangeStep::Up => self.set_prescaler(self.prescaler + 1),
+            RangeStep::Down => self.set_prescaler(self.prescaler - 1),
+            RangeStep::Keep => {
+                self.period_sum += period as u64;
+                self.width_sum += width as u64;
+                self.count += 1;
+                if self.count == self.periods {
+                    let m = measurement(
+                        self.clock,
+                        self.prescaler_division(),
+                        self.count as u32,
+                        self.period_sum,
+                        self.width_sum,
+                    );
+                    self.reset_average();
+                    return Ok(m);
+                }
+            }
+        }
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Disable the TCC and return it
+    pub fn free(self) -> TCC {
+        self.tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+        self.tcc
+    }
+
+    fn reset_average(&mut self) {
+        self.count = 0;
+        self.period_sum = 0;
+        self.width_sum = 0;
+    }
+
+    /// Restart the counter with another prescaler. `CTRLA` is
+    /// enable-protected.
+    fn set_prescaler(&mut self, prescaler: usize) {
+        self.tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+        self.tcc.ctrla.write(|w| {
+            w.prescaler()
+                .bits(prescaler as u8)
+                .cpten0()
+                .set_bit()
+                .cpten1()
+                .set_bit()
+        });
+        self.tcc.intflag.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        self.tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+
+        self.prescaler = prescaler;
+        self.settling = true;
+        self.reset_average();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: u32 = 0xFFFF;
+
+    #[test]
+    fn range_switches_with_hysteresis() {
+        // Overflows move up, until the largest prescaler
+        assert_eq!(range_step(0, None, MAX), RangeStep::Up);
+        assert_eq!(range_step(7, None, MAX), RangeStep::Keep);
+
+        // Above 7/8 of the range
+        assert_eq!(range_step(2, Some(60_000), MAX), RangeStep::Up);
+        // Between the thresholds
+        assert_eq!(range_step(2, Some(40_000), MAX), RangeStep::Keep);
+        assert_eq!(range_step(2, Some(20_000), MAX), RangeStep::Keep);
+        // Would take less than half of the range at DIV2
+        assert_eq!(range_step(2, Some(16_000), MAX), RangeStep::Down);
+        // DIV64 to DIV16 multiplies the period by 4
+        assert_eq!(range_step(5, Some(8_200), MAX), RangeStep::Keep);
+        assert_eq!(range_step(5, Some(8_000), MAX), RangeStep::Down);
+        // Nothing below DIV1
+        assert_eq!(range_step(0, Some(10), MAX), RangeStep::Keep);
+    }
+
+    #[test]
+    fn no_toggling_at_boundaries() {
+        // Any period moved up ends up in the stable band, and vice versa
+        for prescaler in 0..7 {
+            for &period in &[MAX - MAX / 8 + 1, MAX] {
+                assert_eq!(range_step(prescaler, Some(period), MAX), RangeStep::Up);
+                let ratio = DIVISIONS[prescaler + 1] / DIVISIONS[prescaler];
+                let next = period / ratio;
+                assert_ne!(range_step(prescaler + 1, Some(next), MAX), RangeStep::Down);
+            }
+        }
+        for prescaler in 1..8 {
+            let ratio = DIVISIONS[prescaler] / DIVISIONS[prescaler - 1];
+            let period = MAX / 2 / ratio - 1;
+            assert_eq!(range_step(prescaler, Some(period), MAX), RangeStep::Down);
+            assert_ne!(
+                range_step(prescaler - 1, Some(period * ratio), MAX),
+                RangeStep::Up
+            );
+        }
+    }
+
+    #[test]
+    fn measurements_in_engineering_units() {
+        // 120 MHz / 16, four periods of 7500 ticks: 1 kHz at 25 % duty
+        let m = measurement(Hertz(120_000_000), 16, 4, 30_000, 7_500);
+        assert_eq!(m.freq, Hertz(1_000));
+        assert_eq!(m.duty_permille, 250);
+
+        // A fan tachometer at 33 Hz, through DIV1024
+        let m = measurement(Hertz(120_000_000), 1024, 1, 3551, 1775);
+        assert_eq!(m.freq, Hertz(33));
+        assert_eq!(m.duty_permille, 500);
+    }
+}
@@ -0,0 +1,166 @@
+//! # Measurement window of the FREQM
+//!
+//! The frequency meter (FREQM) counts the periods of the measured clock
+//! (`GCLK_FREQM_MSR`) during `REFNUM` periods of the reference clock
+//! (`GCLK_FREQM_REF`). The measured frequency is then
+//!
+//! ```text
+//! f_msr = VALUE * f_ref / REFNUM
+//! ```
+//!
+//! One count of `VALUE` is worth `f_ref / REFNUM`, the resolution of the
+//! measurement. A larger `REFNUM` gives a finer resolution, at the cost of a
+//! longer measurement window of `REFNUM / f_ref`. `REFNUM` is an 8-bit field,
+//! so the finest resolution is `f_ref / 255`: a slower reference clock gives
+//! a finer resolution, and a longer window. The 24-bit `VALUE` also limits
+//! the highest frequency measurable in a window.
+//!
+//! [`select_refnum`] picks the smallest `REFNUM`, i.e. the shortest window,
+//! that achieves a given resolution. For instance, with a 32.768 kHz
+//! reference:
+//!
+//! | Resolution | `REFNUM` | Window   |
+//! |------------|----------|----------|
+//! | 32768 Hz   | 1        | 31 µs    |
+//! | 1000 Hz    | 33       | 1.008 ms |
+//! | 129 Hz     | 255      | 7.782 ms |
+//!
+//! This HAL does not drive the FREQM yet. Configure the window with
+//! [`set_window`], then start a measurement through the PAC, and convert the
+//! result with [`MeasurementWindow::frequency`]:
+//!
+//! ```
+//! let window = select_refnum(32_768.hz(), 1_000.hz())?;
+//! set_window(&freqm, window);
+//! freqm.ctrla.write(|w| w.enable().set_bit());
+//! freqm.ctrlb.write(|w| w.start().set_bit());
+//! while freqm.status.read().busy().bit_is_set() {}
+//! let freq = window.frequency(freqm.value.read().value().bits());
+//! ```
+
+use crate::target_device::FREQM;
+use crate::time::Hertz;
+
+/// Largest value of `CFGA.REFNUM`
+const MAX_REFNUM: u32 = 255;
+
+/// Largest value of the 24-bit `VALUE` register
+const MAX_VALUE: u64 = (1 << 24) - 1;
+
+/// Error of [`select_refnum`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// The requested resolution is zero
+    ZeroResolution,
+    /// The requested resolution is finer than `f_ref / 255`, and needs a
+    /// slower reference clock
+    ResolutionTooFine,
+}
+
+/// Measurement window of the FREQM, set by `CFGA.REFNUM`
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeasurementWindow {
+    refnum: u8,
+    reference: Hertz,
+}
+
+impl MeasurementWindow {
+    /// Number of reference clock periods of the window (`REFNUM`)
+    #[inline]
+    pub fn refnum(&self) -> u8 {
+        self.refnum
+    }
+
+    /// Duration of the window, in microseconds, rounded up
+    #[inline]
+    pub fn duration_us(&self) -> u32 {
+        let reference = self.reference.0 as u64;
+        ((self.refnum as u64 * 1_000_000 + reference - 1) / reference) as u32
+    }
+
+    /// Frequency change of one count of `VALUE`, rounded up
+    #[inline]
+    pub fn resolution(&self) -> Hertz {
+        let refnum = self.refnum as u32;
+        Hertz((self.reference.0 + refnum - 1) / refnum)
+    }
+
+    /// Highest frequency which doesn't overflow `VALUE` during the window
+    #[inline]
+    pub fn max_freq(&self) -> Hertz {
+        let max = MAX_VALUE * self.reference.0 as u64 / self.refnum as u64;
+        Hertz(max.min(u32::MAX as u64) as u32)
+    }
+
+    /// Frequency of the measured clock, given the measured `VALUE`
+    #[inline]
+    pub fn frequency(&self, value: u32) -> Hertz {
+        let freq = value as u64 * self.reference.0 as u64 / self.refnum as u64;
+        Hertz(freq as u32)
+    }
+}
+
+/// Pick the shortest measurement window with a resolution of `resolution` or
+/// finer, given the frequency of the reference clock
+pub fn select_refnum(
+    reference: impl Into<Hertz>,
+    resolution: impl Into<Hertz>,
+) -> Result<MeasurementWindow, WindowError> {
+    let reference = reference.into();
+    let resolution = resolution.into().0;
+    if resolution == 0 {
+        return Err(WindowError::ZeroResolution);
+    }
+    let refnum = ((reference.0 + resolution - 1) / resolution).max(1);
+    if refnum > MAX_REFNUM {
+        return Err(WindowError::ResolutionTooFine);
+    }
+    Ok(MeasurementWindow {
+        refnum: refnum as u8,
+        reference,
+    })
+}
+
+/// Set the measurement window of `freqm`, disabling it first, since `CFGA`
+/// is enable-protected
+pub fn set_window(freqm: &FREQM, window: MeasurementWindow) {
+    freqm.ctrla.write(|w| w.enable().clear_bit());
+    while freqm.syncbusy.read().enable().bit_is_set() {}
+    freqm
+        .cfga
+        .write(|w| unsafe { w.refnum().bits(window.refnum) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refnum_for_a_target_resolution() {
+        let select = |resolution| select_refnum(Hertz(32_768), Hertz(resolution));
+        assert_eq!(select(32_768).unwrap().refnum(), 1);
+        assert_eq!(select(100_000).unwrap().refnum(), 1);
+        // 32768 / 32 = 1024 Hz would be too coarse
+        let window = select(1_000).unwrap();
+        assert_eq!(window.refnum(), 33);
+        assert_eq!(window.resolution(), Hertz(993));
+        assert_eq!(window.duration_us(), 1008);
+        assert_eq!(select(129).unwrap().refnum(), 255);
+        assert_eq!(select(128), Err(WindowError::ResolutionTooFine));
+        assert_eq!(select(0), Err(WindowError::ZeroResolution));
+    }
+
+    #[test]
+    fn value_conversion() {
+        // 48 MHz measured during 33 periods of 32.768 kHz, within 993 Hz
+        let window = select_refnum(Hertz(32_768), Hertz(1_000)).unwrap();
+        assert_eq!(window.frequency(48_339), Hertz(47_999_162));
+        assert_eq!(window.frequency(48_340), Hertz(48_000_155));
+        // Far above any clock of the chip, with a 32.768 kHz reference
+        assert_eq!(window.max_freq(), Hertz(u32::MAX));
+        let window = select_refnum(Hertz(32_768), Hertz(129)).unwrap();
+        assert_eq!(window.max_freq(), Hertz(2_155_905_024));
+    }
+}
@@ -1,6 +1,7 @@
 pub mod calibration;
 pub mod clock;
 pub mod eic;
+pub mod oscctrl_irq;
 pub mod qspi;
 pub(crate) mod sercom;
 pub mod timer;
@@ -18,8 +19,38 @@ pub use serial_number::*;
 #[cfg(feature = "unproven")]
 pub mod adc;
 
+#[cfg(all(
+    feature = "unproven",
+    any(feature = "same51", feature = "same53", feature = "same54")
+))]
+pub mod can_timestamp;
+
+#[cfg(feature = "unproven")]
+pub mod evsys;
+
+#[cfg(feature = "unproven")]
+pub mod freq_meter;
+
+#[cfg(feature = "unproven")]
+pub mod freqm;
+
 #[cfg(feature = "unproven")]
 pub mod pwm;
 
+#[cfg(feature = "unproven")]
+pub mod sd_card;
+
+#[cfg(feature = "unproven")]
+pub mod sdhc;
+
+#[cfg(feature = "unproven")]
+pub mod supc;
+
+#[cfg(feature = "unproven")]
+pub mod synced_pwm;
+
+#[cfg(feature = "unproven")]
+pub mod tcc_capture;
+
 #[cfg(feature = "unproven")]
 pub mod watchdog;
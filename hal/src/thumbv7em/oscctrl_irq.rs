@@ -0,0 +1,337 @@
+//! # Oscillator interrupt dispatch
+//!
+//! The `OSCCTRL` peripheral spreads its interrupt flags across five IRQ lines,
+//! one per oscillator:
+//!
+//! | IRQ line         | [`OscLine`] | Flags                                     |
+//! |------------------|-------------|-------------------------------------------|
+//! | `OSCCTRL_XOSC0`  | `Xosc0`     | `XOSCRDY0`, `XOSCFAIL0`                   |
+//! | `OSCCTRL_XOSC1`  | `Xosc1`     | `XOSCRDY1`, `XOSCFAIL1`                   |
+//! | `OSCCTRL_DFLL`   | `Dfll`      | `DFLLRDY`, `DFLLOOB`, `DFLLLCKF`, `DFLLLCKC`, `DFLLRCS` |
+//! | `OSCCTRL_DPLL0`  | `Dpll0`     | `DPLL0LCKR`, `DPLL0LCKF`, `DPLL0LTO`, `DPLL0LDRTO` |
+//! | `OSCCTRL_DPLL1`  | `Dpll1`     | `DPLL1LCKR`, `DPLL1LCKF`, `DPLL1LTO`, `DPLL1LDRTO` |
+//!
+//! The `OSC32KCTRL` peripheral has a single IRQ line, carrying the
+//! `XOSC32KRDY` and `XOSC32KFAIL` flags.
+//!
+//! This module keeps a table of handlers, one per line. Register a handler
+//! with [`set_handler`], enable the flags of interest with
+//! [`enable_interrupts`], unmask the IRQ line in the NVIC, and call
+//! [`on_oscctrl_interrupt`] from the interrupt handler. It reads `INTFLAG`,
+//! keeps the flags that belong to the line and are enabled in `INTENSET`,
+//! clears them, and passes them to the registered handler.
+//!
+//! ```
+//! fn xosc0_failed(flags: OscFlags) {
+//!     if flags.contains(OscFlags::XOSCFAIL0) {
+//!         // Switch the main clock to a backup source
+//!     }
+//! }
+//!
+//! oscctrl_irq::set_handler(OscLine::Xosc0, Some(xosc0_failed));
+//! oscctrl_irq::enable_interrupts(OscFlags::XOSCFAIL0);
+//! unsafe { NVIC::unmask(OscLine::Xosc0.interrupt()) };
+//!
+//! #[interrupt]
+//! fn OSCCTRL_XOSC0() {
+//!     oscctrl_irq::on_oscctrl_interrupt(OscLine::Xosc0 as u8);
+//! }
+//! ```
+//!
+//! The `OSC32KCTRL` interrupt works the same way, through
+//! [`set_osc32k_handler`], [`enable_osc32k_interrupts`] and
+//! [`on_osc32kctrl_interrupt`].
+
+use core::cell::Cell;
+
+use bitflags::bitflags;
+use cortex_m::interrupt::{self, Mutex};
+
+use crate::target_device::{Interrupt, OSC32KCTRL, OSCCTRL};
+
+//==============================================================================
+// Flags
+//==============================================================================
+
+bitflags! {
+    /// Interrupt flags of the `OSCCTRL` peripheral
+    ///
+    /// The binary format of the underlying bits exactly matches the `INTFLAG`
+    /// register.
+    pub struct OscFlags: u32 {
+        const XOSCRDY0 = 1 << 0;
+        const XOSCRDY1 = 1 << 1;
+        const XOSCFAIL0 = 1 << 2;
+        const XOSCFAIL1 = 1 << 3;
+        const DFLLRDY = 1 << 8;
+        const DFLLOOB = 1 << 9;
+        const DFLLLCKF = 1 << 10;
+        const DFLLLCKC = 1 << 11;
+        const DFLLRCS = 1 << 12;
+        const DPLL0LCKR = 1 << 16;
+        const DPLL0LCKF = 1 << 17;
+        const DPLL0LTO = 1 << 18;
+        const DPLL0LDRTO = 1 << 19;
+        const DPLL1LCKR = 1 << 24;
+        const DPLL1LCKF = 1 << 25;
+        const DPLL1LTO = 1 << 26;
+        const DPLL1LDRTO = 1 << 27;
+    }
+}
+
+bitflags! {
+    /// Interrupt flags of the `OSC32KCTRL` peripheral
+    ///
+    /// The binary format of the underlying bits exactly matches the `INTFLAG`
+    /// register.
+    pub struct Osc32kFlags: u32 {
+        const XOSC32KRDY = 1 << 0;
+        const XOSC32KFAIL = 1 << 2;
+    }
+}
+
+/// Raw `INTFLAG` masks of each `OSCCTRL` IRQ line, indexed by [`OscLine`]
+pub const LINE_FLAGS: [u32; 5] = [
+    0x0000_0005, // XOSCRDY0, XOSCFAIL0
+    0x0000_000A, // XOSCRDY1, XOSCFAIL1
+    0x0000_1F00, // DFLLRDY, DFLLOOB, DFLLLCKF, DFLLLCKC, DFLLRCS
+    0x000F_0000, // DPLL0LCKR, DPLL0LCKF, DPLL0LTO, DPLL0LDRTO
+    0x0F00_0000, // DPLL1LCKR, DPLL1LCKF, DPLL1LTO, DPLL1LDRTO
+];
+
+//==============================================================================
+// OscLine
+//==============================================================================
+
+/// One of the five `OSCCTRL` IRQ lines
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OscLine {
+    Xosc0 = 0,
+    Xosc1 = 1,
+    Dfll = 2,
+    Dpll0 = 3,
+    Dpll1 = 4,
+}
+
+impl OscLine {
+    /// Convert an IRQ line index into an `OscLine`
+    #[inline]
+    pub fn from_line(line: u8) -> Option<Self> {
+        match line {
+            0 => Some(OscLine::Xosc0),
+            1 => Some(OscLine::Xosc1),
+            2 => Some(OscLine::Dfll),
+            3 => Some(OscLine::Dpll0),
+            4 => Some(OscLine::Dpll1),
+            _ => None,
+        }
+    }
+
+    /// Find the IRQ line that carries the given flags
+    ///
+    /// Returns `None` if `flags` is empty or spans more than one line.
+    #[inline]
+    pub fn of_flags(flags: OscFlags) -> Option<Self> {
+        let line = LINE_FLAGS
+            .iter()
+            .position(|&mask| !flags.is_empty() && flags.bits() & !mask == 0)?;
+        Self::from_line(line as u8)
+    }
+
+    /// Flags carried by this IRQ line
+    #[inline]
+    pub fn flags(self) -> OscFlags {
+        OscFlags::from_bits_truncate(LINE_FLAGS[self as usize])
+    }
+
+    /// NVIC interrupt for this IRQ line
+    #[inline]
+    pub fn interrupt(self) -> Interrupt {
+        match self {
+            OscLine::Xosc0 => Interrupt::OSCCTRL_XOSC0,
+            OscLine::Xosc1 => Interrupt::OSCCTRL_XOSC1,
+            OscLine::Dfll => Interrupt::OSCCTRL_DFLL,
+            OscLine::Dpll0 => Interrupt::OSCCTRL_DPLL0,
+            OscLine::Dpll1 => Interrupt::OSCCTRL_DPLL1,
+        }
+    }
+}
+
+//==============================================================================
+// Handlers
+//==============================================================================
+
+/// Handler for the flags of an `OSCCTRL` IRQ line
+pub type OscHandler = fn(OscFlags);
+
+/// Handler for the flags of the `OSC32KCTRL` IRQ line
+pub type Osc32kHandler = fn(Osc32kFlags);
+
+static HANDLERS: Mutex<[Cell<Option<OscHandler>>; 5]> = Mutex::new([
+    Cell::new(None),
+    Cell::new(None),
+    Cell::new(None),
+    Cell::new(None),
+    Cell::new(None),
+]);
+
+static OSC32K_HANDLER: Mutex<Cell<Option<Osc32kHandler>>> = Mutex::new(Cell::new(None));
+
+/// Register the handler for an `OSCCTRL` IRQ line, or remove it with `None`
+pub fn set_handler(line: OscLine, handler: Option<OscHandler>) {
+    interrupt::free(|cs| HANDLERS.borrow(cs)[line as usize].set(handler));
+}
+
+/// Register the handler for the `OSC32KCTRL` IRQ line, or remove it with
+/// `None`
+pub fn set_osc32k_handler(handler: Option<Osc32kHandler>) {
+    interrupt::free(|cs| OSC32K_HANDLER.borrow(cs).set(handler));
+}
+
+/// Enable the given `OSCCTRL` interrupt flags
+#[inline]
+pub fn enable_interrupts(flags: OscFlags) {
+    // Safe because INTENSET is a write-one-to-set register, so no other bits
+    // are affected
+    unsafe { (*OSCCTRL::ptr()).intenset.write(|w| w.bits(flags.bits())) };
+}
+
+/// Disable the given `OSCCTRL` interrupt flags
+#[inline]
+pub fn disable_interrupts(flags: OscFlags) {
+    // Safe because INTENCLR is a write-one-to-clear register, so no other bits
+    // are affected
+    unsafe { (*OSCCTRL::ptr()).intenclr.write(|w| w.bits(flags.bits())) };
+}
+
+/// Enable the given `OSC32KCTRL` interrupt flags
+#[inline]
+pub fn enable_osc32k_interrupts(flags: Osc32kFlags) {
+    // Safe for the same reason as `enable_interrupts`
+    unsafe {
+        (*OSC32KCTRL::ptr())
+            .intenset
+            .write(|w| w.bits(flags.bits()))
+    };
+}
+
+/// Disable the given `OSC32KCTRL` interrupt flags
+#[inline]
+pub fn disable_osc32k_interrupts(flags: Osc32kFlags) {
+    // Safe for the same reason as `disable_interrupts`
+    unsafe {
+        (*OSC32KCTRL::ptr())
+            .intenclr
+            .write(|w| w.bits(flags.bits()))
+    };
+}
+
+/// Flags of `line` that are both pending and enabled
+#[inline]
+fn pending(line: OscLine, intflag: u32, intenset: u32) -> OscFlags {
+    OscFlags::from_bits_truncate(intflag & intenset & LINE_FLAGS[line as usize])
+}
+
+/// Dispatch an `OSCCTRL` interrupt
+///
+/// Call this from the handler of the IRQ line with index `line`, i.e.
+/// `OscLine::Xosc0 as u8` for `OSCCTRL_XOSC0`. The pending and enabled flags
+/// of that line are cleared and passed to the registered handler. If no
+/// handler is registered, the flags are cleared anyway, so that the interrupt
+/// does not fire again immediately. Invalid line indices are ignored.
+pub fn on_oscctrl_interrupt(line: u8) {
+    let line = match OscLine::from_line(line) {
+        Some(line) => line,
+        None => return,
+    };
+    // Safe because INTFLAG is cleared by writing ones, so only the flags of
+    // this line are affected
+    let oscctrl = unsafe { &*OSCCTRL::ptr() };
+    let flags = pending(
+        line,
+        oscctrl.intflag.read().bits(),
+        oscctrl.intenset.read().bits(),
+    );
+    if flags.is_empty() {
+        return;
+    }
+    oscctrl.intflag.write(|w| unsafe { w.bits(flags.bits()) });
+    let handler = interrupt::free(|cs| HANDLERS.borrow(cs)[line as usize].get());
+    if let Some(handler) = handler {
+        handler(flags);
+    }
+}
+
+/// Dispatch an `OSC32KCTRL` interrupt
+///
+/// Call this from the `OSC32KCTRL` interrupt handler. It behaves like
+/// [`on_oscctrl_interrupt`].
+pub fn on_osc32kctrl_interrupt() {
+    // Safe for the same reason as in `on_oscctrl_interrupt`
+    let osc32kctrl = unsafe { &*OSC32KCTRL::ptr() };
+    let flags = Osc32kFlags::from_bits_truncate(
+        osc32kctrl.intflag.read().bits() & osc32kctrl.intenset.read().bits(),
+    );
+    if flags.is_empty() {
+        return;
+    }
+    osc32kctrl
+        .intflag
+        .write(|w| unsafe { w.bits(flags.bits()) });
+    let handler = interrupt::free(|cs| OSC32K_HANDLER.borrow(cs).get());
+    if let Some(handler) = handler {
+        handler(flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_flags_partition_intflag() {
+        let mut all = 0;
+        for (i, &mask) in LINE_FLAGS.iter().enumerate() {
+            assert_eq!(all & mask, 0, "line {} overlaps another line", i);
+            all |= mask;
+        }
+        assert_eq!(all, OscFlags::all().bits());
+    }
+
+    #[test]
+    fn flags_map_to_their_line() {
+        assert_eq!(OscLine::of_flags(OscFlags::XOSCFAIL0), Some(OscLine::Xosc0));
+        assert_eq!(OscLine::of_flags(OscFlags::XOSCRDY1), Some(OscLine::Xosc1));
+        assert_eq!(OscLine::of_flags(OscFlags::DFLLRCS), Some(OscLine::Dfll));
+        assert_eq!(OscLine::of_flags(OscFlags::DPLL0LCKF), Some(OscLine::Dpll0));
+        assert_eq!(
+            OscLine::of_flags(OscFlags::DPLL1LCKR | OscFlags::DPLL1LTO),
+            Some(OscLine::Dpll1)
+        );
+        assert_eq!(
+            OscLine::of_flags(OscFlags::XOSCFAIL0 | OscFlags::XOSCFAIL1),
+            None
+        );
+        assert_eq!(OscLine::of_flags(OscFlags::empty()), None);
+        for i in 0..5 {
+            assert_eq!(OscLine::from_line(i).unwrap() as u8, i);
+        }
+        assert_eq!(OscLine::from_line(5), None);
+    }
+
+    #[test]
+    fn pending_masks_line_and_enabled() {
+        let intflag = (OscFlags::XOSCFAIL0 | OscFlags::XOSCRDY0 | OscFlags::DPLL0LCKR).bits();
+        let intenset = (OscFlags::XOSCFAIL0 | OscFlags::DPLL0LCKR).bits();
+        assert_eq!(
+            pending(OscLine::Xosc0, intflag, intenset),
+            OscFlags::XOSCFAIL0
+        );
+        assert_eq!(
+            pending(OscLine::Dpll0, intflag, intenset),
+            OscFlags::DPLL0LCKR
+        );
+        assert!(pending(OscLine::Dfll, intflag, intenset).is_empty());
+    }
+}
@@ -221,6 +221,25 @@ pub enum Channel {
     _7,
 }
 
+/// Value of the `PATT` register: the enabled outputs in `PGE`, the low byte,
+/// and their levels in `PGV`, the high byte
+#[inline]
+fn pattern_bits(enable_mask: u8, value_mask: u8) -> u16 {
+    enable_mask as u16 | (value_mask as u16) << 8
+}
+
+/// Mask of the `DRVCTRL.INVENn` bit inverting the output of `channel`
+#[inline]
+fn inversion_mask(channel: Channel) -> u32 {
+    1 << (16 + channel as u32)
+}
+
+/// Compare value of a 50% duty cycle, for a counter counting up to `top`
+#[inline]
+fn half_duty(top: u32) -> u32 {
+    (top + 1) / 2
+}
+
 pub enum TCC0Pinout {
     Pa8(Pa8<PfF>),
     Pa9(Pa9<PfF>),
@@ -432,6 +451,61 @@ impl $TYPE {
     }
 }
 
+impl $TYPE {
+    /// Force the outputs selected by `enable_mask` to the levels of
+    /// `value_mask`, from the start of the next PWM period
+    ///
+    /// Bit `n` of each mask is output `WO[n]`. The pattern is written to the
+    /// `PATTBUF` buffer, and copied to `PATT` on the next update condition, when
+    /// the counter wraps around. A commutation table can then be stepped once
+    /// per period, from the overflow interrupt, without glitches: a pattern
+    /// written during period `n` is output from period `n + 1`. A pattern
+    /// written twice in the same period only applies its last value.
+    pub fn set_pattern(&mut self, enable_mask: u8, value_mask: u8) {
+        let bits = pattern_bits(enable_mask, value_mask);
+        self.tcc.pattbuf.write(|w| unsafe { w.bits(bits) });
+    }
+
+    /// Return every output to its PWM waveform, from the start of the next
+    /// PWM period
+    pub fn clear_pattern(&mut self) {
+        self.set_pattern(0, 0);
+    }
+
+    /// Invert the output of `channel` (`DRVCTRL.INVENn`), swapping its high
+    /// and low phases
+    ///
+    /// The TCC is briefly disabled while the setting is changed.
+    pub fn set_inverted(&mut self, channel: Channel, inverted: bool) {
+        let mask = inversion_mask(channel);
+        self.tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+        self.tcc.drvctrl.modify(|r, w| unsafe {
+            if inverted {
+                w.bits(r.bits() | mask)
+            } else {
+                w.bits(r.bits() & !mask)
+            }
+        });
+        self.tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+    }
+
+    /// Output a clock at the PWM frequency on `channel`, with a 50% duty
+    /// cycle, inverted if `inverted` is set
+    ///
+    /// The `GCLK_IO` outputs of the clock generators can't be inverted: their
+    /// `OOV` bit only selects the level driven while the generator is off.
+    /// This is the alternative for external chips expecting an inverted
+    /// clock. The clock is an exact 50% duty cycle when the period counts an
+    /// even number of cycles of the TCC clock.
+    pub fn clock_output(&mut self, channel: Channel, inverted: bool) {
+        let top = self.get_max_duty();
+        self.set_duty(channel, half_duty(top));
+        self.set_inverted(channel, inverted);
+    }
+}
+
 impl Pwm for $TYPE {
     type Channel = Channel;
     type Time = Hertz;
@@ -512,3 +586,27 @@ pwm_tcc! {
     Tcc3Pwm: (TCC3, TCC3Pinout, Tcc2Tcc3Clock, apbcmask, tcc3_, TccPwm3Wrapper),
     Tcc4Pwm: (TCC4, TCC4Pinout, Tcc4Clock,     apbdmask, tcc4_, TccPwm4Wrapper),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_register_encoding() {
+        assert_eq!(pattern_bits(0, 0), 0x0000);
+        assert_eq!(pattern_bits(0xFF, 0), 0x00FF);
+        assert_eq!(pattern_bits(0, 0xFF), 0xFF00);
+        // Step 1 of a BLDC commutation: WO[0] high, WO[3] low, others PWM
+        assert_eq!(pattern_bits(0b1001, 0b0001), 0x0109);
+    }
+
+    #[test]
+    fn inverted_clock_output() {
+        assert_eq!(inversion_mask(Channel::_0), 1 << 16);
+        assert_eq!(inversion_mask(Channel::_3), 1 << 19);
+        // A period of 4 cycles is high for 2, the 5 cycles of an odd period
+        // are split 3 to 2
+        assert_eq!(half_duty(3), 2);
+        assert_eq!(half_duty(4), 2);
+    }
+}
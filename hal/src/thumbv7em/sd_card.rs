@@ -0,0 +1,272 @@
+//! # SD card block access
+//!
+//! [`SdCard`] implements the SD card protocol on top of an [`Sdhc`], to read
+//! and write 512-byte blocks.
+//!
+//! [`SdCard::init`] identifies the card and brings it to the transfer state:
+//!
+//! | Command  | Purpose                                                     |
+//! |----------|-------------------------------------------------------------|
+//! | `CMD0`   | Reset the card to the idle state                            |
+//! | `CMD8`   | Check the supply voltage; only version 2.00 cards answer    |
+//! | `ACMD41` | Start the card initialization, until it reports ready       |
+//! | `CMD2`   | Read the card identification (CID)                          |
+//! | `CMD3`   | Get the relative card address (RCA)                         |
+//! | `CMD7`   | Select the card                                             |
+//! | `ACMD6`  | Switch the card to the 4-bit bus                            |
+//! | `CMD16`  | Set the block length of standard capacity cards             |
+//!
+//! Identification runs at 400 kHz, then the card clock is raised to 25 MHz.
+//! Single blocks are then transferred with `CMD17` and `CMD24`, and multiple
+//! blocks with `CMD18` and `CMD25`, through ADMA2. High capacity cards
+//! (SDHC/SDXC) are addressed in blocks, standard capacity cards in bytes.
+//!
+//! ```
+//! let sdhc = Sdhc::sdhc0(&sdhc0_clock, peripherals.SDHC0, &mut mclk);
+//! let mut card = SdCard::new(sdhc);
+//! card.init()?;
+//! let mut block = [0u8; 512];
+//! card.read_block(0, &mut block)?;
+//! ```
+//!
+//! [`SdCard`] also implements [`BlockDevice`], like the SPI-mode driver
+//! [`SdSpi`](crate::sdspi::SdSpi), so application code works with both.
+
+use core::ops::Deref;
+
+use crate::block_device::BlockDevice;
+use crate::sdhc::{self, Adma2Table, BusWidth, Response, Sdhc, BLOCK_SIZE};
+use crate::target_device::sdhc0;
+use crate::time::U32Ext;
+
+/// Argument of `CMD8`: 2.7-3.6 V supply, with the `0xAA` check pattern
+const CMD8_ARG: u32 = 0x1AA;
+
+/// Voltage window of `ACMD41`: 3.2-3.4 V
+const OCR_VOLTAGE_WINDOW: u32 = 0x0030_0000;
+/// OCR bit set by the card once its initialization is complete
+const OCR_READY: u32 = 1 << 31;
+/// OCR bit of high capacity cards, and of the host supporting them
+const OCR_CCS: u32 = 1 << 30;
+
+/// Number of `ACMD41` attempts before giving up, about one second at 400 kHz
+const ACMD41_ATTEMPTS: u32 = 4000;
+
+/// Number of ADMA2 descriptors of a card, for up to 512 blocks per transfer
+const ADMA2_ENTRIES: usize = 4;
+
+/// Error flags of the R1 card status
+const R1_ERRORS: u32 = 0xFDF9_8008;
+
+/// SD card error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Error reported by the host controller
+    Sdhc(sdhc::Error),
+    /// Error reported in the card status
+    Card(u32),
+    /// The card does not support the supply voltage, or is a version 1.x
+    /// card, which is not supported
+    UnsupportedCard,
+    /// The card did not complete its initialization in time
+    InitTimeout,
+    /// The card was not initialized
+    NotInitialized,
+}
+
+impl From<sdhc::Error> for Error {
+    fn from(error: sdhc::Error) -> Self {
+        Error::Sdhc(error)
+    }
+}
+
+/// Argument of `ACMD41`, announcing support for high capacity cards
+pub(crate) fn acmd41_arg() -> u32 {
+    OCR_CCS | OCR_VOLTAGE_WINDOW
+}
+
+/// Argument of the commands addressing the card with `rca`
+pub(crate) fn rca_arg(rca: u16) -> u32 {
+    (rca as u32) << 16
+}
+
+/// Argument of the block commands, for the block `lba`
+pub(crate) fn block_arg(lba: u32, high_capacity: bool) -> u32 {
+    if high_capacity {
+        lba
+    } else {
+        lba * BLOCK_SIZE as u32
+    }
+}
+
+/// Check an R1 card status
+fn check_status(status: u32) -> Result<u32, Error> {
+    if status & R1_ERRORS != 0 {
+        Err(Error::Card(status))
+    } else {
+        Ok(status)
+    }
+}
+
+/// An SD card connected to an [`Sdhc`]
+///
+/// See the [module-level documentation](self) for more details.
+pub struct SdCard<SDHC> {
+    sdhc: Sdhc<SDHC>,
+    rca: Option<u16>,
+    high_capacity: bool,
+    table: Adma2Table<ADMA2_ENTRIES>,
+}
+
+impl<SDHC: Deref<Target = sdhc0::RegisterBlock>> SdCard<SDHC> {
+    /// Wrap an SDHC. The card must then be initialized with
+    /// [`SdCard::init`].
+    pub fn new(sdhc: Sdhc<SDHC>) -> Self {
+        SdCard {
+            sdhc,
+            rca: None,
+            high_capacity: false,
+            table: Adma2Table::new(),
+        }
+    }
+
+    /// Identify and select the card, and switch to a 25 MHz 4-bit bus
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.rca = None;
+        self.sdhc.set_bus_width(BusWidth::OneBit);
+        self.sdhc.set_clock(400.khz());
+
+        self.sdhc.command(0, 0, Response::None)?;
+        match self.sdhc.command(8, CMD8_ARG, Response::R7) {
+            Ok(r7) if r7[0] & 0xFFF == CMD8_ARG => (),
+            Ok(_) | Err(sdhc::Error::CommandTimeout) => return Err(Error::UnsupportedCard),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut attempts = 0;
+        let ocr = loop {
+            self.app_command(0)?;
+            let ocr = self.sdhc.command(41, acmd41_arg(), Response::R3)?[0];
+            if ocr & OCR_READY != 0 {
+                break ocr;
+            }
+            attempts += 1;
+            if attempts == ACMD41_ATTEMPTS {
+                return Err(Error::InitTimeout);
+            }
+        };
+        self.high_capacity = ocr & OCR_CCS != 0;
+
+        self.sdhc.command(2, 0, Response::R2)?;
+        let rca = (self.sdhc.command(3, 0, Response::R6)?[0] >> 16) as u16;
+        check_status(self.sdhc.command(7, rca_arg(rca), Response::R1b)?[0])?;
+        self.rca = Some(rca);
+
+        self.sdhc.set_clock(25.mhz());
+        self.app_command(rca)?;
+        check_status(self.sdhc.command(6, 0b10, Response::R1)?[0])?;
+        self.sdhc.set_bus_width(BusWidth::FourBit);
+        if !self.high_capacity {
+            check_status(self.sdhc.command(16, BLOCK_SIZE as u32, Response::R1)?[0])?;
+        }
+        Ok(())
+    }
+
+    /// Whether the card is a high capacity (SDHC/SDXC) card
+    pub fn is_high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+
+    /// Read the block `lba`
+    pub fn read_block(&mut self, lba: u32, block: &mut [u8; BLOCK_SIZE]) -> Result<(), Error> {
+        self.rca.ok_or(Error::NotInitialized)?;
+        let status = self
+            .sdhc
+            .read_block(17, block_arg(lba, self.high_capacity), block)?;
+        check_status(status).map(|_| ())
+    }
+
+    /// Write the block `lba`
+    pub fn write_block(&mut self, lba: u32, block: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        self.rca.ok_or(Error::NotInitialized)?;
+        let status = self
+            .sdhc
+            .write_block(24, block_arg(lba, self.high_capacity), block)?;
+        check_status(status).map(|_| ())
+    }
+
+    /// Read consecutive blocks starting at `lba`, filling `blocks`
+    ///
+    /// `blocks` must be 4-byte aligned, and hold a whole number of blocks, up
+    /// to 512.
+    pub fn read_blocks(&mut self, lba: u32, blocks: &mut [u8]) -> Result<(), Error> {
+        self.rca.ok_or(Error::NotInitialized)?;
+        let arg = block_arg(lba, self.high_capacity);
+        let status = self.sdhc.read_blocks(&mut self.table, 18, arg, blocks)?;
+        check_status(status).map(|_| ())
+    }
+
+    /// Write consecutive blocks starting at `lba`, from `blocks`
+    ///
+    /// `blocks` must be 4-byte aligned, and hold a whole number of blocks, up
+    /// to 512.
+    pub fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), Error> {
+        self.rca.ok_or(Error::NotInitialized)?;
+        let arg = block_arg(lba, self.high_capacity);
+        let status = self.sdhc.write_blocks(&mut self.table, 25, arg, blocks)?;
+        check_status(status).map(|_| ())
+    }
+
+    /// Return the SDHC
+    pub fn free(self) -> Sdhc<SDHC> {
+        self.sdhc
+    }
+
+    /// Send `CMD55`, announcing an application command
+    fn app_command(&mut self, rca: u16) -> Result<(), Error> {
+        check_status(self.sdhc.command(55, rca_arg(rca), Response::R1)?[0]).map(|_| ())
+    }
+}
+
+impl<SDHC: Deref<Target = sdhc0::RegisterBlock>> BlockDevice for SdCard<SDHC> {
+    type Error = Error;
+
+    fn read_block(&mut self, lba: u32, block: &mut [u8; BLOCK_SIZE]) -> Result<(), Error> {
+        SdCard::read_block(self, lba, block)
+    }
+
+    fn write_block(&mut self, lba: u32, block: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        SdCard::write_block(self, lba, block)
+    }
+
+    fn read_blocks(&mut self, lba: u32, blocks: &mut [u8]) -> Result<(), Error> {
+        SdCard::read_blocks(self, lba, blocks)
+    }
+
+    fn write_blocks(&mut self, lba: u32, blocks: &[u8]) -> Result<(), Error> {
+        SdCard::write_blocks(self, lba, blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_arguments() {
+        assert_eq!(acmd41_arg(), 0x4030_0000);
+        assert_eq!(rca_arg(0xB368), 0xB368_0000);
+        assert_eq!(block_arg(3, true), 3);
+        assert_eq!(block_arg(3, false), 1536);
+    }
+
+    #[test]
+    fn card_status_errors() {
+        // READY_FOR_DATA in the transfer state
+        assert_eq!(check_status(0x0000_0900), Ok(0x0000_0900));
+        // APP_CMD is not an error
+        assert_eq!(check_status(0x0000_0920), Ok(0x0000_0920));
+        // ADDRESS_ERROR
+        assert_eq!(check_status(0x4000_0900), Err(Error::Card(0x4000_0900)));
+    }
+}
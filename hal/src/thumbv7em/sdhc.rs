@@ -0,0 +1,1044 @@
+//! # SD/MMC Host Controller
+//!
+//! The SDHC drives SD cards over the 1-bit or 4-bit SD bus. This module
+//! covers the setup of the controller: card power, the `SDCLK` card clock and
+//! the bus width, as well as sending commands and transferring single
+//! blocks. The card protocol itself is implemented by
+//! [`SdCard`](crate::sd_card::SdCard). The interrupt enables are available
+//! from the PAC registers (`NISIER` and `EISIER`).
+//!
+//! The SDHC pins (`SDCMD`, `SDCK` and `SDDAT0..3`) must be configured for
+//! peripheral function I beforehand.
+//!
+//! ## Card clock
+//!
+//! `SDCLK` is divided from the base clock, i.e. the frequency of the SDHC
+//! generic clock. In divided clock mode, a 10-bit divider `N` gives
+//!
+//! ```text
+//! SDCLK = base / (2 * N)
+//! SDCLK = base              if N = 0
+//! ```
+//!
+//! [`Sdhc::set_clock`] picks the smallest `N` that does not exceed the
+//! requested frequency. `N` is split across `CCR.SDCLKFSEL` (the 8 lower
+//! bits) and `CCR.USDCLKFSEL` (the 2 upper bits).
+//!
+//! Changing the divider follows the sequence of the SD Host Controller
+//! specification:
+//!
+//! 1. Stop `SDCLK` (`CCR.SDCLKEN`)
+//! 2. Write the divider, with the internal clock enabled (`CCR.INTCLKEN`)
+//! 3. Wait for the internal clock to be stable (`CCR.INTCLKS`)
+//! 4. Start `SDCLK` again
+//!
+//! The base clock must be at least as fast as the bus speed, since the
+//! divider can only slow it down. The achievable `SDCLK` frequencies are
+//! `base`, `base / 2`, `base / 4`, `base / 6`..., so a base clock just above
+//! the bus speed loses almost half of it: with a 120 MHz base clock, the
+//! default speed runs at 20 MHz and the high speed at 30 MHz. Base clocks of
+//! 50 or 100 MHz reach both speeds exactly.
+//! [`Sdhc::configure_sdhc0_clock`] checks the generator before configuring
+//! the SDHC clock, and rejects it when `SDCLK` would be slower than half of
+//! the bus speed:
+//!
+//! ```
+//! // 100 MHz from DPLL0, divided from 200 MHz
+//! let gclk2 = clocks
+//!     .configure_gclk_divider_and_source(ClockGenId::GCLK2, 2, ClockSource::DPLL0, false)
+//!     .unwrap();
+//! let mut slow = SharedPclk::new(clocks.slow_32k(&gclk1).unwrap());
+//! let sdhc0_clock =
+//!     Sdhc::configure_sdhc0_clock(&mut clocks, &gclk2, &slow.user(), BusSpeed::High)?;
+//! ```
+//!
+//! The slow clock (`GCLK_SDHC0_SLOW`) is shared with the other SDHC and the
+//! SERCOM slow clocks, and is typically fed from a 32 kHz generator. It is
+//! proven by a user of a [`SharedPclk`](clock::SharedPclk).
+//!
+//! ## Multi-block transfers
+//!
+//! [`Sdhc::read_blocks`] and [`Sdhc::write_blocks`] move many blocks with a
+//! single command, using the ADMA2 engine of the SDHC instead of the CPU.
+//! The engine follows a table of 64-bit descriptors, built by
+//! [`Adma2Table`]:
+//!
+//! | Bits  | Field                                              |
+//! |-------|----------------------------------------------------|
+//! | 0     | `VALID`                                            |
+//! | 1     | `END`, set on the last descriptor                  |
+//! | 2     | `INT`, raises `NISTR.DMAINT` once done             |
+//! | 4..6  | `ACT`, `0b10` to transfer data                     |
+//! | 16..32| Length in bytes, `0` meaning 65536                 |
+//! | 32..64| Address of the data                                |
+//!
+//! With 32-bit addressing, the table and the data must be 4-byte aligned.
+//! Each descriptor moves up to 64 KiB, i.e. 128 blocks.
+//!
+//! ## Card detect and write protect
+//!
+//! Card sockets usually have a card detect switch, and full-size SD sockets a
+//! write protect switch as well. When they are wired to the `SDCD` and `SDWP`
+//! pins of the SDHC, [`Sdhc`] reports their state through the present state
+//! register (`PSR`). Otherwise, wrap the GPIO pins in a [`DetectPins`]. Both
+//! implement [`CardDetect`].
+//!
+//! Mechanical switches bounce for a few milliseconds when a card is inserted
+//! or removed. The SDHC debounces `SDCD` itself, and [`Sdhc::card_present`]
+//! only reports a card once the state is stable (`PSR.CARDSS`). GPIO pins are
+//! not debounced: check the pin again after 10 to 50 ms, or enable the
+//! filter of the EIC when using its interrupts. Either way, give the card
+//! some time to power up after an insertion before initializing it.
+//!
+//! The insertion and removal interrupts (`NISTR.CINS` and `NISTR.CREM`) are
+//! enabled with [`Sdhc::enable_card_interrupts`]. The SDHC interrupt handler
+//! then calls [`Sdhc::handle_card_interrupt`], which hands the event to a
+//! callback:
+//!
+//! ```
+//! sdhc.enable_card_interrupts();
+//!
+//! #[interrupt]
+//! fn SDHC0() {
+//!     sdhc.handle_card_interrupt(|event| match event {
+//!         CardEvent::Inserted => CARD_CHANGED.store(true, Ordering::Release),
+//!         CardEvent::Removed => CARD_PRESENT.store(false, Ordering::Release),
+//!     });
+//! }
+//! ```
+//!
+//! ## Card initialization
+//!
+//! A card must be identified at 400 kHz or less, in 1-bit mode. Once
+//! selected, the clock can be raised to 25 MHz (default speed), or 50 MHz
+//! after switching the card to high speed. The bus width is switched to
+//! 4-bit after `ACMD6`:
+//!
+//! ```
+//! let mut sdhc = Sdhc::sdhc0(&sdhc0_clock, peripherals.SDHC0, &mut mclk);
+//! // Identify the card at 400 kHz: CMD0, CMD8, ACMD41, CMD2, CMD3, CMD7
+//! sdhc.set_clock(25.mhz());
+//! // ACMD6 to switch the card to 4-bit
+//! sdhc.set_bus_width(BusWidth::FourBit);
+//! ```
+
+use core::ops::Deref;
+use core::sync::atomic;
+
+use crate::clock::{self, GClock, GenericClockController, PclkUser, Slow32kClock};
+use crate::hal::digital::v2::InputPin;
+#[cfg(feature = "min-samd51n")]
+use crate::target_device::SDHC1;
+use crate::target_device::{sdhc0, MCLK, SDHC0};
+use crate::time::{Hertz, U32Ext};
+use crate::typelevel::NoneT;
+
+/// Width of the SD data bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusWidth {
+    /// `SDDAT0` only
+    OneBit,
+    /// `SDDAT0` to `SDDAT3`
+    FourBit,
+}
+
+pub use crate::block_device::BLOCK_SIZE;
+
+/// Response expected from a command, as named by the SD specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// No response
+    None,
+    /// Card status
+    R1,
+    /// Card status, followed by a busy signal on `SDDAT0`
+    R1b,
+    /// CID or CSD register, 136 bits
+    R2,
+    /// OCR register, without CRC
+    R3,
+    /// Published RCA
+    R6,
+    /// Card interface condition
+    R7,
+}
+
+/// SDHC error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The card did not respond to a command
+    CommandTimeout,
+    /// The response to a command failed its CRC, end bit or index check
+    CommandResponse,
+    /// The card did not send or accept data in time
+    DataTimeout,
+    /// A data block failed its CRC or end bit check
+    DataCrc,
+    /// The ADMA2 engine failed to fetch a descriptor or to access data
+    Dma,
+    /// The buffer is not a whole number of blocks, is not 4-byte aligned, or
+    /// doesn't fit in the descriptor table
+    InvalidBuffer,
+    /// Another error, with the value of `EISTR`
+    Other(u16),
+}
+
+/// `EISTR` flags of the command line
+const EISTR_CMD_ERRORS: u16 = 0b1110;
+/// `EISTR` flags of the data lines
+const EISTR_DAT_ERRORS: u16 = 0b110_0000;
+
+/// Decode the error flags of `EISTR`
+pub(crate) fn eistr_error(eistr: u16) -> Error {
+    if eistr & 0x1 != 0 {
+        Error::CommandTimeout
+    } else if eistr & EISTR_CMD_ERRORS != 0 {
+        Error::CommandResponse
+    } else if eistr & 0x10 != 0 {
+        Error::DataTimeout
+    } else if eistr & EISTR_DAT_ERRORS != 0 {
+        Error::DataCrc
+    } else if eistr & 0x200 != 0 {
+        Error::Dma
+    } else {
+        Error::Other(eistr)
+    }
+}
+
+/// Encode the `CR` register starting command `index`
+///
+/// `RESPTYP` selects no response, a 136-bit response or a 48-bit response
+/// with or without busy. The CRC and index checks don't apply to every
+/// response type.
+pub(crate) fn cr_bits(index: u8, response: Response, data: bool) -> u16 {
+    const CRC: u16 = 1 << 3;
+    const INDEX: u16 = 1 << 4;
+    let (resptyp, checks) = match response {
+        Response::None => (0, 0),
+        Response::R2 => (1, CRC),
+        Response::R3 => (2, 0),
+        Response::R1 | Response::R6 | Response::R7 => (2, CRC | INDEX),
+        Response::R1b => (3, CRC | INDEX),
+    };
+    ((index as u16 & 0x3F) << 8) | ((data as u16) << 5) | checks | resptyp
+}
+
+/// `NISTR` flags of the card insertion and removal (`CINS` and `CREM`)
+const NISTR_CARD_EVENTS: u16 = 0b1100_0000;
+/// `NISTR` flags cleared before a command. The card events are left to
+/// [`Sdhc::handle_card_interrupt`].
+const NISTR_COMMAND_CLEAR: u16 = !NISTR_CARD_EVENTS;
+
+/// `PSR` flag of an inserted card
+const PSR_CARDINS: u32 = 1 << 16;
+/// `PSR` flag of a stable card detect state
+const PSR_CARDSS: u32 = 1 << 17;
+/// `PSR` level of the write protect pin, cleared when protected
+const PSR_WRPPL: u32 = 1 << 19;
+
+/// Card insertion or removal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardEvent {
+    /// A card was inserted
+    Inserted,
+    /// The card was removed
+    Removed,
+}
+
+/// Whether `PSR` reports an inserted card, once debounced
+pub(crate) fn psr_card_present(psr: u32) -> bool {
+    psr & (PSR_CARDINS | PSR_CARDSS) == PSR_CARDINS | PSR_CARDSS
+}
+
+/// Whether `PSR` reports a write protected card
+pub(crate) fn psr_write_protected(psr: u32) -> bool {
+    psr & PSR_WRPPL == 0
+}
+
+/// Decode the card events of `NISTR`
+///
+/// When a bouncing switch raised both `CINS` and `CREM`, the order is lost,
+/// so the event follows the current state of the card detect.
+pub(crate) fn card_event(nistr: u16, present: bool) -> Option<CardEvent> {
+    match (nistr & NISTR_CARD_EVENTS != 0, present) {
+        (false, _) => None,
+        (true, true) => Some(CardEvent::Inserted),
+        (true, false) => Some(CardEvent::Removed),
+    }
+}
+
+/// Largest number of bytes moved by one ADMA2 descriptor
+const ADMA2_MAX_LEN: usize = 65536;
+
+/// An ADMA2 descriptor, in its in-memory layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Adma2Descriptor {
+    attr: u16,
+    len: u16,
+    addr: u32,
+}
+
+impl Adma2Descriptor {
+    const VALID: u16 = 1 << 0;
+    const END: u16 = 1 << 1;
+    const INT: u16 = 1 << 2;
+    const ACT_TRAN: u16 = 0b10 << 4;
+
+    const EMPTY: Self = Adma2Descriptor {
+        attr: 0,
+        len: 0,
+        addr: 0,
+    };
+
+    /// Descriptor transferring `len` bytes at `addr`, with `len` at most
+    /// 65536
+    fn transfer(addr: u32, len: usize, last: bool) -> Self {
+        let end = if last { Self::END | Self::INT } else { 0 };
+        Adma2Descriptor {
+            attr: Self::VALID | Self::ACT_TRAN | end,
+            // 65536 wraps to 0, as expected by the engine
+            len: len as u16,
+            addr,
+        }
+    }
+}
+
+/// A table of `ENTRIES` ADMA2 descriptors
+///
+/// It must stay in place while a transfer runs, which the borrow taken by
+/// [`Sdhc::read_blocks`] and [`Sdhc::write_blocks`] guarantees.
+#[repr(C, align(4))]
+pub struct Adma2Table<const ENTRIES: usize> {
+    descriptors: [Adma2Descriptor; ENTRIES],
+}
+
+impl<const ENTRIES: usize> Adma2Table<ENTRIES> {
+    /// Largest transfer described by the table, in bytes
+    pub const MAX_LEN: usize = ENTRIES * ADMA2_MAX_LEN;
+
+    /// Create an empty table
+    pub const fn new() -> Self {
+        Adma2Table {
+            descriptors: [Adma2Descriptor::EMPTY; ENTRIES],
+        }
+    }
+
+    /// Describe the transfer of `len` bytes at `addr`, and return the number
+    /// of descriptors used
+    pub(crate) fn build(&mut self, addr: u32, len: usize) -> Result<usize, Error> {
+        if len == 0 || len > Self::MAX_LEN || len % BLOCK_SIZE != 0 || addr % 4 != 0 {
+            return Err(Error::InvalidBuffer);
+        }
+        let count = (len + ADMA2_MAX_LEN - 1) / ADMA2_MAX_LEN;
+        for (i, desc) in self.descriptors[..count].iter_mut().enumerate() {
+            let offset = i * ADMA2_MAX_LEN;
+            let chunk = (len - offset).min(ADMA2_MAX_LEN);
+            *desc = Adma2Descriptor::transfer(addr + offset as u32, chunk, i + 1 == count);
+        }
+        Ok(count)
+    }
+
+    /// Descriptors of the table
+    pub fn descriptors(&self) -> &[Adma2Descriptor; ENTRIES] {
+        &self.descriptors
+    }
+}
+
+impl<const ENTRIES: usize> Default for Adma2Table<ENTRIES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Highest `SDCLK` frequency of the default speed mode. Faster clocks require
+/// the high speed mode of the host.
+const DEFAULT_SPEED_MAX: Hertz = Hertz(25_000_000);
+
+/// Highest `SDCLK` frequency of the high speed mode
+const HIGH_SPEED_MAX: Hertz = Hertz(50_000_000);
+
+/// Speed mode of the SD bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusSpeed {
+    /// Up to 25 MHz
+    Default,
+    /// Up to 50 MHz, once the card is switched to high speed with `CMD6`
+    High,
+}
+
+impl BusSpeed {
+    /// Highest `SDCLK` frequency of the speed mode
+    pub fn max_freq(self) -> Hertz {
+        match self {
+            BusSpeed::Default => DEFAULT_SPEED_MAX,
+            BusSpeed::High => HIGH_SPEED_MAX,
+        }
+    }
+}
+
+/// Error returned when configuring the SDHC clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockError {
+    /// The base clock can't drive `SDCLK` at the bus speed. Holds the
+    /// fastest `SDCLK` frequency within the bus speed.
+    TooSlow(Hertz),
+    /// The SDHC clock is already configured
+    InUse,
+}
+
+/// Check that the `base` clock drives `SDCLK` at `speed`, i.e. at least at
+/// half of its maximum frequency, and return the resulting frequency
+pub fn check_base_clock(base: Hertz, speed: BusSpeed) -> Result<Hertz, ClockError> {
+    let max = speed.max_freq();
+    let freq = sdclk_freq(base, sdclk_divider(base, max));
+    if freq.0 < max.0 / 2 {
+        Err(ClockError::TooSlow(freq))
+    } else {
+        Ok(freq)
+    }
+}
+
+/// Largest value of the 10-bit `SDCLK` divider
+const MAX_DIVIDER: u32 = 0x3FF;
+
+/// Return the smallest `SDCLK` divider `N` keeping the card clock at or below
+/// `freq`, given the `base` clock frequency
+pub(crate) fn sdclk_divider(base: Hertz, freq: Hertz) -> u16 {
+    if freq.0 >= base.0 {
+        return 0;
+    }
+    let double = 2 * freq.0.max(1) as u64;
+    let n = (base.0 as u64 + double - 1) / double;
+    n.min(MAX_DIVIDER as u64) as u16
+}
+
+/// Return the `SDCLK` frequency obtained with the divider `n`
+pub(crate) fn sdclk_freq(base: Hertz, n: u16) -> Hertz {
+    match n {
+        0 => base,
+        n => Hertz(base.0 / (2 * n as u32)),
+    }
+}
+
+/// Encode the divider `n` into the `CCR.SDCLKFSEL` (bits 8 to 15) and
+/// `CCR.USDCLKFSEL` (bits 6 and 7) fields
+pub(crate) fn ccr_divider_bits(n: u16) -> u16 {
+    ((n & 0xFF) << 8) | ((n >> 8 & 0x3) << 6)
+}
+
+/// An SD/MMC host controller
+///
+/// See the [module-level documentation](self) for more details.
+pub struct Sdhc<SDHC> {
+    sdhc: SDHC,
+    base: Hertz,
+    freq: Hertz,
+}
+
+impl<SDHC: Deref<Target = sdhc0::RegisterBlock>> Sdhc<SDHC> {
+    /// Reset the controller, power the card at 3.3 V and start `SDCLK` at
+    /// 400 kHz or less, in 1-bit mode
+    ///
+    /// `base` is the frequency of the SDHC generic clock.
+    pub fn new(base: Hertz, sdhc: SDHC) -> Self {
+        sdhc.srr.write(|w| w.swrstall().set_bit());
+        while sdhc.srr.read().swrstall().bit_is_set() {}
+
+        sdhc.pcr.write(|w| w.sdbvsel()._3v3().sdbpwr().on());
+        // Report every status flag, and use the longest data timeout
+        sdhc.nister().write(|w| unsafe { w.bits(0x01FF) });
+        sdhc.eister().write(|w| unsafe { w.bits(0x03FF) });
+        sdhc.tcr.write(|w| unsafe { w.dtcval().bits(0xE) });
+
+        let mut sdhc = Sdhc {
+            sdhc,
+            base,
+            freq: Hertz(0),
+        };
+        sdhc.set_clock(400.khz());
+        sdhc.set_bus_width(BusWidth::OneBit);
+        sdhc
+    }
+
+    /// Set the `SDCLK` frequency to `freq` or the closest lower frequency,
+    /// and return the resulting frequency
+    ///
+    /// The high speed mode of the host (`HC1R.HSEN`) is enabled above
+    /// 25 MHz. The card must be switched to high speed first.
+    pub fn set_clock(&mut self, freq: impl Into<Hertz>) -> Hertz {
+        let n = sdclk_divider(self.base, freq.into());
+        self.freq = sdclk_freq(self.base, n);
+
+        self.sdhc.ccr.modify(|_, w| w.sdclken().clear_bit());
+        // CLKGSEL cleared selects the divided clock mode
+        self.sdhc
+            .ccr
+            .write(|w| unsafe { w.bits(ccr_divider_bits(n)).intclken().set_bit() });
+        while self.sdhc.ccr.read().intclks().bit_is_clear() {}
+
+        let high_speed = self.freq.0 > DEFAULT_SPEED_MAX.0;
+        self.sdhc.hc1r().modify(|_, w| w.hsen().bit(high_speed));
+        self.sdhc.ccr.modify(|_, w| w.sdclken().set_bit());
+        self.freq
+    }
+
+    /// Return the `SDCLK` frequency
+    pub fn clock(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Select the width of the data bus
+    ///
+    /// The card must be switched to the same width, with `ACMD6`.
+    pub fn set_bus_width(&mut self, width: BusWidth) {
+        self.sdhc.hc1r().modify(|_, w| match width {
+            BusWidth::OneBit => w.dw()._1bit(),
+            BusWidth::FourBit => w.dw()._4bit(),
+        });
+    }
+
+    /// Return the width of the data bus
+    pub fn bus_width(&self) -> BusWidth {
+        if self.sdhc.hc1r().read().dw().bit_is_set() {
+            BusWidth::FourBit
+        } else {
+            BusWidth::OneBit
+        }
+    }
+
+    /// Send command `index` with the argument `arg`, and wait for its
+    /// response
+    ///
+    /// The 48-bit responses are returned in the first word, without the
+    /// index and CRC. The 136-bit responses are returned as bits 8 to 127,
+    /// least significant word first.
+    pub fn command(&mut self, index: u8, arg: u32, response: Response) -> Result<[u32; 4], Error> {
+        self.start_command(index, arg, response, false)?;
+        if response == Response::R1b {
+            self.wait_transfer()?;
+        }
+        Ok(self.response())
+    }
+
+    /// Send command `index`, which reads one block, into `block`
+    ///
+    /// Return the card status of the R1 response.
+    pub fn read_block(
+        &mut self,
+        index: u8,
+        arg: u32,
+        block: &mut [u8; BLOCK_SIZE],
+    ) -> Result<u32, Error> {
+        self.setup_block(true);
+        self.start_command(index, arg, Response::R1, true)?;
+        let status = self.response()[0];
+
+        self.wait_status(1 << 5)?;
+        for chunk in block.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&self.sdhc.bdpr.read().bits().to_le_bytes());
+        }
+        self.wait_transfer()?;
+        Ok(status)
+    }
+
+    /// Send command `index`, which writes one block, from `block`
+    ///
+    /// Return once the card is done programming the block, with the card
+    /// status of the R1 response.
+    pub fn write_block(
+        &mut self,
+        index: u8,
+        arg: u32,
+        block: &[u8; BLOCK_SIZE],
+    ) -> Result<u32, Error> {
+        self.setup_block(false);
+        self.start_command(index, arg, Response::R1, true)?;
+        let status = self.response()[0];
+
+        self.wait_status(1 << 4)?;
+        for chunk in block.chunks_exact(4) {
+            let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.sdhc.bdpr.write(|w| unsafe { w.bits(word) });
+        }
+        // Transfer complete includes the busy signal of the programming
+        self.wait_transfer()?;
+        Ok(status)
+    }
+
+    /// Send command `index`, which reads multiple blocks, into `blocks`
+    /// using ADMA2
+    ///
+    /// `CMD12` is sent automatically once all blocks are read. Return the
+    /// card status of the R1 response.
+    pub fn read_blocks<const ENTRIES: usize>(
+        &mut self,
+        table: &mut Adma2Table<ENTRIES>,
+        index: u8,
+        arg: u32,
+        blocks: &mut [u8],
+    ) -> Result<u32, Error> {
+        table.build(blocks.as_mut_ptr() as u32, blocks.len())?;
+        self.setup_adma(table, blocks.len(), true);
+        self.adma_transfer(index, arg)
+    }
+
+    /// Send command `index`, which writes multiple blocks, from `blocks`
+    /// using ADMA2
+    ///
+    /// `CMD12` is sent automatically once all blocks are written, and this
+    /// returns once the card is done programming. Return the card status of
+    /// the R1 response.
+    pub fn write_blocks<const ENTRIES: usize>(
+        &mut self,
+        table: &mut Adma2Table<ENTRIES>,
+        index: u8,
+        arg: u32,
+        blocks: &[u8],
+    ) -> Result<u32, Error> {
+        table.build(blocks.as_ptr() as u32, blocks.len())?;
+        self.setup_adma(table, blocks.len(), false);
+        self.adma_transfer(index, arg)
+    }
+
+    /// Enable the card insertion and removal interrupts
+    ///
+    /// See [`Sdhc::handle_card_interrupt`].
+    pub fn enable_card_interrupts(&mut self) {
+        self.sdhc
+            .nistr()
+            .write(|w| unsafe { w.bits(NISTR_CARD_EVENTS) });
+        self.sdhc
+            .nisier()
+            .modify(|_, w| w.cins().set_bit().crem().set_bit());
+    }
+
+    /// Disable the card insertion and removal interrupts
+    pub fn disable_card_interrupts(&mut self) {
+        self.sdhc
+            .nisier()
+            .modify(|_, w| w.cins().clear_bit().crem().clear_bit());
+    }
+
+    /// Clear a pending card insertion or removal, and call `callback` with
+    /// the event
+    ///
+    /// Call this from the SDHC interrupt handler. Returns the event, or `None`
+    /// if no card event was pending.
+    pub fn handle_card_interrupt(&mut self, callback: impl FnOnce(CardEvent)) -> Option<CardEvent> {
+        let nistr = self.sdhc.nistr().read().bits();
+        self.sdhc
+            .nistr()
+            .write(|w| unsafe { w.bits(nistr & NISTR_CARD_EVENTS) });
+        let present = self.sdhc.psr.read().cardins().bit_is_set();
+        let event = card_event(nistr, present);
+        if let Some(event) = event {
+            callback(event);
+        }
+        event
+    }
+
+    /// Stop `SDCLK`, power off the card and return the controller
+    pub fn free(self) -> SDHC {
+        self.sdhc
+            .ccr
+            .modify(|_, w| w.sdclken().clear_bit().intclken().clear_bit());
+        self.sdhc.pcr.write(|w| w.sdbpwr().off());
+        self.sdhc
+    }
+}
+
+impl<SDHC: Deref<Target = sdhc0::RegisterBlock>> Sdhc<SDHC> {
+    fn setup_block(&mut self, read: bool) {
+        self.sdhc
+            .bsr
+            .write(|w| unsafe { w.bits(BLOCK_SIZE as u16) });
+        self.sdhc.bcr.write(|w| unsafe { w.bits(1) });
+        // Single block, without DMA or auto command
+        self.sdhc
+            .tmr
+            .write(|w| unsafe { w.bits((read as u16) << 4) });
+    }
+
+    fn setup_adma<const ENTRIES: usize>(
+        &mut self,
+        table: &Adma2Table<ENTRIES>,
+        len: usize,
+        read: bool,
+    ) {
+        self.sdhc.hc1r().modify(|_, w| w.dmasel()._32bit());
+        self.sdhc.asar[0].write(|w| unsafe { w.admasa().bits(table as *const _ as u32) });
+        self.sdhc
+            .bsr
+            .write(|w| unsafe { w.bits(BLOCK_SIZE as u16) });
+        self.sdhc
+            .bcr
+            .write(|w| unsafe { w.bits((len / BLOCK_SIZE) as u16) });
+        // DMAEN, BCEN, auto CMD12 and MSBSEL
+        self.sdhc
+            .tmr
+            .write(|w| unsafe { w.bits(0b10_0111 | (read as u16) << 4) });
+    }
+
+    fn adma_transfer(&mut self, index: u8, arg: u32) -> Result<u32, Error> {
+        // The descriptors must be in memory before the engine fetches them
+        atomic::fence(atomic::Ordering::Release);
+        self.start_command(index, arg, Response::R1, true)?;
+        let status = self.response()[0];
+        // DMAINT is raised by the last descriptor, and transfer complete
+        // once the auto CMD12 and the busy signal are done
+        let result = self.wait_status(1 << 3).and_then(|_| self.wait_transfer());
+        atomic::fence(atomic::Ordering::Acquire);
+        result.map(|_| status)
+    }
+
+    fn start_command(
+        &mut self,
+        index: u8,
+        arg: u32,
+        response: Response,
+        data: bool,
+    ) -> Result<(), Error> {
+        while self.sdhc.psr.read().cmdinhc().bit_is_set() {}
+        if data || response == Response::R1b {
+            while self.sdhc.psr.read().cmdinhd().bit_is_set() {}
+        }
+        self.sdhc
+            .nistr()
+            .write(|w| unsafe { w.bits(NISTR_COMMAND_CLEAR) });
+        self.sdhc.eistr().write(|w| unsafe { w.bits(0xFFFF) });
+
+        self.sdhc.arg1r.write(|w| unsafe { w.bits(arg) });
+        self.sdhc
+            .cr
+            .write(|w| unsafe { w.bits(cr_bits(index, response, data)) });
+        self.wait_status(1 << 0)
+    }
+
+    /// Wait for transfer complete, including the busy signal
+    fn wait_transfer(&mut self) -> Result<(), Error> {
+        self.wait_status(1 << 1)
+    }
+
+    /// Wait for the `NISTR` flag `flag`, and clear it. On error, reset the
+    /// command and data lines.
+    fn wait_status(&mut self, flag: u16) -> Result<(), Error> {
+        loop {
+            let nistr = self.sdhc.nistr().read().bits();
+            if nistr & (1 << 15) != 0 {
+                let eistr = self.sdhc.eistr().read().bits();
+                self.sdhc.eistr().write(|w| unsafe { w.bits(eistr) });
+                self.sdhc
+                    .srr
+                    .write(|w| w.swrstcmd().set_bit().swrstdat().set_bit());
+                while self.sdhc.srr.read().bits() & 0b110 != 0 {}
+                return Err(eistr_error(eistr));
+            }
+            if nistr & flag != 0 {
+                self.sdhc.nistr().write(|w| unsafe { w.bits(flag) });
+                return Ok(());
+            }
+        }
+    }
+
+    fn response(&self) -> [u32; 4] {
+        let rr = &self.sdhc.rr;
+        [
+            rr[0].read().bits(),
+            rr[1].read().bits(),
+            rr[2].read().bits(),
+            rr[3].read().bits(),
+        ]
+    }
+}
+
+/// Card detect and write protect status
+///
+/// See the [module-level documentation](self) for more details.
+pub trait CardDetect {
+    /// Whether a card is inserted
+    fn card_present(&self) -> bool;
+
+    /// Whether the write protect switch of the card is set
+    fn write_protected(&self) -> bool;
+}
+
+impl<SDHC: Deref<Target = sdhc0::RegisterBlock>> CardDetect for Sdhc<SDHC> {
+    /// Read `PSR.CARDINS`, once the card detect state is stable. `SDCD` must
+    /// be configured for peripheral function I.
+    fn card_present(&self) -> bool {
+        psr_card_present(self.sdhc.psr.read().bits())
+    }
+
+    /// Read `PSR.WRPPL`. `SDWP` must be configured for peripheral function I.
+    fn write_protected(&self) -> bool {
+        psr_write_protected(self.sdhc.psr.read().bits())
+    }
+}
+
+/// Optional write protect pin of [`DetectPins`]
+///
+/// This trait is implemented for every [`InputPin`], and for [`NoneT`],
+/// which is never write protected.
+pub trait WriteProtectPin {
+    /// Whether the write protect switch is set
+    fn is_protected(&self) -> bool;
+}
+
+impl<P: InputPin> WriteProtectPin for P {
+    /// The pin is high when protected, like `SDWP`
+    fn is_protected(&self) -> bool {
+        self.is_high().unwrap_or(false)
+    }
+}
+
+impl WriteProtectPin for NoneT {
+    fn is_protected(&self) -> bool {
+        false
+    }
+}
+
+/// Card detect and write protect switches wired to GPIO pins
+///
+/// The card detect pin is low when a card is inserted, and the write protect
+/// pin high when the card is protected, like `SDCD` and `SDWP`. Both usually
+/// need a pull-up. The pins are not debounced, see the
+/// [module-level documentation](self).
+pub struct DetectPins<CD, WP = NoneT> {
+    cd: CD,
+    wp: WP,
+}
+
+impl<CD: InputPin> DetectPins<CD> {
+    /// Use a card detect pin only, e.g. for a microSD socket
+    pub fn new(cd: CD) -> Self {
+        DetectPins { cd, wp: NoneT }
+    }
+}
+
+impl<CD: InputPin, WP> DetectPins<CD, WP> {
+    /// Add a write protect pin
+    pub fn with_write_protect<P: InputPin>(self, wp: P) -> DetectPins<CD, P> {
+        DetectPins { cd: self.cd, wp }
+    }
+
+    /// Return the pins
+    pub fn free(self) -> (CD, WP) {
+        (self.cd, self.wp)
+    }
+}
+
+impl<CD: InputPin, WP: WriteProtectPin> CardDetect for DetectPins<CD, WP> {
+    fn card_present(&self) -> bool {
+        self.cd.is_low().unwrap_or(false)
+    }
+
+    fn write_protected(&self) -> bool {
+        self.wp.is_protected()
+    }
+}
+
+macro_rules! sdhc {
+    ($($SDHC:ident: ($ctor:ident, $configure:ident, $clock:ident, $ahbbit:ident),)+) => {
+        $(
+impl Sdhc<$SDHC> {
+    /// Enable the controller in `MCLK` and set it up, see [`Sdhc::new`]
+    pub fn $ctor(clock: &clock::$clock, sdhc: $SDHC, mclk: &mut MCLK) -> Self {
+        mclk.ahbmask.modify(|_, w| w.$ahbbit().set_bit());
+        Self::new(clock.freq(), sdhc)
+    }
+
+    /// Feed the SDHC clock from `generator`, once checked with
+    /// [`check_base_clock`] for `speed`
+    ///
+    /// The user of the [`Slow32kClock`] proves that the shared slow clock is
+    /// configured. Nothing is configured on error.
+    pub fn $configure(
+        clocks: &mut GenericClockController,
+        generator: &GClock,
+        _slow: &PclkUser<Slow32kClock>,
+        speed: BusSpeed,
+    ) -> Result<clock::$clock, ClockError> {
+        check_base_clock(clocks.gclk_freq(generator), speed)?;
+        clocks.$ctor(generator).ok_or(ClockError::InUse)
+    }
+}
+        )+
+    }
+}
+
+sdhc! {
+    SDHC0: (sdhc0, configure_sdhc0_clock, Sdhc0Clock, sdhc0_),
+}
+
+#[cfg(feature = "min-samd51n")]
+sdhc! {
+    SDHC1: (sdhc1, configure_sdhc1_clock, Sdhc1Clock, sdhc1_),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divider_encoding() {
+        let base = Hertz(120_000_000);
+
+        // Identification: 120 MHz / (2 * 150) = 400 kHz
+        let n = sdclk_divider(base, 400.khz().into());
+        assert_eq!(n, 150);
+        assert_eq!(sdclk_freq(base, n), Hertz(400_000));
+        assert_eq!(ccr_divider_bits(n), 0x9600);
+
+        // Default speed: 120 MHz / (2 * 3) = 20 MHz, the closest below 25 MHz
+        let n = sdclk_divider(base, 25.mhz().into());
+        assert_eq!(n, 3);
+        assert_eq!(sdclk_freq(base, n), Hertz(20_000_000));
+        assert_eq!(ccr_divider_bits(n), 0x0300);
+
+        // The upper bits of the divider go to USDCLKFSEL
+        let n = sdclk_divider(base, 100.khz().into());
+        assert_eq!(n, 600);
+        assert_eq!(ccr_divider_bits(n), 0x5880);
+    }
+
+    #[test]
+    fn command_encoding() {
+        // CMD0, no response
+        assert_eq!(cr_bits(0, Response::None, false), 0x0000);
+        // CMD2, 136-bit response with CRC check
+        assert_eq!(cr_bits(2, Response::R2, false), 0x0209);
+        // ACMD41, 48-bit response without checks
+        assert_eq!(cr_bits(41, Response::R3, false), 0x2902);
+        // CMD17, 48-bit response with data
+        assert_eq!(cr_bits(17, Response::R1, true), 0x113A);
+        // CMD7, 48-bit response with busy
+        assert_eq!(cr_bits(7, Response::R1b, false), 0x071B);
+    }
+
+    #[test]
+    fn adma2_table_for_multi_block_transfer() {
+        let mut table = Adma2Table::<4>::new();
+
+        // 8 blocks fit in a single descriptor
+        assert_eq!(table.build(0x2000_0000, 8 * BLOCK_SIZE), Ok(1));
+        assert_eq!(
+            table.descriptors()[0],
+            Adma2Descriptor {
+                attr: 0b10_0111,
+                len: 4096,
+                addr: 0x2000_0000,
+            }
+        );
+
+        // 300 blocks take two full descriptors and a partial one
+        assert_eq!(table.build(0x2000_1000, 300 * BLOCK_SIZE), Ok(3));
+        let d = table.descriptors();
+        assert_eq!(
+            (d[0].attr, d[0].len, d[0].addr),
+            (0b10_0001, 0, 0x2000_1000)
+        );
+        assert_eq!(
+            (d[1].attr, d[1].len, d[1].addr),
+            (0b10_0001, 0, 0x2001_1000)
+        );
+        assert_eq!(
+            (d[2].attr, d[2].len, d[2].addr),
+            (0b10_0111, 22528, 0x2002_1000)
+        );
+
+        // The in-memory layout is fixed at 64 bits per descriptor
+        assert_eq!(core::mem::size_of::<Adma2Descriptor>(), 8);
+    }
+
+    #[test]
+    fn adma2_table_rejects_invalid_buffers() {
+        let mut table = Adma2Table::<1>::new();
+        assert_eq!(table.build(0x2000_0000, 0), Err(Error::InvalidBuffer));
+        assert_eq!(table.build(0x2000_0000, 100), Err(Error::InvalidBuffer));
+        assert_eq!(table.build(0x2000_0002, 512), Err(Error::InvalidBuffer));
+        assert_eq!(
+            table.build(0x2000_0000, 129 * BLOCK_SIZE),
+            Err(Error::InvalidBuffer)
+        );
+        assert_eq!(table.build(0x2000_0000, 128 * BLOCK_SIZE), Ok(1));
+    }
+
+    #[test]
+    fn errors_from_status() {
+        assert_eq!(eistr_error(0x0001), Error::CommandTimeout);
+        assert_eq!(eistr_error(0x0003), Error::CommandTimeout);
+        assert_eq!(eistr_error(0x0002), Error::CommandResponse);
+        assert_eq!(eistr_error(0x0010), Error::DataTimeout);
+        assert_eq!(eistr_error(0x0020), Error::DataCrc);
+        assert_eq!(eistr_error(0x0200), Error::Dma);
+        assert_eq!(eistr_error(0x0080), Error::Other(0x0080));
+    }
+
+    #[test]
+    fn card_detect_from_present_state() {
+        // Inserted, stable and write enabled
+        assert!(psr_card_present(0x000F_0000));
+        assert!(!psr_write_protected(0x000F_0000));
+        // Still bouncing
+        assert!(!psr_card_present(0x0005_0000));
+        // Removed, write protected
+        assert!(!psr_card_present(0x0002_0000));
+        assert!(psr_write_protected(0x0002_0000));
+    }
+
+    #[test]
+    fn card_interrupts() {
+        // CINS and CREM, in NISIER as in NISTR
+        assert_eq!(NISTR_CARD_EVENTS, (1 << 6) | (1 << 7));
+        // Commands leave pending card events alone
+        assert_eq!(NISTR_COMMAND_CLEAR & NISTR_CARD_EVENTS, 0);
+        assert_eq!(NISTR_COMMAND_CLEAR & 0b11, 0b11);
+
+        assert_eq!(card_event(0x0001, true), None);
+        assert_eq!(card_event(0x0040, true), Some(CardEvent::Inserted));
+        assert_eq!(card_event(0x0080, false), Some(CardEvent::Removed));
+        // Both while bouncing: follow the current state
+        assert_eq!(card_event(0x00C0, false), Some(CardEvent::Removed));
+    }
+
+    #[test]
+    fn base_clock_for_bus_speed() {
+        assert_eq!(
+            check_base_clock(Hertz(100_000_000), BusSpeed::High),
+            Ok(Hertz(50_000_000))
+        );
+        assert_eq!(
+            check_base_clock(Hertz(120_000_000), BusSpeed::Default),
+            Ok(Hertz(20_000_000))
+        );
+        assert_eq!(
+            check_base_clock(Hertz(48_000_000), BusSpeed::Default),
+            Ok(Hertz(24_000_000))
+        );
+        // The divider can't speed up a 12 MHz generator to 25 MHz
+        assert_eq!(
+            check_base_clock(Hertz(12_000_000), BusSpeed::Default),
+            Err(ClockError::TooSlow(Hertz(12_000_000)))
+        );
+        // The high speed mode runs at the full 48 MHz of the DFLL
+        assert_eq!(
+            check_base_clock(Hertz(48_000_000), BusSpeed::High),
+            Ok(Hertz(48_000_000))
+        );
+        assert_eq!(
+            check_base_clock(Hertz(20_000_000), BusSpeed::High),
+            Err(ClockError::TooSlow(Hertz(20_000_000)))
+        );
+    }
+
+    #[test]
+    fn divider_limits() {
+        let base = Hertz(48_000_000);
+        assert_eq!(sdclk_divider(base, 50.mhz().into()), 0);
+        assert_eq!(sdclk_freq(base, 0), base);
+        assert_eq!(sdclk_divider(base, 24.mhz().into()), 1);
+        assert_eq!(sdclk_divider(base, Hertz(1)), 0x3FF);
+        assert_eq!(ccr_divider_bits(0x3FF), 0xFFC0);
+    }
+}
@@ -11,7 +11,13 @@ pub mod i2c;
 pub mod pad_map;
 pub mod spi;
 pub mod uart;
+#[cfg(feature = "unproven")]
+pub mod uart_autobaud;
+#[cfg(all(feature = "unproven", feature = "dma"))]
+pub mod uart_dma;
 
 pub use self::i2c::*;
 pub use self::spi::*;
 pub use self::uart::*;
+#[cfg(feature = "unproven")]
+pub use self::uart_autobaud::{AutoBaud, AutoBaudError};
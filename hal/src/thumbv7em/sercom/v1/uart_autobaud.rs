@@ -0,0 +1,296 @@
+//! # UART baud rate detection
+//!
+//! A device connected to a peer of unknown baud rate, from 9600 to 115200
+//! baud, can measure it on the first characters received. [`AutoBaud::detect`]
+//! temporarily routes the RX pin to the EIC (peripheral function A), and
+//! timestamps its edges with a [`TccCapture`] channel. The pin is restored to
+//! its previous mode afterwards, typically before turning it into a SERCOM pad,
+//! and the detected rate can be passed to the UART constructor.
+//!
+//! As for the [`TccCapture`], the edges reach the TCC through EVSYS, which
+//! this HAL does not abstract yet:
+//!
+//! - Enable event generation for the RX pin in the EIC (`EVCTRL.EXTINTEO`),
+//!   detecting both edges
+//! - Configure an EVSYS channel with the EIC as its generator
+//! - Select that channel in the `USER` register of the TCC's `MCx` event user
+//!
+//! ## Measurement
+//!
+//! The shortest interval between two edges is one bit time, as long as the
+//! peer sends a character with an isolated bit. `0x55` (`'U'`) is the best
+//! training character, with ten alternating bits, but most text works. The
+//! intervals are recorded until [`AutoBaud`] is full, or until the timeout
+//! expires, and then:
+//!
+//! - Intervals shorter than half a bit at 115200 baud are glitches, and are
+//!   discarded
+//! - The bit time is the shortest interval matched by at least two others,
+//!   within 1/16. A glitch splitting a longer interval doesn't yield three
+//!   matching pieces.
+//! - The bit time is refined by averaging every interval up to a character
+//!   long, divided by its number of bits
+//! - The rate is snapped to the closest [standard rate](STANDARD_RATES) within
+//!   1/16
+//!
+//! The edges are polled, so interrupts delaying the polling loop by more than
+//! a bit time can merge intervals, which only loses them for the statistics.
+//!
+//! ```
+//! // Edges of the RX pin (EXTINT1) captured by TCC0 channel 0
+//! let mut capture = TccCapture::tcc0(&tcc0_clock, peripherals.TCC0, &mut mclk, 0b1);
+//! let rx: Pin<PB17, _> = pins.d0.into();
+//! let (rx, baud) = AutoBaud::detect(rx, &mut capture, 0, &mut timer, 5.s());
+//! let uart = UART5::new(&uart_clk, baud?, sercom5, &mut mclk, (Pin::from(rx).into_pad(&mut port), tx));
+//! ```
+
+use crate::gpio::v2::{AlternateA, Pin, PinId, PinMode};
+use crate::hal::timer::CountDown;
+use crate::tcc_capture::{CaptureTcc, TccCapture};
+use crate::time::{Hertz, Nanoseconds};
+
+/// Baud rates that detected rates are snapped to
+pub const STANDARD_RATES: [u32; 5] = [9600, 19_200, 38_400, 57_600, 115_200];
+
+/// Number of intervals between edges recorded for a detection
+const INTERVALS: usize = 16;
+
+/// Two durations match if they differ by at most `1 / TOLERANCE`
+const TOLERANCE: u32 = 16;
+
+/// Number of matching intervals needed to trust the bit time
+const CONFIRMATIONS: usize = 3;
+
+/// Longest interval used to refine the bit time, in bits: a whole character
+const MAX_RUN: u32 = 10;
+
+/// Errors of the baud rate detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoBaudError {
+    /// Not enough edges were seen before the timeout
+    Timeout,
+    /// The recorded intervals don't agree on a bit time, e.g. because of
+    /// noise on the line
+    Inconsistent,
+    /// The detected rate is far outside of 9600 to 115200 baud. Holds the
+    /// detected rate.
+    OutOfRange(Hertz),
+}
+
+/// Whether `value` matches `reference` within the tolerance
+#[inline]
+fn matches(value: u32, reference: u32) -> bool {
+    let diff = value.max(reference) - value.min(reference);
+    diff as u64 * TOLERANCE as u64 <= reference as u64
+}
+
+/// Baud rate detector, recording the intervals between the edges of the RX
+/// line
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct AutoBaud {
+    intervals: [u32; INTERVALS],
+    len: usize,
+}
+
+impl AutoBaud {
+    /// Create a detector with no intervals recorded
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the interval between two edges, in counter ticks, unless the
+    /// detector is full
+    #[inline]
+    pub fn push(&mut self, ticks: u32) {
+        if let Some(interval) = self.intervals.get_mut(self.len) {
+            *interval = ticks;
+            self.len += 1;
+        }
+    }
+
+    /// Whether enough intervals are recorded for a detection
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len == INTERVALS
+    }
+
+    /// Bit time, in counter ticks, ignoring intervals shorter than
+    /// `min_ticks`
+    fn bit_ticks(&self, min_ticks: u32) -> Option<u32> {
+        let intervals = &self.intervals[..self.len];
+        let valid = || {
+            intervals
+                .iter()
+                .copied()
+                .filter(|&ticks| ticks >= min_ticks)
+        };
+        let shortest = valid()
+            .filter(|&candidate| {
+                valid().filter(|&ticks| matches(ticks, candidate)).count() >= CONFIRMATIONS
+            })
+            .min()?;
+
+        let (mut ticks, mut bits) = (0u64, 0u64);
+        for interval in valid() {
+            let run = (interval + shortest / 2) / shortest;
+            let error = (interval as i64 - (run * shortest) as i64).abs();
+            if run <= MAX_RUN && error <= (shortest / 4) as i64 {
+                ticks += interval as u64;
+                bits += run as u64;
+            }
+        }
+        Some(((ticks + bits / 2) / bits) as u32)
+    }
+
+    /// Baud rate of the recorded intervals, for a counter running at
+    /// `counter_freq`
+    pub fn baud(&self, counter_freq: impl Into<Hertz>) -> Result<Hertz, AutoBaudError> {
+        let freq = counter_freq.into().0;
+        let max = STANDARD_RATES[STANDARD_RATES.len() - 1];
+        let min_ticks = freq / (2 * max);
+        let bit_ticks = self
+            .bit_ticks(min_ticks.max(1))
+            .ok_or(AutoBaudError::Inconsistent)?;
+        let baud = (freq + bit_ticks / 2) / bit_ticks;
+        if let Some(&rate) = STANDARD_RATES.iter().find(|&&rate| matches(baud, rate)) {
+            return Ok(Hertz(rate));
+        }
+        let min = STANDARD_RATES[0];
+        if baud < min - min / TOLERANCE || baud > max + max / TOLERANCE {
+            Err(AutoBaudError::OutOfRange(Hertz(baud)))
+        } else {
+            Ok(Hertz(baud))
+        }
+    }
+
+    /// Detect the baud rate on the `rx` pin, whose edges are captured by
+    /// `channel` of `capture`, giving up after `timeout`
+    ///
+    /// The pin is switched to the EIC for the detection, and returned in its
+    /// previous mode. Captures pending from before the detection are
+    /// discarded.
+    pub fn detect<I, M, TCC, T>(
+        rx: Pin<I, M>,
+        capture: &mut TccCapture<TCC>,
+        channel: usize,
+        timer: &mut T,
+        timeout: impl Into<Nanoseconds>,
+    ) -> (Pin<I, M>, Result<Hertz, AutoBaudError>)
+    where
+        I: PinId,
+        M: PinMode,
+        TCC: CaptureTcc,
+        T: CountDown<Time = Nanoseconds>,
+    {
+        let rx = rx.into_mode::<AlternateA>();
+        let mut detector = AutoBaud::new();
+        let mut last_edge: Option<u64> = None;
+        capture.read(channel);
+        timer.start(timeout);
+
+        let result = loop {
+            capture.poll_overflow();
+            if let Some(cc) = capture.read(channel) {
+                let edge = capture.timestamp(cc);
+                if let Some(last_edge) = last_edge {
+                    let ticks = (edge - last_edge).min(u32::MAX as u64);
+                    detector.push(ticks as u32);
+                }
+                last_edge = Some(edge);
+                if detector.is_full() {
+                    break detector.baud(capture.freq());
+                }
+            }
+            if timer.wait().is_ok() {
+                break detector.baud(capture.freq()).map_err(|e| match e {
+                    AutoBaudError::Inconsistent => AutoBaudError::Timeout,
+                    e => e,
+                });
+            }
+        };
+        (rx.into_mode::<M>(), result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Record the runs of equal bits of a frame, in bit times
+    fn record(bit_ticks: f64, runs: &[u32]) -> AutoBaud {
+        let mut detector = AutoBaud::new();
+        let mut edge = 0.0;
+        for &run in runs {
+            let next = edge + run as f64 * bit_ticks;
+            detector.push(next as u32 - edge as u32);
+            edge = next;
+        }
+        detector
+    }
+
+    #[test]
+    fn training_character() {
+        // 0x55 at 115200 baud, with a 120 MHz counter: ten alternating bits,
+        // of 1041.67 ticks each
+        let detector = record(120e6 / 115_200.0, &[1; 9]);
+        assert_eq!(detector.bit_ticks(1), Some(1042));
+        assert_eq!(detector.baud(Hertz(120_000_000)), Ok(Hertz(115_200)));
+    }
+
+    #[test]
+    fn text_and_glitches() {
+        // "A" (0x41) at 9600 baud, with a 48 MHz counter: runs of 1, 1, 5, 1
+        // and 1 bits, then "B" (0x42) after an idle line
+        let runs = [1, 1, 5, 1, 1, 40, 2, 1, 4, 1, 1];
+        let detector = record(5000.0, &runs);
+        assert_eq!(detector.baud(Hertz(48_000_000)), Ok(Hertz(9600)));
+
+        // A glitch splits the long run of "A" in three
+        let runs = [5000, 5000, 12_000, 10, 12_990, 5000, 5000];
+        let mut detector = AutoBaud::new();
+        runs.iter().for_each(|&ticks| detector.push(ticks));
+        assert_eq!(detector.bit_ticks(208), Some(5000));
+        assert_eq!(detector.baud(Hertz(48_000_000)), Ok(Hertz(9600)));
+    }
+
+    #[test]
+    fn rates_are_snapped() {
+        // 2% fast
+        let detector = record(48e6 / 58_752.0, &[1; 9]);
+        assert_eq!(detector.baud(Hertz(48_000_000)), Ok(Hertz(57_600)));
+        // Not a standard rate, but in range
+        let detector = record(48e6 / 100_000.0, &[1; 9]);
+        assert_eq!(detector.baud(Hertz(48_000_000)), Ok(Hertz(100_000)));
+        let detector = record(48e6 / 1200.0, &[1; 9]);
+        assert_eq!(
+            detector.baud(Hertz(48_000_000)),
+            Err(AutoBaudError::OutOfRange(Hertz(1200)))
+        );
+        let detector = record(48e6 / 150_000.0, &[1; 9]);
+        assert_eq!(
+            detector.baud(Hertz(48_000_000)),
+            Err(AutoBaudError::OutOfRange(Hertz(150_000)))
+        );
+        // Bits shorter than half a bit at 115200 baud are taken for glitches
+        let detector = record(48e6 / 250_000.0, &[1; 9]);
+        assert_eq!(
+            detector.baud(Hertz(48_000_000)),
+            Err(AutoBaudError::Inconsistent)
+        );
+    }
+
+    #[test]
+    fn needs_confirmed_bit_time() {
+        let detector = record(5000.0, &[1, 1, 4]);
+        assert_eq!(
+            detector.baud(Hertz(48_000_000)),
+            Err(AutoBaudError::Inconsistent)
+        );
+        let mut full = AutoBaud::new();
+        (0..20).for_each(|_| full.push(5000));
+        assert!(full.is_full());
+    }
+}
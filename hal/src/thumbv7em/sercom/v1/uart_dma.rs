@@ -0,0 +1,110 @@
+//! # UART packet reception with DMA and an idle timeout
+//!
+//! Packets of unknown length can be received by DMA, if the end of a packet
+//! is detected as a gap on the line. `UARTn::receive_until_idle_dma` starts a
+//! DMA transfer from the UART into a buffer, and completes it as soon as the
+//! line stays idle for the timeout of an [`IdleTimer`], or when the buffer is
+//! full. It returns the number of bytes received. A buffer longer than
+//! [`MAX_BEATS`](crate::dmac::MAX_BEATS) is handed back with a
+//! [`BufferError`] before anything is started.
+//!
+//! The DMA channel emits an event for every received byte, which restarts the
+//! timer. Events are routed through EVSYS, which this HAL does not abstract
+//! yet:
+//!
+//! - Configure an EVSYS channel with the DMA channel as its generator
+//!   (`EVSYS_ID_GEN_DMAC_CH_n`), using the resynchronized or asynchronous
+//!   path
+//! - Select that EVSYS channel in the `USER` register of the TC's `EVU` event
+//!   user
+//!
+//! The timer waits for the first byte, so the time before the packet starts
+//! does not count as idle. The UART must not have its `RXC` interrupt enabled,
+//! and the DMA channel must keep its default burst length of one beat.
+//!
+//! ```
+//! // 4 character times at 115200 baud
+//! let mut timer = tc3.into_idle_timer(350.us());
+//! let (channel, buffer, len) = uart
+//!     .receive_until_idle_dma(&mut dmac, channel, &mut timer, buffer)
+//!     .map_err(|(_, _, error)| error)?;
+//! handle_packet(&buffer[..len]);
+//! ```
+
+use crate::dmac::channel::{BoundChannel, DmaPeripheral, IntoBoundChannel};
+use crate::dmac::dma_controller::ChId;
+use crate::dmac::transfer::transferred_beats;
+use crate::dmac::{Buffer, BufferError, DmaController, Transfer, TriggerAction};
+use crate::sercom::v2::Sercom;
+use crate::timer::{Count16, IdleTimer};
+
+/// The `DATA` register of a SERCOM, used as a fixed DMA source
+pub(crate) struct DataRegister(*mut u8);
+
+unsafe impl Buffer for DataRegister {
+    type Beat = u8;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut u8 {
+        self.0
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+/// Result of an idle-terminated reception: the channel, the buffer and the
+/// number of bytes received, or the channel and the buffer if it was rejected
+pub type IdleReceive<Id, S> = Result<
+    (BoundChannel<Id, S>, &'static mut [u8], usize),
+    (BoundChannel<Id, S>, &'static mut [u8], BufferError),
+>;
+
+/// Receive into `buffer` until the line is idle or `buffer` is full, and
+/// return the number of bytes received
+pub(crate) fn receive_until_idle<S, Ch, TC>(
+    sercom: &S,
+    dmac: &mut DmaController,
+    channel: Ch,
+    timer: &mut IdleTimer<TC>,
+    buffer: &'static mut [u8],
+) -> IdleReceive<Ch::Id, S>
+where
+    S: Sercom + DmaPeripheral,
+    Ch: IntoBoundChannel<S>,
+    TC: Count16,
+{
+    let data = DataRegister(&sercom.usart_int().data as *const _ as *mut u8);
+    let transfer = match Transfer::try_new(channel.into_bound().unbind(), data, buffer, false) {
+        Ok(transfer) => transfer,
+        Err((channel, _, buffer, e)) => return Err((channel.bind(), buffer, e)),
+    };
+    timer.arm();
+    let transfer = transfer
+        .with_beat_events(dmac)
+        .begin(dmac, S::RX_TRIGGER, TriggerAction::BURST);
+    while !timer.expired() && !transfer.is_complete(dmac) {}
+    let (channel, _, buffer, _) = transfer.stop(dmac);
+    let len = transferred_beats(<Ch::Id as ChId>::U8) as usize;
+    Ok((channel.bind(), buffer, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_register_is_a_fixed_source() {
+        let mut data = DataRegister(0x4100_3028 as *mut u8);
+        assert_eq!(data.dma_ptr() as usize, 0x4100_3028);
+        assert!(!data.incrementing());
+        assert_eq!(data.buffer_len(), 1);
+    }
+}
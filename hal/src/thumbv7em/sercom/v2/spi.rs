@@ -54,6 +54,29 @@
 //! `static` variables. In these cases, the [`pads_alias`] macro can help
 //! simplify the declaration.
 //!
+//! ## Receive-only and transmit-only
+//!
+//! Devices that only ever send or only ever receive data, like displays or
+//! ADCs, don't need both data lines. Leave the unused data [`Pad`] as [`NoneT`]
+//! and the peripheral is configured for one direction only.
+//!
+//! - Receive-only [`Pads`] ([`Rx`] but [`NotTx`]) have no Data Out [`Pad`].
+//!   The peripheral still shifts out a dummy word to clock each word in, but
+//!   `DOPO` routes it to the unused pad (see [`unused_dopo`]), so the pin is
+//!   not driven and remains free for other uses.
+//! - Transmit-only [`Pads`] ([`Tx`] but [`NotRx`]) have no Data In [`Pad`], so
+//!   that pin is free. The receiver (`CTRLB.RXEN`) is left disabled, so the
+//!   DATA register never has to be read and no buffer overflow can occur.
+//!
+//! The driver only implements the traits of the available direction.
+//! Receive-only [`Spi`]s implement [`Read`] and the blocking [`Transfer`],
+//! whose words are shifted out as dummy words. Transmit-only [`Spi`]s implement
+//! [`Write`] and the blocking [`blocking::spi::Write`]. Neither implements
+//! [`FullDuplex`].
+//!
+//! In [`Master`] [`Mode`], the SS [`Pad`] is optional and can be freed as
+//! well.
+//!
 //! # [`Config`]
 //!
 //! Use the [`Pads`] struct to create a [`Config`] struct, which represents the
@@ -117,9 +140,13 @@
 //! let rcvd: u16 = block!(spi.read());
 //! ```
 //!
+//! With the `dma` feature, full-duplex [`Spi`]s can also exchange buffers with
+//! DMA. See the [`dma`] module, which also covers buffer overflows.
+//!
 //! [`enable`]: Config::enable
 //! [`Pin`]: crate::gpio::v2::pin::Pin
 //! [`U255`]: typenum::U255
+//! [`Transfer`]: blocking::spi::Transfer
 
 use core::convert::{TryFrom, TryInto};
 use core::marker::PhantomData;
@@ -141,11 +168,12 @@ use pac::sercom0::spim::ctrla::{CPHA_A, CPOL_A, DIPO_A, DOPO_A, DORD_A, MODE_A};
 use pac::sercom0::RegisterBlock;
 use pac::MCLK;
 
+use crate::clock::PclkWatch;
 use crate::gpio::v2::{AnyPin, SpecificPin};
 use crate::sercom::v2::pads::{IoSet, Map, Pad0, Pad1, Pad2, Pad3, PadNum};
 use crate::sercom::v2::pads::{OptionalPad, Pad, SomePad};
 use crate::sercom::v2::Sercom;
-use crate::time::Hertz;
+use crate::time::{Hertz, Nanoseconds};
 use crate::typelevel::{Is, NoneT, Sealed};
 
 /// Re-export [`typenum`] constants for use as [`Length`] type parameters
@@ -159,6 +187,9 @@ pub mod lengths {
     });
 }
 
+#[cfg(all(feature = "unproven", feature = "dma"))]
+pub mod dma;
+
 //=============================================================================
 // Pad configuration
 //=============================================================================
@@ -253,6 +284,19 @@ impl Dopo for NoneT {
     fn configure(_: &RegisterBlock) {}
 }
 
+/// `DOPO` value for receive-only [`Pads`], which places Data Out on a pad
+/// other than Data In
+///
+/// Data Out can only be on [`Pad0`] or [`Pad3`]. Otherwise, the dummy words
+/// shifted out to clock in the data would be driven onto the Data In pin.
+#[inline]
+pub const fn unused_dopo(dipo: DIPO_A) -> DOPO_A {
+    match dipo {
+        DIPO_A::PAD0 => DOPO_A::PAD2,
+        _ => DOPO_A::PAD0,
+    }
+}
+
 //=============================================================================
 // Pads
 //=============================================================================
@@ -550,14 +594,27 @@ pub trait AnyPads: Sealed + Is<Type = SpecificPads<Self>> {
     /// SS [`Pad`] from the corresponding [`Pads`]
     type SS: OptionalPad;
 
+    /// Whether the receiver is enabled
+    ///
+    /// `CTRLB.RXEN` is only set when there is a Data In [`Pad`], so
+    /// transmit-only [`Pads`] never receive data that would have to be read.
+    const RXEN: bool = <Self::DataIn as OptionalPad>::IS_SOME;
+
     /// Configure the pads with the correct [`Dipo`] and [`Dopo`] values
     ///
-    /// This function will have no effect on the corresponding pad if it is
-    /// [`NoneT`].
+    /// `DIPO` is left untouched if the Data In [`Pad`] is [`NoneT`], since the
+    /// receiver is disabled. If the Data Out [`Pad`] is [`NoneT`], the dummy
+    /// words are still shifted out, so `DOPO` is set to the [`unused_dopo`]
+    /// of the Data In [`Pad`].
     #[inline]
     fn configure(sercom: &RegisterBlock) {
         Self::DataIn::configure(sercom);
-        Self::DataOut::configure(sercom);
+        if <Self::DataOut as OptionalPad>::IS_SOME {
+            Self::DataOut::configure(sercom);
+        } else {
+            let dopo = unused_dopo(<Self::DataIn as Dipo>::VARIANT);
+            sercom.spim().ctrla.modify(|_, w| w.dopo().variant(dopo));
+        }
     }
 }
 
@@ -741,7 +798,8 @@ pub trait Mode: Sealed {
     /// For maximum flexibility, this module chooses to always operate in 32-bit
     /// extension mode. The LENGTH counter is used to control the number of byes
     /// in each SPI transaction. Due to a hardware bug, ICSPACE must be at least
-    /// one. See the silicon errata for more details.
+    /// one. See the silicon errata for more details. Any larger ICSPACE set
+    /// with [`Config::inter_char_spacing`] is preserved.
     #[inline]
     fn configure(sercom: &RegisterBlock) -> () {
         sercom
@@ -752,9 +810,9 @@ pub trait Mode: Sealed {
             .spim()
             .ctrlb
             .modify(|_, w| w.mssen().bit(Self::MSSEN));
-        sercom.spim().ctrlc.write(|w| unsafe {
+        sercom.spim().ctrlc.modify(|r, w| unsafe {
             w.data32b().data_trans_32bit();
-            w.icspace().bits(1)
+            w.icspace().bits(r.icspace().bits().max(1))
         });
         while sercom.spim().syncbusy.read().ctrlb().bit_is_set() {}
     }
@@ -972,6 +1030,33 @@ where
     mode: PhantomData<M>,
     len: PhantomData<L>,
     freq: Hertz,
+    baud: Option<Hertz>,
+}
+
+/// Compute the BAUD register value for a baud rate, given the GCLK frequency
+///
+/// Values outside the achievable range saturate at the extremes.
+fn baud_value(freq: Hertz, baud: Hertz) -> u8 {
+    let baud = (freq.0 / 2 / baud.0).saturating_sub(1);
+    if baud <= u8::MAX as u32 {
+        baud as u8
+    } else {
+        u8::MAX
+    }
+}
+
+/// Largest ICSPACE value
+const MAX_ICSPACE: u8 = 63;
+
+/// Compute the ICSPACE value for an inter-character delay, given the GCLK
+/// frequency and the BAUD register value
+///
+/// Each SCK period lasts `2 * (BAUD + 1)` GCLK cycles. The delay is rounded up
+/// to a whole number of SCK periods, then clamped to the range of ICSPACE.
+fn icspace_value(freq: Hertz, baud: u8, delay: Nanoseconds) -> u8 {
+    let period = 2 * (baud as u64 + 1) * 1_000_000_000;
+    let periods = (delay.0 as u64 * freq.0 as u64 + period - 1) / period;
+    periods.max(1).min(MAX_ICSPACE as u64) as u8
 }
 
 impl<P: AnyPads> Config<P> {
@@ -987,6 +1072,7 @@ impl<P: AnyPads> Config<P> {
             mode: PhantomData,
             len: PhantomData,
             freq: freq.into(),
+            baud: None,
         }
     }
 
@@ -1042,6 +1128,7 @@ where
             mode: PhantomData,
             len: PhantomData,
             freq: self.freq,
+            baud: self.baud,
         }
     }
 
@@ -1160,14 +1247,10 @@ where
     /// half the GCLK frequency. The minimum baud rate is the GCLK frequency /
     /// 512. Values outside this range will saturate at the extremes.
     #[inline]
-    pub fn baud<B: Into<Hertz>>(self, baud: B) -> Self {
+    pub fn baud<B: Into<Hertz>>(mut self, baud: B) -> Self {
         let baud: Hertz = baud.into();
-        let baud = (self.freq.0 / 2 / baud.0).saturating_sub(1);
-        let baud = if baud <= u8::MAX as u32 {
-            baud as u8
-        } else {
-            u8::MAX
-        };
+        self.baud = Some(baud);
+        let baud = baud_value(self.freq, baud);
         self.sercom
             .spim()
             .baud
@@ -1175,6 +1258,51 @@ where
         self
     }
 
+    /// Change the stored GCLK frequency
+    ///
+    /// If a baud rate was previously set with [`Config::baud`], the BAUD
+    /// register is recomputed for the new frequency, so that the baud rate
+    /// stays the same. Use this after changing the GCLK feeding the
+    /// [`Sercom`].
+    #[inline]
+    pub fn gclk_freq<F: Into<Hertz>>(mut self, freq: F) -> Self {
+        self.freq = freq.into();
+        match self.baud {
+            Some(baud) => self.baud(baud),
+            None => self,
+        }
+    }
+
+    /// Set the inter-character spacing, in SCK periods
+    ///
+    /// The hardware idles the bus for `periods` SCK periods between each
+    /// character, which gives slow slaves time to process each byte. The
+    /// granularity is one SCK period, i.e. `2 * (BAUD + 1)` GCLK cycles. Due to
+    /// a hardware bug, the spacing is at least one period, which is also the
+    /// default. Values are clamped to the range 1 to 63.
+    #[inline]
+    pub fn inter_char_spacing(self, periods: u8) -> Self {
+        let periods = periods.max(1).min(MAX_ICSPACE);
+        self.sercom
+            .spim()
+            .ctrlc
+            .modify(|_, w| unsafe { w.icspace().bits(periods) });
+        self
+    }
+
+    /// Set the inter-character spacing as a delay
+    ///
+    /// The delay is converted to SCK periods using the stored GCLK frequency
+    /// and the current BAUD register, so call this after [`Config::baud`]. It
+    /// is rounded up to a whole number of periods and clamped to the range of
+    /// [`Config::inter_char_spacing`].
+    #[inline]
+    pub fn inter_char_delay<D: Into<Nanoseconds>>(self, delay: D) -> Self {
+        let baud = self.sercom.spim().baud.read().baud().bits();
+        let periods = icspace_value(self.freq, baud, delay.into());
+        self.inter_char_spacing(periods)
+    }
+
     /// Control the buffer overflow notification
     ///
     /// If set to true, an [`Error::Overflow`] will be issued as soon as an
@@ -1222,7 +1350,10 @@ where
     where
         Self: ValidConfig,
     {
-        self.sercom.spim().ctrlb.modify(|_, w| w.rxen().set_bit());
+        self.sercom
+            .spim()
+            .ctrlb
+            .modify(|_, w| w.rxen().bit(P::RXEN));
         while self.sercom.spim().syncbusy.read().ctrlb().bit_is_set() {}
         self.enable_peripheral(true);
         Spi { config: self }
@@ -1426,6 +1557,63 @@ where
 {
 }
 
+//=============================================================================
+// Suspended
+//=============================================================================
+
+/// A suspended SPI peripheral
+///
+/// Returned by [`Spi::suspend`], for duty-cycled applications that only need
+/// the peripheral from time to time. The peripheral is disabled, but it keeps
+/// its full configuration, so it can be brought back with
+/// [`Suspended::resume`] instead of going through [`Config`] again. No
+/// transactions are possible while it is suspended.
+///
+/// To save more power, the APB clock of the [`Sercom`] can be stopped as well,
+/// using [`Suspended::disable_apb_clock`]. The registers keep their values
+/// while it is stopped, and [`Suspended::resume`] restarts it. The generic
+/// clock is not managed here, as it may be shared with other peripherals.
+///
+/// Resuming only sets the RXEN (if [`AnyPads::RXEN`]) and ENABLE bits. Each
+/// of them must synchronize to the generic clock domain, which takes a few
+/// periods of the generic and APB clocks, so the latency is a small number of
+/// clock cycles rather than a full reconfiguration.
+pub struct Suspended<C: ValidConfig> {
+    config: C,
+}
+
+impl<C: ValidConfig> Suspended<C> {
+    /// Stop the APB clock of the [`Sercom`] while suspended
+    #[inline]
+    pub fn disable_apb_clock(&mut self, mclk: &MCLK) {
+        self.config.as_mut().sercom.disable_apb_clock(mclk);
+    }
+
+    /// Re-enable the APB clock, if needed, and the SPI peripheral
+    #[inline]
+    pub fn resume(mut self, mclk: &MCLK) -> Spi<C> {
+        let config = self.config.as_mut();
+        config.sercom.enable_apb_clock(mclk);
+        config
+            .sercom
+            .spim()
+            .ctrlb
+            .modify(|_, w| w.rxen().bit(SpiPads::<C>::RXEN));
+        while config.sercom.spim().syncbusy.read().ctrlb().bit_is_set() {}
+        config.enable_peripheral(true);
+        Spi {
+            config: self.config,
+        }
+    }
+
+    /// Return the disabled [`Config`], e.g. to change it or free the
+    /// [`Sercom`]. The APB clock must be running.
+    #[inline]
+    pub fn into_config(self) -> C {
+        self.config
+    }
+}
+
 //=============================================================================
 // Spi
 //=============================================================================
@@ -1537,6 +1725,35 @@ impl<C: ValidConfig> Spi<C> {
         self.config.as_mut().enable_peripheral(true);
     }
 
+    /// Recompute the baud rate after a change of the GCLK frequency
+    ///
+    /// BAUD can only be written while the peripheral is disabled, so this
+    /// calls [`Spi::reconfigure`] with [`Config::gclk_freq`], which may
+    /// interrupt any ongoing transactions.
+    #[inline]
+    pub fn update_gclk_freq<F: Into<Hertz>>(&mut self, freq: F) {
+        let freq = freq.into();
+        self.reconfigure(|config| config.gclk_freq(freq));
+    }
+
+    /// Recompute the baud rate if the GCLK changed frequency, as reported by
+    /// `watch`
+    ///
+    /// Calls [`Spi::update_gclk_freq`] with the new frequency, if the
+    /// generator of the [`Sercom`] changed frequency since the last call.
+    /// Returns whether the baud rate was recomputed. See the
+    /// [`watch`](crate::clock::watch) module.
+    #[inline]
+    pub fn refresh_timing(&mut self, watch: &PclkWatch) -> bool {
+        match watch.take() {
+            Some(freq) => {
+                self.update_gclk_freq(freq);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Change the transaction [`Length`]
     ///
     /// Changing the transaction [`Length`] while is enabled is permissible but
@@ -1640,6 +1857,17 @@ impl<C: ValidConfig> Spi<C> {
         while spim.syncbusy.read().enable().bit_is_set() {}
         self.config
     }
+
+    /// Suspend the SPI peripheral
+    ///
+    /// The peripheral is disabled, but it keeps its full configuration. See
+    /// [`Suspended`] for more details.
+    #[inline]
+    pub fn suspend(self) -> Suspended<C> {
+        Suspended {
+            config: self.disable(),
+        }
+    }
 }
 
 impl<C> Spi<C>
@@ -2205,3 +2433,68 @@ fn write_slice(sercom: &RegisterBlock, buf: &[u8]) -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sercom::v2::pads::IoSet1;
+    use crate::sercom::v2::Sercom0;
+
+    type DataIn = Pad<Sercom0, Pad0, IoSet1>;
+    type DataOut = Pad<Sercom0, Pad3, IoSet1>;
+    type Sclk = Pad<Sercom0, Pad1, IoSet1>;
+
+    #[test]
+    fn rxen_follows_data_in() {
+        type Duplex = Pads<Sercom0, IoSet1, DataIn, DataOut, Sclk>;
+        type RxOnly = Pads<Sercom0, IoSet1, DataIn, NoneT, Sclk>;
+        type TxOnly = Pads<Sercom0, IoSet1, NoneT, DataOut, Sclk>;
+        assert!(<Duplex as AnyPads>::RXEN);
+        assert!(<RxOnly as AnyPads>::RXEN);
+        assert!(!<TxOnly as AnyPads>::RXEN);
+    }
+
+    #[test]
+    fn rx_only_data_out_avoids_data_in() {
+        // `DOPO_A::PAD2` places Data Out on PAD3
+        assert_eq!(unused_dopo(DIPO_A::PAD0), DOPO_A::PAD2);
+        assert_eq!(unused_dopo(DIPO_A::PAD2), DOPO_A::PAD0);
+        assert_eq!(unused_dopo(DIPO_A::PAD3), DOPO_A::PAD0);
+        assert_eq!(<Pad3 as Dopo>::VARIANT, DOPO_A::PAD2);
+    }
+
+    #[test]
+    fn baud_value_follows_gclk_freq() {
+        let baud = Hertz(1_000_000);
+        assert_eq!(baud_value(Hertz(48_000_000), baud), 23);
+        assert_eq!(baud_value(Hertz(12_000_000), baud), 5);
+    }
+
+    #[test]
+    fn baud_value_saturates() {
+        assert_eq!(baud_value(Hertz(48_000_000), Hertz(48_000_000)), 0);
+        assert_eq!(baud_value(Hertz(48_000_000), Hertz(10_000)), u8::MAX);
+    }
+
+    #[test]
+    fn icspace_rounds_up_to_sck_periods() {
+        // BAUD = 23 at 48 MHz is a 1 MHz SCK, with a 1 us period
+        assert_eq!(
+            icspace_value(Hertz(48_000_000), 23, Nanoseconds(10_000)),
+            10
+        );
+        assert_eq!(
+            icspace_value(Hertz(48_000_000), 23, Nanoseconds(10_001)),
+            11
+        );
+    }
+
+    #[test]
+    fn icspace_is_clamped() {
+        assert_eq!(icspace_value(Hertz(48_000_000), 23, Nanoseconds(0)), 1);
+        assert_eq!(
+            icspace_value(Hertz(48_000_000), 23, Nanoseconds(1_000_000)),
+            MAX_ICSPACE
+        );
+    }
+}
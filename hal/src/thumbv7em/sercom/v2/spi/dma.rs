@@ -0,0 +1,340 @@
+//! Full-duplex SPI transfers with DMA
+//!
+//! [`Spi::transfer_dma`] exchanges a buffer of words with a device using two
+//! DMA channels. The TX channel writes the DATA register whenever it is
+//! empty, and the RX channel reads it whenever a word is received.
+//!
+//! # Buffer overflow
+//!
+//! The TX channel keeps the bus busy regardless of the RX channel. If the RX
+//! channel falls behind, e.g. because channels of a higher priority or other
+//! bus masters hold the bus, or because the DMAC is suspended, received words
+//! pile up in the peripheral. Once its buffer is full, the next word is lost
+//! and the `BUFOVF` error is raised. The RX channel then never receives its
+//! last words, so the transfer can't complete on its own.
+//!
+//! The overflow is detected, and the transfer fails with
+//! [`Error::Overflow`], depending on
+//! [`Config::immediate_overflow_notification`]:
+//!
+//! - If set, `BUFOVF` is raised as soon as a word is lost, and both DMA
+//!   transfers are stopped right away.
+//! - If not (the default), `BUFOVF` is only raised when the RX channel reaches
+//!   the position of the lost word in the stream. The words before it are
+//!   received normally, and the transfer fails once the TX channel is done
+//!   and the last word is shifted out.
+//!
+//! Either way, the received data can't be trusted, and the transaction has to
+//! be repeated. When stopped early, the word in progress may still be shifted
+//! out after [`Spi::transfer_dma`] returns.
+//!
+//! ```
+//! spi.reconfigure(|mut config| {
+//!     config.immediate_overflow_notification(true);
+//!     config
+//! });
+//! let (rx_channel, tx_channel, source, destination, result) =
+//!     spi.transfer_dma(&mut dmac, rx_channel, tx_channel, source, destination);
+//! if let Err(DmaError::Spi(Error::Overflow)) = result {
+//!     // Retry, e.g. with a higher priority for the RX channel
+//! }
+//! ```
+
+use core::convert::TryInto;
+use core::task::Poll;
+
+use crate::dmac::channel::{BoundChannel, DmaPeripheral, IntoBoundChannel};
+use crate::dmac::{Buffer, BufferError, DmaController, TransferError, MAX_BEATS};
+
+use super::*;
+
+/// Error of a [`Spi::transfer_dma`]
+#[derive(Debug)]
+pub enum DmaError {
+    /// The SPI peripheral reported an error, e.g. a buffer overflow
+    Spi(Error),
+    /// One of the DMA transfers was aborted by the DMAC
+    Transfer(TransferError),
+    /// The buffers were rejected before anything was started
+    Buffer(BufferError),
+}
+
+/// Number of words exchanged by `source` and `destination`
+///
+/// Unlike [`check_buffers`](crate::dmac::check_buffers), a single-word buffer
+/// isn't repeated: both buffers must have the same length.
+fn exchange_length<S: Buffer, D: Buffer>(
+    source: &S,
+    destination: &D,
+) -> Result<usize, BufferError> {
+    let length = source.buffer_len();
+    if destination.buffer_len() != length {
+        Err(BufferError::LengthMismatch)
+    } else if length > MAX_BEATS {
+        Err(BufferError::TooLong(length))
+    } else {
+        Ok(length)
+    }
+}
+
+/// The DATA register of the SERCOM, used as a fixed DMA source and
+/// destination
+struct DataRegister(*mut u32);
+
+unsafe impl Buffer for DataRegister {
+    type Beat = u32;
+
+    #[inline]
+    fn dma_ptr(&mut self) -> *mut u32 {
+        self.0
+    }
+
+    #[inline]
+    fn incrementing(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn buffer_len(&self) -> usize {
+        1
+    }
+}
+
+/// Status of a DMA exchange, given the `IBON` setting, the SPI errors and
+/// flags, and the status of the RX and TX transfers
+fn exchange_status(
+    ibon: bool,
+    errors: Errors,
+    flags: Flags,
+    rx: Poll<Result<(), TransferError>>,
+    tx: Poll<Result<(), TransferError>>,
+) -> Poll<Result<(), DmaError>> {
+    match (rx, tx) {
+        (Poll::Ready(Err(e)), _) | (_, Poll::Ready(Err(e))) => {
+            Poll::Ready(Err(DmaError::Transfer(e)))
+        }
+        (Poll::Ready(Ok(())), Poll::Ready(Ok(()))) => {
+            Poll::Ready(errors.try_into().map_err(DmaError::Spi))
+        }
+        // Without IBON, the RX transfer waits forever for the lost words once
+        // everything is shifted out
+        (_, tx)
+            if errors.contains(Errors::BUFOVF)
+                && (ibon || (tx.is_ready() && flags.contains(Flags::TXC))) =>
+        {
+            Poll::Ready(Err(DmaError::Spi(Error::Overflow)))
+        }
+        _ => Poll::Pending,
+    }
+}
+
+impl<C> Spi<C>
+where
+    C: ValidConfig,
+    C::Pads: Tx + Rx,
+    SpiSercom<C>: DmaPeripheral,
+{
+    /// Exchange `source` for `destination` with DMA, and return the channels
+    /// and buffers along with the outcome
+    ///
+    /// `rx_channel` reads the DATA register into `destination`, and
+    /// `tx_channel` writes `source` into it. Each beat carries a whole DATA
+    /// word, so use a [`Length`] which is a multiple of four bytes. Returns
+    /// [`Error::Overflow`] if received words were lost, see the
+    /// [module-level documentation](self). The channels are bound to the
+    /// SERCOM, if they weren't already.
+    ///
+    /// Returns [`DmaError::Buffer`] without starting anything if `source` and
+    /// `destination` don't have the same length, or are longer than
+    /// [`MAX_BEATS`].
+    ///
+    /// # Blocking
+    ///
+    /// This busy-waits, without a timeout, until both transfers complete, one
+    /// of them fails, or the overflow is detected. It never returns if the
+    /// channels stall without any of these: e.g. if an interrupt handler
+    /// suspends one of them with the `CHCTRLB` command behind
+    /// [`Transfer::suspend`](crate::dmac::Transfer::suspend), disables their
+    /// priority level with [`DmaController::disable_levels`], or disables
+    /// the SERCOM. Leave the channels and the SERCOM alone until the exchange
+    /// returns.
+    #[allow(clippy::type_complexity)]
+    pub fn transfer_dma<R, T, S, D>(
+        &mut self,
+        dmac: &mut DmaController,
+        rx_channel: R,
+        tx_channel: T,
+        source: S,
+        destination: D,
+    ) -> (
+        BoundChannel<R::Id, SpiSercom<C>>,
+        BoundChannel<T::Id, SpiSercom<C>>,
+        S,
+        D,
+        Result<(), DmaError>,
+    )
+    where
+        R: IntoBoundChannel<SpiSercom<C>>,
+        T: IntoBoundChannel<SpiSercom<C>>,
+        S: Buffer<Beat = u32>,
+        D: Buffer<Beat = u32>,
+    {
+        let rx_channel = rx_channel.into_bound();
+        let tx_channel = tx_channel.into_bound();
+        if let Err(e) = exchange_length(&source, &destination) {
+            return (
+                rx_channel,
+                tx_channel,
+                source,
+                destination,
+                Err(DmaError::Buffer(e)),
+            );
+        }
+        let (ibon, data) = unsafe {
+            let spim = self.sercom().spim();
+            (spim.ctrla.read().ibon().bit(), spim.data.as_ptr())
+        };
+
+        // Words and errors left over from previous transactions don't count
+        while self.read_flags().contains(Flags::RXC) {
+            unsafe { self.read_data() };
+        }
+        self.clear_errors(Errors::BUFOVF);
+        self.clear_flags(Flags::TXC);
+
+        // The buffers were checked above, so neither transfer is rejected
+        let mut rx = match rx_channel.begin_rx(dmac, DataRegister(data), destination) {
+            Ok(rx) => rx,
+            Err((rx_channel, _, destination, e)) => {
+                return (
+                    rx_channel,
+                    tx_channel,
+                    source,
+                    destination,
+                    Err(DmaError::Buffer(e)),
+                )
+            }
+        };
+        let mut tx = match tx_channel.begin_tx(dmac, source, DataRegister(data)) {
+            Ok(tx) => tx,
+            Err((tx_channel, source, _, e)) => {
+                let (rx_channel, _, destination, _) = rx.stop(dmac);
+                return (
+                    rx_channel.bind(),
+                    tx_channel,
+                    source,
+                    destination,
+                    Err(DmaError::Buffer(e)),
+                );
+            }
+        };
+        let mut rx_status = Poll::Pending;
+        let mut tx_status = Poll::Pending;
+        let result = loop {
+            if rx_status.is_pending() {
+                rx_status = rx.poll(dmac);
+            }
+            if tx_status.is_pending() {
+                tx_status = tx.poll(dmac);
+            }
+            let errors = self.read_errors();
+            let flags = self.read_flags();
+            if let Poll::Ready(result) = exchange_status(ibon, errors, flags, rx_status, tx_status)
+            {
+                break result;
+            }
+        };
+
+        let (rx_channel, _, destination, _) = rx.stop(dmac);
+        let (tx_channel, source, _, _) = tx.stop(dmac);
+        (
+            rx_channel.bind(),
+            tx_channel.bind(),
+            source,
+            destination,
+            result,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmac::TransferErrorKind;
+
+    const PENDING: Poll<Result<(), TransferError>> = Poll::Pending;
+    const DONE: Poll<Result<(), TransferError>> = Poll::Ready(Ok(()));
+
+    #[test]
+    fn completes_without_errors() {
+        let status = exchange_status(false, Errors::empty(), Flags::TXC, DONE, DONE);
+        assert!(matches!(status, Poll::Ready(Ok(()))));
+        let status = exchange_status(false, Errors::empty(), Flags::DRE, PENDING, DONE);
+        assert!(status.is_pending());
+    }
+
+    #[test]
+    fn buffer_overflow_fails_the_transfer() {
+        let overflow = |status| matches!(status, Poll::Ready(Err(DmaError::Spi(Error::Overflow))));
+        // Raised at completion
+        let status = exchange_status(false, Errors::BUFOVF, Flags::TXC, DONE, DONE);
+        assert!(overflow(status));
+
+        // Raised in the stream without IBON: the RX transfer never completes,
+        // but only once the last word is shifted out
+        let status = exchange_status(false, Errors::BUFOVF, Flags::DRE, PENDING, PENDING);
+        assert!(status.is_pending());
+        let status = exchange_status(false, Errors::BUFOVF, Flags::DRE, PENDING, DONE);
+        assert!(status.is_pending());
+        let status = exchange_status(false, Errors::BUFOVF, Flags::TXC, PENDING, DONE);
+        assert!(overflow(status));
+
+        // Raised immediately with IBON
+        let status = exchange_status(true, Errors::BUFOVF, Flags::DRE, PENDING, PENDING);
+        assert!(overflow(status));
+    }
+
+    #[test]
+    fn dmac_errors_take_priority() {
+        let error = TransferError {
+            channel: 1,
+            kind: TransferErrorKind::Transfer,
+            remaining_beats: Some(3),
+            source_address: None,
+            destination_address: None,
+        };
+        let status = exchange_status(
+            true,
+            Errors::BUFOVF,
+            Flags::TXC,
+            DONE,
+            Poll::Ready(Err(error)),
+        );
+        assert!(matches!(status, Poll::Ready(Err(DmaError::Transfer(e))) if e == error));
+    }
+
+    #[test]
+    fn buffers_are_checked_before_the_exchange() {
+        let mut words = [0u32; 4];
+        let (source, destination) = words.split_at_mut(2);
+        assert_eq!(exchange_length(&source, &destination), Ok(2));
+        assert_eq!(
+            exchange_length(&source, &&mut destination[..1]),
+            Err(BufferError::LengthMismatch)
+        );
+        // A single word isn't repeated to the length of the other buffer
+        let word = DataRegister(0x4000_3028 as *mut u32);
+        assert_eq!(
+            exchange_length(&word, &source),
+            Err(BufferError::LengthMismatch)
+        );
+    }
+
+    #[test]
+    fn data_register_is_fixed() {
+        let mut data = DataRegister(0x4000_3028 as *mut u32);
+        assert_eq!(data.dma_ptr() as usize, 0x4000_3028);
+        assert!(!data.incrementing());
+        assert_eq!(data.buffer_len(), 1);
+    }
+}
@@ -0,0 +1,171 @@
+//! # Internal voltage reference
+//!
+//! The ADCs, the DAC and the analog comparators can all use the internal
+//! voltage reference of the SUPC (`INTREF`), whose level is set in
+//! `SUPC.VREF`. [`Vref`] owns that register, so that the drivers don't change
+//! the reference of each other.
+//!
+//! Each driver using the reference holds a [`VrefUser`], handed out by
+//! [`Vref::user`]. The level and the standby behaviour of the reference can
+//! only be changed while it has no users, and the `SUPC` is only given back
+//! by [`Vref::free`] once every user has been [released](Vref::release).
+//!
+//! ```
+//! let mut vref = Vref::new(peripherals.SUPC);
+//! vref.set_level(VrefLevel::_2V0)?;
+//! adc0.use_internal_reference(vref.user());
+//! // Fails: ADC0 holds a user
+//! vref.set_level(VrefLevel::_1V0).unwrap_err();
+//! ```
+
+use crate::target_device::generic::Variant;
+use crate::target_device::{supc, SUPC};
+
+/// Level of the internal voltage reference
+pub type VrefLevel = supc::vref::SEL_A;
+
+/// Errors of the [`Vref`] configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VrefError {
+    /// The reference has users, which rely on its configuration
+    InUse,
+}
+
+/// Voltage of a reference level, in millivolts
+#[inline]
+pub fn millivolts(level: VrefLevel) -> u16 {
+    match level {
+        VrefLevel::_1V0 => 1000,
+        VrefLevel::_1V1 => 1100,
+        VrefLevel::_1V2 => 1200,
+        VrefLevel::_1V25 => 1250,
+        VrefLevel::_2V0 => 2000,
+        VrefLevel::_2V2 => 2200,
+        VrefLevel::_2V4 => 2400,
+        VrefLevel::_2V5 => 2500,
+    }
+}
+
+/// Internal voltage reference shared by the analog peripherals
+///
+/// See the [module-level documentation](self) for more details.
+pub struct Vref {
+    supc: SUPC,
+    users: usize,
+}
+
+impl Vref {
+    /// Take control of the internal voltage reference, leaving its
+    /// configuration unchanged
+    #[inline]
+    pub fn new(supc: SUPC) -> Self {
+        Vref { supc, users: 0 }
+    }
+
+    /// Current level of the reference, or `None` if the field holds a
+    /// reserved value
+    #[inline]
+    pub fn level(&self) -> Option<VrefLevel> {
+        match self.supc.vref.read().sel().variant() {
+            Variant::Val(level) => Some(level),
+            Variant::Res(_) => None,
+        }
+    }
+
+    /// Number of users not released yet
+    #[inline]
+    pub fn users(&self) -> usize {
+        self.users
+    }
+
+    /// Set the level of the reference
+    pub fn set_level(&mut self, level: VrefLevel) -> Result<(), VrefError> {
+        self.check_unused()?;
+        self.supc.vref.modify(|_, w| w.sel().variant(level));
+        Ok(())
+    }
+
+    /// Set whether the reference only runs while a peripheral requests it
+    /// (`ONDEMAND`), and whether it keeps running in standby (`RUNSTDBY`)
+    pub fn set_standby(&mut self, on_demand: bool, run_in_standby: bool) -> Result<(), VrefError> {
+        self.check_unused()?;
+        self.supc.vref.modify(|_, w| {
+            w.ondemand().bit(on_demand);
+            w.runstdby().bit(run_in_standby)
+        });
+        Ok(())
+    }
+
+    /// Route the reference to an ADC input channel (`VREFOE`), to measure it
+    ///
+    /// This doesn't change the reference, so it is allowed while it has
+    /// users. The reference is not available on a pin.
+    #[inline]
+    pub fn route_to_adc(&mut self, enable: bool) {
+        self.supc.vref.modify(|_, w| w.vrefoe().bit(enable));
+    }
+
+    /// Add a user of the reference, which can't be reconfigured until it is
+    /// released
+    #[inline]
+    pub fn user(&mut self) -> VrefUser {
+        self.users += 1;
+        VrefUser {
+            millivolts: self.level().map_or(0, millivolts),
+        }
+    }
+
+    /// Release a user of the reference
+    #[inline]
+    pub fn release(&mut self, _user: VrefUser) {
+        self.users -= 1;
+    }
+
+    /// Return the `SUPC`, if the reference has no users left
+    #[inline]
+    pub fn free(self) -> Result<SUPC, Self> {
+        if self.users == 0 {
+            Ok(self.supc)
+        } else {
+            Err(self)
+        }
+    }
+
+    #[inline]
+    fn check_unused(&self) -> Result<(), VrefError> {
+        if self.users == 0 {
+            Ok(())
+        } else {
+            Err(VrefError::InUse)
+        }
+    }
+}
+
+/// Proof that the internal voltage reference keeps its level
+///
+/// Only a [`Vref`] creates users.
+#[derive(Debug)]
+pub struct VrefUser {
+    millivolts: u16,
+}
+
+impl VrefUser {
+    /// Voltage of the reference, in millivolts
+    #[inline]
+    pub fn millivolts(&self) -> u16 {
+        self.millivolts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_voltages() {
+        assert_eq!(millivolts(VrefLevel::_1V0), 1000);
+        assert_eq!(millivolts(VrefLevel::_1V25), 1250);
+        assert_eq!(millivolts(VrefLevel::_2V5), 2500);
+        assert_eq!(VrefLevel::_2V5 as u8, 7);
+    }
+}
@@ -0,0 +1,537 @@
+//! # Phase-shifted PWM across TCC instances
+//!
+//! Interleaved converters drive `N` power stages at the same frequency, each
+//! one shifted by `360 / N` degrees, to cancel the ripple of the stages. The
+//! channels of a single TCC share its counter, so they can only be shifted by
+//! moving their edges within the period. [`SyncedPwmGroup`] instead runs one
+//! counter per stage: it configures several TCCs with the same period, and
+//! starts them with exact offsets between their counters.
+//!
+//! The first member of the group is the reference, at 0 degrees. The other
+//! members, the followers, lag it by their phase. Every member counts at the
+//! frequency of its TCC clock, without prescaler, so the TCC clocks must all
+//! come from the same GCLK generator. The waveform is single-slope PWM
+//! (`NPWM`), and the members' pins must be set to their TCC function, as for
+//! the [`pwm`](crate::pwm) TCC drivers. The TCs are not supported.
+//!
+//! ## Synchronized start
+//!
+//! Starting the counters one after the other from the CPU leaves offsets
+//! which depend on the bus and on interrupts. Instead, the overflow event of
+//! the reference goes through an EVSYS channel, on the asynchronous path, to
+//! the event input 0 of every follower, which starts its counter
+//! (`EVCTRL.EVACT0 = START`). The followers wait, stopped, with their counter
+//! preloaded to where it must be when the reference wraps to zero, and all
+//! start on the same overflow. A start event has no effect on a running
+//! counter, so the route stays in place:
+//!
+//! - [`SyncedPwmGroup::align`] stops every member, preloads the followers and
+//!   restarts the reference from zero with the `RETRIGGER` command
+//! - [`SyncedPwmGroup::set_phase`] only stops the follower being shifted,
+//!   preloads it, and lets the next overflow of the reference restart it.
+//!   Its outputs hold their level while it waits, for up to one period.
+//!
+//! The `USER` register of a follower is detached while it is preloaded, so
+//! that an overflow can't restart it with a stale count. A fixed latency
+//! between the overflow and the start of the followers shows up as a
+//! constant phase error, which [`SyncedPwmGroup::set_start_compensation`]
+//! corrects.
+//!
+//! ## Verification
+//!
+//! [`SyncedPwmGroup::verify`] reads the counters back, with the `READSYNC`
+//! command. The reads can't be simultaneous, so the counter of each follower
+//! is read between two reads of the reference counter. The follower must
+//! then lag the reference by its phase, within the counts that elapsed
+//! between both reads of the reference, which bound the [`Alignment`]
+//! tolerance.
+//!
+//! The EVSYS channel is still configured with raw register writes, as this
+//! HAL does not abstract EVSYS yet. The group owns the channel passed to
+//! [`SyncedPwmGroup::new`], and the `USER` registers of the event input 0 of
+//! its followers.
+//!
+//! ```
+//! // Four phases at 100 kHz, from a 120 MHz GCLK0
+//! let mut group = SyncedPwmGroup::new(
+//!     [tcc0.into(), tcc1.into(), tcc2.into(), tcc3.into()],
+//!     gclk0.freq(),
+//!     100.khz(),
+//!     0,
+//!     &mut mclk,
+//! )?;
+//! group.set_phases_evenly();
+//! for member in 0..4 {
+//!     group.set_duty(member, Channel::_0, group.max_duty() / 4);
+//! }
+//! assert!(group.verify().iter().all(Alignment::is_aligned));
+//! ```
+
+use crate::pwm::Channel;
+use crate::target_device::{evsys, tcc0, EVSYS, MCLK, TCC0, TCC1, TCC2};
+#[cfg(feature = "min-samd51j")]
+use crate::target_device::{TCC3, TCC4};
+use crate::time::Hertz;
+
+/// Number of EVSYS channels
+const EVSYS_CHANNELS: u8 = 32;
+
+/// Errors of [`SyncedPwmGroup::new`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncedPwmError {
+    /// The period needs more counts than the counter of a member holds
+    PeriodTooLong,
+    /// The period is shorter than two counts of the TCC clock
+    PeriodTooShort,
+    /// The EVSYS channel doesn't exist
+    InvalidChannel(u8),
+}
+
+/// Fixed properties of a TCC instance
+#[derive(Clone, Copy)]
+struct TccInfo {
+    /// Width of the counter, in bits
+    counter_bits: u32,
+    /// EVSYS generator of the overflow event
+    ovf_event: u8,
+    /// Index of the `USER` register of the event input 0
+    ev0_user: usize,
+}
+
+macro_rules! synced_tcc {
+    ($($(#[$cfg:meta])? $Variant:ident: ($TCC:ident, $apmask:ident, $apbits:ident, $bits:literal, $ovf:literal, $ev0:literal),)+) => {
+        /// A TCC instance of a [`SyncedPwmGroup`]
+        ///
+        /// Every TCC converts into it with `into()`.
+        pub enum SyncedTcc {
+            $(
+                $(#[$cfg])?
+                #[allow(missing_docs)]
+                $Variant($TCC),
+            )+
+        }
+
+        $(
+            $(#[$cfg])?
+            impl From<$TCC> for SyncedTcc {
+                #[inline]
+                fn from(tcc: $TCC) -> Self {
+                    SyncedTcc::$Variant(tcc)
+                }
+            }
+        )+
+
+        impl SyncedTcc {
+            #[inline]
+            fn regs(&self) -> &tcc0::RegisterBlock {
+                match self {
+                    $(
+                        $(#[$cfg])?
+                        SyncedTcc::$Variant(tcc) => tcc,
+                    )+
+                }
+            }
+
+            #[inline]
+            fn info(&self) -> TccInfo {
+                match self {
+                    $(
+                        $(#[$cfg])?
+                        SyncedTcc::$Variant(_) => TccInfo {
+                            counter_bits: $bits,
+                            ovf_event: $ovf,
+                            ev0_user: $ev0,
+                        },
+                    )+
+                }
+            }
+
+            #[inline]
+            fn enable_bus_clock(&self, mclk: &mut MCLK) {
+                match self {
+                    $(
+                        $(#[$cfg])?
+                        SyncedTcc::$Variant(_) => {
+                            mclk.$apmask.modify(|_, w| w.$apbits().set_bit())
+                        }
+                    )+
+                }
+            }
+        }
+    };
+}
+
+synced_tcc! {
+    Tcc0: (TCC0, apbbmask, tcc0_, 24, 0x29, 17),
+    Tcc1: (TCC1, apbbmask, tcc1_, 24, 0x32, 25),
+    Tcc2: (TCC2, apbcmask, tcc2_, 16, 0x39, 31),
+    #[cfg(feature = "min-samd51j")]
+    Tcc3: (TCC3, apbcmask, tcc3_, 16, 0x3F, 36),
+    #[cfg(feature = "min-samd51j")]
+    Tcc4: (TCC4, apbdmask, tcc4_, 16, 0x44, 40),
+}
+
+/// Measured alignment of a member of a [`SyncedPwmGroup`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Alignment {
+    /// Lag of the member behind its phase, in counts of the TCC clock.
+    /// Negative when the member is early.
+    pub error: i32,
+    /// Uncertainty of `error`, in counts, from the time between the reads of
+    /// the counters
+    pub tolerance: u32,
+}
+
+impl Alignment {
+    /// Whether the member is at its phase, within the tolerance
+    #[inline]
+    pub fn is_aligned(&self) -> bool {
+        self.error.unsigned_abs() <= self.tolerance
+    }
+}
+
+/// Value of `PER` for a PWM at `pwm_freq` from a TCC clock at `clock_freq`,
+/// for counters of `counter_bits`
+fn period_top(clock_freq: u32, pwm_freq: u32, counter_bits: u32) -> Result<u32, SyncedPwmError> {
+    let counts = (clock_freq + pwm_freq / 2)
+        .checked_div(pwm_freq)
+        .ok_or(SyncedPwmError::PeriodTooLong)?;
+    if counts < 2 {
+        Err(SyncedPwmError::PeriodTooShort)
+    } else if counts as u64 > 1 << counter_bits {
+        Err(SyncedPwmError::PeriodTooLong)
+    } else {
+        Ok(counts - 1)
+    }
+}
+
+/// Lag of `degrees`, in counts of a period ending at `top`, rounded
+fn lag_counts(top: u32, degrees: u16) -> u32 {
+    let period = top as u64 + 1;
+    let lag = (period * (degrees % 360) as u64 + 180) / 360;
+    (lag % period) as u32
+}
+
+/// Count to preload a follower with, for it to lag by `degrees` once started
+/// by the overflow of the reference, `compensation` counts late
+fn preload_count(top: u32, degrees: u16, compensation: u32) -> u32 {
+    let period = top as u64 + 1;
+    let lag = lag_counts(top, degrees) as u64;
+    ((period - lag + compensation as u64) % period) as u32
+}
+
+/// Alignment of a follower read at `count`, between two reads of the
+/// reference at `before` and `after`, given its expected lag
+fn alignment(top: u32, lag: u32, before: u32, count: u32, after: u32) -> Alignment {
+    let period = top as i64 + 1;
+    let elapsed = (after as i64 - before as i64).rem_euclid(period);
+    // Where the follower was when the reference was first read
+    let expected = (before as i64 - lag as i64).rem_euclid(period);
+    // The follower was read in the middle of the window, at best
+    let ahead = (count as i64 - expected).rem_euclid(period) - elapsed / 2;
+    let ahead = if ahead > period / 2 {
+        ahead - period
+    } else {
+        ahead
+    };
+    Alignment {
+        error: -ahead as i32,
+        tolerance: ((elapsed + 1) / 2) as u32,
+    }
+}
+
+/// Synchronize and read the counter of a running TCC
+#[inline]
+fn read_count(tcc: &tcc0::RegisterBlock) -> u32 {
+    tcc.ctrlbset.write(|w| w.cmd().readsync());
+    while tcc.syncbusy.read().ctrlb().bit_is_set() {}
+    while tcc.ctrlbset.read().cmd().bits() != 0 {}
+    tcc.count().read().count().bits()
+}
+
+/// Stop the counter of a TCC
+#[inline]
+fn stop(tcc: &tcc0::RegisterBlock) {
+    tcc.ctrlbset.write(|w| w.cmd().stop());
+    while tcc.syncbusy.read().ctrlb().bit_is_set() {}
+    while tcc.ctrlbset.read().cmd().bits() != 0 {}
+}
+
+/// TCCs running the same PWM period with fixed phase offsets
+///
+/// See the [module-level documentation](self) for more details.
+pub struct SyncedPwmGroup<const N: usize> {
+    members: [SyncedTcc; N],
+    phases: [u16; N],
+    top: u32,
+    compensation: u32,
+    evsys_channel: u8,
+}
+
+impl<const N: usize> SyncedPwmGroup<N> {
+    /// Configure `members` for a PWM at `pwm_freq`, and start them in phase
+    ///
+    /// `clock_freq` is the frequency of the TCC clocks, which must all come
+    /// from the same GCLK generator. The overflow of the first member starts
+    /// the others through EVSYS channel `evsys_channel`. The duty cycles
+    /// start at zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the group is empty.
+    pub fn new(
+        members: [SyncedTcc; N],
+        clock_freq: Hertz,
+        pwm_freq: impl Into<Hertz>,
+        evsys_channel: u8,
+        mclk: &mut MCLK,
+    ) -> Result<Self, SyncedPwmError> {
+        assert!(N > 0, "a synced PWM group needs a reference");
+        if evsys_channel >= EVSYS_CHANNELS {
+            return Err(SyncedPwmError::InvalidChannel(evsys_channel));
+        }
+        let counter_bits = members
+            .iter()
+            .map(|member| member.info().counter_bits)
+            .min()
+            .unwrap_or(16);
+        let top = period_top(clock_freq.0, pwm_freq.into().0, counter_bits)?;
+
+        mclk.apbbmask.modify(|_, w| w.evsys_().set_bit());
+        for (index, member) in members.iter().enumerate() {
+            member.enable_bus_clock(mclk);
+            let tcc = member.regs();
+            tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+            while tcc.syncbusy.read().enable().bit_is_set() {}
+            tcc.ctrla.write(|w| w.swrst().set_bit());
+            while tcc.syncbusy.read().swrst().bit_is_set() {}
+            tcc.wave.write(|w| w.wavegen().npwm());
+            if index > 0 {
+                tcc.evctrl.write(|w| w.evact0().start().tcei0().set_bit());
+            }
+            tcc.per().write(|w| unsafe { w.bits(top) });
+            tcc.ctrla.modify(|_, w| w.enable().set_bit());
+            while tcc.syncbusy.read().enable().bit_is_set() {}
+        }
+
+        let mut group = SyncedPwmGroup {
+            members,
+            phases: [0; N],
+            top,
+            compensation: 0,
+            evsys_channel,
+        };
+        group.align();
+        Ok(group)
+    }
+
+    #[inline]
+    fn evsys() -> &'static evsys::RegisterBlock {
+        // SAFETY: The group only writes to its own EVSYS channel and to the
+        // USER registers of its followers
+        unsafe { &*EVSYS::ptr() }
+    }
+
+    /// Route, or with `false` detach, the event input 0 of `member` to the
+    /// group's EVSYS channel
+    #[inline]
+    fn attach(&self, member: usize, attached: bool) {
+        let user = self.members[member].info().ev0_user;
+        let channel = if attached { self.evsys_channel + 1 } else { 0 };
+        Self::evsys().user[user].write(|w| unsafe { w.channel().bits(channel) });
+    }
+
+    /// Stop and preload `member`, for the next overflow of the reference to
+    /// start it at its phase
+    fn preload(&mut self, member: usize) {
+        self.attach(member, false);
+        let tcc = self.members[member].regs();
+        stop(tcc);
+        let count = preload_count(self.top, self.phases[member], self.compensation);
+        tcc.count().write(|w| unsafe { w.count().bits(count) });
+        while tcc.syncbusy.read().count().bit_is_set() {}
+        self.attach(member, true);
+    }
+
+    /// Stop every member, and restart them all at their phase
+    ///
+    /// The reference restarts from zero, and the followers start on its next
+    /// overflow, so every output holds its level for up to one period.
+    pub fn align(&mut self) {
+        let reference = self.members[0].regs();
+        stop(reference);
+        let ovf_event = self.members[0].info().ovf_event;
+        Self::evsys().channel[self.evsys_channel as usize]
+            .channel
+            .write(|w| unsafe { w.evgen().bits(ovf_event).path().asynchronous() });
+        for member in 1..N {
+            self.preload(member);
+        }
+        let reference = self.members[0].regs();
+        reference.ctrlbset.write(|w| w.cmd().retrigger());
+        while reference.syncbusy.read().ctrlb().bit_is_set() {}
+    }
+
+    /// Shift follower `member` to lag the reference by `degrees`, modulo
+    /// 360, and re-align it
+    ///
+    /// The other members keep running. The shifted member restarts on the
+    /// next overflow of the reference, and its outputs hold their level
+    /// until then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `member` is the reference, or not a member of the group.
+    pub fn set_phase(&mut self, member: usize, degrees: u16) {
+        assert!(
+            member > 0 && member < N,
+            "invalid follower {} of a group of {}",
+            member,
+            N
+        );
+        self.phases[member] = degrees % 360;
+        self.preload(member);
+    }
+
+    /// Spread the phases of the members evenly, by `360 / N` degrees, and
+    /// re-align the group
+    pub fn set_phases_evenly(&mut self) {
+        for member in 1..N {
+            self.phases[member] = (member * 360 / N) as u16;
+        }
+        self.align();
+    }
+
+    /// Phase of `member`, in degrees of lag behind the reference
+    #[inline]
+    pub fn phase(&self, member: usize) -> u16 {
+        self.phases[member]
+    }
+
+    /// Advance the followers by `counts`, to compensate for the latency
+    /// between the overflow of the reference and their start. Applies from
+    /// the next [`align`](Self::align) or [`set_phase`](Self::set_phase).
+    ///
+    /// The latency is the constant error [`verify`](Self::verify) reports
+    /// right after an alignment.
+    #[inline]
+    pub fn set_start_compensation(&mut self, counts: u32) {
+        self.compensation = counts % (self.top + 1);
+    }
+
+    /// Maximum duty cycle, the value of `PER`
+    #[inline]
+    pub fn max_duty(&self) -> u32 {
+        self.top
+    }
+
+    /// Set the duty cycle of `channel` of `member`, from the next period
+    ///
+    /// The compare value is written to `CCBUF`, and applies when the counter
+    /// of the member wraps around.
+    #[inline]
+    pub fn set_duty(&mut self, member: usize, channel: Channel, duty: u32) {
+        let tcc = self.members[member].regs();
+        tcc.ccbuf()[channel as usize].write(|w| unsafe { w.ccbuf().bits(duty) });
+    }
+
+    /// Read the counters back, and return the alignment of every member
+    ///
+    /// The reference is always aligned. See
+    /// [Verification](self#verification).
+    pub fn verify(&self) -> [Alignment; N] {
+        let mut alignments = [Alignment::default(); N];
+        let reference = self.members[0].regs();
+        for (member, alignment) in alignments.iter_mut().enumerate().skip(1) {
+            let before = read_count(reference);
+            let count = read_count(self.members[member].regs());
+            let after = read_count(reference);
+            let lag = lag_counts(self.top, self.phases[member]);
+            *alignment = self::alignment(self.top, lag, before, count, after);
+        }
+        alignments
+    }
+
+    /// Disable every member, detach the followers from EVSYS, and return the
+    /// TCCs
+    pub fn free(self) -> [SyncedTcc; N] {
+        for member in 1..N {
+            self.attach(member, false);
+        }
+        Self::evsys().channel[self.evsys_channel as usize]
+            .channel
+            .reset();
+        for member in self.members.iter() {
+            let tcc = member.regs();
+            tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+            while tcc.syncbusy.read().enable().bit_is_set() {}
+        }
+        self.members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_from_frequencies() {
+        // 100 kHz from 120 MHz
+        assert_eq!(period_top(120_000_000, 100_000, 16), Ok(1199));
+        assert_eq!(
+            period_top(120_000_000, 1000, 16),
+            Err(SyncedPwmError::PeriodTooLong)
+        );
+        assert_eq!(period_top(120_000_000, 1000, 24), Ok(119_999));
+        assert_eq!(
+            period_top(120_000_000, 100_000_000, 16),
+            Err(SyncedPwmError::PeriodTooShort)
+        );
+        assert_eq!(
+            period_top(120_000_000, 0, 24),
+            Err(SyncedPwmError::PeriodTooLong)
+        );
+    }
+
+    #[test]
+    fn preloads_lag_the_reference() {
+        // Four phases of a 1200-count period
+        assert_eq!(lag_counts(1199, 90), 300);
+        assert_eq!(preload_count(1199, 0, 0), 0);
+        assert_eq!(preload_count(1199, 90, 0), 900);
+        assert_eq!(preload_count(1199, 180, 0), 600);
+        assert_eq!(preload_count(1199, 270, 0), 300);
+        // Three phases of a 1000-count period round to the closest count
+        assert_eq!(lag_counts(999, 120), 333);
+        assert_eq!(lag_counts(999, 240), 667);
+        // Followers starting 3 counts late
+        assert_eq!(preload_count(1199, 90, 3), 903);
+        assert_eq!(preload_count(1199, 0, 3), 3);
+    }
+
+    #[test]
+    fn alignment_within_read_window() {
+        // Follower at 90 degrees, read 10 counts after the reference, which
+        // moved 20 counts between its reads
+        let aligned = alignment(1199, 300, 500, 210, 520);
+        assert_eq!(
+            aligned,
+            Alignment {
+                error: 0,
+                tolerance: 10
+            }
+        );
+        assert!(aligned.is_aligned());
+        // Across the wrap of the reference
+        let wrapped = alignment(1199, 300, 1190, 900, 10);
+        assert_eq!(wrapped.tolerance, 10);
+        assert!(wrapped.is_aligned());
+        // 25 counts late
+        let late = alignment(1199, 300, 500, 185, 520);
+        assert_eq!(late.error, 25);
+        assert!(!late.is_aligned());
+        // 30 counts early, across the wrap of the follower
+        let early = alignment(1199, 1000, 1100, 140, 1120);
+        assert_eq!(early.error, -30);
+    }
+}
@@ -0,0 +1,304 @@
+//! # Input capture with the TCC
+//!
+//! When the capture of one of its channels is enabled, a TCC copies its
+//! counter into the channel's `CC` register whenever that channel receives an
+//! event. The TCCs have more channels than the TCs (six on TCC0, four on TCC1,
+//! three on TCC2 and two on TCC3 and TCC4), which makes them a good fit for
+//! decoding several pulse trains with a single timer, like the outputs of an
+//! RC receiver.
+//!
+//! [`TccCapture`] configures the selected channels for capture on their event
+//! input (`EVCTRL.MCEIx`). As for the [`EventCounter`], the events have to be
+//! routed to the TCC through EVSYS, which this HAL does not abstract yet:
+//!
+//! - Enable event generation for the input pin in the EIC (`EVCTRL.EXTINTEO`)
+//! - Configure an EVSYS channel with the EIC as its generator
+//! - Select that channel in the `USER` register of the TCC's `MCx` event user
+//!
+//! The `CAPTMODE` minimum/maximum capture modes of later silicon revisions are
+//! not described by the PAC, so every capture stores the plain counter value.
+//!
+//! ## Timestamps
+//!
+//! The counter is 24 bits wide on TCC0 and TCC1, and 16 bits wide on the other
+//! instances. [`TccCapture::timestamp`] extends a captured value to 64 bits by
+//! counting the counter overflows. Overflows have to be accounted for at least
+//! once per counter period, by calling [`TccCapture::poll_overflow`], e.g.
+//! from the TCC overflow interrupt.
+//!
+//! ## DMA
+//!
+//! With the `dma` feature, [`TccCapture::stream`] moves every capture of a
+//! channel to a buffer, using the channel's `MCx` DMA trigger. This frees the
+//! CPU from reading fast bursts of captures, like the echoes of a laser
+//! time-of-flight measurement.
+//!
+//! ## Example
+//!
+//! Decoding six RC PWM inputs, with each EIC line configured to detect both
+//! edges and routed to the matching TCC0 channel:
+//!
+//! ```
+//! let mut capture = TccCapture::tcc0(&tcc0_clock, peripherals.TCC0, &mut mclk, 0b11_1111);
+//! let mut rising = [0u64; 6];
+//! loop {
+//!     for ch in 0..6 {
+//!         if let Some(cc) = capture.read(ch) {
+//!             let time = capture.timestamp(cc);
+//!             if pins.is_high(ch) {
+//!                 rising[ch] = time;
+//!             } else {
+//!                 let ticks = time - rising[ch];
+//!                 pulses[ch] = ticks * 1_000_000 / capture.freq().0 as u64;
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`EventCounter`]: crate::timer::EventCounter
+
+use core::ops::Deref;
+
+use crate::clock;
+use crate::target_device::{tcc0, MCLK, TCC0, TCC1, TCC2};
+#[cfg(feature = "min-samd51j")]
+use crate::target_device::{TCC3, TCC4};
+use crate::time::Hertz;
+
+#[cfg(all(feature = "unproven", feature = "dma"))]
+use crate::dmac::{
+    channel::{Busy, Channel, Ready},
+    dma_controller::ChId,
+    transfer::BufferPair,
+    Buffer, BufferError, DmaController, Transfer, TriggerAction, TriggerSource,
+};
+
+/// A TCC instance that can be used for input capture
+///
+/// This trait is implemented for every TCC of the chip, and is not meant to be
+/// implemented outside of this module.
+pub trait CaptureTcc: Deref<Target = tcc0::RegisterBlock> {
+    /// Number of compare/capture channels
+    const CHANNELS: usize;
+    /// Width of the counter, in bits
+    const COUNTER_BITS: u32;
+
+    /// DMA trigger source raised by each capture of a channel
+    #[cfg(all(feature = "unproven", feature = "dma"))]
+    fn capture_trigger(channel: usize) -> TriggerSource;
+}
+
+/// A TCC capturing its counter on the event inputs of its channels
+///
+/// See the [module-level documentation](self) for more details.
+pub struct TccCapture<TCC> {
+    freq: Hertz,
+    tcc: TCC,
+    overflows: u32,
+}
+
+impl<TCC: CaptureTcc> TccCapture<TCC> {
+    /// Reset the TCC and enable the capture of the channels selected by the
+    /// `channels` bit mask
+    ///
+    /// The counter runs at the frequency of the TCC clock, without prescaler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` selects a channel that this TCC doesn't have.
+    pub fn new(freq: Hertz, tcc: TCC, channels: u8) -> Self {
+        assert!(
+            (channels as usize) < (1 << TCC::CHANNELS),
+            "invalid capture channels {:#x}",
+            channels
+        );
+
+        tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while tcc.syncbusy.read().enable().bit_is_set() {}
+        tcc.ctrla.write(|w| w.swrst().set_bit());
+        while tcc.syncbusy.read().swrst().bit_is_set() {}
+
+        // CPTENx and MCEIx are bits 24+x and 16+x respectively
+        tcc.ctrla
+            .write(|w| unsafe { w.bits((channels as u32) << 24) });
+        tcc.evctrl
+            .write(|w| unsafe { w.bits((channels as u32) << 16) });
+        tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        while tcc.syncbusy.read().enable().bit_is_set() {}
+
+        TccCapture {
+            freq,
+            tcc,
+            overflows: 0,
+        }
+    }
+
+    /// Frequency of the counter
+    pub fn freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Return the last capture of `channel`, if a new one happened since the
+    /// last call
+    ///
+    /// Reading the value clears the channel's `MCx` flag.
+    pub fn read(&mut self, channel: usize) -> Option<u32> {
+        if self.tcc.intflag.read().bits() & (1 << (16 + channel)) != 0 {
+            Some(self.tcc.cc()[channel].read().bits())
+        } else {
+            None
+        }
+    }
+
+    /// Whether a capture was lost because the previous capture of the same
+    /// channel was not read in time
+    ///
+    /// This clears the error flag, which is shared by all channels.
+    pub fn capture_error(&mut self) -> bool {
+        let error = self.tcc.intflag.read().err().bit_is_set();
+        self.tcc.intflag.write(|w| w.err().set_bit());
+        error
+    }
+
+    /// Account for a counter overflow, if one happened since the last call
+    ///
+    /// This must be called at least once per counter period, e.g. from the
+    /// TCC overflow interrupt, to keep the timestamps correct.
+    pub fn poll_overflow(&mut self) {
+        if self.tcc.intflag.read().ovf().bit_is_set() {
+            self.tcc.intflag.write(|w| w.ovf().set_bit());
+            self.overflows = self.overflows.wrapping_add(1);
+        }
+    }
+
+    /// Number of counter overflows accounted for so far
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Extend a captured counter value to a 64-bit timestamp, in counter ticks
+    ///
+    /// The capture must be more recent than the last accounted overflow.
+    pub fn timestamp(&mut self, capture: u32) -> u64 {
+        let pending = self.tcc.intflag.read().ovf().bit_is_set();
+        extend_capture(self.overflows, pending, capture, TCC::COUNTER_BITS)
+    }
+
+    /// Stream the captures of `channel` to `buffer`, using the channel's
+    /// capture DMA trigger
+    ///
+    /// The transfer completes once `buffer` is full, or never if `circular`
+    /// is set. Captures are not flagged to the CPU while the transfer runs.
+    /// A buffer longer than [`MAX_BEATS`](crate::dmac::MAX_BEATS) is handed
+    /// back with the DMA channel, and the transfer isn't started.
+    #[cfg(all(feature = "unproven", feature = "dma"))]
+    #[allow(clippy::type_complexity)]
+    pub fn stream<Id, B>(
+        &mut self,
+        dmac: &mut DmaController,
+        chan: Channel<Id, Ready>,
+        channel: usize,
+        buffer: B,
+        circular: bool,
+    ) -> Result<
+        Transfer<Channel<Id, Busy>, BufferPair<&'static mut u32, B>>,
+        (Channel<Id, Ready>, B, BufferError),
+    >
+    where
+        Id: ChId,
+        B: Buffer<Beat = u32> + 'static,
+    {
+        // SAFETY: The capture register is only read by the DMAC while the
+        // transfer runs
+        let cc = unsafe { &mut *self.tcc.cc()[channel].as_ptr() };
+        match Transfer::try_new(chan, cc, buffer, circular) {
+            Ok(transfer) => {
+                Ok(transfer.begin(dmac, TCC::capture_trigger(channel), TriggerAction::BURST))
+            }
+            Err((chan, _, buffer, e)) => Err((chan, buffer, e)),
+        }
+    }
+
+    /// Disable the TCC and return it
+    pub fn free(self) -> TCC {
+        self.tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        while self.tcc.syncbusy.read().enable().bit_is_set() {}
+        self.tcc
+    }
+}
+
+/// Extend a capture of a `bits`-wide counter to 64 bits, given the number of
+/// accounted overflows and whether another overflow is pending
+///
+/// A pending overflow only applies to the capture if it happened before the
+/// capture. Since the capture is recent, a value in the lower half of the
+/// counter range means the counter wrapped before it was taken.
+fn extend_capture(overflows: u32, pending: bool, capture: u32, bits: u32) -> u64 {
+    let mut overflows = overflows as u64;
+    if pending && capture < 1 << (bits - 1) {
+        overflows += 1;
+    }
+    (overflows << bits) | capture as u64
+}
+
+macro_rules! tcc_capture {
+    ($($TCC:ident: ($ctor:ident, $clock:ident, $apmask:ident, $apbits:ident, $bits:literal, [$($mc:ident),+]),)+) => {
+        $(
+impl CaptureTcc for $TCC {
+    const CHANNELS: usize = [$(stringify!($mc)),+].len();
+    const COUNTER_BITS: u32 = $bits;
+
+    #[cfg(all(feature = "unproven", feature = "dma"))]
+    fn capture_trigger(channel: usize) -> TriggerSource {
+        [$(TriggerSource::$mc),+][channel]
+    }
+}
+
+impl TccCapture<$TCC> {
+    /// Enable the TCC in `MCLK` and configure it for input capture, see
+    /// [`TccCapture::new`]
+    pub fn $ctor(clock: &clock::$clock, tcc: $TCC, mclk: &mut MCLK, channels: u8) -> Self {
+        mclk.$apmask.modify(|_, w| w.$apbits().set_bit());
+        Self::new(clock.freq(), tcc, channels)
+    }
+}
+        )+
+    }
+}
+
+tcc_capture! {
+    TCC0: (tcc0, Tcc0Tcc1Clock, apbbmask, tcc0_, 24, [TCC0_MC_0, TCC0_MC_1, TCC0_MC_2, TCC0_MC_3, TCC0_MC_4, TCC0_MC_5]),
+    TCC1: (tcc1, Tcc0Tcc1Clock, apbbmask, tcc1_, 24, [TCC1_MC_0, TCC1_MC_1, TCC1_MC_2, TCC1_MC_3]),
+    TCC2: (tcc2, Tcc2Tcc3Clock, apbcmask, tcc2_, 16, [TCC2_MC_0, TCC2_MC_1, TCC2_MC_2]),
+}
+
+#[cfg(feature = "min-samd51j")]
+tcc_capture! {
+    TCC3: (tcc3, Tcc2Tcc3Clock, apbcmask, tcc3_, 16, [TCC3_MC_0, TCC3_MC_1]),
+    TCC4: (tcc4, Tcc4Clock, apbdmask, tcc4_, 16, [TCC4_MC_0, TCC4_MC_1]),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_extend_across_overflows() {
+        assert_eq!(extend_capture(0, false, 0x1234, 16), 0x1234);
+        assert_eq!(extend_capture(3, false, 0xFFFF, 16), 0x3_FFFF);
+        // The counter wrapped before this capture, but the overflow is not
+        // accounted for yet
+        assert_eq!(extend_capture(3, true, 0x0010, 16), 0x4_0010);
+        // Captured just before the pending overflow
+        assert_eq!(extend_capture(3, true, 0xFFF0, 16), 0x3_FFF0);
+        assert_eq!(extend_capture(1, false, 0x80_0000, 24), 0x180_0000);
+    }
+
+    #[test]
+    fn channel_counts() {
+        assert_eq!(TCC0::CHANNELS, 6);
+        assert_eq!(TCC1::CHANNELS, 4);
+        assert_eq!(TCC2::CHANNELS, 3);
+    }
+}
@@ -133,6 +133,258 @@ where
     }
 }
 
+/// A [`TimerCounter`] that counts input events over a gate period
+///
+/// In this mode, the counter increments once for every event received on its
+/// event input, instead of once per clock tick. Counting the events that
+/// arrive during a known gate period yields their frequency. Unlike input
+/// capture, this works just as well for low duty-cycle pulse trains, like the
+/// ones produced by anemometers or flow sensors.
+///
+/// The TC does not select its own input; the events have to be routed to it
+/// through EVSYS, which this HAL does not abstract yet:
+///
+/// - Enable event generation for the input pin in the EIC (`EVCTRL.EXTINTEO`)
+/// - Configure an EVSYS channel with the EIC as its generator, using the
+///   asynchronous path if the input can be faster than the GCLK of the TC
+/// - Select that channel in the `USER` register of the TC's `EVU` event user
+///
+/// The gate period is defined by another timer. [`measure`] blocks on any
+/// [`CountDown`] timer for one gate period. Alternatively, call [`end_gate`]
+/// from the interrupt handler of a periodic timer, passing its period.
+///
+/// The counter is 16 bits wide, so the gate period must be short enough that
+/// no more than 65535 events arrive within it. Use [`overflowed`] to check.
+///
+/// [`measure`]: EventCounter::measure
+/// [`end_gate`]: EventCounter::end_gate
+/// [`overflowed`]: EventCounter::overflowed
+pub struct EventCounter<TC> {
+    freq: Hertz,
+    tc: TC,
+    count: u16,
+    overflow: bool,
+    gate: Nanoseconds,
+}
+
+impl<TC> TimerCounter<TC>
+where
+    TC: Count16,
+{
+    /// Reconfigure the timer to count input events instead of clock ticks
+    ///
+    /// Counting starts immediately, but the first gate period only begins
+    /// with [`EventCounter::restart`], [`EventCounter::end_gate`] or
+    /// [`EventCounter::measure`].
+    pub fn into_event_counter(self) -> EventCounter<TC> {
+        let count = self.tc.count_16();
+
+        // Disable the timer while we reconfigure it
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().enable().bit_is_set() {}
+
+        count.ctrla.write(|w| w.swrst().set_bit());
+        // the SVD erroneously marks swrst as write-only, so we
+        // need to manually read the bit here
+        while count.ctrla.read().bits() & 1 != 0 {}
+
+        // Increment the counter on every incoming event
+        count.evctrl.write(|w| {
+            w.evact().count();
+            w.tcei().set_bit()
+        });
+
+        count.ctrla.modify(|_, w| {
+            w.prescaler().div1();
+            w.enable().set_bit();
+            w.runstdby().set_bit()
+        });
+        while count.syncbusy.read().enable().bit_is_set() {}
+
+        EventCounter {
+            freq: self.freq,
+            tc: self.tc,
+            count: 0,
+            overflow: false,
+            gate: Nanoseconds(0),
+        }
+    }
+}
+
+impl<TC> EventCounter<TC>
+where
+    TC: Count16,
+{
+    /// Clear the counter and start a new gate period
+    pub fn restart(&mut self) {
+        let count = self.tc.count_16();
+        count.ctrlbset.write(|w| w.cmd().retrigger());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+        count.intflag.write(|w| w.ovf().set_bit());
+    }
+
+    /// End the current gate period, which lasted `gate`
+    ///
+    /// This records the number of events counted since the last call to
+    /// [`restart`](Self::restart) or `end_gate`, then immediately starts the
+    /// next gate period. Returns the recorded count.
+    pub fn end_gate<T>(&mut self, gate: T) -> u16
+    where
+        T: Into<Nanoseconds>,
+    {
+        let count = self.tc.count_16();
+        count.ctrlbset.write(|w| w.cmd().readsync());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+        while count.syncbusy.read().count().bit_is_set() {}
+        self.count = count.count.read().count().bits();
+        self.overflow = count.intflag.read().ovf().bit_is_set();
+        self.gate = gate.into();
+        self.restart();
+        self.count
+    }
+
+    /// Count events for one gate period, timed by `timer`
+    ///
+    /// This blocks until `timer` expires, then returns the measured frequency.
+    pub fn measure<G, T>(&mut self, timer: &mut G, gate: T) -> Hertz
+    where
+        G: CountDown,
+        T: Into<G::Time> + Into<Nanoseconds> + Copy,
+    {
+        timer.start(gate);
+        self.restart();
+        let _ = nb::block!(timer.wait());
+        self.end_gate(gate);
+        self.frequency()
+    }
+
+    /// Number of events counted during the last gate period
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    /// Whether the counter overflowed during the last gate period
+    ///
+    /// If so, the recorded count and frequency are meaningless and a shorter
+    /// gate period is needed.
+    pub fn overflowed(&self) -> bool {
+        self.overflow
+    }
+
+    /// Frequency of the input events during the last gate period
+    pub fn frequency(&self) -> Hertz {
+        event_frequency(self.count, self.gate)
+    }
+
+    /// Stop counting events and return the underlying [`TimerCounter`]
+    pub fn into_timer(self) -> TimerCounter<TC> {
+        let count = self.tc.count_16();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().enable().bit_is_set() {}
+        count.evctrl.reset();
+        TimerCounter {
+            freq: self.freq,
+            tc: self.tc,
+        }
+    }
+}
+
+/// A [`TimerCounter`] that expires after a period without input events
+///
+/// Every input event restarts the count, and the timer stops once it runs for
+/// a whole timeout without an event. This detects the end of a burst of
+/// events, like the idle gap after a UART packet received by DMA, see
+/// `UARTn::receive_until_idle_dma`.
+///
+/// As for [`EventCounter`], the events have to be routed to the TC through
+/// EVSYS, into the `USER` register of the TC's `EVU` event user. The timer
+/// starts stopped, and the first event starts it.
+pub struct IdleTimer<TC> {
+    freq: Hertz,
+    tc: TC,
+}
+
+impl<TC> TimerCounter<TC>
+where
+    TC: Count16,
+{
+    /// Reconfigure the timer to expire `timeout` after the last input event
+    pub fn into_idle_timer<T>(mut self, timeout: T) -> IdleTimer<TC>
+    where
+        T: Into<Nanoseconds>,
+    {
+        // Compute the period and prescaler
+        self.start(timeout);
+        let count = self.tc.count_16();
+
+        // EVCTRL and the one-shot mode can only be changed while disabled
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().enable().bit_is_set() {}
+
+        // Restart the count on every incoming event
+        count.evctrl.write(|w| {
+            w.evact().retrigger();
+            w.tcei().set_bit()
+        });
+        count.ctrlbset.write(|w| w.oneshot().set_bit());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+
+        count.ctrla.modify(|_, w| w.enable().set_bit());
+        while count.syncbusy.read().enable().bit_is_set() {}
+
+        let mut timer = IdleTimer {
+            freq: self.freq,
+            tc: self.tc,
+        };
+        timer.arm();
+        timer
+    }
+}
+
+impl<TC> IdleTimer<TC>
+where
+    TC: Count16,
+{
+    /// Stop the timer and clear its expiry, so that it waits for the next
+    /// input event
+    pub fn arm(&mut self) {
+        let count = self.tc.count_16();
+        count.ctrlbset.write(|w| w.cmd().stop());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+        count.intflag.write(|w| w.ovf().set_bit());
+    }
+
+    /// Whether the timer expired since it was last armed
+    pub fn expired(&self) -> bool {
+        self.tc.count_16().intflag.read().ovf().bit_is_set()
+    }
+
+    /// Return the underlying [`TimerCounter`]
+    pub fn into_timer(self) -> TimerCounter<TC> {
+        let count = self.tc.count_16();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().enable().bit_is_set() {}
+        count.evctrl.reset();
+        count.ctrlbclr.write(|w| w.oneshot().set_bit());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+        TimerCounter {
+            freq: self.freq,
+            tc: self.tc,
+        }
+    }
+}
+
+/// Frequency of `count` events spread over a `gate` period, rounded to the
+/// nearest Hertz and saturated at `u32::MAX`
+fn event_frequency(count: u16, gate: Nanoseconds) -> Hertz {
+    let gate = gate.0 as u64;
+    if gate == 0 {
+        return Hertz(0);
+    }
+    let freq = (count as u64 * 1_000_000_000 + gate / 2) / gate;
+    Hertz(freq.min(u32::MAX as u64) as u32)
+}
+
 macro_rules! tc {
     ($($TYPE:ident: ($TC:ident, $mclk:ident, $clock:ident, $apmask:ident),)+) => {
         $(
@@ -212,3 +464,28 @@ impl CountDown for SpinTimer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_frequency_over_gate() {
+        assert_eq!(
+            event_frequency(1234, Nanoseconds(1_000_000_000)),
+            Hertz(1234)
+        );
+        assert_eq!(
+            event_frequency(1234, Nanoseconds(100_000_000)),
+            Hertz(12_340)
+        );
+        // 3 events in 7 ms is 428.57 Hz
+        assert_eq!(event_frequency(3, Nanoseconds(7_000_000)), Hertz(429));
+        // Saturates instead of wrapping
+        assert_eq!(
+            event_frequency(u16::MAX, Nanoseconds(1_000)),
+            Hertz(u32::MAX)
+        );
+        assert_eq!(event_frequency(10, Nanoseconds(0)), Hertz(0));
+    }
+}
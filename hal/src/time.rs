@@ -1,4 +1,8 @@
 //! Time units
+//!
+//! With the `fugit` feature enabled, [`Hertz`] converts to and from
+//! [`fugit::HertzU32`], so rates from `fugit`-based crates can be passed to
+//! any API taking `impl Into<Hertz>`.
 
 // Frequency based
 
@@ -244,6 +248,22 @@ impl From<MegaHertz> for Nanoseconds {
     }
 }
 
+// fugit interop
+
+#[cfg(feature = "fugit")]
+impl From<fugit::HertzU32> for Hertz {
+    fn from(rate: fugit::HertzU32) -> Self {
+        Hertz(rate.raw())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Hertz> for fugit::HertzU32 {
+    fn from(hz: Hertz) -> Self {
+        fugit::HertzU32::from_raw(hz.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::time::*;
@@ -277,4 +297,14 @@ mod tests {
         let as_ns: Nanoseconds = 2.mhz().into();
         assert_eq!(as_ns.0, 500_u32);
     }
+
+    #[cfg(feature = "fugit")]
+    #[test]
+    fn convert_fugit_round_trip() {
+        let rate = fugit::HertzU32::from_raw(12_000_000);
+        let as_hz: Hertz = rate.into();
+        assert_eq!(as_hz.0, 12_000_000_u32);
+        let back: fugit::HertzU32 = as_hz.into();
+        assert_eq!(back, rate);
+    }
 }
@@ -0,0 +1,52 @@
+#!/usr/bin/env python3
+"""Dump the clock map emitted by `atsamd_hal::clock_map!` from an ELF file.
+
+Usage: clock_map.py <firmware.elf>
+
+The `.clock_map` section is extracted with `arm-none-eabi-objcopy` (override
+with the OBJCOPY environment variable). Each 8-byte entry is printed with the
+raw register values, see `ClockMapEntry` for their meaning.
+"""
+
+import os
+import struct
+import subprocess
+import sys
+import tempfile
+
+
+def read_section(elf):
+    objcopy = os.environ.get("OBJCOPY", "arm-none-eabi-objcopy")
+    with tempfile.NamedTemporaryFile() as out:
+        subprocess.run(
+            [objcopy, "-O", "binary", "--only-section=.clock_map", elf, out.name],
+            check=True,
+        )
+        return out.read()
+
+
+def parse(data):
+    if len(data) % 8:
+        raise ValueError("truncated clock map: %d bytes" % len(data))
+    for offset in range(0, len(data), 8):
+        kind, gclk, ident, _, div = struct.unpack_from("<BBBBI", data, offset)
+        if kind == 0:
+            yield "GCLK%d: source %d, divider %d" % (gclk, ident, max(div, 1))
+        elif kind == 1:
+            yield "channel %d: GCLK%d" % (ident, gclk)
+        else:
+            raise ValueError("unknown entry kind %d at offset %d" % (kind, offset))
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit(__doc__)
+    data = read_section(sys.argv[1])
+    if not data:
+        sys.exit("no .clock_map section, check the linker script")
+    for line in parse(data):
+        print(line)
+
+
+if __name__ == "__main__":
+    main()